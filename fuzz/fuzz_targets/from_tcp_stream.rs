@@ -0,0 +1,16 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate milstian_http;
+
+use milstian_http::request::{Message, ParserConfig};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Message::from_tcp_stream(data);
+
+    let config = ParserConfig {
+        strict: true,
+        ..ParserConfig::default()
+    };
+    let _ = Message::from_tcp_stream_with_config(data, &config);
+});
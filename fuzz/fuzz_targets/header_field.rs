@@ -0,0 +1,17 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate milstian_http;
+
+use milstian_http::request::Message;
+use std::str;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = str::from_utf8(data) {
+        if let Some((_, value)) = Message::get_header_field(line) {
+            let _ = value.to_string();
+            let _ = value.get_key_value("boundary");
+        }
+        let _ = Message::get_request_line(line);
+    }
+});
@@ -0,0 +1,20 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate milstian_http;
+
+use milstian_http::request::Message;
+
+// The first byte selects the boundary length, the rest is used as boundary and body
+fuzz_target!(|data: &[u8]| {
+    if let Some((length, rest)) = data.split_first() {
+        let length = (*length as usize % 72).min(rest.len());
+        let (boundary, body) = rest.split_at(length);
+        let mut request =
+            b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=".to_vec();
+        request.extend_from_slice(boundary);
+        request.extend_from_slice(b"\r\n\r\n");
+        request.extend_from_slice(body);
+        let _ = Message::from_tcp_stream(&request);
+    }
+});
@@ -0,0 +1,278 @@
+//! # Generators of request byte streams for property testing, enabled by the `quickcheck` feature.
+//!
+//! ```rust
+//! extern crate milstian_http;
+//! extern crate quickcheck;
+//! use milstian_http::arbitrary::ValidRequest;
+//! use milstian_http::request::Message;
+//!
+//! fn prop(request: ValidRequest) -> bool {
+//!     Message::from_tcp_stream(&request.bytes).is_some()
+//! }
+//!
+//! fn main() {
+//!     quickcheck::quickcheck(prop as fn(ValidRequest) -> bool);
+//! }
+//! ```
+
+use quickcheck::{Arbitrary, Gen};
+
+use request::{Message, Method, Protocol};
+
+const HEADER_NAMES: &[&str] = &[
+    "Accept",
+    "Accept-Encoding",
+    "Accept-Language",
+    "Cache-Control",
+    "Connection",
+    "Cookie",
+    "Host",
+    "Referer",
+    "User-Agent",
+    "X-Requested-With",
+];
+
+const TOKEN_CHARACTERS: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_.";
+
+/// # A well-formed request byte stream together with the values it encodes
+#[derive(Clone, Debug)]
+pub struct ValidRequest {
+    pub bytes: Vec<u8>,
+    pub method: Method,
+    pub protocol: Protocol,
+    pub request_uri: String,
+}
+
+/// # A request byte stream with malformed or hostile content
+#[derive(Clone, Debug)]
+pub struct AdversarialRequest {
+    pub bytes: Vec<u8>,
+}
+
+impl Arbitrary for Method {
+    fn arbitrary(g: &mut Gen) -> Method {
+        *g.choose(&[
+            Method::Connect,
+            Method::Delete,
+            Method::Get,
+            Method::Head,
+            Method::Options,
+            Method::Patch,
+            Method::Post,
+            Method::Put,
+            Method::Trace,
+        ])
+        .unwrap_or(&Method::Get)
+    }
+}
+
+impl Arbitrary for Protocol {
+    fn arbitrary(g: &mut Gen) -> Protocol {
+        *g.choose(&[Protocol::V1_0, Protocol::V1_1, Protocol::V2_0])
+            .unwrap_or(&Protocol::V1_1)
+    }
+}
+
+fn range(g: &mut Gen, low: usize, high: usize) -> usize {
+    low + usize::arbitrary(g) % (high - low + 1)
+}
+
+fn token(g: &mut Gen, max_length: usize) -> String {
+    let length = range(g, 1, max_length);
+    (0..length)
+        .map(|_| *g.choose(TOKEN_CHARACTERS).unwrap_or(&b'a') as char)
+        .collect()
+}
+
+fn pairs(g: &mut Gen, max_count: usize) -> String {
+    let count = range(g, 1, max_count);
+    let pairs: Vec<String> = (0..count)
+        .map(|_| format!("{}={}", token(g, 8), token(g, 8)))
+        .collect();
+    pairs.join("&")
+}
+
+impl Arbitrary for ValidRequest {
+    fn arbitrary(g: &mut Gen) -> ValidRequest {
+        let method = Method::arbitrary(g);
+        let protocol = Protocol::arbitrary(g);
+
+        let segment_count = range(g, 0, 4);
+        let mut request_uri = String::new();
+        for _ in 0..segment_count {
+            request_uri.push('/');
+            request_uri.push_str(&token(g, 8));
+        }
+        if request_uri.is_empty() {
+            request_uri.push('/');
+        }
+        if bool::arbitrary(g) {
+            request_uri.push('?');
+            request_uri.push_str(&pairs(g, 3));
+        }
+
+        let mut bytes = format!(
+            "{} {} {}\r\n",
+            Message::get_method_text(&method),
+            &request_uri,
+            Message::get_protocol_text(&protocol)
+        )
+        .into_bytes();
+
+        let header_count = range(g, 0, 5);
+        for _ in 0..header_count {
+            let name = g.choose(HEADER_NAMES).unwrap_or(&"Accept");
+            bytes.extend_from_slice(format!("{}: {}\r\n", name, token(g, 16)).as_bytes());
+        }
+
+        let body = match method {
+            Method::Patch | Method::Post | Method::Put if bool::arbitrary(g) => pairs(g, 4),
+            _ => String::new(),
+        };
+        if !body.is_empty() {
+            bytes.extend_from_slice(
+                format!(
+                    "Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n",
+                    body.len()
+                )
+                .as_bytes(),
+            );
+        }
+        bytes.extend_from_slice(b"\r\n");
+        bytes.extend_from_slice(body.as_bytes());
+
+        ValidRequest {
+            bytes,
+            method,
+            protocol,
+            request_uri,
+        }
+    }
+}
+
+impl Arbitrary for AdversarialRequest {
+    fn arbitrary(g: &mut Gen) -> AdversarialRequest {
+        let mut bytes = ValidRequest::arbitrary(g).bytes;
+        let mutation_count = range(g, 1, 4);
+        for _ in 0..mutation_count {
+            let position = range(g, 0, bytes.len());
+            match range(g, 0, 7) {
+                // Replace a byte with a random one
+                0 => {
+                    if position < bytes.len() {
+                        bytes[position] = u8::arbitrary(g);
+                    }
+                }
+                // Insert random bytes
+                1 => {
+                    let insert: Vec<u8> = Vec::arbitrary(g);
+                    bytes.splice(position..position, insert);
+                }
+                // Truncate the stream
+                2 => bytes.truncate(position),
+                // Insert line breaks and bare carriage returns or new-lines
+                3 => {
+                    let insert: &[u8] = g
+                        .choose(&[&b"\r\n"[..], b"\r", b"\n", b"\r\n\r\n"])
+                        .unwrap_or(&&b"\r\n"[..]);
+                    bytes.splice(position..position, insert.iter().cloned());
+                }
+                // Insert null bytes
+                4 => {
+                    bytes.splice(position..position, vec![0; range(g, 1, 4)]);
+                }
+                // Insert a multi-part content type with a random boundary and body
+                5 => {
+                    let boundary: String = if bool::arbitrary(g) {
+                        token(g, 70)
+                    } else {
+                        String::arbitrary(g)
+                    };
+                    let header = format!(
+                        "Content-Type: multipart/form-data; boundary={}\r\n",
+                        boundary
+                    );
+                    let header_end = bytes
+                        .windows(2)
+                        .position(|window| window == b"\r\n")
+                        .map(|index| index + 2)
+                        .unwrap_or(0);
+                    bytes.splice(header_end..header_end, header.into_bytes());
+                    let parts = range(g, 0, 3);
+                    for _ in 0..parts {
+                        bytes.extend_from_slice(b"--");
+                        bytes.extend_from_slice(boundary.as_bytes());
+                        if bool::arbitrary(g) {
+                            bytes.extend_from_slice(b"--");
+                        }
+                        bytes.extend_from_slice(b"\r\n");
+                        let part: Vec<u8> = Vec::arbitrary(g);
+                        bytes.extend_from_slice(&part);
+                    }
+                }
+                // Insert an overly long header line
+                6 => {
+                    let line = format!("X-Long: {}\r\n", "a".repeat(g.size() * 64));
+                    bytes.splice(position..position, line.into_bytes());
+                }
+                // Repeat the stream
+                _ => {
+                    let copy = bytes.clone();
+                    bytes.extend_from_slice(&copy);
+                }
+            }
+        }
+        AdversarialRequest { bytes }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = AdversarialRequest>> {
+        Box::new(
+            self.bytes
+                .shrink()
+                .map(|bytes| AdversarialRequest { bytes }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::quickcheck;
+
+    #[test]
+    fn test_valid_request() {
+        fn prop(request: ValidRequest) -> bool {
+            if let Some(message) = Message::from_tcp_stream(&request.bytes) {
+                return message.request_line.method == request.method
+                    && message.request_line.protocol == request.protocol
+                    && message.request_line.request_uri == request.request_uri;
+            }
+            false
+        }
+        quickcheck(prop as fn(ValidRequest) -> bool);
+    }
+
+    #[test]
+    fn test_adversarial_request_never_panics() {
+        fn prop(request: AdversarialRequest) -> bool {
+            let _ = Message::from_tcp_stream(&request.bytes);
+            if let Ok(line) = ::std::str::from_utf8(&request.bytes) {
+                let _ = Message::get_header_field(line);
+            }
+            true
+        }
+        quickcheck(prop as fn(AdversarialRequest) -> bool);
+    }
+
+    #[test]
+    fn test_adversarial_request() {
+        for size in 1..100 {
+            let mut g = Gen::new(size);
+            let request = AdversarialRequest::arbitrary(&mut g);
+            for shrunk in request.shrink().take(10) {
+                assert!(shrunk.bytes.len() <= request.bytes.len());
+            }
+        }
+    }
+}
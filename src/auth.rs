@@ -0,0 +1,111 @@
+//! # Handles credentials of the Authorization header and other secret-bearing headers.
+//!
+//! Secrets are compared with `crypto::secret_eq` so the time a comparison takes doesn't tell
+//! how much of a guess was right.
+
+use crypto;
+use request;
+
+/// # Get the token of an Authorization header with the Bearer scheme (RFC 6750)
+/// The scheme is case-insensitive, tokens with characters outside of token68 are rejected.
+/// ## Usage
+/// ```rust
+/// use milstian_http::auth::bearer_token;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nAuthorization: bearer mF_9.B5f-4.1JqM\r\n\r\n")
+///     .expect("A request");
+/// assert_eq!(bearer_token(&request), Some("mF_9.B5f-4.1JqM".to_string()));
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nAuthorization: Basic YTpi\r\n\r\n")
+///     .expect("A request");
+/// assert_eq!(bearer_token(&request), None);
+/// ```
+pub fn bearer_token(request: &request::Message) -> Option<String> {
+    let authorization = request.header("Authorization")?.to_string();
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    let token = token.trim_start();
+    if scheme.eq_ignore_ascii_case("Bearer") && is_token68(token) {
+        Some(token.to_string())
+    } else {
+        None
+    }
+}
+
+/// # Does the request carry the expected bearer token?
+/// ## Usage
+/// ```rust
+/// use milstian_http::auth::verify_bearer;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nAuthorization: Bearer abc\r\n\r\n")
+///     .expect("A request");
+/// assert!(verify_bearer(&request, "abc"));
+/// assert!(!verify_bearer(&request, "abd"));
+/// ```
+pub fn verify_bearer(request: &request::Message, expected: &str) -> bool {
+    bearer_token(request).is_some_and(|token| crypto::secret_eq(expected, &token))
+}
+
+/// # Does header name, like an API key header, hold the expected secret?
+/// ## Usage
+/// ```rust
+/// use milstian_http::auth::verify_header;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nX-Api-Key: k3y\r\n\r\n")
+///     .expect("A request");
+/// assert!(verify_header(&request, "x-api-key", "k3y"));
+/// assert!(!verify_header(&request, "X-Api-Key", "key"));
+/// ```
+pub fn verify_header(request: &request::Message, name: &str, expected: &str) -> bool {
+    request
+        .header(name)
+        .is_some_and(|value| crypto::secret_eq(expected, value.to_string().trim()))
+}
+
+/// Characters of a token68, optionally padded with trailing '='
+fn is_token68(value: &str) -> bool {
+    let token = value.trim_end_matches('=');
+    !token.is_empty()
+        && token
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "-._~+/".contains(character))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(authorization: &str) -> request::Message {
+        request::Message::from_tcp_stream(
+            format!("GET / HTTP/1.1\r\nAuthorization: {}\r\n\r\n", authorization).as_bytes(),
+        )
+        .expect("A request")
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(
+            bearer_token(&request("Bearer   a+b/c==")),
+            Some("a+b/c==".to_string())
+        );
+        assert_eq!(bearer_token(&request("Bearer")), None);
+        assert_eq!(bearer_token(&request("Bearer =")), None);
+        assert_eq!(bearer_token(&request("Bearer a=b")), None);
+        assert_eq!(bearer_token(&request("Bearer a b")), None);
+        assert_eq!(bearer_token(&request("Bearers abc")), None);
+        assert_eq!(
+            bearer_token(
+                &request::Message::from_tcp_stream(b"GET / HTTP/1.1\r\n\r\n").expect("A request")
+            ),
+            None
+        );
+
+        assert!(verify_bearer(&request("BEARER abc"), "abc"));
+        assert!(!verify_bearer(&request("Bearer abc"), "ab"));
+        assert!(!verify_bearer(&request("Bearer abc"), ""));
+        assert!(verify_header(
+            &request("Bearer abc"),
+            "Authorization",
+            "Bearer abc"
+        ));
+        assert!(!verify_header(&request("Bearer abc"), "X-Api-Key", ""));
+    }
+}
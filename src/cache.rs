@@ -0,0 +1,1106 @@
+//! # Handles everything related to HTTP caching.
+
+use std::fmt;
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use date;
+use request;
+use response;
+
+/// # Largest value of an Age header, older responses are sent with this value
+pub const MAX_AGE: u64 = 2_147_483_648;
+
+/// # Directives of a Cache-Control response header
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct CacheControl {
+    pub immutable: bool,
+    pub max_age: Option<u64>,
+    pub must_revalidate: bool,
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub no_transform: bool,
+    pub private: bool,
+    pub proxy_revalidate: bool,
+    pub public: bool,
+    pub s_maxage: Option<u64>,
+    pub stale_if_error: Option<u64>, // Seconds of stale use when revalidation fails
+    pub stale_while_revalidate: Option<u64>, // Seconds of stale use while revalidating
+}
+
+impl CacheControl {
+    /// # Check that no directives contradict each other
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::CacheControl;
+    /// assert!(CacheControl { public: true, ..CacheControl::default() }.is_valid());
+    /// assert!(!CacheControl { public: true, private: true, ..CacheControl::default() }.is_valid());
+    /// ```
+    pub fn is_valid(&self) -> bool {
+        let has_directives = !self.to_string().is_empty();
+        has_directives && !(self.public && self.private)
+    }
+
+    /// # Parse the directives of a Cache-Control header value
+    /// Names are case-insensitive, unknown directives are ignored and so are ages that are not
+    /// numbers, the first of repeated ages is used.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::CacheControl;
+    /// let cache_control = CacheControl::parse("Public, max-age=\"60\", no-cache=\"Set-Cookie, Age\"");
+    /// assert!(cache_control.public);
+    /// assert!(cache_control.no_cache);
+    /// assert_eq!(cache_control.max_age, Some(60));
+    /// ```
+    pub fn parse(value: &str) -> CacheControl {
+        let mut cache_control = CacheControl::default();
+        for directive in split_unquoted(value, ',') {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim())),
+                None => (directive.trim(), None),
+            };
+            let seconds = argument.and_then(|argument| {
+                let argument = if argument.len() > 1 && argument.starts_with('"') {
+                    unquote(argument)
+                } else {
+                    argument.to_string()
+                };
+                argument.parse::<u64>().ok()
+            });
+            match name.to_lowercase().as_str() {
+                "immutable" => cache_control.immutable = true,
+                "max-age" => cache_control.max_age = cache_control.max_age.or(seconds),
+                "must-revalidate" => cache_control.must_revalidate = true,
+                "no-cache" => cache_control.no_cache = true,
+                "no-store" => cache_control.no_store = true,
+                "no-transform" => cache_control.no_transform = true,
+                "private" => cache_control.private = true,
+                "proxy-revalidate" => cache_control.proxy_revalidate = true,
+                "public" => cache_control.public = true,
+                "s-maxage" => cache_control.s_maxage = cache_control.s_maxage.or(seconds),
+                "stale-if-error" => {
+                    cache_control.stale_if_error = cache_control.stale_if_error.or(seconds)
+                }
+                "stale-while-revalidate" => {
+                    cache_control.stale_while_revalidate =
+                        cache_control.stale_while_revalidate.or(seconds)
+                }
+                _ => {}
+            }
+        }
+        cache_control
+    }
+}
+
+impl fmt::Display for CacheControl {
+    /// # Render directives as a Cache-Control header value
+    /// ```rust
+    /// use milstian_http::cache::CacheControl;
+    /// assert_eq!(
+    ///     CacheControl { public: true, max_age: Some(60), ..CacheControl::default() }.to_string(),
+    ///     "public, max-age=60".to_string()
+    /// );
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut directives: Vec<String> = Vec::new();
+        if self.public {
+            directives.push(String::from("public"));
+        }
+        if self.private {
+            directives.push(String::from("private"));
+        }
+        if self.no_cache {
+            directives.push(String::from("no-cache"));
+        }
+        if self.no_store {
+            directives.push(String::from("no-store"));
+        }
+        if self.no_transform {
+            directives.push(String::from("no-transform"));
+        }
+        if self.must_revalidate {
+            directives.push(String::from("must-revalidate"));
+        }
+        if self.proxy_revalidate {
+            directives.push(String::from("proxy-revalidate"));
+        }
+        if let Some(max_age) = self.max_age {
+            directives.push(format!("max-age={}", max_age));
+        }
+        if let Some(s_maxage) = self.s_maxage {
+            directives.push(format!("s-maxage={}", s_maxage));
+        }
+        if self.immutable {
+            directives.push(String::from("immutable"));
+        }
+        if let Some(stale_while_revalidate) = self.stale_while_revalidate {
+            directives.push(format!("stale-while-revalidate={}", stale_while_revalidate));
+        }
+        if let Some(stale_if_error) = self.stale_if_error {
+            directives.push(format!("stale-if-error={}", stale_if_error));
+        }
+        write!(f, "{}", directives.join(", "))
+    }
+}
+
+/// # Validators of a representation for conditional requests
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Validators {
+    pub etag: String,                      // Entity tag including quotes and weak prefix
+    pub last_modified: Option<SystemTime>, // Truncated to whole seconds like HTTP-dates
+}
+
+impl Validators {
+    /// # Derive a weak entity tag and modification time from file metadata
+    /// The tag changes with the size, modification time and on Unix the inode of the file.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::Validators;
+    /// use std::env;
+    /// use std::fs;
+    /// let path = env::temp_dir().join("milstian-validators-example.txt");
+    /// fs::write(&path, b"Hello").expect("A file");
+    /// let validators = Validators::from_metadata(&fs::metadata(&path).expect("Metadata"));
+    /// assert!(validators.etag.starts_with("W/\""));
+    /// assert!(validators.last_modified.is_some());
+    /// fs::remove_file(&path).expect("A removed file");
+    /// ```
+    pub fn from_metadata(metadata: &fs::Metadata) -> Validators {
+        let modified = metadata.modified().ok();
+        let nanos = modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos())
+            .unwrap_or(0);
+        #[cfg(unix)]
+        let etag = format!(
+            "W/\"{:x}-{:x}-{:x}\"",
+            metadata.ino(),
+            metadata.len(),
+            nanos
+        );
+        #[cfg(not(unix))]
+        let etag = format!("W/\"{:x}-{:x}\"", metadata.len(), nanos);
+        Validators {
+            etag,
+            last_modified: modified.and_then(|modified| {
+                let seconds = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+                Some(UNIX_EPOCH + Duration::from_secs(seconds))
+            }),
+        }
+    }
+
+    /// # Set the ETag and Last-Modified headers of response
+    pub fn apply(&self, response: &mut response::Message) {
+        response
+            .headers
+            .insert("ETag".to_string(), self.etag.clone());
+        if let Some(last_modified) = self.last_modified {
+            response.headers.insert(
+                "Last-Modified".to_string(),
+                date::format_http_date(last_modified),
+            );
+        }
+    }
+
+    /// # Can a GET or HEAD request be answered with 304 Not Modified?
+    /// If-None-Match is compared weakly and takes precedence over If-Modified-Since.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::Validators;
+    /// use milstian_http::request::Message;
+    /// let validators = Validators {
+    ///     etag: "W/\"1-2\"".to_string(),
+    ///     last_modified: None,
+    /// };
+    /// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nIf-None-Match: \"0\", \"1-2\"\r\n")
+    ///     .expect("A request");
+    /// assert!(validators.is_not_modified(&request));
+    /// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\n").expect("A request");
+    /// assert!(!validators.is_not_modified(&request));
+    /// ```
+    pub fn is_not_modified(&self, request: &request::Message) -> bool {
+        let method = &request.request_line.method;
+        if *method != request::Method::Get && *method != request::Method::Head {
+            return false;
+        }
+        if let Some(if_none_match) = request.header("If-None-Match") {
+            let if_none_match = if_none_match.to_string();
+            return if_none_match.trim() == "*"
+                || if_none_match
+                    .split(',')
+                    .any(|tag| get_opaque_tag(tag) == get_opaque_tag(&self.etag));
+        }
+        match (request.header("If-Modified-Since"), self.last_modified) {
+            (Some(if_modified_since), Some(last_modified)) => {
+                match date::parse_http_date(&if_modified_since.to_string()) {
+                    Some(since) => last_modified <= since,
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// # Get how long a response is fresh for from Cache-Control or Expires (RFC 9111 section 4.2.1)
+/// Shared caches prefer s-maxage over max-age, Expires is relative to Date and an invalid
+/// Expires means already stale. Returns None without explicit freshness information.
+/// ## Usage
+/// ```rust
+/// use milstian_http::cache::freshness_lifetime;
+/// use milstian_http::response::Message;
+/// use std::time::Duration;
+/// let mut response = Message::default();
+/// assert_eq!(freshness_lifetime(&response, true), None);
+/// response
+///     .headers
+///     .insert("Cache-Control".to_string(), "max-age=60, s-maxage=10".to_string());
+/// assert_eq!(freshness_lifetime(&response, false), Some(Duration::from_secs(60)));
+/// assert_eq!(freshness_lifetime(&response, true), Some(Duration::from_secs(10)));
+/// ```
+pub fn freshness_lifetime(response: &response::Message, shared: bool) -> Option<Duration> {
+    let cache_control = get_header(response, "Cache-Control")
+        .map(|value| CacheControl::parse(value))
+        .unwrap_or_default();
+    let seconds = if shared {
+        cache_control.s_maxage.or(cache_control.max_age)
+    } else {
+        cache_control.max_age
+    };
+    if let Some(seconds) = seconds {
+        return Some(Duration::from_secs(seconds));
+    }
+    let expires = get_header(response, "Expires")?;
+    let date = get_header(response, "Date").and_then(|date| date::parse_http_date(date))?;
+    Some(
+        date::parse_http_date(expires)
+            .and_then(|expires| expires.duration_since(date).ok())
+            .unwrap_or_default(),
+    )
+}
+
+/// # The age of a stored response, to update its Age header when served again
+/// The initial age is corrected for clock skew and network delay like RFC 9111 section 4.2.3
+/// and grows with the time the response was stored.
+/// ## Usage
+/// ```rust
+/// use milstian_http::cache::Age;
+/// use milstian_http::response::Message;
+/// use std::time::{Duration, UNIX_EPOCH};
+/// let mut response = Message::default();
+/// response
+///     .headers
+///     .insert("Date".to_string(), "Thu, 01 Jan 1970 00:01:40 GMT".to_string());
+/// response.headers.insert("Age".to_string(), "5".to_string());
+/// let request_time = UNIX_EPOCH + Duration::from_secs(100);
+/// let response_time = UNIX_EPOCH + Duration::from_secs(102);
+/// let age = Age::new(&response, request_time, response_time);
+/// let now = UNIX_EPOCH + Duration::from_secs(160);
+/// assert_eq!(age.current(now), Duration::from_secs(65));
+/// age.apply(&mut response, now);
+/// assert_eq!(response.headers.get("Age"), Some(&"65".to_string()));
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Age {
+    pub corrected_initial_age: Duration,
+    pub response_time: SystemTime, // When the response was received and stored
+}
+
+impl Age {
+    /// # Calculate the initial age of response from its Date and Age headers
+    /// request_time is when the request was sent and response_time when the response arrived.
+    pub fn new(
+        response: &response::Message,
+        request_time: SystemTime,
+        response_time: SystemTime,
+    ) -> Age {
+        let apparent_age = get_header(response, "Date")
+            .and_then(|date| date::parse_http_date(date))
+            .and_then(|date| response_time.duration_since(date).ok())
+            .unwrap_or_default();
+        let age_value = get_header(response, "Age")
+            .and_then(|age| age.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let response_delay = response_time
+            .duration_since(request_time)
+            .unwrap_or_default();
+        Age {
+            corrected_initial_age: apparent_age.max(age_value + response_delay),
+            response_time,
+        }
+    }
+
+    /// # Get the age at now
+    pub fn current(&self, now: SystemTime) -> Duration {
+        let resident_time = now.duration_since(self.response_time).unwrap_or_default();
+        self.corrected_initial_age + resident_time
+    }
+
+    /// # Is response still fresh at now?
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::Age;
+    /// use milstian_http::response::Message;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// let mut response = Message::default();
+    /// response
+    ///     .headers
+    ///     .insert("Cache-Control".to_string(), "max-age=60".to_string());
+    /// let age = Age::new(&response, UNIX_EPOCH, UNIX_EPOCH);
+    /// assert!(age.is_fresh(&response, UNIX_EPOCH + Duration::from_secs(59), false));
+    /// assert!(!age.is_fresh(&response, UNIX_EPOCH + Duration::from_secs(60), false));
+    /// ```
+    pub fn is_fresh(&self, response: &response::Message, now: SystemTime, shared: bool) -> bool {
+        freshness_lifetime(response, shared).is_some_and(|lifetime| lifetime > self.current(now))
+    }
+
+    /// # May response be served at now while it is revalidated in the background? (RFC 5861)
+    /// Fresh responses may be served, stale ones within their stale-while-revalidate window.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::Age;
+    /// use milstian_http::response::Message;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// let mut response = Message::default();
+    /// response.headers.insert(
+    ///     "Cache-Control".to_string(),
+    ///     "max-age=60, stale-while-revalidate=30".to_string(),
+    /// );
+    /// let age = Age::new(&response, UNIX_EPOCH, UNIX_EPOCH);
+    /// let at = |seconds: u64| UNIX_EPOCH + Duration::from_secs(seconds);
+    /// assert!(age.can_serve_while_revalidating(&response, at(89), true));
+    /// assert!(!age.can_serve_while_revalidating(&response, at(90), true));
+    /// ```
+    pub fn can_serve_while_revalidating(
+        &self,
+        response: &response::Message,
+        now: SystemTime,
+        shared: bool,
+    ) -> bool {
+        self.can_serve_stale(response, now, shared, |cache_control| {
+            cache_control.stale_while_revalidate
+        })
+    }
+
+    /// # May response be served at now when revalidating it failed with an error? (RFC 5861)
+    /// Fresh responses may be served, stale ones within their stale-if-error window.
+    pub fn can_serve_on_error(
+        &self,
+        response: &response::Message,
+        now: SystemTime,
+        shared: bool,
+    ) -> bool {
+        self.can_serve_stale(response, now, shared, |cache_control| {
+            cache_control.stale_if_error
+        })
+    }
+
+    /// Is response within its freshness lifetime plus the window of stale use at now?
+    /// Directives that require validation forbid stale use, so does a missing lifetime.
+    fn can_serve_stale<F>(
+        &self,
+        response: &response::Message,
+        now: SystemTime,
+        shared: bool,
+        window: F,
+    ) -> bool
+    where
+        F: Fn(&CacheControl) -> Option<u64>,
+    {
+        let cache_control = get_header(response, "Cache-Control")
+            .map(|value| CacheControl::parse(value))
+            .unwrap_or_default();
+        if cache_control.no_cache
+            || cache_control.no_store
+            || cache_control.must_revalidate
+            || (shared && cache_control.proxy_revalidate)
+        {
+            return false;
+        }
+        let window = Duration::from_secs(window(&cache_control).unwrap_or(0));
+        freshness_lifetime(response, shared)
+            .is_some_and(|lifetime| lifetime + window > self.current(now))
+    }
+
+    /// # Set the Age header of response to the age at now in whole seconds
+    pub fn apply(&self, response: &mut response::Message, now: SystemTime) {
+        let seconds = self.current(now).as_secs().min(MAX_AGE);
+        // A number of seconds is always a valid value
+        let _ = response.set_header("Age", &seconds.to_string());
+    }
+}
+
+/// # Directives of a Surrogate-Control header for gateway caches (Edge Architecture 1.0)
+/// Surrogates act on these directives instead of Cache-Control and remove the header before
+/// forwarding the response.
+/// ## Usage
+/// ```rust
+/// use milstian_http::cache::SurrogateControl;
+/// let surrogate_control = SurrogateControl {
+///     content: Some("ESI/1.0".to_string()),
+///     max_age: Some(300),
+///     ..SurrogateControl::default()
+/// };
+/// assert!(surrogate_control.is_valid());
+/// assert_eq!(
+///     surrogate_control.to_string(),
+///     "max-age=300, content=\"ESI/1.0\"".to_string()
+/// );
+/// assert_eq!(SurrogateControl::parse(&surrogate_control.to_string()), surrogate_control);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SurrogateControl {
+    pub content: Option<String>, // Processing capabilities the surrogate needs, like ESI/1.0
+    pub max_age: Option<u64>,
+    pub no_store: bool,
+    pub no_store_remote: bool, // Only surrogates close to the origin may store the response
+}
+
+impl SurrogateControl {
+    /// # Parse the directives of a Surrogate-Control header value
+    /// Directives targeted at a device with a ;name suffix are applied like untargeted ones.
+    pub fn parse(value: &str) -> SurrogateControl {
+        let mut surrogate_control = SurrogateControl::default();
+        for directive in split_unquoted(value, ',') {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim())),
+                None => (directive.trim(), None),
+            };
+            let argument = argument.map(|argument| {
+                let argument = argument.split(';').next().unwrap_or("").trim();
+                if argument.len() > 1 && argument.starts_with('"') && argument.ends_with('"') {
+                    unquote(argument)
+                } else {
+                    argument.to_string()
+                }
+            });
+            let name = name.split(';').next().unwrap_or("").trim().to_lowercase();
+            match (name.as_str(), argument) {
+                ("content", Some(content)) if surrogate_control.content.is_none() => {
+                    surrogate_control.content = Some(content)
+                }
+                ("max-age", Some(seconds)) if surrogate_control.max_age.is_none() => {
+                    surrogate_control.max_age = seconds.parse().ok()
+                }
+                ("no-store", _) => surrogate_control.no_store = true,
+                ("no-store-remote", _) => surrogate_control.no_store_remote = true,
+                _ => {}
+            }
+        }
+        surrogate_control
+    }
+
+    /// # Check that there are directives and content can be sent as a quoted string
+    pub fn is_valid(&self) -> bool {
+        !self.to_string().is_empty()
+            && self
+                .content
+                .as_ref()
+                .is_none_or(|content| !content.is_empty() && is_visible_ascii(content))
+    }
+}
+
+impl fmt::Display for SurrogateControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut directives: Vec<String> = Vec::new();
+        if self.no_store {
+            directives.push(String::from("no-store"));
+        }
+        if self.no_store_remote {
+            directives.push(String::from("no-store-remote"));
+        }
+        if let Some(max_age) = self.max_age {
+            directives.push(format!("max-age={}", max_age));
+        }
+        if let Some(ref content) = self.content {
+            directives.push(format!("content=\"{}\"", escape(content)));
+        }
+        write!(f, "{}", directives.join(", "))
+    }
+}
+
+/// # Why a cache forwarded a request, the fwd parameter of Cache-Status
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Forward {
+    Bypass,   // Configured to not serve from cache
+    Method,   // The request method can't be served from cache
+    Miss,     // No stored response
+    Partial,  // Only part of the response was stored
+    Request,  // The request asked for a fresh response
+    Stale,    // A stored response needed validation
+    UriMiss,  // No stored response for the URI
+    VaryMiss, // Stored responses didn't match the request by Vary
+}
+
+impl Forward {
+    /// # Parse a fwd token, case-sensitive like structured field tokens
+    pub fn parse(value: &str) -> Option<Forward> {
+        match value {
+            "bypass" => Some(Forward::Bypass),
+            "method" => Some(Forward::Method),
+            "miss" => Some(Forward::Miss),
+            "partial" => Some(Forward::Partial),
+            "request" => Some(Forward::Request),
+            "stale" => Some(Forward::Stale),
+            "uri-miss" => Some(Forward::UriMiss),
+            "vary-miss" => Some(Forward::VaryMiss),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Forward {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let token = match *self {
+            Forward::Bypass => "bypass",
+            Forward::Method => "method",
+            Forward::Miss => "miss",
+            Forward::Partial => "partial",
+            Forward::Request => "request",
+            Forward::Stale => "stale",
+            Forward::UriMiss => "uri-miss",
+            Forward::VaryMiss => "vary-miss",
+        };
+        write!(f, "{}", token)
+    }
+}
+
+/// # How one cache handled a request, a member of a Cache-Status header (RFC 9211)
+/// ## Usage
+/// ```rust
+/// use milstian_http::cache::{CacheStatus, Forward};
+/// let mut status = CacheStatus::forward("OriginCache", Forward::UriMiss);
+/// status.forward_status = Some(200);
+/// status.stored = true;
+/// assert!(status.is_valid());
+/// assert_eq!(
+///     status.to_string(),
+///     "OriginCache;fwd=uri-miss;fwd-status=200;stored".to_string()
+/// );
+/// let mut status = CacheStatus::hit("Example Cache");
+/// status.ttl = Some(-30);
+/// assert_eq!(status.to_string(), "\"Example Cache\";hit;ttl=-30".to_string());
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheStatus {
+    pub cache: String, // Name of the cache, sent as a token when possible
+    pub collapsed: bool,
+    pub detail: Option<String>,
+    pub forward: Option<Forward>,
+    pub forward_status: Option<u16>, // Status of the response from the next hop
+    pub hit: bool,
+    pub key: Option<String>,
+    pub stored: bool,
+    pub ttl: Option<i64>, // Seconds of freshness left, negative when stale
+}
+
+impl CacheStatus {
+    /// # A response served from the named cache
+    pub fn hit(cache: &str) -> CacheStatus {
+        CacheStatus {
+            cache: cache.to_string(),
+            hit: true,
+            ..CacheStatus::default()
+        }
+    }
+
+    /// # A request forwarded by the named cache
+    pub fn forward(cache: &str, forward: Forward) -> CacheStatus {
+        CacheStatus {
+            cache: cache.to_string(),
+            forward: Some(forward),
+            ..CacheStatus::default()
+        }
+    }
+
+    /// # Check the values can be serialized and a hit was not also forwarded
+    pub fn is_valid(&self) -> bool {
+        !self.cache.is_empty()
+            && is_visible_ascii(&self.cache)
+            && !(self.hit && self.forward.is_some())
+            && self
+                .forward_status
+                .is_none_or(|status| (100..600).contains(&status))
+            && self.key.as_ref().is_none_or(|key| is_visible_ascii(key))
+            && self
+                .detail
+                .as_ref()
+                .is_none_or(|detail| is_visible_ascii(detail))
+    }
+}
+
+impl fmt::Display for CacheStatus {
+    /// # Render the entry as a structured field item with parameters
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_item(f, &self.cache)?;
+        if self.hit {
+            write!(f, ";hit")?;
+        }
+        if let Some(forward) = self.forward {
+            write!(f, ";fwd={}", forward)?;
+        }
+        if let Some(forward_status) = self.forward_status {
+            write!(f, ";fwd-status={}", forward_status)?;
+        }
+        if let Some(ttl) = self.ttl {
+            write!(f, ";ttl={}", ttl)?;
+        }
+        if self.stored {
+            write!(f, ";stored")?;
+        }
+        if self.collapsed {
+            write!(f, ";collapsed")?;
+        }
+        if let Some(ref key) = self.key {
+            write!(f, ";key=\"{}\"", escape(key))?;
+        }
+        if let Some(ref detail) = self.detail {
+            write!(f, ";detail=")?;
+            write_item(f, detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// # Parse the entries of a Cache-Status header value, closest to the origin first
+/// Entries without a valid cache name are skipped, so are parameters with unexpected values,
+/// and only the first of repeated parameters is used.
+/// ## Usage
+/// ```rust
+/// use milstian_http::cache::{parse_cache_status, Forward};
+/// let statuses = parse_cache_status("OriginCache; hit; ttl=1100, \"CDN Company Here\"; fwd=stale");
+/// assert_eq!(statuses.len(), 2);
+/// assert!(statuses[0].hit);
+/// assert_eq!(statuses[0].ttl, Some(1100));
+/// assert_eq!(statuses[1].cache, "CDN Company Here".to_string());
+/// assert_eq!(statuses[1].forward, Some(Forward::Stale));
+/// ```
+pub fn parse_cache_status(value: &str) -> Vec<CacheStatus> {
+    let mut statuses: Vec<CacheStatus> = Vec::new();
+    for member in split_unquoted(value, ',') {
+        let mut parameters = split_unquoted(member, ';').into_iter();
+        let cache = match parse_item(parameters.next().unwrap_or("").trim()) {
+            Some(cache) => cache,
+            None => continue,
+        };
+        let mut status = CacheStatus {
+            cache,
+            ..CacheStatus::default()
+        };
+        let mut seen: Vec<String> = Vec::new();
+        for parameter in parameters {
+            let (key, value) = match parameter.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (parameter.trim(), None),
+            };
+            if seen.iter().any(|existing| existing == key) {
+                continue;
+            }
+            seen.push(key.to_string());
+            match (key, value) {
+                ("hit", _) => status.hit = parse_boolean(value).unwrap_or(false),
+                ("stored", _) => status.stored = parse_boolean(value).unwrap_or(false),
+                ("collapsed", _) => status.collapsed = parse_boolean(value).unwrap_or(false),
+                ("fwd", Some(value)) => status.forward = Forward::parse(value),
+                ("fwd-status", Some(value)) => status.forward_status = value.parse().ok(),
+                ("ttl", Some(value)) => status.ttl = value.parse().ok(),
+                ("key", Some(value)) if value.starts_with('"') => status.key = parse_item(value),
+                ("detail", Some(value)) => status.detail = parse_item(value),
+                _ => {}
+            }
+        }
+        statuses.push(status);
+    }
+    statuses
+}
+
+/// Get a header of response by name, case-insensitive
+fn get_header<'a>(response: &'a response::Message, name: &str) -> Option<&'a String> {
+    response
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+/// The entity tag without weak prefix, used for weak comparison
+fn get_opaque_tag(tag: &str) -> &str {
+    let tag = tag.trim();
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+/// Escape backslashes and quotes for a quoted string
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Printable ASCII including spaces, what a structured field string may hold
+fn is_visible_ascii(value: &str) -> bool {
+    value
+        .chars()
+        .all(|character| (' '..='~').contains(&character))
+}
+
+/// A structured field token starts with a letter or * and continues with tchar, : or /
+fn is_sf_token(value: &str) -> bool {
+    value
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '*')
+        && value.chars().all(|character| {
+            character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~:/".contains(character)
+        })
+}
+
+/// Write a token as is and anything else as a quoted string
+fn write_item(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    if is_sf_token(value) {
+        write!(f, "{}", value)
+    } else {
+        write!(f, "\"{}\"", escape(value))
+    }
+}
+
+/// Parse a structured field token or quoted string
+fn parse_item(value: &str) -> Option<String> {
+    if value.len() > 1 && value.starts_with('"') && value.ends_with('"') {
+        Some(unquote(value))
+    } else if is_sf_token(value) {
+        Some(value.to_string())
+    } else {
+        None
+    }
+}
+
+/// Parse a structured field boolean, a parameter without a value is true
+fn parse_boolean(value: Option<&str>) -> Option<bool> {
+    match value {
+        None | Some("?1") => Some(true),
+        Some("?0") => Some(false),
+        _ => None,
+    }
+}
+
+/// Split value by separator outside of quoted strings
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut start = 0;
+    let mut is_quoted = false;
+    let mut is_escaped = false;
+    for (index, character) in value.char_indices() {
+        if is_escaped {
+            is_escaped = false;
+        } else if is_quoted && character == '\\' {
+            is_escaped = true;
+        } else if character == '"' {
+            is_quoted = !is_quoted;
+        } else if character == separator && !is_quoted {
+            parts.push(&value[start..index]);
+            start = index + 1;
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Remove the quotes and escapes of a quoted string
+fn unquote(value: &str) -> String {
+    let mut unquoted = String::new();
+    let mut is_escaped = false;
+    for character in value[1..value.len() - 1].chars() {
+        if character == '\\' && !is_escaped {
+            is_escaped = true;
+        } else {
+            unquoted.push(character);
+            is_escaped = false;
+        }
+    }
+    unquoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_validators() {
+        let path =
+            env::temp_dir().join(format!("milstian-validators-{}.txt", ::std::process::id()));
+        fs::write(&path, b"Hello").expect("A file");
+        let first = Validators::from_metadata(&fs::metadata(&path).expect("Metadata"));
+        assert_eq!(
+            first,
+            Validators::from_metadata(&fs::metadata(&path).expect("Metadata"))
+        );
+        fs::write(&path, b"Hello world").expect("A file");
+        let second = Validators::from_metadata(&fs::metadata(&path).expect("Metadata"));
+        assert_ne!(first.etag, second.etag);
+        fs::remove_file(&path).expect("A removed file");
+
+        let mut response = response::Message::default();
+        second.apply(&mut response);
+        assert_eq!(response.headers.get("ETag"), Some(&second.etag));
+        let last_modified = response.headers.get("Last-Modified").expect("A date");
+        assert_eq!(date::parse_http_date(last_modified), second.last_modified);
+
+        let validators = Validators {
+            etag: "\"abc\"".to_string(),
+            last_modified: date::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+        };
+        let is_not_modified = |request: &[u8]| {
+            validators
+                .is_not_modified(&request::Message::from_tcp_stream(request).expect("A request"))
+        };
+        assert!(is_not_modified(
+            b"HEAD / HTTP/1.1\r\nIf-None-Match: W/\"abc\"\r\n"
+        ));
+        assert!(is_not_modified(b"GET / HTTP/1.1\r\nIf-None-Match: *\r\n"));
+        assert!(!is_not_modified(b"POST / HTTP/1.1\r\nIf-None-Match: *\r\n"));
+        assert!(is_not_modified(
+            b"GET / HTTP/1.1\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"
+        ));
+        assert!(!is_not_modified(
+            b"GET / HTTP/1.1\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:36 GMT\r\n"
+        ));
+        // A mismatching entity tag wins over a matching date
+        assert!(!is_not_modified(b"GET / HTTP/1.1\r\nIf-None-Match: \"def\"\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"));
+    }
+
+    #[test]
+    fn test_age() {
+        let mut response = response::Message::default();
+        response.headers.insert(
+            "date".to_string(),
+            "Thu, 01 Jan 1970 00:01:40 GMT".to_string(),
+        );
+        let at = |seconds: u64| UNIX_EPOCH + Duration::from_secs(seconds);
+
+        // The apparent age wins over a smaller Age, a clock ahead of ours gives no apparent age
+        let age = Age::new(&response, at(110), at(130));
+        assert_eq!(age.corrected_initial_age, Duration::from_secs(30));
+        response
+            .headers
+            .insert("AGE".to_string(), "100".to_string());
+        let age = Age::new(&response, at(110), at(130));
+        assert_eq!(age.corrected_initial_age, Duration::from_secs(120));
+        let age = Age::new(&response, at(50), at(60));
+        assert_eq!(age.corrected_initial_age, Duration::from_secs(110));
+        assert_eq!(age.current(at(30)), Duration::from_secs(110));
+
+        age.apply(&mut response, at(70));
+        assert_eq!(response.headers.get("AGE"), Some(&"120".to_string()));
+        assert!(!response.headers.contains_key("Age"));
+        age.apply(
+            &mut response,
+            UNIX_EPOCH + Duration::from_secs(u32::MAX as u64 * 2),
+        );
+        assert_eq!(response.headers.get("AGE"), Some(&MAX_AGE.to_string()));
+
+        response.headers.insert("Age".to_string(), "x".to_string());
+        response.headers.remove("AGE");
+        let age = Age::new(&response, at(100), at(100));
+        assert_eq!(age.corrected_initial_age, Duration::ZERO);
+
+        // Expires is relative to Date, invalid dates are in the past
+        assert_eq!(freshness_lifetime(&response, false), None);
+        response.headers.insert(
+            "Expires".to_string(),
+            "Thu, 01 Jan 1970 00:02:00 GMT".to_string(),
+        );
+        assert_eq!(
+            freshness_lifetime(&response, false),
+            Some(Duration::from_secs(20))
+        );
+        assert!(age.is_fresh(&response, at(119), true));
+        assert!(!age.is_fresh(&response, at(120), true));
+        response
+            .headers
+            .insert("Expires".to_string(), "0".to_string());
+        assert_eq!(freshness_lifetime(&response, false), Some(Duration::ZERO));
+        response.headers.insert(
+            "Cache-Control".to_string(),
+            "max-age=x, MAX-AGE=5".to_string(),
+        );
+        assert_eq!(
+            freshness_lifetime(&response, true),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn test_cache_status() {
+        let status = CacheStatus {
+            cache: "ReverseProxy".to_string(),
+            collapsed: true,
+            detail: Some("limit reached".to_string()),
+            forward: Some(Forward::VaryMiss),
+            forward_status: Some(304),
+            key: Some("GET /a \"b\"".to_string()),
+            stored: true,
+            ttl: Some(0),
+            ..CacheStatus::default()
+        };
+        assert!(status.is_valid());
+        let value = status.to_string();
+        assert_eq!(
+            value,
+            "ReverseProxy;fwd=vary-miss;fwd-status=304;ttl=0;stored;collapsed;key=\"GET /a \\\"b\\\"\";detail=\"limit reached\""
+                .to_string()
+        );
+        assert_eq!(parse_cache_status(&value), vec![status.clone()]);
+
+        let mut invalid = status.clone();
+        invalid.hit = true;
+        assert!(!invalid.is_valid());
+        invalid = status.clone();
+        invalid.forward_status = Some(99);
+        assert!(!invalid.is_valid());
+        invalid = status.clone();
+        invalid.key = Some("line\nbreak".to_string());
+        assert!(!invalid.is_valid());
+        assert!(!CacheStatus::default().is_valid());
+
+        assert!(parse_cache_status("").is_empty());
+        assert_eq!(
+            parse_cache_status(
+                "a;hit=?0;stored=?1;ttl=x;fwd=other;key=token, 1bad;hit, b;hit;hit=?0;detail=\"x\""
+            ),
+            vec![
+                CacheStatus {
+                    cache: "a".to_string(),
+                    stored: true,
+                    ..CacheStatus::default()
+                },
+                CacheStatus {
+                    cache: "b".to_string(),
+                    detail: Some("x".to_string()),
+                    hit: true,
+                    ..CacheStatus::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(CacheControl::default().to_string(), "".to_string());
+        assert_eq!(
+            CacheControl {
+                no_store: true,
+                no_cache: true,
+                ..CacheControl::default()
+            }
+            .to_string(),
+            "no-cache, no-store".to_string()
+        );
+        assert_eq!(
+            CacheControl {
+                private: true,
+                max_age: Some(0),
+                s_maxage: Some(10),
+                must_revalidate: true,
+                immutable: true,
+                ..CacheControl::default()
+            }
+            .to_string(),
+            "private, must-revalidate, max-age=0, s-maxage=10, immutable".to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(CacheControl::parse(""), CacheControl::default());
+        assert_eq!(
+            CacheControl::parse(
+                "private=\"Set-Cookie\", MUST-REVALIDATE, max-age=10, max-age=20, s-maxage=-1, stale-if-error=5, Stale-While-Revalidate=\"7\", immutable, no-store, no-transform, proxy-revalidate"
+            ),
+            CacheControl {
+                immutable: true,
+                max_age: Some(10),
+                must_revalidate: true,
+                no_store: true,
+                no_transform: true,
+                private: true,
+                proxy_revalidate: true,
+                stale_if_error: Some(5),
+                stale_while_revalidate: Some(7),
+                ..CacheControl::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_serve_stale() {
+        let at = |seconds: u64| UNIX_EPOCH + Duration::from_secs(seconds);
+        let response = |cache_control: &str| {
+            let mut response = response::Message::default();
+            response
+                .headers
+                .insert("Cache-Control".to_string(), cache_control.to_string());
+            response
+        };
+        let age = Age::new(&response(""), at(0), at(0));
+
+        let stale =
+            response("max-age=10, s-maxage=20, stale-while-revalidate=5, stale-if-error=50");
+        assert!(age.can_serve_while_revalidating(&stale, at(14), false));
+        assert!(!age.can_serve_while_revalidating(&stale, at(15), false));
+        assert!(age.can_serve_while_revalidating(&stale, at(24), true));
+        assert!(age.can_serve_on_error(&stale, at(59), false));
+        assert!(!age.can_serve_on_error(&stale, at(60), false));
+
+        let fresh_only = response("max-age=10");
+        assert!(age.can_serve_on_error(&fresh_only, at(9), false));
+        assert!(!age.can_serve_on_error(&fresh_only, at(10), false));
+        assert!(!age.can_serve_on_error(&response("stale-if-error=50"), at(0), false));
+
+        for directive in ["no-cache", "no-store", "must-revalidate"].iter() {
+            let forbidden = response(&format!("max-age=10, stale-if-error=50, {}", directive));
+            assert!(!age.can_serve_on_error(&forbidden, at(1), false));
+        }
+        let proxy_revalidate = response("max-age=10, stale-if-error=50, proxy-revalidate");
+        assert!(age.can_serve_on_error(&proxy_revalidate, at(20), false));
+        assert!(!age.can_serve_on_error(&proxy_revalidate, at(20), true));
+    }
+
+    #[test]
+    fn test_surrogate_control() {
+        assert!(!SurrogateControl::default().is_valid());
+        assert_eq!(
+            SurrogateControl::parse(
+                "No-Store-Remote, max-age=60;edge, max-age=10, content=\"ESI/1.0 ESI-Inline/1.0\", no-store;dev"
+            ),
+            SurrogateControl {
+                content: Some("ESI/1.0 ESI-Inline/1.0".to_string()),
+                max_age: Some(60),
+                no_store: true,
+                no_store_remote: true,
+            }
+        );
+        let surrogate_control = SurrogateControl {
+            content: Some(String::new()),
+            ..SurrogateControl::default()
+        };
+        assert!(!surrogate_control.is_valid());
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(!CacheControl::default().is_valid());
+        assert!(CacheControl {
+            no_store: true,
+            ..CacheControl::default()
+        }
+        .is_valid());
+        assert!(!CacheControl {
+            public: true,
+            private: true,
+            ..CacheControl::default()
+        }
+        .is_valid());
+    }
+}
@@ -0,0 +1,255 @@
+//! # Handles conversion between HTTP messages and the Common Gateway Interface (RFC 3875).
+
+use std::collections::HashMap;
+use std::str;
+
+use capitalize_key;
+use request;
+use response;
+
+/// # Information about the server and connection that is not part of the request
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ServerInfo {
+    pub pass_authorization: bool, // Pass the Authorization header on as HTTP_AUTHORIZATION
+    pub remote_addr: String,
+    pub script_name: String,
+    pub server_name: String,
+    pub server_port: u16,
+    pub server_software: String,
+}
+
+/// # Map a request to CGI meta-variables
+/// Header fields become HTTP_ variables except for Proxy, which would set the HTTP_PROXY
+/// variable scripts read as their outgoing proxy, and names with underscores, which would be
+/// indistinguishable from names with hyphens. Authorization is left out unless the server passes
+/// it on. The script name only matches whole path segments.
+/// ## Usage
+/// ```rust
+/// use milstian_http::cgi::{to_environment, ServerInfo};
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(
+///     b"GET /index.php/extra?a=b HTTP/1.1\r\nHost: localhost\r\nUser-Agent: Random\r\n\r\n"
+/// ).expect("A request");
+/// let server = ServerInfo {
+///     script_name: "/index.php".to_string(),
+///     ..ServerInfo::default()
+/// };
+/// let environment = to_environment(&request, &server);
+/// assert_eq!(environment.get("REQUEST_METHOD"), Some(&"GET".to_string()));
+/// assert_eq!(environment.get("QUERY_STRING"), Some(&"a=b".to_string()));
+/// assert_eq!(environment.get("PATH_INFO"), Some(&"/extra".to_string()));
+/// assert_eq!(environment.get("HTTP_USER_AGENT"), Some(&"Random".to_string()));
+/// ```
+pub fn to_environment(request: &request::Message, server: &ServerInfo) -> HashMap<String, String> {
+    let mut environment: HashMap<String, String> = HashMap::new();
+    let request_line = &request.request_line;
+
+    environment.insert("GATEWAY_INTERFACE".to_string(), "CGI/1.1".to_string());
+    environment.insert(
+        "REQUEST_METHOD".to_string(),
+        request::Message::get_method_text(&request_line.method),
+    );
+    environment.insert(
+        "SERVER_PROTOCOL".to_string(),
+        request::Message::get_protocol_text(&request_line.protocol),
+    );
+    environment.insert(
+        "QUERY_STRING".to_string(),
+        request_line.query_string.to_string(),
+    );
+    environment.insert(
+        "REQUEST_URI".to_string(),
+        request_line.request_uri.to_string(),
+    );
+    environment.insert("SCRIPT_NAME".to_string(), server.script_name.to_string());
+
+    // Path info is the part of the path that follows the script name
+    let path_info = match request_line
+        .request_uri_base
+        .strip_prefix(server.script_name.as_str())
+    {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => &request_line.request_uri_base[..],
+    };
+    if !path_info.is_empty() {
+        environment.insert("PATH_INFO".to_string(), path_info.to_string());
+    }
+
+    environment.insert("SERVER_NAME".to_string(), server.server_name.to_string());
+    environment.insert("SERVER_PORT".to_string(), server.server_port.to_string());
+    environment.insert(
+        "SERVER_SOFTWARE".to_string(),
+        server.server_software.to_string(),
+    );
+    environment.insert("REMOTE_ADDR".to_string(), server.remote_addr.to_string());
+
+    for (key, value) in request.headers.iter() {
+        if key.contains('_') {
+            continue;
+        }
+        let name = key.to_uppercase().replace('-', "_");
+        match name.as_ref() {
+            "PROXY" => {}
+            "AUTHORIZATION" if !server.pass_authorization => {}
+            "CONTENT_LENGTH" | "CONTENT_TYPE" => {
+                environment.insert(name, value.to_string());
+            }
+            _ => {
+                environment.insert(format!("HTTP_{}", name), value.to_string());
+            }
+        }
+    }
+
+    environment
+}
+
+/// # Convert CGI script output into a response, returns None if header section is incomplete
+/// ## Usage
+/// ```rust
+/// use milstian_http::cgi::from_output;
+/// let response = from_output(
+///     b"Status: 404 Not Found\nContent-Type: text/plain\n\nMissing",
+///     "HTTP/1.1",
+/// ).expect("A response");
+/// assert_eq!(response.status, "404 Not Found".to_string());
+/// assert_eq!(response.headers.get("Content-Type"), Some(&"text/plain".to_string()));
+/// assert_eq!(response.body, b"Missing".to_vec());
+/// ```
+pub fn from_output(output: &[u8], protocol: &str) -> Option<response::Message> {
+    let mut headers: HashMap<String, String> = HashMap::new();
+    let mut status: Option<String> = None;
+    let mut start = 0;
+
+    loop {
+        let line_end = start + output[start..].iter().position(|byte| byte == &b'\n')?;
+        let line = str::from_utf8(&output[start..line_end]).ok()?;
+        let line = line.trim_end_matches('\r');
+        start = line_end + 1;
+
+        // Empty line ends the header section
+        if line.is_empty() {
+            break;
+        }
+
+        let parts: Vec<&str> = line.splitn(2, ':').collect();
+        if parts.len() != 2 {
+            return None;
+        }
+        let key = capitalize_key(parts[0].trim());
+        let value = parts[1].trim().to_string();
+        if key == "Status" {
+            status = Some(value);
+        } else {
+            headers.insert(key, value);
+        }
+    }
+
+    // Scripts returning a location without a status are redirects
+    let status = match status {
+        Some(status) => status,
+        None if headers.contains_key("Location") => String::from("302 Found"),
+        None => String::from("200 OK"),
+    };
+
+    Some(response::Message::new(
+        protocol.to_string(),
+        status,
+        headers,
+        output[start..].to_vec(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_environment() {
+        let request = request::Message::from_tcp_stream(
+            b"POST /cgi-bin/script.cgi?x=1 HTTP/1.0\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 3\r\nX-Forwarded-For: 10.0.0.1\r\nProxy: http://attacker\r\nX_Forwarded_For: 10.0.0.2\r\nAuthorization: Basic YTpi\r\n\r\na=b",
+        )
+        .expect("A request");
+        let server = ServerInfo {
+            pass_authorization: false,
+            remote_addr: "127.0.0.1".to_string(),
+            script_name: "/cgi-bin/script.cgi".to_string(),
+            server_name: "localhost".to_string(),
+            server_port: 8080,
+            server_software: "milstian".to_string(),
+        };
+        let environment = to_environment(&request, &server);
+        assert_eq!(
+            environment.get("GATEWAY_INTERFACE"),
+            Some(&"CGI/1.1".to_string())
+        );
+        assert_eq!(environment.get("REQUEST_METHOD"), Some(&"POST".to_string()));
+        assert_eq!(
+            environment.get("SERVER_PROTOCOL"),
+            Some(&"HTTP/1.0".to_string())
+        );
+        assert_eq!(
+            environment.get("CONTENT_TYPE"),
+            Some(&"application/x-www-form-urlencoded".to_string())
+        );
+        assert_eq!(environment.get("CONTENT_LENGTH"), Some(&"3".to_string()));
+        assert_eq!(
+            environment.get("HTTP_X_FORWARDED_FOR"),
+            Some(&"10.0.0.1".to_string())
+        );
+        assert!(!environment.contains_key("HTTP_CONTENT_TYPE"));
+        assert!(!environment.contains_key("HTTP_PROXY"));
+        assert!(!environment.contains_key("HTTP_AUTHORIZATION"));
+        assert!(!environment.contains_key("PATH_INFO"));
+        assert_eq!(environment.get("SERVER_PORT"), Some(&"8080".to_string()));
+        assert_eq!(
+            environment.get("REMOTE_ADDR"),
+            Some(&"127.0.0.1".to_string())
+        );
+
+        // Authorization is passed on when the server opts in
+        let server = ServerInfo {
+            pass_authorization: true,
+            ..server
+        };
+        let environment = to_environment(&request, &server);
+        assert_eq!(
+            environment.get("HTTP_AUTHORIZATION"),
+            Some(&"Basic YTpi".to_string())
+        );
+
+        // The script name matches whole path segments
+        let request =
+            request::Message::from_tcp_stream(b"GET /cgi-bin/script.cgi.bak/x HTTP/1.1\r\n\r\n")
+                .expect("A request");
+        let environment = to_environment(&request, &server);
+        assert_eq!(
+            environment.get("PATH_INFO"),
+            Some(&"/cgi-bin/script.cgi.bak/x".to_string())
+        );
+        let request =
+            request::Message::from_tcp_stream(b"GET /cgi-bin/script.cgi/x HTTP/1.1\r\n\r\n")
+                .expect("A request");
+        let environment = to_environment(&request, &server);
+        assert_eq!(environment.get("PATH_INFO"), Some(&"/x".to_string()));
+    }
+
+    #[test]
+    fn test_from_output() {
+        let response = from_output(b"Content-Type: text/html\r\n\r\n<p>Hi</p>", "HTTP/1.1")
+            .expect("A response");
+        assert_eq!(response.status, "200 OK".to_string());
+        assert_eq!(response.body, b"<p>Hi</p>".to_vec());
+
+        let response = from_output(b"location: /login\n\n", "HTTP/1.0").expect("A redirect");
+        assert_eq!(response.status, "302 Found".to_string());
+        assert_eq!(response.protocol, "HTTP/1.0".to_string());
+        assert_eq!(
+            response.headers.get("Location"),
+            Some(&"/login".to_string())
+        );
+        assert!(response.body.is_empty());
+
+        assert!(from_output(b"Content-Type: text/html\r\n", "HTTP/1.1").is_none());
+        assert!(from_output(b"Not a header\n\nBody", "HTTP/1.1").is_none());
+    }
+}
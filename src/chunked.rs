@@ -0,0 +1,196 @@
+//! # Handles the chunked transfer coding (RFC 7230 section 4.1).
+
+/// # Result of decoding a chunked body
+#[derive(Debug, Eq, PartialEq)]
+pub enum Status {
+    Complete(Vec<u8>, usize), // Decoded body and number of bytes consumed
+    Incomplete,
+    Invalid,
+}
+
+/// # Can a request body with this Transfer-Encoding value be delimited?
+/// Chunked must be the final coding and appear only once, any other value leaves the body length
+/// to be guessed and is rejected rather than interpreted.
+/// ## Usage
+/// ```rust
+/// use milstian_http::chunked::is_valid_transfer_encoding;
+/// assert!(is_valid_transfer_encoding("chunked"));
+/// assert!(is_valid_transfer_encoding("gzip, Chunked"));
+/// assert!(!is_valid_transfer_encoding("chunked, gzip"));
+/// assert!(!is_valid_transfer_encoding("xchunked"));
+/// assert!(!is_valid_transfer_encoding("chunked, chunked"));
+/// assert!(!is_valid_transfer_encoding(", chunked"));
+/// ```
+pub fn is_valid_transfer_encoding(value: &str) -> bool {
+    let codings: Vec<&str> = value.split(',').map(str::trim).collect();
+    let is_chunked = |coding: &str| coding.eq_ignore_ascii_case("chunked");
+    codings.last().is_some_and(|coding| is_chunked(coding))
+        && codings.iter().filter(|coding| is_chunked(coding)).count() == 1
+        && codings.iter().all(|coding| !coding.is_empty())
+}
+
+/// # Get the first coding of a Transfer-Encoding value that can't be decoded
+/// Only chunked is decoded, a body with further codings would be passed on still encoded.
+/// ## Usage
+/// ```rust
+/// use milstian_http::chunked::get_unsupported_coding;
+/// assert_eq!(get_unsupported_coding("chunked"), None);
+/// assert_eq!(get_unsupported_coding("gzip, chunked"), Some("gzip"));
+/// ```
+pub fn get_unsupported_coding(value: &str) -> Option<&str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .find(|coding| !coding.eq_ignore_ascii_case("chunked"))
+}
+
+fn find_line_end(data: &[u8], start: usize) -> Option<usize> {
+    data.get(start..)?
+        .windows(2)
+        .position(|window| window == b"\r\n")
+        .map(|position| start + position)
+}
+
+/// # Decode a chunked body, trailers are skipped
+/// ## Usage
+/// ```rust
+/// use milstian_http::chunked::{decode, Status};
+/// assert_eq!(
+///     decode(b"3\r\nabc\r\n2;name=value\r\nde\r\n0\r\n\r\nnext"),
+///     Status::Complete(b"abcde".to_vec(), 31)
+/// );
+/// assert_eq!(decode(b"3\r\nab"), Status::Incomplete);
+/// assert_eq!(decode(b"x\r\nabc\r\n"), Status::Invalid);
+/// ```
+pub fn decode(data: &[u8]) -> Status {
+    let mut body: Vec<u8> = Vec::new();
+    let mut position = 0;
+    loop {
+        let line_end = match find_line_end(data, position) {
+            Some(line_end) => line_end,
+            None => return Status::Incomplete,
+        };
+
+        // Chunk size is only hexadecimal digits and may be followed by extensions
+        let digits = data[position..line_end]
+            .iter()
+            .take_while(|byte| byte.is_ascii_hexdigit())
+            .count();
+        if digits == 0 || digits > 15 {
+            return Status::Invalid;
+        }
+        if position + digits != line_end && data[position + digits] != b';' {
+            return Status::Invalid;
+        }
+        let size = match std::str::from_utf8(&data[position..position + digits])
+            .ok()
+            .and_then(|size_text| usize::from_str_radix(size_text, 16).ok())
+        {
+            Some(size) => size,
+            None => return Status::Invalid,
+        };
+        position = line_end + 2;
+
+        if size == 0 {
+            // Skip trailer fields until the empty line
+            loop {
+                let trailer_end = match find_line_end(data, position) {
+                    Some(trailer_end) => trailer_end,
+                    None => return Status::Incomplete,
+                };
+                let is_empty = trailer_end == position;
+                position = trailer_end + 2;
+                if is_empty {
+                    return Status::Complete(body, position);
+                }
+            }
+        }
+
+        let chunk_end = match position
+            .checked_add(size)
+            .and_then(|end| end.checked_add(2))
+        {
+            Some(chunk_end) => chunk_end,
+            None => return Status::Invalid,
+        };
+        if data.len() < chunk_end {
+            return Status::Incomplete;
+        }
+        body.extend_from_slice(&data[position..position + size]);
+        position += size;
+        if &data[position..position + 2] != b"\r\n" {
+            return Status::Invalid;
+        }
+        position += 2;
+    }
+}
+
+/// # Encode data as a single chunk, empty data is encoded as the last chunk
+/// ## Usage
+/// ```rust
+/// use milstian_http::chunked::encode_chunk;
+/// assert_eq!(encode_chunk(b"abcdefghijklmnopq"), b"11\r\nabcdefghijklmnopq\r\n".to_vec());
+/// assert_eq!(encode_chunk(b""), b"0\r\n\r\n".to_vec());
+/// ```
+pub fn encode_chunk(data: &[u8]) -> Vec<u8> {
+    let mut output = format!("{:x}\r\n", data.len()).into_bytes();
+    output.extend_from_slice(data);
+    output.extend_from_slice(b"\r\n");
+    output
+}
+
+/// # Encode a complete body as chunks of at most chunk size bytes
+/// ## Usage
+/// ```rust
+/// use milstian_http::chunked::{decode, encode, Status};
+/// let encoded = encode(b"abcde", 2);
+/// assert_eq!(encoded, b"2\r\nab\r\n2\r\ncd\r\n1\r\ne\r\n0\r\n\r\n".to_vec());
+/// assert_eq!(decode(&encoded), Status::Complete(b"abcde".to_vec(), encoded.len()));
+/// ```
+pub fn encode(body: &[u8], chunk_size: usize) -> Vec<u8> {
+    let mut output = Vec::new();
+    for chunk in body.chunks(chunk_size.max(1)) {
+        output.append(&mut encode_chunk(chunk));
+    }
+    output.append(&mut encode_chunk(b""));
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"0\r\n\r\n"), Status::Complete(Vec::new(), 5));
+        assert_eq!(
+            decode(b"A\r\n0123456789\r\n0\r\nExpires: never\r\n\r\n"),
+            Status::Complete(b"0123456789".to_vec(), 36)
+        );
+        assert_eq!(decode(b""), Status::Incomplete);
+        assert_eq!(decode(b"0\r\n"), Status::Incomplete);
+        assert_eq!(decode(b"0\r\nExpires: never\r\n"), Status::Incomplete);
+        assert_eq!(decode(b"3\r\nabcd\r\n"), Status::Invalid);
+        assert_eq!(decode(b"ffffffffffffffffff\r\n"), Status::Invalid);
+        assert_eq!(decode(b"\r\n"), Status::Invalid);
+        assert_eq!(decode(b"-1\r\n"), Status::Invalid);
+        assert_eq!(decode(b"+3\r\nabc\r\n"), Status::Invalid);
+        assert_eq!(decode(b" 3\r\nabc\r\n"), Status::Invalid);
+        assert_eq!(decode(b"3 \r\nabc\r\n"), Status::Invalid);
+        assert_eq!(decode(b"3x;a=b\r\nabc\r\n"), Status::Invalid);
+        assert_eq!(decode(b"0000000000000003\r\nabc\r\n"), Status::Invalid);
+        assert_eq!(
+            decode(b"00000000000003;a=b\r\nabc\r\n0\r\n\r\n"),
+            Status::Complete(b"abc".to_vec(), 30)
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b"", 10), b"0\r\n\r\n".to_vec());
+        assert_eq!(
+            encode(b"abc", 0),
+            b"1\r\na\r\n1\r\nb\r\n1\r\nc\r\n0\r\n\r\n".to_vec()
+        );
+    }
+}
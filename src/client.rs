@@ -0,0 +1,344 @@
+//! # A minimal blocking HTTP client.
+//!
+//! Requests are written to any `Read + Write` stream and responses are read with framing based on
+//! the request method, status code, Transfer-Encoding and Content-Length headers.
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str;
+
+use capitalize_key;
+use chunked;
+use headers::Headers;
+use request;
+use request::Method;
+use response;
+
+const READ_BUFFER_SIZE: usize = 4096;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// # Connect to address, send request and read the response
+/// ## Usage
+/// ```rust,no_run
+/// use milstian_http::client;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+///     .expect("A request");
+/// let response = client::request("localhost:8888", &request).expect("A response");
+/// assert_eq!(response.status, "200 OK".to_string());
+/// ```
+pub fn request<A: ToSocketAddrs>(
+    address: A,
+    request: &request::Message,
+) -> io::Result<response::Message> {
+    let mut stream = TcpStream::connect(address)?;
+    send(&mut stream, request)
+}
+
+/// # Write request to stream and read the response
+pub fn send<S: Read + Write>(
+    stream: &mut S,
+    request: &request::Message,
+) -> io::Result<response::Message> {
+    stream.write_all(&request.to_bytes())?;
+    stream.flush()?;
+    read_response(stream, &request.request_line.method)
+}
+
+/// # Read a response for a request with method from reader
+pub fn read_response<R: Read>(reader: &mut R, method: &Method) -> io::Result<response::Message> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk = [0; READ_BUFFER_SIZE];
+    loop {
+        let read = reader.read(&mut chunk)?;
+        buffer.extend_from_slice(&chunk[..read]);
+        let is_closed = read == 0;
+        if let Some(message) = parse_response(&buffer, method, is_closed)? {
+            return Ok(message);
+        }
+        if is_closed {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before response was complete",
+            ));
+        }
+    }
+}
+
+/// Try to decode a response, returns None while more data is needed
+fn parse_response(
+    buffer: &[u8],
+    method: &Method,
+    is_closed: bool,
+) -> io::Result<Option<response::Message>> {
+    let header_end = match buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(header_end) => header_end,
+        None => return Ok(None),
+    };
+    let head = str::from_utf8(&buffer[..header_end])
+        .map_err(|_| invalid_data("response header is not valid UTF-8"))?;
+    let rest = &buffer[header_end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or("");
+    let status_parts: Vec<&str> = status_line.splitn(2, ' ').collect();
+    if status_parts.len() != 2 || !status_parts[0].starts_with("HTTP/") {
+        return Err(invalid_data("invalid status line"));
+    }
+    let protocol = status_parts[0].to_string();
+    let status = status_parts[1].trim().to_string();
+    let code: u16 = status
+        .get(0..3)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid_data("invalid status code"))?;
+
+    // Repeated fields are combined into a list, except Set-Cookie whose values may hold commas
+    let mut headers = Headers::new();
+    for line in lines {
+        let parts: Vec<&str> = line.splitn(2, ':').collect();
+        if parts.len() != 2 {
+            return Err(invalid_data("invalid header field"));
+        }
+        let key = capitalize_key(parts[0].trim());
+        let value = parts[1].trim().to_string();
+        if key == "Set-Cookie" {
+            headers.append(key, value);
+            continue;
+        }
+        let combined = match headers.get(&key) {
+            Some(existing) => format!("{}, {}", existing, value),
+            None => value,
+        };
+        headers.insert(key, combined);
+    }
+
+    // Interim responses are followed by the final response
+    if (100..200).contains(&code) && code != 101 {
+        return parse_response(rest, method, is_closed);
+    }
+
+    let has_body = *method != Method::Head && code >= 200 && code != 204 && code != 304;
+    let is_chunked = headers.get("Transfer-Encoding").is_some_and(|value| {
+        value
+            .rsplit(',')
+            .next()
+            .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+    });
+    let body = if !has_body {
+        Vec::new()
+    } else if is_chunked {
+        match chunked::decode(rest) {
+            chunked::Status::Complete(body, _) => body,
+            chunked::Status::Incomplete => return Ok(None),
+            chunked::Status::Invalid => return Err(invalid_data("invalid chunked body")),
+        }
+    } else if let Some(content_length) = headers.get("Content-Length") {
+        let content_length = request::Message::get_content_length(content_length)
+            .ok_or_else(|| invalid_data("invalid content-length"))?;
+        if rest.len() < content_length {
+            return Ok(None);
+        }
+        rest[..content_length].to_vec()
+    } else if is_closed {
+        rest.to_vec()
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(response::Message::new(
+        protocol, status, headers, body,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_send() {
+        let mut stream = MockStream {
+            input: Cursor::new(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: keep-alive\r\n\r\nHelloExtra"
+                    .to_vec(),
+            ),
+            output: Vec::new(),
+        };
+        let request =
+            request::Message::from_tcp_stream(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .expect("A request");
+        let response = send(&mut stream, &request).expect("A response");
+        assert_eq!(stream.output, request.to_bytes());
+        assert_eq!(response.protocol, "HTTP/1.1".to_string());
+        assert_eq!(response.status, "200 OK".to_string());
+        assert_eq!(response.body, b"Hello".to_vec());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        // Chunked bodies are decoded
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n2\r\nab\r\n0\r\n\r\n",
+            &Method::Get,
+            false,
+        )
+        .expect("A valid response")
+        .expect("A complete response");
+        assert_eq!(response.body, b"ab".to_vec());
+
+        // Incomplete bodies need more data
+        assert!(parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nab",
+            &Method::Get,
+            false
+        )
+        .expect("A valid response")
+        .is_none());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n", &Method::Get, false)
+            .expect("A valid response")
+            .is_none());
+
+        // Bodies without framing are read until close
+        assert!(
+            parse_response(b"HTTP/1.0 200 OK\r\n\r\nab", &Method::Get, false)
+                .expect("A valid response")
+                .is_none()
+        );
+        let response = parse_response(b"HTTP/1.0 200 OK\r\n\r\nab", &Method::Get, true)
+            .expect("A valid response")
+            .expect("A complete response");
+        assert_eq!(response.body, b"ab".to_vec());
+
+        // Responses to HEAD and 204 or 304 responses have no body
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+            &Method::Head,
+            false,
+        )
+        .expect("A valid response")
+        .expect("A complete response");
+        assert!(response.body.is_empty());
+        let response = parse_response(b"HTTP/1.1 304 Not Modified\r\n\r\n", &Method::Get, false)
+            .expect("A valid response")
+            .expect("A complete response");
+        assert_eq!(response.status, "304 Not Modified".to_string());
+
+        // Interim responses are skipped
+        let response = parse_response(
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n",
+            &Method::Post,
+            false,
+        )
+        .expect("A valid response")
+        .expect("A complete response");
+        assert_eq!(response.status, "201 Created".to_string());
+
+        // Switching Protocols has no body, what follows is the new protocol
+        let response = parse_response(
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: h2c\r\n\r\nframes",
+            &Method::Get,
+            false,
+        )
+        .expect("A valid response")
+        .expect("A complete response");
+        assert!(response.body.is_empty());
+
+        // Only a final chunked coding frames the body
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked, gzip\r\n\r\n2\r\nab\r\n0\r\n\r\n",
+            &Method::Get,
+            true,
+        )
+        .expect("A valid response")
+        .expect("A complete response");
+        assert_eq!(response.body, b"2\r\nab\r\n0\r\n\r\n".to_vec());
+
+        // A list of identical lengths is one length
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2, 2\r\n\r\nabc",
+            &Method::Get,
+            false,
+        )
+        .expect("A valid response")
+        .expect("A complete response");
+        assert_eq!(response.body, b"ab".to_vec());
+        assert!(parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2, 3\r\n\r\nabc",
+            &Method::Get,
+            false
+        )
+        .is_err());
+
+        // Set-Cookie fields are kept apart since cookie values may hold commas
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nSet-Cookie: a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT\r\nset-cookie: b=2\r\nContent-Length: 0\r\n\r\n",
+            &Method::Get,
+            false,
+        )
+        .expect("A valid response")
+        .expect("A complete response");
+        assert_eq!(
+            response
+                .headers
+                .get_all("Set-Cookie")
+                .collect::<Vec<&String>>(),
+            vec![
+                &"a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT".to_string(),
+                &"b=2".to_string()
+            ]
+        );
+
+        // Repeated headers are combined
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nVary: Accept\r\nvary: Cookie\r\nContent-Length: 0\r\n\r\n",
+            &Method::Get,
+            false,
+        )
+        .expect("A valid response")
+        .expect("A complete response");
+        assert_eq!(
+            response.headers.get("Vary"),
+            Some(&"Accept, Cookie".to_string())
+        );
+
+        assert!(parse_response(b"FTP 200 OK\r\n\r\n", &Method::Get, false).is_err());
+        assert!(parse_response(b"HTTP/1.1 abc\r\n\r\n", &Method::Get, false).is_err());
+        assert!(parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: x\r\n\r\n",
+            &Method::Get,
+            false
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_read_response_eof() {
+        let mut reader = Cursor::new(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nab".to_vec());
+        let error = read_response(&mut reader, &Method::Get).expect_err("An incomplete response");
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
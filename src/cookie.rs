@@ -0,0 +1,514 @@
+//! # Handles the Cookie request header and Set-Cookie response headers (RFC 6265).
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crypto;
+use date;
+use request;
+
+/// # Parse a Cookie header value into name and value pairs in order
+/// ## Usage
+/// ```rust
+/// use milstian_http::cookie::parse_cookie_header;
+/// assert_eq!(
+///     parse_cookie_header("session=abc; theme=\"dark\""),
+///     vec![
+///         ("session".to_string(), "abc".to_string()),
+///         ("theme".to_string(), "dark".to_string())
+///     ]
+/// );
+/// ```
+pub fn parse_cookie_header(header: &str) -> Vec<(String, String)> {
+    header
+        .split(';')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let name = name.trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some((name.to_string(), value.trim().trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+/// # Get the cookies of a request, the first occurrence of a name wins
+/// ## Usage
+/// ```rust
+/// use milstian_http::cookie::get_cookies;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nCookie: a=1; b=2; a=3\r\n\r\n")
+///     .expect("A request");
+/// let cookies = get_cookies(&request);
+/// assert_eq!(cookies.get("a"), Some(&"1".to_string()));
+/// assert_eq!(cookies.get("b"), Some(&"2".to_string()));
+/// ```
+pub fn get_cookies(request: &request::Message) -> HashMap<String, String> {
+    let mut cookies: HashMap<String, String> = HashMap::new();
+    if let Some(header) = request.header("Cookie") {
+        for (name, value) in parse_cookie_header(&header.to_string()) {
+            cookies.entry(name).or_insert(value);
+        }
+    }
+    cookies
+}
+
+/// # Does the request carry cookie name with the expected secret value?
+/// Values are compared in constant time, like for CSRF tokens or session identifiers.
+/// ## Usage
+/// ```rust
+/// use milstian_http::cookie::verify_cookie;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nCookie: csrf=abc123\r\n\r\n")
+///     .expect("A request");
+/// assert!(verify_cookie(&request, "csrf", "abc123"));
+/// assert!(!verify_cookie(&request, "csrf", "abc124"));
+/// assert!(!verify_cookie(&request, "session", "abc123"));
+/// ```
+pub fn verify_cookie(request: &request::Message, name: &str, expected: &str) -> bool {
+    get_cookies(request)
+        .get(name)
+        .is_some_and(|value| crypto::secret_eq(expected, value))
+}
+
+/// # Reasons why a cookie can't be sent
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CookieError {
+    InvalidDomain,
+    InvalidName,
+    InvalidPath,
+    InvalidPrefix, // A __Secure- or __Host- cookie lacks its required attributes
+    InvalidValue,
+    SameSiteNoneWithoutSecure, // Browsers reject SameSite=None cookies that are not Secure
+}
+
+impl fmt::Display for CookieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CookieError::InvalidDomain => write!(f, "invalid cookie domain"),
+            CookieError::InvalidName => write!(f, "invalid cookie name"),
+            CookieError::InvalidPath => write!(f, "invalid cookie path"),
+            CookieError::InvalidPrefix => {
+                write!(
+                    f,
+                    "cookie prefix requires Secure, and for __Host- Path=/ without Domain"
+                )
+            }
+            CookieError::InvalidValue => write!(f, "invalid cookie value"),
+            CookieError::SameSiteNoneWithoutSecure => {
+                write!(f, "cookie with SameSite=None must be Secure")
+            }
+        }
+    }
+}
+
+impl error::Error for CookieError {}
+
+/// # The SameSite attribute of a cookie
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SameSite {
+    Lax,
+    None,
+    Strict,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+            SameSite::Strict => write!(f, "Strict"),
+        }
+    }
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value.chars().all(|character| {
+            character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(character)
+        })
+}
+
+fn is_cookie_value(value: &str) -> bool {
+    let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    value.chars().all(|character| {
+        ('\u{21}'..='\u{7e}').contains(&character)
+            && character != '"'
+            && character != ','
+            && character != ';'
+            && character != '\\'
+    })
+}
+
+fn is_attribute_value(value: &str) -> bool {
+    !value.is_empty()
+        && !value
+            .chars()
+            .any(|character| character.is_control() || character == ';')
+}
+
+/// Cookie name prefixes are matched case-insensitively like browsers do
+fn has_prefix(name: &str, prefix: &str) -> bool {
+    name.len() >= prefix.len()
+        && name.is_char_boundary(prefix.len())
+        && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// # Get the cookies of a request, prefixed cookies are dropped unless the connection is secure
+///
+/// Cookies named `__Secure-` or `__Host-` can only be set from secure origins, so on an
+/// insecure connection they were not set by the server.
+/// ## Usage
+/// ```rust
+/// use milstian_http::cookie::get_cookies_checked;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nCookie: __Host-id=1; theme=dark\r\n\r\n")
+///     .expect("A request");
+/// assert_eq!(get_cookies_checked(&request, true).len(), 2);
+/// let cookies = get_cookies_checked(&request, false);
+/// assert_eq!(cookies.len(), 1);
+/// assert_eq!(cookies.get("theme"), Some(&"dark".to_string()));
+/// ```
+pub fn get_cookies_checked(request: &request::Message, is_secure: bool) -> HashMap<String, String> {
+    let mut cookies = get_cookies(request);
+    if !is_secure {
+        cookies.retain(|name, _| !has_prefix(name, "__Secure-") && !has_prefix(name, "__Host-"));
+    }
+    cookies
+}
+
+/// # A cookie sent with a Set-Cookie response header
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SetCookie {
+    pub domain: Option<String>,
+    pub expires: Option<SystemTime>,
+    pub http_only: bool,
+    pub max_age: Option<Duration>, // Sent in whole seconds
+    pub name: String,
+    pub path: Option<String>,
+    pub same_site: Option<SameSite>,
+    pub secure: bool,
+    pub value: String,
+}
+
+impl SetCookie {
+    /// # Create a cookie without attributes
+    pub fn new(name: &str, value: &str) -> SetCookie {
+        SetCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            ..SetCookie::default()
+        }
+    }
+
+    /// # Check that name, value and attributes produce a cookie browsers accept
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cookie::{CookieError, SameSite, SetCookie};
+    /// assert!(SetCookie::new("session", "abc").validate().is_ok());
+    /// assert_eq!(
+    ///     SetCookie::new("bad name", "abc").validate(),
+    ///     Err(CookieError::InvalidName)
+    /// );
+    /// assert_eq!(
+    ///     SetCookie::new("session", "a;b").validate(),
+    ///     Err(CookieError::InvalidValue)
+    /// );
+    /// let cookie = SetCookie {
+    ///     same_site: Some(SameSite::None),
+    ///     ..SetCookie::new("session", "abc")
+    /// };
+    /// assert_eq!(cookie.validate(), Err(CookieError::SameSiteNoneWithoutSecure));
+    /// let cookie = SetCookie {
+    ///     secure: true,
+    ///     ..SetCookie::new("__Host-session", "abc")
+    /// };
+    /// assert_eq!(cookie.validate(), Err(CookieError::InvalidPrefix));
+    /// ```
+    pub fn validate(&self) -> Result<(), CookieError> {
+        if !is_token(&self.name) {
+            return Err(CookieError::InvalidName);
+        }
+        if !is_cookie_value(&self.value) {
+            return Err(CookieError::InvalidValue);
+        }
+        if let Some(ref domain) = self.domain {
+            if !is_attribute_value(domain) || domain.contains(char::is_whitespace) {
+                return Err(CookieError::InvalidDomain);
+            }
+        }
+        if let Some(ref path) = self.path {
+            if !is_attribute_value(path) || !path.starts_with('/') {
+                return Err(CookieError::InvalidPath);
+            }
+        }
+        if self.same_site == Some(SameSite::None) && !self.secure {
+            return Err(CookieError::SameSiteNoneWithoutSecure);
+        }
+        if has_prefix(&self.name, "__Secure-") && !self.secure {
+            return Err(CookieError::InvalidPrefix);
+        }
+        if has_prefix(&self.name, "__Host-")
+            && (!self.secure || self.domain.is_some() || self.path.as_deref() != Some("/"))
+        {
+            return Err(CookieError::InvalidPrefix);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SetCookie {
+    /// # Format cookie as a Set-Cookie header value
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cookie::SetCookie;
+    /// let cookie = SetCookie {
+    ///     http_only: true,
+    ///     path: Some("/".to_string()),
+    ///     ..SetCookie::new("session", "abc")
+    /// };
+    /// assert_eq!(cookie.to_string(), "session=abc; Path=/; HttpOnly".to_string());
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", date::format_http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(ref domain) = self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(ref path) = self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+/// # Signs cookie values with HMAC-SHA256, older keys are still accepted when verifying
+#[derive(Clone)]
+pub struct Signer {
+    keys: Vec<Vec<u8>>, // The first key signs, all keys verify
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Signer {{ keys: {} }}", self.keys.len())
+    }
+}
+
+impl Signer {
+    /// # Create a signer with a single key
+    pub fn new(key: &[u8]) -> Signer {
+        Signer {
+            keys: vec![key.to_vec()],
+        }
+    }
+
+    /// # Create a signer for key rotation, the first key signs, returns None without keys
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cookie::Signer;
+    /// let old = Signer::new(b"old key");
+    /// let signed = old.sign("session", "abc");
+    /// let rotated = Signer::with_keys(vec![b"new key".to_vec(), b"old key".to_vec()])
+    ///     .expect("A signer");
+    /// assert_eq!(rotated.verify("session", &signed), Some("abc".to_string()));
+    /// assert_ne!(rotated.sign("session", "abc"), signed);
+    /// ```
+    pub fn with_keys(keys: Vec<Vec<u8>>) -> Option<Signer> {
+        if keys.is_empty() {
+            return None;
+        }
+        Some(Signer { keys })
+    }
+
+    fn get_signature(key: &[u8], name: &str, value: &str) -> [u8; 32] {
+        // The name is signed too so a value can't be moved to another cookie
+        crypto::hmac_sha256(key, format!("{}={}", name, value).as_bytes())
+    }
+
+    /// # Sign the value of cookie name, the signature is appended after a '.'
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cookie::Signer;
+    /// let signer = Signer::new(b"secret");
+    /// let signed = signer.sign("session", "abc");
+    /// assert!(signed.starts_with("abc."));
+    /// assert_eq!(signer.verify("session", &signed), Some("abc".to_string()));
+    /// assert_eq!(signer.verify("other", &signed), None);
+    /// assert_eq!(signer.verify("session", &signed.replace("abc", "abd")), None);
+    /// ```
+    pub fn sign(&self, name: &str, value: &str) -> String {
+        format!(
+            "{}.{}",
+            value,
+            crypto::base64url_encode(&Signer::get_signature(&self.keys[0], name, value))
+        )
+    }
+
+    /// # Verify a signed value of cookie name and return the original value
+    pub fn verify(&self, name: &str, signed: &str) -> Option<String> {
+        let (value, signature) = signed.rsplit_once('.')?;
+        let signature = crypto::base64url_decode(signature)?;
+        if self.keys.iter().any(|key| {
+            crypto::constant_time_eq(&Signer::get_signature(key, name, value), &signature)
+        }) {
+            return Some(value.to_string());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cookie_header() {
+        assert_eq!(
+            parse_cookie_header(" a = 1 ;b=;=c;d;e=x=y"),
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "".to_string()),
+                ("e".to_string(), "x=y".to_string())
+            ]
+        );
+        assert!(parse_cookie_header("").is_empty());
+    }
+
+    #[test]
+    fn test_set_cookie_to_string() {
+        let cookie = SetCookie {
+            domain: Some("example.com".to_string()),
+            http_only: true,
+            max_age: Some(Duration::from_millis(60_500)),
+            path: Some("/app".to_string()),
+            same_site: Some(SameSite::Lax),
+            secure: true,
+            ..SetCookie::new("id", "1")
+        };
+        assert_eq!(
+            cookie.to_string(),
+            "id=1; Max-Age=60; Domain=example.com; Path=/app; Secure; HttpOnly; SameSite=Lax"
+                .to_string()
+        );
+        let cookie = SetCookie {
+            expires: Some(SystemTime::UNIX_EPOCH),
+            ..SetCookie::new("id", "")
+        };
+        assert_eq!(
+            cookie.to_string(),
+            "id=; Expires=Thu, 01 Jan 1970 00:00:00 GMT".to_string()
+        );
+    }
+
+    #[test]
+    fn test_set_cookie_validate() {
+        assert!(SetCookie::new("id", "").validate().is_ok());
+        assert!(SetCookie::new("id", "\"quoted\"").validate().is_ok());
+        assert_eq!(
+            SetCookie::new("", "x").validate(),
+            Err(CookieError::InvalidName)
+        );
+        assert_eq!(
+            SetCookie::new("id", "a b").validate(),
+            Err(CookieError::InvalidValue)
+        );
+        assert_eq!(
+            SetCookie::new("id", "\"").validate(),
+            Err(CookieError::InvalidValue)
+        );
+        let cookie = SetCookie {
+            domain: Some("example.com; Secure".to_string()),
+            ..SetCookie::new("id", "1")
+        };
+        assert_eq!(cookie.validate(), Err(CookieError::InvalidDomain));
+        let cookie = SetCookie {
+            path: Some("app".to_string()),
+            ..SetCookie::new("id", "1")
+        };
+        assert_eq!(cookie.validate(), Err(CookieError::InvalidPath));
+        let cookie = SetCookie {
+            same_site: Some(SameSite::None),
+            secure: true,
+            ..SetCookie::new("id", "1")
+        };
+        assert!(cookie.validate().is_ok());
+    }
+
+    #[test]
+    fn test_set_cookie_prefixes() {
+        let cookie = SetCookie {
+            secure: true,
+            path: Some("/".to_string()),
+            ..SetCookie::new("__Host-id", "1")
+        };
+        assert!(cookie.validate().is_ok());
+        let cookie = SetCookie {
+            domain: Some("example.com".to_string()),
+            ..cookie
+        };
+        assert_eq!(cookie.validate(), Err(CookieError::InvalidPrefix));
+        let cookie = SetCookie {
+            domain: None,
+            path: Some("/app".to_string()),
+            ..cookie
+        };
+        assert_eq!(cookie.validate(), Err(CookieError::InvalidPrefix));
+
+        assert_eq!(
+            SetCookie::new("__secure-id", "1").validate(),
+            Err(CookieError::InvalidPrefix)
+        );
+        let cookie = SetCookie {
+            secure: true,
+            domain: Some("example.com".to_string()),
+            ..SetCookie::new("__Secure-id", "1")
+        };
+        assert!(cookie.validate().is_ok());
+        assert!(SetCookie::new("__Securely", "1").validate().is_ok());
+    }
+
+    #[test]
+    fn test_get_cookies_checked() {
+        let request = request::Message::from_tcp_stream(
+            b"GET / HTTP/1.1\r\nCookie: __SECURE-a=1; __host-b=2; c=3\r\n\r\n",
+        )
+        .expect("A request");
+        let cookies = get_cookies_checked(&request, false);
+        assert_eq!(cookies.len(), 1);
+        assert!(cookies.contains_key("c"));
+        assert_eq!(get_cookies_checked(&request, true).len(), 3);
+    }
+
+    #[test]
+    fn test_signer() {
+        let signer = Signer::new(b"key");
+        let signed = signer.sign("a", "x.y");
+        assert_eq!(signer.verify("a", &signed), Some("x.y".to_string()));
+        assert_eq!(signer.verify("a", "x.y"), None);
+        assert_eq!(signer.verify("a", "no signature"), None);
+        assert_eq!(Signer::new(b"other").verify("a", &signed), None);
+        assert!(Signer::with_keys(Vec::new()).is_none());
+        assert_eq!(format!("{:?}", signer), "Signer { keys: 1 }".to_string());
+    }
+}
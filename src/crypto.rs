@@ -0,0 +1,322 @@
+//! # Cryptographic primitives and encodings used for signing header values.
+
+const SHA256_INITIAL: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+const SHA256_ROUNDS: [u32; 64] = [
+    0x428a_2f98,
+    0x7137_4491,
+    0xb5c0_fbcf,
+    0xe9b5_dba5,
+    0x3956_c25b,
+    0x59f1_11f1,
+    0x923f_82a4,
+    0xab1c_5ed5,
+    0xd807_aa98,
+    0x1283_5b01,
+    0x2431_85be,
+    0x550c_7dc3,
+    0x72be_5d74,
+    0x80de_b1fe,
+    0x9bdc_06a7,
+    0xc19b_f174,
+    0xe49b_69c1,
+    0xefbe_4786,
+    0x0fc1_9dc6,
+    0x240c_a1cc,
+    0x2de9_2c6f,
+    0x4a74_84aa,
+    0x5cb0_a9dc,
+    0x76f9_88da,
+    0x983e_5152,
+    0xa831_c66d,
+    0xb003_27c8,
+    0xbf59_7fc7,
+    0xc6e0_0bf3,
+    0xd5a7_9147,
+    0x06ca_6351,
+    0x1429_2967,
+    0x27b7_0a85,
+    0x2e1b_2138,
+    0x4d2c_6dfc,
+    0x5338_0d13,
+    0x650a_7354,
+    0x766a_0abb,
+    0x81c2_c92e,
+    0x9272_2c85,
+    0xa2bf_e8a1,
+    0xa81a_664b,
+    0xc24b_8b70,
+    0xc76c_51a3,
+    0xd192_e819,
+    0xd699_0624,
+    0xf40e_3585,
+    0x106a_a070,
+    0x19a4_c116,
+    0x1e37_6c08,
+    0x2748_774c,
+    0x34b0_bcb5,
+    0x391c_0cb3,
+    0x4ed8_aa4a,
+    0x5b9c_ca4f,
+    0x682e_6ff3,
+    0x748f_82ee,
+    0x78a5_636f,
+    0x84c8_7814,
+    0x8cc7_0208,
+    0x90be_fffa,
+    0xa450_6ceb,
+    0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+const BASE64URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn sha256_block(state: &mut [u32; 8], block: &[u8]) {
+    let mut words = [0u32; 64];
+    for (index, chunk) in block.chunks(4).enumerate() {
+        words[index] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    for index in 16..64 {
+        let s0 = words[index - 15].rotate_right(7)
+            ^ words[index - 15].rotate_right(18)
+            ^ (words[index - 15] >> 3);
+        let s1 = words[index - 2].rotate_right(17)
+            ^ words[index - 2].rotate_right(19)
+            ^ (words[index - 2] >> 10);
+        words[index] = words[index - 16]
+            .wrapping_add(s0)
+            .wrapping_add(words[index - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for index in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let choice = (e & f) ^ (!e & g);
+        let temporary1 = h
+            .wrapping_add(s1)
+            .wrapping_add(choice)
+            .wrapping_add(SHA256_ROUNDS[index])
+            .wrapping_add(words[index]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let majority = (a & b) ^ (a & c) ^ (b & c);
+        let temporary2 = s0.wrapping_add(majority);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temporary1);
+        d = c;
+        c = b;
+        b = a;
+        a = temporary1.wrapping_add(temporary2);
+    }
+    for (value, added) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+        *value = value.wrapping_add(*added);
+    }
+}
+
+/// # Calculate the SHA-256 digest of data
+/// ## Usage
+/// ```rust
+/// use milstian_http::crypto::sha256;
+/// assert_eq!(
+///     sha256(b"abc")[..4],
+///     [0xba, 0x78, 0x16, 0xbf]
+/// );
+/// ```
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state = SHA256_INITIAL;
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+    for block in message.chunks(64) {
+        sha256_block(&mut state, block);
+    }
+
+    let mut digest = [0u8; 32];
+    for (index, value) in state.iter().enumerate() {
+        digest[index * 4..index * 4 + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+/// # Calculate the HMAC-SHA256 of message with key (RFC 2104)
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block_key = [0u8; 64];
+    if key.len() > 64 {
+        block_key[..32].copy_from_slice(&sha256(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner: Vec<u8> = block_key.iter().map(|byte| byte ^ 0x36).collect();
+    inner.extend_from_slice(message);
+    let mut outer: Vec<u8> = block_key.iter().map(|byte| byte ^ 0x5c).collect();
+    outer.extend_from_slice(&sha256(&inner));
+    sha256(&outer)
+}
+
+/// # Compare two byte strings in time that only depends on their lengths
+/// ## Usage
+/// ```rust
+/// use milstian_http::crypto::constant_time_eq;
+/// assert!(constant_time_eq(b"secret", b"secret"));
+/// assert!(!constant_time_eq(b"secret", b"secreT"));
+/// assert!(!constant_time_eq(b"secret", b"secrets"));
+/// ```
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// # Compare a received secret with the expected one without revealing its length or content
+/// Both are hashed first so the comparison takes the same time whatever their lengths, use it
+/// for tokens, API keys and signatures instead of `==`. An empty expected secret never matches.
+/// ## Usage
+/// ```rust
+/// use milstian_http::crypto::secret_eq;
+/// assert!(secret_eq("s3cr3t", "s3cr3t"));
+/// assert!(!secret_eq("s3cr3t", "s3cr3"));
+/// assert!(!secret_eq("", ""));
+/// ```
+pub fn secret_eq(expected: &str, received: &str) -> bool {
+    let is_equal = constant_time_eq(&sha256(expected.as_bytes()), &sha256(received.as_bytes()));
+    is_equal && !expected.is_empty()
+}
+
+/// # Encode data as unpadded base64url (RFC 4648 section 5)
+/// ## Usage
+/// ```rust
+/// use milstian_http::crypto::base64url_encode;
+/// assert_eq!(base64url_encode(b"\xfb\xffa"), "-_9h".to_string());
+/// ```
+pub fn base64url_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() * 4).div_ceil(3));
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let value = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for index in 0..=chunk.len() {
+            let sextet = (value >> (18 - index * 6)) & 0x3f;
+            encoded.push(BASE64URL_ALPHABET[sextet as usize] as char);
+        }
+    }
+    encoded
+}
+
+/// # Decode unpadded base64url, returns None for invalid input
+/// Input is only accepted in its canonical form, where bits past the last byte are zero.
+/// ## Usage
+/// ```rust
+/// use milstian_http::crypto::base64url_decode;
+/// assert_eq!(base64url_decode("-_9h"), Some(b"\xfb\xffa".to_vec()));
+/// assert_eq!(base64url_decode("a"), None);
+/// assert_eq!(base64url_decode("Zg"), Some(b"f".to_vec()));
+/// assert_eq!(base64url_decode("Zh"), None);
+/// ```
+pub fn base64url_decode(data: &str) -> Option<Vec<u8>> {
+    if data.len() % 4 == 1 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.as_bytes().chunks(4) {
+        let mut value: u32 = 0;
+        for (index, byte) in chunk.iter().enumerate() {
+            let sextet = BASE64URL_ALPHABET
+                .iter()
+                .position(|character| character == byte)?;
+            value |= (sextet as u32) << (18 - index * 6);
+        }
+        let unused_bits = (1u32 << (24 - 8 * (chunk.len() - 1))) - 1;
+        if value & unused_bits != 0 {
+            return None;
+        }
+        let bytes = [(value >> 16) as u8, (value >> 8) as u8, value as u8];
+        decoded.extend_from_slice(&bytes[..chunk.len() - 1]);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            to_hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            to_hex(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            to_hex(&sha256(&[b'a'; 1000])),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test cases 1, 2 and 6
+        assert_eq!(
+            to_hex(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            to_hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_base64url() {
+        assert_eq!(base64url_encode(b""), "".to_string());
+        assert_eq!(base64url_encode(b"f"), "Zg".to_string());
+        assert_eq!(base64url_encode(b"fo"), "Zm8".to_string());
+        assert_eq!(base64url_encode(b"foo"), "Zm9v".to_string());
+        assert_eq!(base64url_encode(b"foobar"), "Zm9vYmFy".to_string());
+        for length in 0..40 {
+            let data: Vec<u8> = (0..length).map(|byte| (byte * 37) as u8).collect();
+            assert_eq!(base64url_decode(&base64url_encode(&data)), Some(data));
+        }
+        assert_eq!(base64url_decode("Zm9v="), None);
+        assert_eq!(base64url_decode("Zm+v"), None);
+        assert_eq!(base64url_decode("Zm9"), None);
+    }
+}
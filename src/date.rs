@@ -0,0 +1,199 @@
+//! # Handles HTTP-date formatting and parsing (RFC 7231 section 7.1.1.1).
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Convert days since the epoch to year, month and day
+fn get_civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Convert year, month and day to days since the epoch
+fn get_days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// # Format a time as an IMF-fixdate, times before the epoch are clamped to it
+/// ## Usage
+/// ```rust
+/// use milstian_http::date::format_http_date;
+/// use std::time::{Duration, UNIX_EPOCH};
+/// assert_eq!(
+///     format_http_date(UNIX_EPOCH + Duration::from_secs(784111777)),
+///     "Sun, 06 Nov 1994 08:49:37 GMT".to_string()
+/// );
+/// ```
+pub fn format_http_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0) as i64;
+    let days = seconds.div_euclid(86_400);
+    let seconds_of_day = seconds.rem_euclid(86_400);
+    let (year, month, day) = get_civil_from_days(days);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+fn get_month(name: &str) -> Option<u32> {
+    MONTHS
+        .iter()
+        .position(|month| *month == name)
+        .map(|index| index as u32 + 1)
+}
+
+fn get_time_of_day(value: &str) -> Option<u64> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 3 || parts.iter().any(|part| part.len() != 2) {
+        return None;
+    }
+    let hours: u64 = parts[0].parse().ok()?;
+    let minutes: u64 = parts[1].parse().ok()?;
+    let seconds: u64 = parts[2].parse().ok()?;
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+fn get_time(year: i64, month: u32, day: u32, time_of_day: u64) -> Option<SystemTime> {
+    if !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+    let days = get_days_from_civil(year, month, day);
+    // Reject days that overflow into the next month
+    if get_civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(days as u64 * 86_400 + time_of_day))
+}
+
+/// # Parse an IMF-fixdate, RFC 850 or asctime date
+/// ## Usage
+/// ```rust
+/// use milstian_http::date::parse_http_date;
+/// use std::time::{Duration, UNIX_EPOCH};
+/// let time = UNIX_EPOCH + Duration::from_secs(784111777);
+/// assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+/// assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(time));
+/// assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(time));
+/// assert_eq!(parse_http_date("yesterday"), None);
+/// ```
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    match parts.len() {
+        // IMF-fixdate: Sun, 06 Nov 1994 08:49:37 GMT
+        6 if parts[0].ends_with(',') && parts[5] == "GMT" => {
+            if parts[1].len() != 2 || parts[3].len() != 4 {
+                return None;
+            }
+            get_time(
+                parts[3].parse().ok()?,
+                get_month(parts[2])?,
+                parts[1].parse().ok()?,
+                get_time_of_day(parts[4])?,
+            )
+        }
+        // RFC 850: Sunday, 06-Nov-94 08:49:37 GMT
+        4 if parts[0].ends_with(',') && parts[3] == "GMT" => {
+            let date: Vec<&str> = parts[1].split('-').collect();
+            if date.len() != 3 || date[0].len() != 2 || date[2].len() != 2 {
+                return None;
+            }
+            let year: i64 = date[2].parse().ok()?;
+            // Two digit years more than 50 years in the future are in the past
+            let year = if year < 70 { 2000 + year } else { 1900 + year };
+            get_time(
+                year,
+                get_month(date[1])?,
+                date[0].parse().ok()?,
+                get_time_of_day(parts[2])?,
+            )
+        }
+        // asctime: Sun Nov  6 08:49:37 1994
+        5 => {
+            if parts[4].len() != 4 {
+                return None;
+            }
+            get_time(
+                parts[4].parse().ok()?,
+                get_month(parts[1])?,
+                parts[2].parse().ok()?,
+                get_time_of_day(parts[3])?,
+            )
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_http_date() {
+        assert_eq!(
+            format_http_date(UNIX_EPOCH),
+            "Thu, 01 Jan 1970 00:00:00 GMT".to_string()
+        );
+        assert_eq!(
+            format_http_date(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "Tue, 29 Feb 2000 00:00:00 GMT".to_string()
+        );
+        assert_eq!(
+            format_http_date(UNIX_EPOCH + Duration::from_secs(4_102_444_799)),
+            "Thu, 31 Dec 2099 23:59:59 GMT".to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        for seconds in [0, 68_169_599, 951_782_400, 1_700_000_000, 4_102_444_799].iter() {
+            let time = UNIX_EPOCH + Duration::from_secs(*seconds);
+            assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+        }
+        assert_eq!(parse_http_date("Thu, 31 Feb 2000 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 24:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Thu, 01 Foo 1970 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Thu, 1 Jan 1970 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 UTC"), None);
+        assert_eq!(
+            parse_http_date("Thursday, 01-Jan-70 00:00:01 GMT"),
+            Some(UNIX_EPOCH + Duration::from_secs(1))
+        );
+        assert_eq!(parse_http_date(""), None);
+    }
+}
@@ -0,0 +1,815 @@
+//! # Deserializes decoded query and form values into user types.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+use std::str;
+use std::str::FromStr;
+
+use serde::de;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeOwned, Deserializer, IntoDeserializer, MapAccess, Visitor};
+
+use request::MultiPartValue;
+
+/// # Reasons why values could not be deserialized
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DeserializeError {
+    Custom(String), // Raised by the deserialized type, like a missing field
+    InvalidValue {
+        expected: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeserializeError::Custom(message) => write!(f, "{}", message),
+            DeserializeError::InvalidValue { expected, value } => {
+                write!(f, "Invalid value {:?}, expected {}", value, expected)
+            }
+        }
+    }
+}
+
+impl error::Error for DeserializeError {}
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(message: T) -> DeserializeError {
+        DeserializeError::Custom(message.to_string())
+    }
+}
+
+/// # Deserialize decoded keys and their values into a type
+/// Repeated keys fill sequence fields, other fields get the last value.
+/// ## Usage
+/// ```rust
+/// extern crate milstian_http;
+/// #[macro_use]
+/// extern crate serde_derive;
+/// use milstian_http::deserialize::from_values;
+/// use std::collections::HashMap;
+///
+/// #[derive(Deserialize)]
+/// struct Search {
+///     page: u32,
+///     tag: Vec<String>,
+/// }
+///
+/// fn main() {
+///     let mut values: HashMap<String, Vec<String>> = HashMap::new();
+///     values.insert("page".to_string(), vec!["2".to_string()]);
+///     values.insert("tag".to_string(), vec!["a".to_string(), "b".to_string()]);
+///     let search: Search = from_values(values).expect("A search");
+///     assert_eq!(search.page, 2);
+///     assert_eq!(search.tag, vec!["a".to_string(), "b".to_string()]);
+/// }
+/// ```
+pub fn from_values<T: DeserializeOwned>(
+    values: HashMap<String, Vec<String>>,
+) -> Result<T, DeserializeError> {
+    T::deserialize(MapDeserializer::new(
+        values
+            .into_iter()
+            .map(|(key, values)| (key, ValuesDeserializer(values))),
+    ))
+}
+
+/// All values of a key
+struct ValuesDeserializer(Vec<String>);
+
+impl ValuesDeserializer {
+    fn last(mut self) -> Result<ValueDeserializer, DeserializeError> {
+        match self.0.pop() {
+            Some(value) => Ok(ValueDeserializer(value)),
+            None => Err(de::Error::custom("missing value")),
+        }
+    }
+
+    fn visit_values<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        let mut sequence = SeqDeserializer::new(self.0.into_iter().map(ValueDeserializer));
+        let value = visitor.visit_seq(&mut sequence)?;
+        sequence.end()?;
+        Ok(value)
+    }
+}
+
+macro_rules! forward_to_last {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+                self.last()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValuesDeserializer {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        if self.0.len() == 1 {
+            self.last()?.deserialize_any(visitor)
+        } else {
+            self.visit_values(visitor)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.visit_values(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.visit_values(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.visit_values(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.last()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.last()?.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.last()?.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_last! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_unit deserialize_map deserialize_identifier
+        deserialize_ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DeserializeError> for ValuesDeserializer {
+    type Deserializer = ValuesDeserializer;
+
+    fn into_deserializer(self) -> ValuesDeserializer {
+        self
+    }
+}
+
+/// A single value of a key
+struct ValueDeserializer(String);
+
+impl ValueDeserializer {
+    fn parse<T: FromStr>(&self, expected: &'static str) -> Result<T, DeserializeError> {
+        self.0.parse().map_err(|_| DeserializeError::InvalidValue {
+            expected,
+            value: self.0.clone(),
+        })
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident $expected:expr,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+                visitor.$visit(self.parse($expected)?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match self.0.as_str() {
+            "1" | "on" | "true" => visitor.visit_bool(true),
+            "0" | "off" | "false" => visitor.visit_bool(false),
+            _ => Err(DeserializeError::InvalidValue {
+                expected: "a boolean",
+                value: self.0,
+            }),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8 "an integer",
+        deserialize_i16 => visit_i16 "an integer",
+        deserialize_i32 => visit_i32 "an integer",
+        deserialize_i64 => visit_i64 "an integer",
+        deserialize_u8 => visit_u8 "an unsigned integer",
+        deserialize_u16 => visit_u16 "an unsigned integer",
+        deserialize_u32 => visit_u32 "an unsigned integer",
+        deserialize_u64 => visit_u64 "an unsigned integer",
+        deserialize_f32 => visit_f32 "a number",
+        deserialize_f64 => visit_f64 "a number",
+        deserialize_char => visit_char "a character",
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        ValuesDeserializer(vec![self.0]).visit_values(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DeserializeError> for ValueDeserializer {
+    type Deserializer = ValueDeserializer;
+
+    fn into_deserializer(self) -> ValueDeserializer {
+        self
+    }
+}
+
+/// # An uploaded file of a multi-part body
+/// Spooled parts keep their body on disk at path, which is removed when the request is dropped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Upload {
+    pub body: Vec<u8>,                // Empty when spooled to a file
+    pub content_type: Option<String>, // From the Content-Type of the part
+    pub filename: Option<String>,     // From the Content-Disposition of the part
+    pub path: Option<PathBuf>,        // Set when the part was spooled to a file
+    pub size: usize,
+}
+
+impl Upload {
+    /// # Stream the contents whether they are in memory or spooled
+    pub fn reader<'a>(&'a self) -> io::Result<Box<dyn Read + 'a>> {
+        match self.path {
+            Some(ref path) => Ok(Box::new(fs::File::open(path)?)),
+            None => Ok(Box::new(&self.body[..])),
+        }
+    }
+}
+
+const UPLOAD_FIELDS: &[&str] = &["body", "content_type", "filename", "path", "size"];
+
+impl<'de> de::Deserialize<'de> for Upload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Upload, D::Error> {
+        deserializer.deserialize_struct("Upload", UPLOAD_FIELDS, UploadVisitor)
+    }
+}
+
+struct UploadVisitor;
+
+impl<'de> Visitor<'de> for UploadVisitor {
+    type Value = Upload;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an uploaded file")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<Upload, M::Error> {
+        let mut upload = Upload::default();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "body" => upload.body = map.next_value()?,
+                "content_type" => upload.content_type = map.next_value()?,
+                "filename" => upload.filename = map.next_value()?,
+                "path" => upload.path = map.next_value::<Option<String>>()?.map(PathBuf::from),
+                "size" => upload.size = map.next_value()?,
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(upload)
+    }
+}
+
+/// # Deserialize the parts of a multi-part body into a type
+/// Text parts fill string, numeric and boolean fields, `Vec<u8>` fields get the bytes of a part
+/// and [`Upload`] fields get the uploaded file, spooled parts are read from disk when needed.
+/// ## Usage
+/// ```rust
+/// extern crate milstian_http;
+/// #[macro_use]
+/// extern crate serde_derive;
+/// use milstian_http::deserialize::Upload;
+/// use milstian_http::request::{BodyContentType, Message};
+///
+/// #[derive(Deserialize)]
+/// struct Profile {
+///     age: u8,
+///     avatar: Upload,
+///     name: String,
+/// }
+///
+/// fn main() {
+///     let request = Message::from_tcp_stream(
+///         b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=----X\r\n\r\n\
+///         ------X\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nAda\r\n\
+///         ------X\r\nContent-Disposition: form-data; name=\"age\"\r\n\r\n36\r\n\
+///         ------X\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"a.png\"\r\n\
+///         Content-Type: image/png\r\n\r\nPNG\r\n------X--\r\n",
+///     )
+///     .expect("A request");
+///     if let BodyContentType::MultiPart(ref values) = request.body {
+///         let profile: Profile =
+///             milstian_http::deserialize::from_multipart(values).expect("A profile");
+///         assert_eq!(profile.age, 36);
+///         assert_eq!(profile.name, "Ada".to_string());
+///         assert_eq!(profile.avatar.body, b"PNG".to_vec());
+///         assert_eq!(profile.avatar.filename, Some("a.png".to_string()));
+///     }
+/// }
+/// ```
+pub fn from_multipart<T: DeserializeOwned>(
+    values: &HashMap<String, MultiPartValue>,
+) -> Result<T, DeserializeError> {
+    T::deserialize(MapDeserializer::new(
+        values
+            .iter()
+            .map(|(key, value)| (key.clone(), PartDeserializer(value))),
+    ))
+}
+
+/// A part of a multi-part body
+struct PartDeserializer<'a>(&'a MultiPartValue);
+
+impl<'a> PartDeserializer<'a> {
+    fn bytes(&self) -> Result<Vec<u8>, DeserializeError> {
+        match self.0.file {
+            Some(ref file) => fs::read(file.path()).map_err(de::Error::custom),
+            None => Ok(self.0.body.clone()),
+        }
+    }
+
+    fn text(&self) -> Result<ValueDeserializer, DeserializeError> {
+        let bytes = self.bytes()?;
+        match String::from_utf8(bytes) {
+            Ok(text) => Ok(ValueDeserializer(text)),
+            Err(error) => Err(DeserializeError::InvalidValue {
+                expected: "UTF-8 text",
+                value: String::from_utf8_lossy(error.as_bytes()).into_owned(),
+            }),
+        }
+    }
+
+    fn upload(&self) -> Vec<(&'static str, UploadField)> {
+        let mut fields = vec![("size", UploadField::Size(self.0.size()))];
+        let content_type = match self.0.file {
+            Some(ref file) => {
+                fields.push((
+                    "path",
+                    UploadField::Text(file.path().to_string_lossy().into_owned()),
+                ));
+                file.content_type.clone()
+            }
+            None => {
+                fields.push(("body", UploadField::Bytes(self.0.body.clone())));
+                self.0
+                    .headers
+                    .get("Content-Type")
+                    .map(|content_type| content_type.to_string())
+            }
+        };
+        if let Some(content_type) = content_type {
+            fields.push(("content_type", UploadField::Text(content_type)));
+        }
+        if let Some(ref filename) = self.0.filename {
+            fields.push(("filename", UploadField::Text(filename.clone())));
+        }
+        fields
+    }
+}
+
+macro_rules! forward_to_text {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+                self.text()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'a, 'de> de::Deserializer<'de> for PartDeserializer<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        let bytes = self.bytes()?;
+        match String::from_utf8(bytes) {
+            Ok(text) => visitor.visit_string(text),
+            Err(error) => visitor.visit_byte_buf(error.into_bytes()),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_byte_buf(self.bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        visitor.visit_byte_buf(self.bytes()?)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        UploadField::Bytes(self.bytes()?).deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        let mut map = MapDeserializer::new(self.upload().into_iter());
+        let value = visitor.visit_map(&mut map)?;
+        map.end()?;
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.text()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.text()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_text! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_unit
+        deserialize_map deserialize_identifier
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'a, 'de> IntoDeserializer<'de, DeserializeError> for PartDeserializer<'a> {
+    type Deserializer = PartDeserializer<'a>;
+
+    fn into_deserializer(self) -> PartDeserializer<'a> {
+        self
+    }
+}
+
+/// A field of an uploaded file
+enum UploadField {
+    Bytes(Vec<u8>),
+    Size(usize),
+    Text(String),
+}
+
+impl<'de> de::Deserializer<'de> for UploadField {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match self {
+            UploadField::Bytes(bytes) => {
+                let mut sequence = SeqDeserializer::new(bytes.into_iter());
+                let value = visitor.visit_seq(&mut sequence)?;
+                sequence.end()?;
+                Ok(value)
+            }
+            UploadField::Size(size) => visitor.visit_u64(size as u64),
+            UploadField::Text(text) => visitor.visit_string(text),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit
+        unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DeserializeError> for UploadField {
+    type Deserializer = UploadField;
+
+    fn into_deserializer(self) -> UploadField {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use request::{BodyContentType, Message, ParserConfig};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Order {
+        Ascending,
+        Descending,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Search {
+        active: bool,
+        order: Order,
+        page: Option<u32>,
+        query: String,
+        ratio: f64,
+        #[serde(default)]
+        tag: Vec<String>,
+    }
+
+    fn get_values(pairs: &[(&str, &str)]) -> HashMap<String, Vec<String>> {
+        let mut values: HashMap<String, Vec<String>> = HashMap::new();
+        for (key, value) in pairs {
+            values
+                .entry(key.to_string())
+                .or_default()
+                .push(value.to_string());
+        }
+        values
+    }
+
+    #[test]
+    fn test_from_values() {
+        let search: Search = from_values(get_values(&[
+            ("active", "1"),
+            ("order", "descending"),
+            ("query", "first"),
+            ("query", "hello world"),
+            ("ratio", "0.5"),
+            ("tag", "a"),
+            ("tag", "b"),
+            ("unknown", "x"),
+        ]))
+        .expect("A search");
+        assert_eq!(
+            search,
+            Search {
+                active: true,
+                order: Order::Descending,
+                page: None,
+                query: "hello world".to_string(),
+                ratio: 0.5,
+                tag: vec!["a".to_string(), "b".to_string()],
+            }
+        );
+
+        let search: Search = from_values(get_values(&[
+            ("active", "false"),
+            ("order", "ascending"),
+            ("page", "3"),
+            ("query", ""),
+            ("ratio", "1"),
+        ]))
+        .expect("A search");
+        assert_eq!(search.page, Some(3));
+        assert_eq!(search.order, Order::Ascending);
+        assert!(search.tag.is_empty());
+
+        let error = from_values::<Search>(get_values(&[
+            ("active", "yes"),
+            ("order", "ascending"),
+            ("query", ""),
+            ("ratio", "1"),
+        ]))
+        .expect_err("An invalid boolean");
+        assert_eq!(
+            error,
+            DeserializeError::InvalidValue {
+                expected: "a boolean",
+                value: "yes".to_string()
+            }
+        );
+
+        let error = from_values::<Search>(get_values(&[
+            ("active", "on"),
+            ("order", "ascending"),
+            ("page", "-1"),
+            ("query", ""),
+            ("ratio", "1"),
+        ]))
+        .expect_err("An invalid page");
+        assert_eq!(
+            error.to_string(),
+            "Invalid value \"-1\", expected an unsigned integer"
+        );
+
+        let error =
+            from_values::<Search>(get_values(&[("active", "on")])).expect_err("Missing fields");
+        assert_eq!(
+            error,
+            DeserializeError::Custom("missing field `order`".to_string())
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Profile {
+        age: u8,
+        avatar: Upload,
+        document: Option<Upload>,
+        name: String,
+        signature: Vec<u8>,
+    }
+
+    fn get_parts(body: &str, config: &ParserConfig) -> HashMap<String, MultiPartValue> {
+        let request = Message::from_tcp_stream_with_config(
+            format!(
+                "POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=----X\r\n\r\n{}------X--\r\n",
+                body
+            )
+            .as_bytes(),
+            config,
+        )
+        .expect("A request");
+        match request.body {
+            BodyContentType::MultiPart(values) => values,
+            _ => panic!("Expected a multi-part body"),
+        }
+    }
+
+    #[test]
+    fn test_from_multipart() {
+        let body = "------X\r\nContent-Disposition: form-data; name=\"age\"\r\n\r\n36\r\n\
+            ------X\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nAda\r\n\
+            ------X\r\nContent-Disposition: form-data; name=\"signature\"\r\n\r\nsig\r\n\
+            ------X\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"a.png\"\r\n\
+            Content-Type: image/png\r\n\r\nPNG\r\n";
+        let profile: Profile =
+            from_multipart(&get_parts(body, &ParserConfig::default())).expect("A profile");
+        assert_eq!(
+            profile,
+            Profile {
+                age: 36,
+                avatar: Upload {
+                    body: b"PNG".to_vec(),
+                    content_type: Some("image/png".to_string()),
+                    filename: Some("a.png".to_string()),
+                    path: None,
+                    size: 3,
+                },
+                document: None,
+                name: "Ada".to_string(),
+                signature: b"sig".to_vec(),
+            }
+        );
+
+        // Spooled parts keep their path and are read from disk for bytes and text
+        let config = ParserConfig {
+            multipart_spool_threshold: Some(2),
+            ..ParserConfig::default()
+        };
+        let values = get_parts(body, &config);
+        let profile: Profile = from_multipart(&values).expect("A profile");
+        assert_eq!(profile.name, "Ada".to_string());
+        assert_eq!(profile.signature, b"sig".to_vec());
+        assert!(profile.avatar.body.is_empty());
+        assert_eq!(profile.avatar.size, 3);
+        assert_eq!(profile.avatar.filename, Some("a.png".to_string()));
+        assert!(profile.avatar.path.is_some());
+        let mut contents = Vec::new();
+        profile
+            .avatar
+            .reader()
+            .expect("A reader")
+            .read_to_end(&mut contents)
+            .expect("Contents");
+        assert_eq!(contents, b"PNG".to_vec());
+
+        let error = from_multipart::<Profile>(&get_parts(
+            "------X\r\nContent-Disposition: form-data; name=\"age\"\r\n\r\nold\r\n",
+            &ParserConfig::default(),
+        ))
+        .expect_err("An invalid age");
+        assert_eq!(
+            error,
+            DeserializeError::InvalidValue {
+                expected: "an unsigned integer",
+                value: "old".to_string()
+            }
+        );
+
+        let values: HashMap<String, String> = from_multipart(&get_parts(
+            "------X\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n",
+            &ParserConfig::default(),
+        ))
+        .expect("A map");
+        assert_eq!(values.get("a"), Some(&"1".to_string()));
+    }
+
+    #[test]
+    fn test_from_values_collections() {
+        let values: HashMap<String, Vec<u8>> =
+            from_values(get_values(&[("a", "1"), ("a", "2"), ("b", "3")])).expect("A map");
+        assert_eq!(values.get("a"), Some(&vec![1, 2]));
+        assert_eq!(values.get("b"), Some(&vec![3]));
+
+        let values: HashMap<String, String> =
+            from_values(get_values(&[("a", "1"), ("a", "2")])).expect("A map");
+        assert_eq!(values.get("a"), Some(&"2".to_string()));
+    }
+}
@@ -0,0 +1,472 @@
+//! # Handles encoding and decoding of FastCGI records.
+//!
+//! Requests are framed as BEGIN_REQUEST, PARAMS and STDIN records, responses are collected from
+//! STDOUT, STDERR and END_REQUEST records.
+
+use std::collections::HashMap;
+
+use cgi;
+use response;
+
+const VERSION: u8 = 1;
+const HEADER_LENGTH: usize = 8;
+const MAX_CONTENT_LENGTH: usize = 65535;
+const ROLE_RESPONDER: u16 = 1;
+const FLAG_KEEP_CONN: u8 = 1;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecordType {
+    BeginRequest,
+    AbortRequest,
+    EndRequest,
+    Params,
+    Stdin,
+    Stdout,
+    Stderr,
+    Data,
+    GetValues,
+    GetValuesResult,
+    UnknownType,
+    Other(u8),
+}
+
+impl RecordType {
+    fn from_byte(byte: u8) -> RecordType {
+        match byte {
+            1 => RecordType::BeginRequest,
+            2 => RecordType::AbortRequest,
+            3 => RecordType::EndRequest,
+            4 => RecordType::Params,
+            5 => RecordType::Stdin,
+            6 => RecordType::Stdout,
+            7 => RecordType::Stderr,
+            8 => RecordType::Data,
+            9 => RecordType::GetValues,
+            10 => RecordType::GetValuesResult,
+            11 => RecordType::UnknownType,
+            other => RecordType::Other(other),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            RecordType::BeginRequest => 1,
+            RecordType::AbortRequest => 2,
+            RecordType::EndRequest => 3,
+            RecordType::Params => 4,
+            RecordType::Stdin => 5,
+            RecordType::Stdout => 6,
+            RecordType::Stderr => 7,
+            RecordType::Data => 8,
+            RecordType::GetValues => 9,
+            RecordType::GetValuesResult => 10,
+            RecordType::UnknownType => 11,
+            RecordType::Other(other) => other,
+        }
+    }
+}
+
+/// # A single FastCGI record
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    pub content: Vec<u8>,
+    pub record_type: RecordType,
+    pub request_id: u16,
+}
+
+impl Record {
+    /// # Encode record with padding to a multiple of eight bytes
+    /// Content longer than a record can hold is split over consecutive records of the same type.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::fastcgi::{Record, RecordType};
+    /// let record = Record {
+    ///     content: b"abc".to_vec(),
+    ///     record_type: RecordType::Stdin,
+    ///     request_id: 1,
+    /// };
+    /// assert_eq!(
+    ///     record.to_bytes(),
+    ///     vec![1, 5, 0, 1, 0, 3, 5, 0, b'a', b'b', b'c', 0, 0, 0, 0, 0]
+    /// );
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + self.content.len() + 7);
+        if self.content.is_empty() {
+            self.push_bytes(&[], &mut bytes);
+        }
+        for chunk in self.content.chunks(MAX_CONTENT_LENGTH) {
+            self.push_bytes(chunk, &mut bytes);
+        }
+        bytes
+    }
+
+    /// Append one record with content to bytes
+    fn push_bytes(&self, content: &[u8], bytes: &mut Vec<u8>) {
+        let padding_length = (8 - content.len() % 8) % 8;
+        bytes.push(VERSION);
+        bytes.push(self.record_type.to_byte());
+        bytes.push((self.request_id >> 8) as u8);
+        bytes.push(self.request_id as u8);
+        bytes.push((content.len() >> 8) as u8);
+        bytes.push(content.len() as u8);
+        bytes.push(padding_length as u8);
+        bytes.push(0);
+        bytes.extend_from_slice(content);
+        bytes.resize(bytes.len() + padding_length, 0);
+    }
+
+    /// # Decode a record from the start of bytes, returns record and number of bytes consumed
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::fastcgi::{Record, RecordType};
+    /// let (record, consumed) =
+    ///     Record::from_bytes(&[1, 6, 0, 1, 0, 2, 6, 0, b'h', b'i', 0, 0, 0, 0, 0, 0])
+    ///         .expect("A record");
+    /// assert_eq!(record.record_type, RecordType::Stdout);
+    /// assert_eq!(record.content, b"hi".to_vec());
+    /// assert_eq!(consumed, 16);
+    /// assert!(Record::from_bytes(&[1, 6, 0, 1, 0, 2]).is_none());
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Option<(Record, usize)> {
+        if bytes.len() < HEADER_LENGTH || bytes[0] != VERSION {
+            return None;
+        }
+        let request_id = (u16::from(bytes[2]) << 8) | u16::from(bytes[3]);
+        let content_length = ((bytes[4] as usize) << 8) | bytes[5] as usize;
+        let padding_length = bytes[6] as usize;
+        let end = HEADER_LENGTH + content_length + padding_length;
+        if bytes.len() < end {
+            return None;
+        }
+        Some((
+            Record {
+                content: bytes[HEADER_LENGTH..HEADER_LENGTH + content_length].to_vec(),
+                record_type: RecordType::from_byte(bytes[1]),
+                request_id,
+            },
+            end,
+        ))
+    }
+}
+
+fn encode_length(length: usize, output: &mut Vec<u8>) {
+    if length < 128 {
+        output.push(length as u8);
+    } else {
+        output.push(((length >> 24) as u8) | 0x80);
+        output.push((length >> 16) as u8);
+        output.push((length >> 8) as u8);
+        output.push(length as u8);
+    }
+}
+
+fn decode_length(bytes: &[u8], position: &mut usize) -> Option<usize> {
+    let first = *bytes.get(*position)?;
+    if first < 128 {
+        *position += 1;
+        return Some(first as usize);
+    }
+    let length = bytes.get(*position..*position + 4)?;
+    *position += 4;
+    Some(
+        (((length[0] & 0x7f) as usize) << 24)
+            | ((length[1] as usize) << 16)
+            | ((length[2] as usize) << 8)
+            | length[3] as usize,
+    )
+}
+
+/// # Encode parameters as FastCGI name-value pairs, sorted by name
+/// ## Usage
+/// ```rust
+/// use milstian_http::fastcgi::{decode_params, encode_params};
+/// use std::collections::HashMap;
+/// let mut params = HashMap::new();
+/// params.insert("REQUEST_METHOD".to_string(), "GET".to_string());
+/// let encoded = encode_params(&params);
+/// assert_eq!(encoded[0..2].to_vec(), vec![14, 3]);
+/// assert_eq!(decode_params(&encoded), Some(params));
+/// ```
+pub fn encode_params(params: &HashMap<String, String>) -> Vec<u8> {
+    let mut params: Vec<(&String, &String)> = params.iter().collect();
+    params.sort();
+    let mut output = Vec::new();
+    for (name, value) in params {
+        encode_length(name.len(), &mut output);
+        encode_length(value.len(), &mut output);
+        output.extend_from_slice(name.as_bytes());
+        output.extend_from_slice(value.as_bytes());
+    }
+    output
+}
+
+/// # Decode FastCGI name-value pairs
+pub fn decode_params(bytes: &[u8]) -> Option<HashMap<String, String>> {
+    let mut params = HashMap::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let name_length = decode_length(bytes, &mut position)?;
+        let value_length = decode_length(bytes, &mut position)?;
+        let name_end = position.checked_add(name_length)?;
+        let value_end = name_end.checked_add(value_length)?;
+        let name = String::from_utf8_lossy(bytes.get(position..name_end)?).into_owned();
+        let value = String::from_utf8_lossy(bytes.get(name_end..value_end)?).into_owned();
+        params.insert(name, value);
+        position = value_end;
+    }
+    Some(params)
+}
+
+fn push_stream(record_type: RecordType, request_id: u16, content: &[u8], output: &mut Vec<u8>) {
+    for chunk in content.chunks(MAX_CONTENT_LENGTH) {
+        output.append(
+            &mut Record {
+                content: chunk.to_vec(),
+                record_type,
+                request_id,
+            }
+            .to_bytes(),
+        );
+    }
+
+    // Streams are terminated by an empty record
+    output.append(
+        &mut Record {
+            content: Vec::new(),
+            record_type,
+            request_id,
+        }
+        .to_bytes(),
+    );
+}
+
+/// # Encode a responder request from CGI parameters and a body
+/// ## Usage
+/// ```rust
+/// use milstian_http::cgi::{to_environment, ServerInfo};
+/// use milstian_http::fastcgi::{encode_request, Record, RecordType};
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET /index.php HTTP/1.1\r\n\r\n").expect("A request");
+/// let params = to_environment(&request, &ServerInfo::default());
+/// let bytes = encode_request(1, &params, b"", false);
+/// let (record, _) = Record::from_bytes(&bytes).expect("A record");
+/// assert_eq!(record.record_type, RecordType::BeginRequest);
+/// ```
+pub fn encode_request(
+    request_id: u16,
+    params: &HashMap<String, String>,
+    body: &[u8],
+    keep_connection: bool,
+) -> Vec<u8> {
+    let flags = if keep_connection { FLAG_KEEP_CONN } else { 0 };
+    let mut output = Record {
+        content: vec![
+            (ROLE_RESPONDER >> 8) as u8,
+            ROLE_RESPONDER as u8,
+            flags,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ],
+        record_type: RecordType::BeginRequest,
+        request_id,
+    }
+    .to_bytes();
+    push_stream(
+        RecordType::Params,
+        request_id,
+        &encode_params(params),
+        &mut output,
+    );
+    push_stream(RecordType::Stdin, request_id, body, &mut output);
+    output
+}
+
+/// # The collected output of a FastCGI application for one request
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Response {
+    pub app_status: u32,
+    pub protocol_status: u8,
+    pub stderr: Vec<u8>,
+    pub stdout: Vec<u8>,
+}
+
+impl Response {
+    /// # Convert the standard output of the application into a HTTP response
+    pub fn to_message(&self, protocol: &str) -> Option<response::Message> {
+        cgi::from_output(&self.stdout, protocol)
+    }
+}
+
+/// # Decode records for a request, returns None until END_REQUEST has been received
+/// ## Usage
+/// ```rust
+/// use milstian_http::fastcgi::{decode_response, Record, RecordType};
+/// let mut bytes = Record {
+///     content: b"Content-Type: text/plain\r\n\r\nHello".to_vec(),
+///     record_type: RecordType::Stdout,
+///     request_id: 1,
+/// }.to_bytes();
+/// assert!(decode_response(&bytes, 1).is_none());
+/// bytes.append(&mut Record {
+///     content: vec![0, 0, 0, 0, 0, 0, 0, 0],
+///     record_type: RecordType::EndRequest,
+///     request_id: 1,
+/// }.to_bytes());
+/// let response = decode_response(&bytes, 1).expect("A response");
+/// let message = response.to_message("HTTP/1.1").expect("A message");
+/// assert_eq!(message.body, b"Hello".to_vec());
+/// ```
+pub fn decode_response(bytes: &[u8], request_id: u16) -> Option<Response> {
+    let mut response = Response::default();
+    let mut position = 0;
+    while let Some((record, consumed)) = Record::from_bytes(&bytes[position..]) {
+        position += consumed;
+        if record.request_id != request_id {
+            continue;
+        }
+        match record.record_type {
+            RecordType::Stdout => response.stdout.extend_from_slice(&record.content),
+            RecordType::Stderr => response.stderr.extend_from_slice(&record.content),
+            RecordType::EndRequest => {
+                if record.content.len() >= 5 {
+                    response.app_status = (u32::from(record.content[0]) << 24)
+                        | (u32::from(record.content[1]) << 16)
+                        | (u32::from(record.content[2]) << 8)
+                        | u32::from(record.content[3]);
+                    response.protocol_status = record.content[4];
+                }
+                return Some(response);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_round_trip() {
+        for length in &[0, 1, 7, 8, 9, 300] {
+            let record = Record {
+                content: vec![42; *length],
+                record_type: RecordType::Params,
+                request_id: 258,
+            };
+            let bytes = record.to_bytes();
+            assert_eq!(bytes.len() % 8, 0);
+            let (decoded, consumed) = Record::from_bytes(&bytes).expect("A record");
+            assert_eq!(decoded, record);
+            assert_eq!(consumed, bytes.len());
+        }
+        assert!(Record::from_bytes(&[2, 6, 0, 1, 0, 0, 0, 0]).is_none());
+
+        // Content a record can't hold is split instead of truncated
+        let record = Record {
+            content: vec![42; MAX_CONTENT_LENGTH + 2],
+            record_type: RecordType::Stdout,
+            request_id: 1,
+        };
+        let bytes = record.to_bytes();
+        let (first, consumed) = Record::from_bytes(&bytes).expect("A record");
+        assert_eq!(first.content.len(), MAX_CONTENT_LENGTH);
+        let (second, rest) = Record::from_bytes(&bytes[consumed..]).expect("A record");
+        assert_eq!(second.content, vec![42; 2]);
+        assert_eq!(consumed + rest, bytes.len());
+    }
+
+    #[test]
+    fn test_params() {
+        let mut params = HashMap::new();
+        params.insert("A".to_string(), "x".repeat(200));
+        params.insert("B".to_string(), String::new());
+        let encoded = encode_params(&params);
+        assert_eq!(encoded[0..5].to_vec(), vec![1, 0x80, 0, 0, 200]);
+        assert_eq!(decode_params(&encoded), Some(params));
+        assert!(decode_params(&[5, 5, b'a']).is_none());
+        assert!(decode_params(&[0x80]).is_none());
+    }
+
+    #[test]
+    fn test_encode_request() {
+        let mut params = HashMap::new();
+        params.insert("REQUEST_METHOD".to_string(), "POST".to_string());
+        let body = vec![1; MAX_CONTENT_LENGTH + 1];
+        let bytes = encode_request(7, &params, &body, true);
+
+        let mut records = Vec::new();
+        let mut position = 0;
+        while let Some((record, consumed)) = Record::from_bytes(&bytes[position..]) {
+            position += consumed;
+            records.push(record);
+        }
+        assert_eq!(position, bytes.len());
+        let types: Vec<RecordType> = records.iter().map(|record| record.record_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                RecordType::BeginRequest,
+                RecordType::Params,
+                RecordType::Params,
+                RecordType::Stdin,
+                RecordType::Stdin,
+                RecordType::Stdin
+            ]
+        );
+        assert_eq!(records[0].content, vec![0, 1, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(decode_params(&records[1].content), Some(params));
+        assert!(records[2].content.is_empty());
+        assert_eq!(records[3].content.len(), MAX_CONTENT_LENGTH);
+        assert_eq!(records[4].content.len(), 1);
+        assert!(records[5].content.is_empty());
+        assert!(records.iter().all(|record| record.request_id == 7));
+    }
+
+    #[test]
+    fn test_decode_response() {
+        let mut bytes = Record {
+            content: b"Status: 500 Internal Server Error\r\n\r\n".to_vec(),
+            record_type: RecordType::Stdout,
+            request_id: 2,
+        }
+        .to_bytes();
+        bytes.append(
+            &mut Record {
+                content: b"other request".to_vec(),
+                record_type: RecordType::Stdout,
+                request_id: 3,
+            }
+            .to_bytes(),
+        );
+        bytes.append(
+            &mut Record {
+                content: b"warning".to_vec(),
+                record_type: RecordType::Stderr,
+                request_id: 2,
+            }
+            .to_bytes(),
+        );
+        bytes.append(
+            &mut Record {
+                content: vec![0, 0, 0, 1, 0, 0, 0, 0],
+                record_type: RecordType::EndRequest,
+                request_id: 2,
+            }
+            .to_bytes(),
+        );
+        let response = decode_response(&bytes, 2).expect("A response");
+        assert_eq!(response.app_status, 1);
+        assert_eq!(response.protocol_status, 0);
+        assert_eq!(response.stderr, b"warning".to_vec());
+        assert_eq!(
+            response.to_message("HTTP/1.1").expect("A message").status,
+            "500 Internal Server Error".to_string()
+        );
+    }
+}
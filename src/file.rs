@@ -0,0 +1,273 @@
+//! # Serves files from disk with content coding negotiation.
+//!
+//! Precompressed siblings like `app.js.br` and `app.js.gz` are served when the Accept-Encoding
+//! header of the request permits them, otherwise a configured compressor or the identity coding
+//! is used.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use negotiation;
+use request;
+use response;
+
+/// # Precompressed sibling extensions by content coding, in server preference
+pub const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// # Compresses bodies on the fly with a content coding
+#[derive(Clone, Copy, Debug)]
+pub struct Compressor {
+    pub encoding: &'static str,
+    pub compress: fn(&[u8]) -> io::Result<Vec<u8>>,
+}
+
+/// # Options for serving files
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub compressors: Vec<Compressor>, // Used when no precompressed sibling is acceptable
+    pub min_compress_size: u64,       // Smaller files are not compressed on the fly
+    pub precompressed: bool,          // Look for .br and .gz siblings
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            compressors: Vec::new(),
+            min_compress_size: 1024,
+            precompressed: true,
+        }
+    }
+}
+
+/// # Guess the media type of a file from its extension
+/// ## Usage
+/// ```rust
+/// use milstian_http::file::get_media_type;
+/// use std::path::Path;
+/// assert_eq!(get_media_type(Path::new("index.HTML")), "text/html; charset=utf-8");
+/// assert_eq!(get_media_type(Path::new("archive")), "application/octet-stream");
+/// ```
+pub fn get_media_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("css") => "text/css; charset=utf-8",
+        Some("gif") => "image/gif",
+        Some("htm") | Some("html") => "text/html; charset=utf-8",
+        Some("ico") => "image/x-icon",
+        Some("jpeg") | Some("jpg") => "image/jpeg",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("wasm") => "application/wasm",
+        Some("webp") => "image/webp",
+        Some("woff2") => "font/woff2",
+        Some("xml") => "application/xml",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Get the path of a precompressed sibling like app.js.br
+fn get_sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(extension);
+    PathBuf::from(sibling)
+}
+
+/// # Build a response with the file at path in the best acceptable content coding
+/// The Content-Type is that of the original file and Vary includes Accept-Encoding.
+/// ## Usage
+/// ```rust
+/// use milstian_http::file::{serve, Options};
+/// use milstian_http::request::Message;
+/// use std::fs;
+/// let directory = std::env::temp_dir().join("milstian-file-doc");
+/// fs::create_dir_all(&directory).expect("A directory");
+/// fs::write(directory.join("app.js"), b"let a = 1;").expect("A file");
+/// fs::write(directory.join("app.js.gz"), b"compressed").expect("A file");
+/// let request = Message::from_tcp_stream(b"GET /app.js HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n")
+///     .expect("A request");
+/// let response = serve(&request, &directory.join("app.js"), &Options::default())
+///     .expect("A response");
+/// assert_eq!(response.headers.get("Content-Encoding"), Some(&"gzip".to_string()));
+/// assert_eq!(
+///     response.headers.get("Content-Type"),
+///     Some(&"text/javascript; charset=utf-8".to_string())
+/// );
+/// assert_eq!(response.body, b"compressed".to_vec());
+/// ```
+pub fn serve(
+    request: &request::Message,
+    path: &Path,
+    options: &Options,
+) -> io::Result<response::Message> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "not a file"));
+    }
+
+    // Available codings in server preference, identity last
+    let mut available: Vec<&str> = Vec::new();
+    if options.precompressed {
+        for (encoding, extension) in PRECOMPRESSED.iter() {
+            if get_sibling(path, extension).is_file() {
+                available.push(encoding);
+            }
+        }
+    }
+    if metadata.len() >= options.min_compress_size {
+        for compressor in options.compressors.iter() {
+            if !available.contains(&compressor.encoding) {
+                available.push(compressor.encoding);
+            }
+        }
+    }
+    available.push("identity");
+
+    let mut response = response::Message::with_status(200);
+    let encoding = if request.header("Accept-Encoding").is_some() {
+        negotiation::negotiate_encoding(request, &mut response, &available).unwrap_or("identity")
+    } else {
+        response.add_vary("Accept-Encoding");
+        "identity"
+    };
+
+    let body = if encoding == "identity" {
+        fs::read(path)?
+    } else {
+        let precompressed = PRECOMPRESSED
+            .iter()
+            .find(|(precompressed, _)| options.precompressed && *precompressed == encoding)
+            .map(|(_, extension)| get_sibling(path, extension))
+            .filter(|sibling| sibling.is_file());
+        match precompressed {
+            Some(sibling) => fs::read(sibling)?,
+            None => {
+                let compressor = options
+                    .compressors
+                    .iter()
+                    .find(|compressor| compressor.encoding == encoding)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no compressor"))?;
+                (compressor.compress)(&fs::read(path)?)?
+            }
+        }
+    };
+
+    if encoding != "identity" {
+        response
+            .headers
+            .insert("Content-Encoding".to_string(), encoding.to_string());
+    }
+    response
+        .headers
+        .insert("Content-Type".to_string(), get_media_type(path).to_string());
+    response.set_content_length(body.len());
+    if request.request_line.method != request::Method::Head {
+        response.body = body;
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn reverse(body: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(body.iter().rev().cloned().collect())
+    }
+
+    fn request(accept_encoding: &str) -> request::Message {
+        request::Message::from_tcp_stream(
+            format!(
+                "GET / HTTP/1.1\r\nAccept-Encoding: {}\r\n\r\n",
+                accept_encoding
+            )
+            .as_bytes(),
+        )
+        .expect("A request")
+    }
+
+    #[test]
+    fn test_serve() {
+        let directory = env::temp_dir().join("milstian-file-test");
+        fs::create_dir_all(&directory).expect("A directory");
+        let path = directory.join("style.css");
+        fs::write(&path, b"abc").expect("A file");
+        fs::write(get_sibling(&path, "br"), b"br").expect("A file");
+        let options = Options {
+            compressors: vec![Compressor {
+                encoding: "reverse",
+                compress: reverse,
+            }],
+            min_compress_size: 0,
+            precompressed: true,
+        };
+
+        let response = serve(&request("gzip, br;q=0.5"), &path, &options).expect("A response");
+        assert_eq!(response.body, b"br".to_vec());
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some(&"text/css; charset=utf-8".to_string())
+        );
+        assert_eq!(
+            response.headers.get("Vary"),
+            Some(&"Accept-Encoding".to_string())
+        );
+        assert_eq!(
+            response.headers.get("Content-Length"),
+            Some(&"2".to_string())
+        );
+
+        // On the fly compression when no sibling is acceptable
+        let response = serve(&request("reverse"), &path, &options).expect("A response");
+        assert_eq!(response.body, b"cba".to_vec());
+        assert_eq!(
+            response.headers.get("Content-Encoding"),
+            Some(&"reverse".to_string())
+        );
+
+        // Identity when nothing else is acceptable or the header is missing
+        let response = serve(&request("gzip"), &path, &options).expect("A response");
+        assert_eq!(response.body, b"abc".to_vec());
+        assert_eq!(response.headers.get("Content-Encoding"), None);
+        let response = serve(
+            &request::Message::from_tcp_stream(b"HEAD / HTTP/1.1\r\n\r\n").expect("A request"),
+            &path,
+            &options,
+        )
+        .expect("A response");
+        assert!(response.body.is_empty());
+        assert_eq!(
+            response.headers.get("Content-Length"),
+            Some(&"3".to_string())
+        );
+        assert_eq!(
+            response.headers.get("Vary"),
+            Some(&"Accept-Encoding".to_string())
+        );
+
+        // Small files and disabled siblings
+        let options = Options {
+            min_compress_size: 4,
+            precompressed: false,
+            ..options
+        };
+        let response = serve(&request("br, reverse"), &path, &options).expect("A response");
+        assert_eq!(response.body, b"abc".to_vec());
+
+        assert_eq!(
+            serve(&request("br"), &directory, &options)
+                .expect_err("An error")
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,613 @@
+//! # Reports how raw request bytes are framed, for debugging clients.
+//!
+//! The analysis finds the spans of the request line, header fields and body without building a
+//! `request::Message`, and notes anything other parsers might read differently, like bare line
+//! feeds or conflicting Content-Length fields. Comparing strict and lenient parses of the same
+//! bytes shows where the parser itself depends on how forgiving it is configured to be.
+
+use std::fmt;
+use std::ops::Range;
+
+use record::Summary;
+use request::{Message, ParseError, ParserConfig};
+
+/// # How the length of the message body is determined (RFC 9112 section 6.3)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BodyFraming {
+    Chunked,              // Transfer-Encoding with chunked as the final coding
+    ContentLength(usize), // A valid Content-Length without Transfer-Encoding
+    Empty,                // Neither field, so a request has no body
+    Unframed,             // Transfer-Encoding without chunked last, the length is unknown
+}
+
+impl fmt::Display for BodyFraming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BodyFraming::Chunked => write!(f, "chunked"),
+            BodyFraming::ContentLength(length) => write!(f, "content-length {}", length),
+            BodyFraming::Empty => write!(f, "empty"),
+            BodyFraming::Unframed => write!(f, "unframed"),
+        }
+    }
+}
+
+/// # Something in the framing that parsers may interpret differently, at a byte offset
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Anomaly {
+    BareLineFeed(usize),          // A line ending without carriage return
+    ConflictingContentLength,     // Content-Length fields with different values
+    ContentLengthWithChunked,     // Both Content-Length and chunked Transfer-Encoding
+    IncompleteBody(usize),        // Bytes missing from the end of the body
+    IncompleteHeader,             // No empty line ends the header block
+    InvalidChunk(usize),          // A chunk size line that can't be read
+    InvalidContentLength,         // A Content-Length that is not a number
+    ObsoleteLineFolding(usize),   // A header line continuing the previous one
+    WhitespaceBeforeColon(usize), // Like "Host : a", forbidden since smuggling relies on it
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anomaly::BareLineFeed(offset) => write!(f, "bare line feed at {}", offset),
+            Anomaly::ConflictingContentLength => write!(f, "conflicting Content-Length"),
+            Anomaly::ContentLengthWithChunked => {
+                write!(f, "Content-Length with chunked Transfer-Encoding")
+            }
+            Anomaly::IncompleteBody(missing) => write!(f, "body misses {} bytes", missing),
+            Anomaly::IncompleteHeader => write!(f, "header block does not end"),
+            Anomaly::InvalidChunk(offset) => write!(f, "invalid chunk at {}", offset),
+            Anomaly::InvalidContentLength => write!(f, "invalid Content-Length"),
+            Anomaly::ObsoleteLineFolding(offset) => {
+                write!(f, "obsolete line folding at {}", offset)
+            }
+            Anomaly::WhitespaceBeforeColon(offset) => {
+                write!(f, "whitespace before colon at {}", offset)
+            }
+        }
+    }
+}
+
+/// # A header field line and its parts
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub span: Range<usize>, // The line without its ending, with any folded lines
+    pub value: String,      // Trimmed, folded lines are joined by a space
+}
+
+/// # The framing detected in raw request bytes
+/// ## Usage
+/// ```rust
+/// use milstian_http::framing::{analyze, BodyFraming};
+/// let report = analyze(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\n\r\nabcGET");
+/// assert_eq!(report.request_line, Some(0..15));
+/// assert_eq!(report.fields[0].name, "Host".to_string());
+/// assert_eq!(report.fields[0].span, 17..24);
+/// assert_eq!(report.framing, Some(BodyFraming::ContentLength(3)));
+/// assert_eq!(report.body, Some(47..50));
+/// assert_eq!(report.leftover, 50..53);
+/// assert!(report.anomalies.is_empty());
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Report {
+    pub anomalies: Vec<Anomaly>,
+    pub body: Option<Range<usize>>, // The body as framed, with chunk framing and trailers
+    pub chunks: Vec<Range<usize>>,  // Data of each chunk of a chunked body
+    pub fields: Vec<Field>,
+    pub framing: Option<BodyFraming>, // None until the header block ends
+    pub header_end: Option<usize>,    // After the empty line that ends the header block
+    pub leftover: Range<usize>,       // Bytes after the message, like a pipelined request
+    pub request_line: Option<Range<usize>>, // The first line without its ending
+}
+
+impl fmt::Display for Report {
+    /// # Describe the report on one line per part
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.request_line {
+            Some(ref span) => writeln!(f, "request line {:?}", span)?,
+            None => writeln!(f, "request line incomplete")?,
+        }
+        for field in self.fields.iter() {
+            writeln!(f, "field {} {:?}", field.name, field.span)?;
+        }
+        match (&self.framing, &self.body) {
+            (Some(framing), Some(body)) => writeln!(f, "body {} {:?}", framing, body)?,
+            (Some(framing), None) => writeln!(f, "body {}", framing)?,
+            (None, _) => writeln!(f, "body unknown")?,
+        }
+        for anomaly in self.anomalies.iter() {
+            writeln!(f, "anomaly {}", anomaly)?;
+        }
+        write!(f, "leftover {:?}", self.leftover)
+    }
+}
+
+/// Find the next line from start, returns the line end and the start of the next line
+fn next_line(bytes: &[u8], start: usize) -> Option<(usize, usize)> {
+    let newline = start + bytes.get(start..)?.iter().position(|byte| *byte == b'\n')?;
+    if newline > start && bytes[newline - 1] == b'\r' {
+        Some((newline - 1, newline + 1))
+    } else {
+        Some((newline, newline + 1))
+    }
+}
+
+/// # Analyze the framing of a request at the start of bytes
+/// Lines may end with a bare line feed like many parsers accept, which is noted as an anomaly.
+/// ## Usage
+/// ```rust
+/// use milstian_http::framing::{analyze, Anomaly, BodyFraming};
+/// let report = analyze(
+///     b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 4\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+/// );
+/// assert_eq!(report.framing, Some(BodyFraming::Chunked));
+/// assert_eq!(report.chunks, vec![69..72]);
+/// assert_eq!(report.anomalies, vec![Anomaly::ContentLengthWithChunked]);
+/// assert!(report.leftover.is_empty());
+/// ```
+pub fn analyze(bytes: &[u8]) -> Report {
+    let mut report = Report {
+        leftover: bytes.len()..bytes.len(),
+        ..Report::default()
+    };
+    let mut position = match next_line(bytes, 0) {
+        Some((line_end, next)) => {
+            if line_end + 1 == next {
+                report.anomalies.push(Anomaly::BareLineFeed(line_end));
+            }
+            report.request_line = Some(0..line_end);
+            next
+        }
+        None => {
+            report.anomalies.push(Anomaly::IncompleteHeader);
+            return report;
+        }
+    };
+
+    // Header fields until the empty line
+    loop {
+        let (line_end, next) = match next_line(bytes, position) {
+            Some(line) => line,
+            None => {
+                report.anomalies.push(Anomaly::IncompleteHeader);
+                return report;
+            }
+        };
+        if line_end + 1 == next {
+            report.anomalies.push(Anomaly::BareLineFeed(line_end));
+        }
+        if line_end == position {
+            position = next;
+            break;
+        }
+        let line = String::from_utf8_lossy(&bytes[position..line_end]).into_owned();
+        if line.starts_with(' ') || line.starts_with('\t') {
+            report
+                .anomalies
+                .push(Anomaly::ObsoleteLineFolding(position));
+            if let Some(field) = report.fields.last_mut() {
+                field.span.end = line_end;
+                field.value = format!("{} {}", field.value, line.trim());
+            }
+        } else {
+            let (name, value) = line.split_once(':').unwrap_or((&line, ""));
+            if name.ends_with(' ') || name.ends_with('\t') {
+                report.anomalies.push(Anomaly::WhitespaceBeforeColon(
+                    position + name.trim_end().len(),
+                ));
+            }
+            report.fields.push(Field {
+                name: name.trim().to_string(),
+                span: position..line_end,
+                value: value.trim().to_string(),
+            });
+        }
+        position = next;
+    }
+    report.header_end = Some(position);
+
+    // Transfer-Encoding overrides Content-Length
+    let codings: Vec<String> = report
+        .fields
+        .iter()
+        .filter(|field| field.name.eq_ignore_ascii_case("Transfer-Encoding"))
+        .flat_map(|field| field.value.split(','))
+        .map(|coding| coding.trim().to_lowercase())
+        .filter(|coding| !coding.is_empty())
+        .collect();
+    let mut content_length: Option<usize> = None;
+    let mut has_content_length = false;
+    for field in report.fields.iter() {
+        if !field.name.eq_ignore_ascii_case("Content-Length") {
+            continue;
+        }
+        has_content_length = true;
+        for value in field.value.split(',').map(str::trim) {
+            match value.parse::<usize>() {
+                Ok(length) if value.bytes().all(|byte| byte.is_ascii_digit()) => {
+                    if content_length.is_some_and(|existing| existing != length)
+                        && !report
+                            .anomalies
+                            .contains(&Anomaly::ConflictingContentLength)
+                    {
+                        report.anomalies.push(Anomaly::ConflictingContentLength);
+                    }
+                    content_length.get_or_insert(length);
+                }
+                _ => {
+                    if !report.anomalies.contains(&Anomaly::InvalidContentLength) {
+                        report.anomalies.push(Anomaly::InvalidContentLength);
+                    }
+                }
+            }
+        }
+    }
+
+    let framing = if !codings.is_empty() {
+        if has_content_length {
+            report.anomalies.push(Anomaly::ContentLengthWithChunked);
+        }
+        if codings.last().map(String::as_str) == Some("chunked") {
+            BodyFraming::Chunked
+        } else {
+            BodyFraming::Unframed
+        }
+    } else {
+        match content_length {
+            Some(length) => BodyFraming::ContentLength(length),
+            None => BodyFraming::Empty,
+        }
+    };
+
+    let body_end = match framing {
+        BodyFraming::Chunked => analyze_chunks(bytes, position, &mut report),
+        BodyFraming::ContentLength(length) => {
+            let end = position.saturating_add(length);
+            if end > bytes.len() {
+                report
+                    .anomalies
+                    .push(Anomaly::IncompleteBody(end - bytes.len()));
+            }
+            end.min(bytes.len())
+        }
+        BodyFraming::Empty => position,
+        BodyFraming::Unframed => bytes.len(),
+    };
+    if framing != BodyFraming::Empty {
+        report.body = Some(position..body_end);
+    }
+    report.framing = Some(framing);
+    report.leftover = body_end..bytes.len();
+    report
+}
+
+/// Record the data of each chunk from start, returns where the chunked body ends
+fn analyze_chunks(bytes: &[u8], start: usize, report: &mut Report) -> usize {
+    let mut position = start;
+    loop {
+        let (line_end, next) = match next_line(bytes, position) {
+            Some(line) => line,
+            None => {
+                report.anomalies.push(Anomaly::IncompleteBody(1));
+                return bytes.len();
+            }
+        };
+        let size_line = String::from_utf8_lossy(&bytes[position..line_end]);
+        let size_text = size_line.split(';').next().unwrap_or("").trim();
+        let size = match usize::from_str_radix(size_text, 16) {
+            Ok(size) if size_text.len() <= 15 => size,
+            _ => {
+                report.anomalies.push(Anomaly::InvalidChunk(position));
+                return bytes.len();
+            }
+        };
+        if size == 0 {
+            // Trailer fields until the empty line
+            position = next;
+            loop {
+                match next_line(bytes, position) {
+                    Some((line_end, next)) => {
+                        let is_empty = line_end == position;
+                        position = next;
+                        if is_empty {
+                            return next;
+                        }
+                    }
+                    None => {
+                        report.anomalies.push(Anomaly::IncompleteBody(2));
+                        return bytes.len();
+                    }
+                }
+            }
+        }
+        let data_end = next.saturating_add(size);
+        if data_end > bytes.len() {
+            report.chunks.push(next..bytes.len());
+            report
+                .anomalies
+                .push(Anomaly::IncompleteBody(data_end + 2 - bytes.len()));
+            return bytes.len();
+        }
+        report.chunks.push(next..data_end);
+        if bytes.get(data_end..data_end + 2) != Some(&b"\r\n"[..]) {
+            report.anomalies.push(Anomaly::InvalidChunk(data_end));
+            return bytes.len();
+        }
+        position = data_end + 2;
+    }
+}
+
+/// # A difference between how strict and lenient parsing read the same request
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Divergence {
+    Acceptance {
+        lenient: Option<ParseError>,
+        strict: Option<ParseError>,
+    },
+    BodyFraming {
+        framed: usize, // Bytes of the body by the framing rules
+        parsed: usize, // Bytes of the body by the lenient parser
+    },
+    BodyLength {
+        lenient: usize,
+        strict: usize,
+    },
+    FieldValue {
+        lenient: String,
+        name: String,
+        strict: String,
+    },
+    Fields {
+        lenient_only: Vec<String>, // Names of fields only lenient parsing found
+        strict_only: Vec<String>,
+    },
+    RequestLine {
+        lenient: String,
+        strict: String,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |error: &Option<ParseError>| match error {
+            Some(error) => format!("rejects with {}", error),
+            None => String::from("accepts"),
+        };
+        match self {
+            Divergence::Acceptance { lenient, strict } => write!(
+                f,
+                "lenient parsing {}, strict parsing {}",
+                describe(lenient),
+                describe(strict)
+            ),
+            Divergence::BodyFraming { framed, parsed } => write!(
+                f,
+                "body is {} bytes by framing but {} bytes parsed",
+                framed, parsed
+            ),
+            Divergence::BodyLength { lenient, strict } => write!(
+                f,
+                "body is {} bytes lenient but {} bytes strict",
+                lenient, strict
+            ),
+            Divergence::FieldValue {
+                lenient,
+                name,
+                strict,
+            } => write!(
+                f,
+                "field {} is {:?} lenient but {:?} strict",
+                name, lenient, strict
+            ),
+            Divergence::Fields {
+                lenient_only,
+                strict_only,
+            } => write!(
+                f,
+                "fields only lenient [{}], only strict [{}]",
+                lenient_only.join(", "),
+                strict_only.join(", ")
+            ),
+            Divergence::RequestLine { lenient, strict } => write!(
+                f,
+                "request line is {:?} lenient but {:?} strict",
+                lenient, strict
+            ),
+        }
+    }
+}
+
+/// # Parse bytes strictly and leniently and report where they differ
+/// Requests that parsers read differently can be used to smuggle requests past intermediaries.
+/// The limits of config apply to both parses, and the body the lenient parser reads is compared
+/// with the body by the framing rules of `analyze`.
+/// ## Usage
+/// ```rust
+/// use milstian_http::framing::{compare, Divergence};
+/// use milstian_http::request::{ParseError, ParserConfig};
+/// let config = ParserConfig::default();
+/// assert!(compare(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n", &config).is_empty());
+/// assert_eq!(
+///     compare(b"GET / HTTP/1.1\r\nHost : a\r\n\r\n", &config),
+///     vec![Divergence::Acceptance {
+///         lenient: None,
+///         strict: Some(ParseError::InvalidHeader),
+///     }]
+/// );
+/// assert_eq!(
+///     compare(b"POST / HTTP/1.1\r\n\r\nab", &config),
+///     vec![Divergence::BodyFraming { framed: 0, parsed: 2 }]
+/// );
+/// ```
+pub fn compare(bytes: &[u8], config: &ParserConfig) -> Vec<Divergence> {
+    let mut lenient_config = config.clone();
+    lenient_config.strict = false;
+    let mut strict_config = config.clone();
+    strict_config.strict = true;
+    let (lenient, strict) = match (
+        Message::from_tcp_stream_with_config(bytes, &lenient_config),
+        Message::from_tcp_stream_with_config(bytes, &strict_config),
+    ) {
+        (Ok(lenient), Ok(strict)) => (Summary::from(&lenient), Summary::from(&strict)),
+        (Err(lenient), Err(strict)) if lenient == strict => return Vec::new(),
+        (lenient, strict) => {
+            return vec![Divergence::Acceptance {
+                lenient: lenient.err(),
+                strict: strict.err(),
+            }]
+        }
+    };
+
+    let mut divergences: Vec<Divergence> = Vec::new();
+    if lenient.request_line != strict.request_line {
+        divergences.push(Divergence::RequestLine {
+            lenient: lenient.request_line.clone(),
+            strict: strict.request_line.clone(),
+        });
+    }
+    let find = |fields: &[(String, String)], name: &str| {
+        fields
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    };
+    let only = |fields: &[(String, String)], others: &[(String, String)]| {
+        fields
+            .iter()
+            .filter(|(name, _)| find(others, name).is_none())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<String>>()
+    };
+    let lenient_only = only(&lenient.fields, &strict.fields);
+    let strict_only = only(&strict.fields, &lenient.fields);
+    if !lenient_only.is_empty() || !strict_only.is_empty() {
+        divergences.push(Divergence::Fields {
+            lenient_only,
+            strict_only,
+        });
+    }
+    for (name, value) in lenient.fields.iter() {
+        if let Some(strict_value) = find(&strict.fields, name) {
+            if *value != strict_value {
+                divergences.push(Divergence::FieldValue {
+                    lenient: value.clone(),
+                    name: name.clone(),
+                    strict: strict_value,
+                });
+            }
+        }
+    }
+    if lenient.wire_size.body != strict.wire_size.body {
+        divergences.push(Divergence::BodyLength {
+            lenient: lenient.wire_size.body,
+            strict: strict.wire_size.body,
+        });
+    }
+    let report = analyze(bytes);
+    if report.framing != Some(BodyFraming::Unframed) {
+        let framed = report.body.map_or(0, |body| body.len());
+        if framed != lenient.wire_size.body {
+            divergences.push(Divergence::BodyFraming {
+                framed,
+                parsed: lenient.wire_size.body,
+            });
+        }
+    }
+    divergences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        let report = analyze(b"GET / HTTP/1.1\nHost : a\r\n folded\r\n\r\nGET");
+        assert_eq!(report.request_line, Some(0..14));
+        assert_eq!(
+            report.fields,
+            vec![Field {
+                name: "Host".to_string(),
+                span: 15..32,
+                value: "a folded".to_string(),
+            }]
+        );
+        assert_eq!(
+            report.anomalies,
+            vec![
+                Anomaly::BareLineFeed(14),
+                Anomaly::WhitespaceBeforeColon(19),
+                Anomaly::ObsoleteLineFolding(25),
+            ]
+        );
+        assert_eq!(report.framing, Some(BodyFraming::Empty));
+        assert_eq!(report.body, None);
+        assert_eq!(report.leftover, 36..39);
+
+        let report =
+            analyze(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6, x\r\n\r\nab");
+        assert_eq!(report.framing, Some(BodyFraming::ContentLength(5)));
+        assert_eq!(
+            report.anomalies,
+            vec![
+                Anomaly::ConflictingContentLength,
+                Anomaly::InvalidContentLength,
+                Anomaly::IncompleteBody(3),
+            ]
+        );
+        assert!(report.leftover.is_empty());
+
+        let report = analyze(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\nabc");
+        assert_eq!(report.framing, Some(BodyFraming::Unframed));
+        assert_eq!(report.body, Some(44..47));
+
+        let report = analyze(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2;a=b\r\nab\r\n0\r\nX: y\r\n\r\nGET",
+        );
+        assert_eq!(report.chunks, vec![54..56]);
+        assert_eq!(report.body, Some(47..69));
+        assert_eq!(report.leftover, 69..72);
+        assert!(report.anomalies.is_empty());
+
+        let report = analyze(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nz\r\n");
+        assert_eq!(report.anomalies, vec![Anomaly::InvalidChunk(47)]);
+
+        let report = analyze(b"GET / HTTP/1.1\r\nHost: a\r\n");
+        assert_eq!(report.framing, None);
+        assert_eq!(report.header_end, None);
+        assert_eq!(report.anomalies, vec![Anomaly::IncompleteHeader]);
+        assert!(report.to_string().contains("body unknown"));
+    }
+
+    #[test]
+    fn test_compare() {
+        let config = ParserConfig::default();
+        let divergences = compare(
+            b"GET  /a  HTTP/1.1\r\nGET /b HTTP/1.1\r\nHost: a\r\n\r\n",
+            &config,
+        );
+        assert_eq!(
+            divergences,
+            vec![Divergence::RequestLine {
+                lenient: "GET /a HTTP/1.1".to_string(),
+                strict: "GET /b HTTP/1.1".to_string(),
+            }]
+        );
+        assert_eq!(
+            divergences[0].to_string(),
+            "request line is \"GET /a HTTP/1.1\" lenient but \"GET /b HTTP/1.1\" strict"
+                .to_string()
+        );
+
+        // Both reject the same way
+        let limited = ParserConfig {
+            max_header_size: 4,
+            ..ParserConfig::default()
+        };
+        assert!(compare(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n", &limited).is_empty());
+
+        // Chunked bodies are framed with their chunk lines
+        let request = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\n0\r\n\r\n";
+        assert!(compare(request, &config).is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+//! # An insertion-ordered container of response header fields.
+//!
+//! Fields are serialized in the order they were added, after the Date and Server fields, so
+//! responses can match the layout of other servers. Sorting by name is available on request.
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::slice;
+use std::vec;
+
+/// # Header fields written before all others
+pub const PRIORITY_HEADERS: [&str; 2] = ["Date", "Server"];
+
+/// # Response header fields that hold a single value and can't be combined into a list
+pub const SINGLETON_HEADERS: [&str; 13] = [
+    "Access-Control-Allow-Origin",
+    "Age",
+    "Content-Length",
+    "Content-Location",
+    "Content-Range",
+    "Content-Type",
+    "Date",
+    "ETag",
+    "Expires",
+    "Last-Modified",
+    "Location",
+    "Retry-After",
+    "Server",
+];
+
+/// # Does the field hold a single value, case-insensitive?
+/// ## Usage
+/// ```rust
+/// use milstian_http::headers::is_singleton;
+/// assert!(is_singleton("content-type"));
+/// assert!(!is_singleton("Vary"));
+/// ```
+pub fn is_singleton(name: &str) -> bool {
+    SINGLETON_HEADERS
+        .iter()
+        .any(|singleton| singleton.eq_ignore_ascii_case(name))
+}
+
+/// # Header fields in insertion order with case-sensitive names
+/// Replacing the value of a field keeps its position.
+/// ## Usage
+/// ```rust
+/// use milstian_http::headers::Headers;
+/// let mut headers = Headers::new();
+/// headers.insert("X-B".to_string(), "1".to_string());
+/// headers.insert("X-A".to_string(), "2".to_string());
+/// headers.insert("X-B".to_string(), "3".to_string());
+/// assert_eq!(
+///     headers.iter().collect::<Vec<(&String, &String)>>(),
+///     vec![
+///         (&"X-B".to_string(), &"3".to_string()),
+///         (&"X-A".to_string(), &"2".to_string())
+///     ]
+/// );
+/// headers.sort();
+/// assert_eq!(headers.keys().collect::<Vec<&String>>(), vec!["X-A", "X-B"]);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// # Create an empty container
+    pub fn new() -> Headers {
+        Headers::default()
+    }
+
+    /// # Get the value of a field
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// # Get the mutable value of a field
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut String> {
+        self.fields
+            .iter_mut()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// # Is there a field called name?
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.fields.iter().any(|(key, _)| key == name)
+    }
+
+    /// # Set a field, returns the value it replaced
+    /// New fields are appended, replaced fields keep their position.
+    pub fn insert(&mut self, name: String, value: String) -> Option<String> {
+        match self.get_mut(&name) {
+            Some(existing) => Some(std::mem::replace(existing, value)),
+            None => {
+                self.fields.push((name, value));
+                None
+            }
+        }
+    }
+
+    /// # Add a field after any others of the same name
+    /// For fields like Set-Cookie whose values can't be combined into one list.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::headers::Headers;
+    /// let mut headers = Headers::new();
+    /// headers.append("Set-Cookie".to_string(), "a=1".to_string());
+    /// headers.append("Set-Cookie".to_string(), "b=2".to_string());
+    /// assert_eq!(
+    ///     headers.get_all("set-cookie").collect::<Vec<&String>>(),
+    ///     vec![&"a=1".to_string(), &"b=2".to_string()]
+    /// );
+    /// ```
+    pub fn append(&mut self, name: String, value: String) {
+        self.fields.push((name, value));
+    }
+
+    /// # Iterate over the values of every field called name, case-insensitive
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a String> {
+        self.fields
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// # Remove a field, returns its value
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let index = self.fields.iter().position(|(key, _)| key == name)?;
+        Some(self.fields.remove(index).1)
+    }
+
+    /// # Get the field called name for in-place manipulation
+    pub fn entry(&mut self, name: String) -> Entry<'_> {
+        Entry {
+            headers: self,
+            name,
+        }
+    }
+
+    /// # Keep the fields for which keep returns true
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&String, &mut String) -> bool,
+    {
+        self.fields.retain_mut(|(key, value)| keep(key, value));
+    }
+
+    /// # Sort the fields by name
+    pub fn sort(&mut self) {
+        self.fields.sort();
+    }
+
+    /// # Iterate over names and values in order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            fields: self.fields.iter(),
+        }
+    }
+
+    /// # Iterate over names in order
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.fields.iter().map(|(key, _)| key)
+    }
+
+    /// # Iterate over values in order
+    pub fn values(&self) -> impl Iterator<Item = &String> {
+        self.fields.iter().map(|(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.fields.clear();
+    }
+
+    /// # Iterate over names and values in serialization order, priority fields first
+    pub fn serialization_order(&self) -> impl Iterator<Item = (&String, &String)> {
+        let priority = PRIORITY_HEADERS.iter().filter_map(move |name| {
+            self.fields
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(key, value)| (key, value))
+        });
+        let rest = self
+            .fields
+            .iter()
+            .filter(|(key, _)| {
+                !PRIORITY_HEADERS
+                    .iter()
+                    .any(|name| key.eq_ignore_ascii_case(name))
+            })
+            .map(|(key, value)| (key, value));
+        priority.chain(rest)
+    }
+}
+
+/// # A field of a container that may be missing
+pub struct Entry<'a> {
+    headers: &'a mut Headers,
+    name: String,
+}
+
+impl<'a> Entry<'a> {
+    /// # Get the value, setting it to default first if the field is missing
+    pub fn or_insert(self, default: String) -> &'a mut String {
+        self.or_insert_with(|| default)
+    }
+
+    /// # Get the value, setting it from default first if the field is missing
+    pub fn or_insert_with<F: FnOnce() -> String>(self, default: F) -> &'a mut String {
+        let index = match self
+            .headers
+            .fields
+            .iter()
+            .position(|(key, _)| *key == self.name)
+        {
+            Some(index) => index,
+            None => {
+                self.headers.fields.push((self.name, default()));
+                self.headers.fields.len() - 1
+            }
+        };
+        &mut self.headers.fields[index].1
+    }
+}
+
+/// # An iterator over the names and values of a container
+pub struct Iter<'a> {
+    fields: slice::Iter<'a, (String, String)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fields.next().map(|(key, value)| (key, value))
+    }
+}
+
+impl<'a> IntoIterator for &'a Headers {
+    type Item = (&'a String, &'a String);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Headers {
+    type Item = (String, String);
+    type IntoIter = vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fields.into_iter()
+    }
+}
+
+impl FromIterator<(String, String)> for Headers {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(fields: I) -> Headers {
+        let mut headers = Headers::new();
+        headers.extend(fields);
+        headers
+    }
+}
+
+impl Extend<(String, String)> for Headers {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, fields: I) {
+        for (name, value) in fields {
+            self.insert(name, value);
+        }
+    }
+}
+
+impl From<HashMap<String, String>> for Headers {
+    /// # Convert unordered fields, they are sorted by name for a stable order
+    fn from(fields: HashMap<String, String>) -> Headers {
+        let mut headers: Headers = fields.into_iter().collect();
+        headers.sort();
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers() {
+        let mut headers = Headers::new();
+        assert!(headers.is_empty());
+        headers.insert("Content-Length".to_string(), "1".to_string());
+        headers.insert("server".to_string(), "milstian".to_string());
+        headers.insert("Connection".to_string(), "close".to_string());
+        headers.insert(
+            "Date".to_string(),
+            "Thu, 01 Jan 1970 00:00:00 GMT".to_string(),
+        );
+        assert_eq!(
+            headers
+                .serialization_order()
+                .map(|(key, _)| key.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["Date", "server", "Content-Length", "Connection"]
+        );
+
+        assert_eq!(headers.get("content-length"), None);
+        *headers
+            .entry("Content-Length".to_string())
+            .or_insert("2".to_string()) += "0";
+        assert_eq!(headers.get("Content-Length"), Some(&"10".to_string()));
+        headers
+            .entry("Vary".to_string())
+            .or_insert_with(|| "Accept".to_string());
+        assert_eq!(headers.keys().last(), Some(&"Vary".to_string()));
+
+        assert_eq!(headers.remove("Connection"), Some("close".to_string()));
+        assert_eq!(headers.remove("Connection"), None);
+        headers.retain(|key, _| key != "server");
+        assert_eq!(headers.len(), 3);
+        assert!(!headers.contains_key("server"));
+
+        let mut unordered: HashMap<String, String> = HashMap::new();
+        unordered.insert("B".to_string(), "2".to_string());
+        unordered.insert("A".to_string(), "1".to_string());
+        let headers = Headers::from(unordered);
+        assert_eq!(
+            headers.into_iter().collect::<Vec<(String, String)>>(),
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "2".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! # Handles client hints (RFC 8942).
+//!
+//! Servers advertise the hints they want in an Accept-CH header and browsers send them on later
+//! requests. Responses that adapt to a hint should list it in Vary as well.
+//!
+//! Device and network hints let servers send smaller responses, like lower quality images, to
+//! clients on slow connections or with little memory.
+
+use std::fmt;
+
+use request;
+
+/// # Header of the brands and significant versions of the user agent
+pub const SEC_CH_UA: &str = "Sec-CH-UA";
+
+/// # Header of whether the user agent prefers a mobile experience
+pub const SEC_CH_UA_MOBILE: &str = "Sec-CH-UA-Mobile";
+
+/// # Header of the platform or operating system of the user agent
+pub const SEC_CH_UA_PLATFORM: &str = "Sec-CH-UA-Platform";
+
+/// # Header of the approximate memory of the device in GiB
+pub const DEVICE_MEMORY: &str = "Device-Memory";
+
+/// # Header of the approximate downlink bandwidth in Mbps
+pub const DOWNLINK: &str = "Downlink";
+
+/// # Header of the effective connection type
+pub const ECT: &str = "ECT";
+
+/// # Header of whether the user wants reduced data usage
+pub const SAVE_DATA: &str = "Save-Data";
+
+/// # A brand of the user agent with its significant version
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Brand {
+    pub name: String,
+    pub version: String, // Empty when the brand has no v parameter
+}
+
+/// # User agent client hints of a request
+/// Hints that are missing or not valid structured fields are left out.
+/// ## Usage
+/// ```rust
+/// use milstian_http::hints::ClientHints;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(
+///     b"GET / HTTP/1.1\r\nSec-CH-UA: \"Chromium\";v=\"112\", \"Not:A-Brand\";v=\"99\"\r\nSec-CH-UA-Mobile: ?1\r\nSec-CH-UA-Platform: \"Android\"\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let hints = ClientHints::from_request(&request);
+/// assert_eq!(hints.brands[0].name, "Chromium".to_string());
+/// assert_eq!(hints.brands[0].version, "112".to_string());
+/// assert_eq!(hints.mobile, Some(true));
+/// assert_eq!(hints.platform, Some("Android".to_string()));
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ClientHints {
+    pub brands: Vec<Brand>,       // From Sec-CH-UA in the order sent
+    pub mobile: Option<bool>,     // From Sec-CH-UA-Mobile
+    pub platform: Option<String>, // From Sec-CH-UA-Platform
+}
+
+impl ClientHints {
+    /// # Get the client hints of a request
+    pub fn from_request(request: &request::Message) -> ClientHints {
+        let header = |name: &str| request.header(name).map(|value| value.to_string());
+        ClientHints {
+            brands: header(SEC_CH_UA)
+                .map(|value| parse_brands(&value))
+                .unwrap_or_default(),
+            mobile: header(SEC_CH_UA_MOBILE).and_then(|value| parse_boolean(&value)),
+            platform: header(SEC_CH_UA_PLATFORM).and_then(|value| parse_string(&value)),
+        }
+    }
+}
+
+/// # Parse the brands of a Sec-CH-UA header, items that are not strings are skipped
+/// ## Usage
+/// ```rust
+/// use milstian_http::hints::parse_brands;
+/// let brands = parse_brands("\"Google Chrome\";v=\"112\", \"Chromium\"");
+/// assert_eq!(brands.len(), 2);
+/// assert_eq!(brands[0].name, "Google Chrome".to_string());
+/// assert_eq!(brands[1].version, String::new());
+/// ```
+pub fn parse_brands(value: &str) -> Vec<Brand> {
+    let mut brands: Vec<Brand> = Vec::new();
+    for item in split_unquoted(value, ',') {
+        let mut parameters = split_unquoted(item, ';').into_iter();
+        let name = match parse_string(parameters.next().unwrap_or("")) {
+            Some(name) => name,
+            None => continue,
+        };
+        let version = parameters
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(key, _)| key.trim() == "v")
+            .and_then(|(_, version)| parse_string(version))
+            .unwrap_or_default();
+        brands.push(Brand { name, version });
+    }
+    brands
+}
+
+/// # Effective connection types from slowest to fastest
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum EffectiveConnectionType {
+    Slow2g,
+    TwoG,
+    ThreeG,
+    FourG,
+}
+
+impl EffectiveConnectionType {
+    /// # Parse an ECT header value case-insensitively
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::hints::EffectiveConnectionType;
+    /// assert_eq!(
+    ///     EffectiveConnectionType::parse("slow-2g"),
+    ///     Some(EffectiveConnectionType::Slow2g)
+    /// );
+    /// assert!(EffectiveConnectionType::TwoG < EffectiveConnectionType::FourG);
+    /// assert_eq!(EffectiveConnectionType::parse("5g"), None);
+    /// ```
+    pub fn parse(value: &str) -> Option<EffectiveConnectionType> {
+        match value.trim().to_lowercase().as_str() {
+            "slow-2g" => Some(EffectiveConnectionType::Slow2g),
+            "2g" => Some(EffectiveConnectionType::TwoG),
+            "3g" => Some(EffectiveConnectionType::ThreeG),
+            "4g" => Some(EffectiveConnectionType::FourG),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EffectiveConnectionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = match self {
+            EffectiveConnectionType::Slow2g => "slow-2g",
+            EffectiveConnectionType::TwoG => "2g",
+            EffectiveConnectionType::ThreeG => "3g",
+            EffectiveConnectionType::FourG => "4g",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+/// # Device and network hints of a request
+/// Numeric hints that are not non-negative numbers are left out.
+/// ## Usage
+/// ```rust
+/// use milstian_http::hints::{DeviceHints, EffectiveConnectionType};
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(
+///     b"GET /photo HTTP/1.1\r\nDevice-Memory: 0.5\r\nDownlink: 0.35\r\nECT: 2g\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let hints = DeviceHints::from_request(&request);
+/// assert_eq!(hints.device_memory, Some(0.5));
+/// assert_eq!(hints.downlink, Some(0.35));
+/// assert_eq!(hints.ect, Some(EffectiveConnectionType::TwoG));
+/// assert!(!hints.save_data);
+/// assert!(hints.is_constrained());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceHints {
+    pub device_memory: Option<f64>, // GiB, from Device-Memory
+    pub downlink: Option<f64>,      // Mbps, from Downlink
+    pub ect: Option<EffectiveConnectionType>,
+    pub save_data: bool, // Whether Save-Data is on
+}
+
+impl DeviceHints {
+    /// # Get the device and network hints of a request
+    pub fn from_request(request: &request::Message) -> DeviceHints {
+        let header = |name: &str| request.header(name).map(|value| value.to_string());
+        let number = |name: &str| {
+            header(name)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .filter(|number| number.is_finite() && *number >= 0.0)
+        };
+        DeviceHints {
+            device_memory: number(DEVICE_MEMORY),
+            downlink: number(DOWNLINK),
+            ect: header(ECT).and_then(|value| EffectiveConnectionType::parse(&value)),
+            save_data: header(SAVE_DATA).is_some_and(|value| {
+                value
+                    .split(';')
+                    .next()
+                    .is_some_and(|value| value.trim().eq_ignore_ascii_case("on"))
+            }),
+        }
+    }
+
+    /// # Whether the client should get reduced responses
+    /// True when Save-Data is on, the connection is 2G or slower, the downlink is below 1 Mbps
+    /// or the device has less than 1 GiB of memory.
+    pub fn is_constrained(&self) -> bool {
+        self.save_data
+            || self
+                .ect
+                .is_some_and(|ect| ect <= EffectiveConnectionType::TwoG)
+            || self.downlink.is_some_and(|downlink| downlink < 1.0)
+            || self.device_memory.is_some_and(|memory| memory < 1.0)
+    }
+}
+
+/// # Hints requested by an Accept-CH header in the order they were added
+/// ## Usage
+/// ```rust
+/// use milstian_http::hints::{AcceptCh, SEC_CH_UA_MOBILE, SEC_CH_UA_PLATFORM};
+/// let accept = AcceptCh::new().hint(SEC_CH_UA_PLATFORM).hint(SEC_CH_UA_MOBILE);
+/// assert!(accept.is_valid());
+/// assert_eq!(accept.to_string(), "Sec-CH-UA-Platform, Sec-CH-UA-Mobile".to_string());
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AcceptCh {
+    pub hints: Vec<String>, // Header names
+}
+
+impl AcceptCh {
+    /// # Create a header without hints
+    pub fn new() -> AcceptCh {
+        AcceptCh::default()
+    }
+
+    /// # Request the hint header name, hints already requested are not repeated
+    pub fn hint(mut self, name: &str) -> AcceptCh {
+        if !self
+            .hints
+            .iter()
+            .any(|hint| hint.eq_ignore_ascii_case(name))
+        {
+            self.hints.push(name.to_string());
+        }
+        self
+    }
+
+    /// # Check that there are hints and their names are tokens
+    pub fn is_valid(&self) -> bool {
+        !self.hints.is_empty() && self.hints.iter().all(|hint| is_token(hint))
+    }
+}
+
+impl fmt::Display for AcceptCh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.hints.join(", "))
+    }
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value.chars().all(|character| {
+            character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(character)
+        })
+}
+
+/// Parse a structured field boolean like ?1
+fn parse_boolean(value: &str) -> Option<bool> {
+    match value.trim() {
+        "?1" => Some(true),
+        "?0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a structured field string, only quotes and backslashes may be escaped
+fn parse_string(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return None;
+    }
+    let mut string = String::new();
+    let mut is_escaped = false;
+    for character in value[1..value.len() - 1].chars() {
+        if !(' '..='~').contains(&character) {
+            return None;
+        }
+        if is_escaped {
+            if character != '"' && character != '\\' {
+                return None;
+            }
+            string.push(character);
+            is_escaped = false;
+        } else if character == '\\' {
+            is_escaped = true;
+        } else if character == '"' {
+            return None;
+        } else {
+            string.push(character);
+        }
+    }
+    if is_escaped {
+        None
+    } else {
+        Some(string)
+    }
+}
+
+/// Split value by separator outside of quoted strings
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut start = 0;
+    let mut is_quoted = false;
+    let mut is_escaped = false;
+    for (index, character) in value.char_indices() {
+        if is_escaped {
+            is_escaped = false;
+        } else if is_quoted && character == '\\' {
+            is_escaped = true;
+        } else if character == '"' {
+            is_quoted = !is_quoted;
+        } else if character == separator && !is_quoted {
+            parts.push(&value[start..index]);
+            start = index + 1;
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_hints() {
+        let brands = parse_brands(
+            " \"A \\\"quoted\\\" brand\" ; v=\"1\" , Token;v=\"2\", \"B\";x=1;v=\"3\"",
+        );
+        assert_eq!(
+            brands,
+            vec![
+                Brand {
+                    name: "A \"quoted\" brand".to_string(),
+                    version: "1".to_string(),
+                },
+                Brand {
+                    name: "B".to_string(),
+                    version: "3".to_string(),
+                },
+            ]
+        );
+        assert!(parse_brands("").is_empty());
+        assert_eq!(parse_string("\"a\\nb\""), None);
+        assert_eq!(parse_string("\"a\"b\""), None);
+        assert_eq!(parse_boolean("1"), None);
+
+        let request = request::Message::from_tcp_stream(
+            b"GET / HTTP/1.1\r\nsec-ch-ua-mobile: ?0\r\nSec-CH-UA-Platform: Linux\r\n\r\n",
+        )
+        .expect("A request");
+        assert_eq!(
+            ClientHints::from_request(&request),
+            ClientHints {
+                brands: Vec::new(),
+                mobile: Some(false),
+                platform: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_device_hints() {
+        let request = request::Message::from_tcp_stream(
+            b"GET / HTTP/1.1\r\nsave-data: On\r\nDownlink: -1\r\nDevice-Memory: NaN\r\nECT: 4G\r\n\r\n",
+        )
+        .expect("A request");
+        let hints = DeviceHints::from_request(&request);
+        assert_eq!(
+            hints,
+            DeviceHints {
+                device_memory: None,
+                downlink: None,
+                ect: Some(EffectiveConnectionType::FourG),
+                save_data: true,
+            }
+        );
+        assert!(hints.is_constrained());
+        assert_eq!(
+            EffectiveConnectionType::parse(&EffectiveConnectionType::Slow2g.to_string()),
+            Some(EffectiveConnectionType::Slow2g)
+        );
+
+        let request = request::Message::from_tcp_stream(
+            b"GET / HTTP/1.1\r\nSave-Data: off\r\nDownlink: 10\r\nDevice-Memory: 8\r\nECT: 3g\r\n\r\n",
+        )
+        .expect("A request");
+        let hints = DeviceHints::from_request(&request);
+        assert_eq!(hints.downlink, Some(10.0));
+        assert!(!hints.is_constrained());
+        assert!(!DeviceHints::default().is_constrained());
+    }
+
+    #[test]
+    fn test_accept_ch() {
+        let accept = AcceptCh::new()
+            .hint(SEC_CH_UA)
+            .hint("sec-ch-ua")
+            .hint(SEC_CH_UA_MOBILE);
+        assert_eq!(
+            accept.to_string(),
+            "Sec-CH-UA, Sec-CH-UA-Mobile".to_string()
+        );
+        assert!(accept.is_valid());
+        assert!(!AcceptCh::new().is_valid());
+        assert!(!AcceptCh::new().hint("Sec CH").is_valid());
+    }
+}
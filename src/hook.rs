@@ -0,0 +1,266 @@
+//! # Composes cross-cutting request and response transformations.
+//!
+//! Request hooks run in order before the handler and may answer the request themselves, response
+//! hooks run in order on every response, including those of request hooks.
+
+use std::sync::Arc;
+
+use host::AllowList;
+use request;
+use response;
+use security::{ContentSecurityPolicy, PermissionsPolicy, ReferrerPolicy};
+use transaction::Transaction;
+
+/// # Inspects or modifies requests before they reach the handler
+/// Returning a response short-circuits the chain, the handler and later request hooks don't run.
+/// ## Usage
+/// ```rust
+/// use milstian_http::hook::RequestHook;
+/// use milstian_http::request::Message;
+/// use milstian_http::response;
+///
+/// struct RequireToken;
+///
+/// impl RequestHook for RequireToken {
+///     fn on_request(&self, request: &mut Message) -> Option<response::Message> {
+///         match request.header("Authorization") {
+///             Some(_) => None,
+///             None => Some(response::Message::with_status(401)),
+///         }
+///     }
+/// }
+///
+/// let mut request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\n\r\n").expect("A request");
+/// assert_eq!(
+///     RequireToken.on_request(&mut request).and_then(|response| response.status_code()),
+///     Some(401)
+/// );
+/// ```
+pub trait RequestHook: Send + Sync {
+    fn on_request(&self, request: &mut request::Message) -> Option<response::Message>;
+}
+
+/// # Inspects or modifies responses before they are sent
+pub trait ResponseHook: Send + Sync {
+    fn on_response(&self, request: &request::Message, response: &mut response::Message);
+}
+
+impl<F> RequestHook for F
+where
+    F: Fn(&mut request::Message) -> Option<response::Message> + Send + Sync,
+{
+    fn on_request(&self, request: &mut request::Message) -> Option<response::Message> {
+        self(request)
+    }
+}
+
+impl<F> ResponseHook for F
+where
+    F: Fn(&request::Message, &mut response::Message) + Send + Sync,
+{
+    fn on_response(&self, request: &request::Message, response: &mut response::Message) {
+        self(request, response)
+    }
+}
+
+impl RequestHook for AllowList {
+    /// # Reject requests for hosts that are not allowed
+    fn on_request(&self, request: &mut request::Message) -> Option<response::Message> {
+        self.validate(request)
+            .err()
+            .map(|violation| violation.to_response())
+    }
+}
+
+impl ResponseHook for ContentSecurityPolicy {
+    /// # Set the policy unless the response has its own, invalid policies are not sent
+    fn on_response(&self, _request: &request::Message, response: &mut response::Message) {
+        if !response.headers.contains_key(self.header_name()) {
+            let _ = response.set_content_security_policy(self);
+        }
+    }
+}
+
+impl ResponseHook for PermissionsPolicy {
+    /// # Set the policy unless the response has its own, invalid policies are not sent
+    fn on_response(&self, _request: &request::Message, response: &mut response::Message) {
+        if !response.headers.contains_key("Permissions-Policy") {
+            let _ = response.set_permissions_policy(self);
+        }
+    }
+}
+
+impl ResponseHook for ReferrerPolicy {
+    /// # Set the policy unless the response has its own
+    fn on_response(&self, _request: &request::Message, response: &mut response::Message) {
+        if !response.headers.contains_key("Referrer-Policy") {
+            response.set_referrer_policy(*self);
+        }
+    }
+}
+
+/// # An ordered chain of request and response hooks around a handler
+/// ## Usage
+/// ```rust,no_run
+/// use milstian_http::hook::Chain;
+/// use milstian_http::host::AllowList;
+/// use milstian_http::response::Message;
+/// use milstian_http::server;
+/// let chain = Chain::new().request_hook(AllowList::new(&["localhost"]));
+/// server::serve("127.0.0.1:8888", chain.into_handler(|_| Message::with_status(204)))
+///     .expect("A running server");
+/// ```
+/// ```rust
+/// use milstian_http::hook::Chain;
+/// use milstian_http::request::Message;
+/// use milstian_http::response;
+/// use milstian_http::security::ReferrerPolicy;
+/// let chain = Chain::new()
+///     .request_hook(|request: &mut Message| {
+///         request.request_line.request_uri = request.request_line.request_uri.to_lowercase();
+///         None
+///     })
+///     .response_hook(ReferrerPolicy::NoReferrer);
+/// let handler = chain.into_handler(|request: &Message| {
+///     let mut response = response::Message::with_status(200);
+///     response.body = request.request_line.request_uri.clone().into_bytes();
+///     response
+/// });
+/// let response = handler(Message::from_tcp_stream(b"GET /A HTTP/1.1\r\n\r\n").expect("A request"));
+/// assert_eq!(response.body, b"/a".to_vec());
+/// assert_eq!(
+///     response.headers.get("Referrer-Policy"),
+///     Some(&"no-referrer".to_string())
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct Chain {
+    request_hooks: Vec<Arc<dyn RequestHook>>,
+    response_hooks: Vec<Arc<dyn ResponseHook>>,
+}
+
+impl Chain {
+    /// # Create a chain without hooks
+    pub fn new() -> Chain {
+        Chain::default()
+    }
+
+    /// # Append a hook that runs before the handler
+    pub fn request_hook<H: RequestHook + 'static>(mut self, hook: H) -> Chain {
+        self.request_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// # Append a hook that runs on every response
+    pub fn response_hook<H: ResponseHook + 'static>(mut self, hook: H) -> Chain {
+        self.response_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// # Run the hooks and handler for the request of transaction and set its response
+    pub fn handle<H>(&self, transaction: &mut Transaction, handler: &H)
+    where
+        H: Fn(&request::Message) -> response::Message + ?Sized,
+    {
+        let mut response = None;
+        for hook in self.request_hooks.iter() {
+            response = hook.on_request(&mut transaction.request);
+            if response.is_some() {
+                break;
+            }
+        }
+        let mut response = response.unwrap_or_else(|| handler(&transaction.request));
+        for hook in self.response_hooks.iter() {
+            hook.on_response(&transaction.request, &mut response);
+        }
+        transaction.respond(response);
+    }
+
+    /// # Wrap handler in the chain for use with the server
+    pub fn into_handler<H>(
+        self,
+        handler: H,
+    ) -> impl Fn(request::Message) -> response::Message + Send + Sync
+    where
+        H: Fn(&request::Message) -> response::Message + Send + Sync,
+    {
+        move |request| {
+            let mut transaction = Transaction::new(request);
+            self.handle(&mut transaction, &handler);
+            transaction
+                .response
+                .unwrap_or_else(|| response::Message::not_found(None))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use security::Source;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn request(bytes: &[u8]) -> request::Message {
+        request::Message::from_tcp_stream(bytes).expect("A request")
+    }
+
+    #[test]
+    fn test_chain() {
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = handled.clone();
+        let chain = Chain::new()
+            .request_hook(AllowList::new(&["example.com"]))
+            .request_hook(|request: &mut request::Message| {
+                if request.request_line.request_uri == "/private" {
+                    Some(response::Message::with_status(403))
+                } else {
+                    None
+                }
+            })
+            .response_hook(ContentSecurityPolicy::new().default_src(&[Source::SelfOrigin]))
+            .response_hook(PermissionsPolicy::new().deny("camera"))
+            .response_hook(|_: &request::Message, response: &mut response::Message| {
+                response
+                    .headers
+                    .insert("X-Hooked".to_string(), "1".to_string());
+            });
+        let handler = chain.clone().into_handler(move |_: &request::Message| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let mut response = response::Message::with_status(200);
+            response
+                .headers
+                .insert("Permissions-Policy".to_string(), "camera=*".to_string());
+            response
+        });
+
+        let response = handler(request(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n"));
+        assert_eq!(response.status_code(), Some(200));
+        assert_eq!(
+            response.headers.get("Content-Security-Policy"),
+            Some(&"default-src 'self'".to_string())
+        );
+        assert_eq!(
+            response.headers.get("Permissions-Policy"),
+            Some(&"camera=*".to_string())
+        );
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+
+        // Short-circuited responses skip the handler but not the response hooks
+        let response = handler(request(b"GET / HTTP/1.1\r\nHost: evil.example\r\n\r\n"));
+        assert_eq!(response.status_code(), Some(421));
+        assert_eq!(response.headers.get("X-Hooked"), Some(&"1".to_string()));
+        let mut transaction = Transaction::new(request(
+            b"GET /private HTTP/1.1\r\nHost: example.com\r\n\r\n",
+        ));
+        chain.handle(&mut transaction, &|_: &request::Message| {
+            response::Message::with_status(200)
+        });
+        let response = transaction.response.expect("A response");
+        assert_eq!(response.status_code(), Some(403));
+        assert_eq!(
+            response.headers.get("Permissions-Policy"),
+            Some(&"camera=()".to_string())
+        );
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+    }
+}
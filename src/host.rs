@@ -0,0 +1,247 @@
+//! # Validates the Host header against the host names a server expects.
+//!
+//! Responding to unexpected hosts enables cache poisoning and password reset poisoning attacks,
+//! requests for other hosts are rejected with 421 Misdirected Request.
+
+use std::collections::HashMap;
+
+use request;
+use response;
+use uri;
+use uri::{HostError, Uri};
+
+/// # Ways the host of a request can be rejected
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Violation {
+    Invalid(HostError),
+    Mismatch, // The Host header differs from the authority of an absolute request URI
+    Missing,  // Neither a Host header nor an absolute request URI
+    NotAllowed(String), // The host is not in the allow-list
+}
+
+impl Violation {
+    /// # Get the status line a server should respond with for this violation
+    pub fn status(&self) -> String {
+        match self {
+            Violation::NotAllowed(_) => String::from("421 Misdirected Request"),
+            _ => String::from("400 Bad Request"),
+        }
+    }
+
+    /// # Build an error response for this violation
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::host::Violation;
+    /// let response = Violation::NotAllowed("evil.example".to_string()).to_response();
+    /// assert_eq!(response.status, "421 Misdirected Request".to_string());
+    /// ```
+    pub fn to_response(&self) -> response::Message {
+        let body = match self {
+            Violation::Invalid(error) => format!("Invalid Host: {}", error),
+            Violation::Mismatch => {
+                String::from("The Host header does not match the request target")
+            }
+            Violation::Missing => String::from("The Host header is required"),
+            Violation::NotAllowed(host) => format!("This server does not serve {}", host),
+        }
+        .into_bytes();
+        let mut headers: HashMap<String, String> = HashMap::new();
+        headers.insert("Content-Type".to_string(), "text/plain".to_string());
+        headers.insert("Content-Length".to_string(), body.len().to_string());
+        response::Message::new("HTTP/1.1".to_string(), self.status(), headers, body)
+    }
+}
+
+/// # Host names a server accepts, like example.com or *.example.com for any subdomain
+/// ## Usage
+/// ```rust
+/// use milstian_http::host::{AllowList, Violation};
+/// use milstian_http::request::Message;
+/// let allow_list = AllowList::new(&["example.com", "*.example.com"]);
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nHost: WWW.example.com:8080\r\n\r\n")
+///     .expect("A request");
+/// assert_eq!(allow_list.validate(&request), Ok("www.example.com".to_string()));
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nHost: evil.example\r\n\r\n")
+///     .expect("A request");
+/// assert_eq!(
+///     allow_list.validate(&request),
+///     Err(Violation::NotAllowed("evil.example".to_string()))
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AllowList {
+    patterns: Vec<String>, // ASCII lowercase, wildcards keep their leading *.
+}
+
+impl AllowList {
+    /// # Create an allow-list, Unicode host names are converted to their ASCII form
+    pub fn new(patterns: &[&str]) -> AllowList {
+        AllowList {
+            patterns: patterns
+                .iter()
+                .map(|pattern| match pattern.strip_prefix("*.") {
+                    Some(suffix) => format!("*.{}", normalize_pattern(suffix)),
+                    None => normalize_pattern(pattern),
+                })
+                .collect(),
+        }
+    }
+
+    /// # Whether a host without port matches a pattern
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::host::AllowList;
+    /// let allow_list = AllowList::new(&["*.example.com"]);
+    /// assert!(allow_list.is_allowed("a.b.example.com"));
+    /// assert!(!allow_list.is_allowed("example.com"));
+    /// assert!(!allow_list.is_allowed("badexample.com"));
+    /// ```
+    pub fn is_allowed(&self, host: &str) -> bool {
+        let host = normalize_pattern(host);
+        self.patterns
+            .iter()
+            .any(|pattern| match pattern.strip_prefix('*') {
+                Some(suffix) => host.len() > suffix.len() && host.ends_with(suffix),
+                None => *pattern == host,
+            })
+    }
+
+    /// # Validate the host of request, returns the ASCII host without port
+    /// The authority of an absolute request URI must match the Host header when both are present.
+    pub fn validate(&self, request: &request::Message) -> Result<String, Violation> {
+        let header = match request.header("Host") {
+            Some(header) => Some(get_host_name(&header.to_string())?),
+            None => None,
+        };
+        let authority = match Uri::parse(&request.request_line.request_uri) {
+            Ok(Uri {
+                authority: Some(authority),
+                ..
+            }) => {
+                // User information is only part of a URI authority, a Host may not carry it
+                let host = match authority.rfind('@') {
+                    Some(index) => &authority[index + 1..],
+                    None => &authority[..],
+                };
+                Some(get_host_name(host)?)
+            }
+            _ => None,
+        };
+        let host = match (header, authority) {
+            (Some(header), Some(authority)) if header != authority => {
+                return Err(Violation::Mismatch)
+            }
+            (_, Some(host)) | (Some(host), None) => host,
+            (None, None) => return Err(Violation::Missing),
+        };
+        if self.is_allowed(&host) {
+            Ok(host)
+        } else {
+            Err(Violation::NotAllowed(host))
+        }
+    }
+}
+
+/// Lowercase a host and convert it to ASCII, invalid hosts are only lowercased
+fn normalize_pattern(host: &str) -> String {
+    let host = host.trim().trim_end_matches('.');
+    uri::host_to_ascii(host).unwrap_or_else(|_| host.to_lowercase())
+}
+
+/// Get the validated ASCII host without port
+fn get_host_name(host: &str) -> Result<String, Violation> {
+    let host = uri::host_to_ascii(host.trim()).map_err(Violation::Invalid)?;
+    let name = if host.starts_with('[') {
+        host.find(']').map(|index| &host[..=index]).unwrap_or(&host)
+    } else {
+        host.split(':').next().unwrap_or("")
+    };
+    Ok(name.trim_end_matches('.').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(allow_list: &AllowList, request: &[u8]) -> Result<String, Violation> {
+        allow_list.validate(&request::Message::from_tcp_stream(request).expect("A request"))
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let allow_list = AllowList::new(&["Bücher.example", "*.example.com", "[::1]"]);
+        assert!(allow_list.is_allowed("xn--bcher-kva.example"));
+        assert!(allow_list.is_allowed("bücher.example."));
+        assert!(allow_list.is_allowed("[::1]"));
+        assert!(!allow_list.is_allowed(".example.com"));
+        assert!(!AllowList::default().is_allowed("localhost"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let allow_list = AllowList::new(&["example.com"]);
+        assert_eq!(
+            validate(&allow_list, b"GET / HTTP/1.1\r\nHost: example.com.\r\n\r\n"),
+            Ok("example.com".to_string())
+        );
+        assert_eq!(
+            validate(&allow_list, b"GET / HTTP/1.1\r\n\r\n"),
+            Err(Violation::Missing)
+        );
+        assert_eq!(
+            validate(&allow_list, b"GET / HTTP/1.0\r\n\r\n"),
+            Err(Violation::Missing)
+        );
+        assert_eq!(
+            validate(&allow_list, b"GET / HTTP/1.1\r\nHost: a b\r\n\r\n"),
+            Err(Violation::Invalid(HostError::InvalidLabel(
+                "a b".to_string()
+            )))
+        );
+
+        assert_eq!(
+            validate(
+                &allow_list,
+                b"GET / HTTP/1.1\r\nHost: evil.example@example.com\r\n\r\n"
+            ),
+            Err(Violation::Invalid(HostError::InvalidLabel(
+                "example@example".to_string()
+            )))
+        );
+
+        // Absolute request URIs take precedence but must agree with the Host header
+        assert_eq!(
+            validate(&allow_list, b"GET http://example.com/a HTTP/1.1\r\n\r\n"),
+            Ok("example.com".to_string())
+        );
+        assert_eq!(
+            validate(
+                &allow_list,
+                b"GET http://user@example.com:80/a HTTP/1.1\r\nHost: example.com\r\n\r\n"
+            ),
+            Ok("example.com".to_string())
+        );
+        assert_eq!(
+            validate(
+                &allow_list,
+                b"GET http://example.com/a HTTP/1.1\r\nHost: evil.example\r\n\r\n"
+            ),
+            Err(Violation::Mismatch)
+        );
+        assert_eq!(
+            validate(&allow_list, b"GET http://evil.example/a HTTP/1.1\r\n\r\n"),
+            Err(Violation::NotAllowed("evil.example".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_violation_to_response() {
+        let response = Violation::Missing.to_response();
+        assert_eq!(response.status, "400 Bad Request".to_string());
+        assert_eq!(response.body, b"The Host header is required".to_vec());
+        assert_eq!(
+            Violation::Mismatch.to_response().status,
+            "400 Bad Request".to_string()
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! # Handles the Idempotency-Key request header for safely retrying non-idempotent requests.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use request;
+use response;
+
+/// # The longest key accepted
+pub const MAX_KEY_LENGTH: usize = 255;
+
+/// # Reasons why an Idempotency-Key header value was rejected
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum KeyError {
+    Empty,
+    InvalidCharacter(char),
+    TooLong { limit: usize, seen: usize },
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyError::Empty => write!(f, "idempotency key is empty"),
+            KeyError::InvalidCharacter(character) => {
+                write!(
+                    f,
+                    "idempotency key contains invalid character {:?}",
+                    character
+                )
+            }
+            KeyError::TooLong { limit, seen } => write!(
+                f,
+                "idempotency key is {} characters, limit is {}",
+                seen, limit
+            ),
+        }
+    }
+}
+
+impl error::Error for KeyError {}
+
+/// # Validate a key, surrounding quotes of a structured field string are removed
+/// ## Usage
+/// ```rust
+/// use milstian_http::idempotency::{validate_key, KeyError};
+/// assert_eq!(
+///     validate_key("\"8e03978e-40d5-43e8-bc93-6894a57f9324\""),
+///     Ok("8e03978e-40d5-43e8-bc93-6894a57f9324".to_string())
+/// );
+/// assert_eq!(validate_key(""), Err(KeyError::Empty));
+/// assert_eq!(validate_key("a\tb"), Err(KeyError::InvalidCharacter('\t')));
+/// ```
+pub fn validate_key(value: &str) -> Result<String, KeyError> {
+    let value = value.trim();
+    let key = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    if key.is_empty() {
+        return Err(KeyError::Empty);
+    }
+    if let Some(character) = key.chars().find(|character| {
+        !(' '..='~').contains(character) || *character == '"' || *character == '\\'
+    }) {
+        return Err(KeyError::InvalidCharacter(character));
+    }
+    if key.len() > MAX_KEY_LENGTH {
+        return Err(KeyError::TooLong {
+            limit: MAX_KEY_LENGTH,
+            seen: key.len(),
+        });
+    }
+    Ok(key.to_string())
+}
+
+/// # Get the validated Idempotency-Key of a request, None if the header is missing
+/// ## Usage
+/// ```rust
+/// use milstian_http::idempotency::get_key;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"POST /payments HTTP/1.1\r\nIdempotency-Key: abc-123\r\n\r\n")
+///     .expect("A request");
+/// assert_eq!(get_key(&request), Ok(Some("abc-123".to_string())));
+/// ```
+pub fn get_key(request: &request::Message) -> Result<Option<String>, KeyError> {
+    match request.header("Idempotency-Key") {
+        Some(value) => validate_key(&value.to_string()).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// # Ways a request can violate idempotency key semantics
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Violation {
+    InProgress, // A request with the same key has not completed yet
+    Invalid(KeyError),
+    Missing,         // The endpoint requires a key
+    PayloadMismatch, // The key was used before with a different request payload
+}
+
+impl Violation {
+    /// # Get the status line a server should respond with for this violation
+    pub fn status(&self) -> String {
+        match self {
+            Violation::InProgress => String::from("409 Conflict"),
+            Violation::Invalid(_) | Violation::Missing => String::from("400 Bad Request"),
+            Violation::PayloadMismatch => String::from("422 Unprocessable Content"),
+        }
+    }
+
+    /// # Build an error response for this violation
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::idempotency::Violation;
+    /// let response = Violation::InProgress.to_response();
+    /// assert_eq!(response.status, "409 Conflict".to_string());
+    /// ```
+    pub fn to_response(&self) -> response::Message {
+        let body = match self {
+            Violation::InProgress => {
+                String::from("A request with this Idempotency-Key is still being processed")
+            }
+            Violation::Invalid(error) => format!("Invalid Idempotency-Key: {}", error),
+            Violation::Missing => String::from("The Idempotency-Key header is required"),
+            Violation::PayloadMismatch => {
+                String::from("The Idempotency-Key was already used with a different payload")
+            }
+        }
+        .into_bytes();
+        let mut headers: HashMap<String, String> = HashMap::new();
+        headers.insert("Content-Type".to_string(), "text/plain".to_string());
+        headers.insert("Content-Length".to_string(), body.len().to_string());
+        response::Message::new("HTTP/1.1".to_string(), self.status(), headers, body)
+    }
+}
+
+/// # Copy a stored response for replay, marked with an Idempotent-Replayed header
+/// ## Usage
+/// ```rust
+/// use milstian_http::idempotency::replay;
+/// use milstian_http::response::Message;
+/// let stored = Message::default();
+/// let replayed = replay(&stored);
+/// assert_eq!(
+///     replayed.headers.get("Idempotent-Replayed"),
+///     Some(&"true".to_string())
+/// );
+/// ```
+pub fn replay(stored: &response::Message) -> response::Message {
+    let mut response = stored.clone();
+    response
+        .headers
+        .insert("Idempotent-Replayed".to_string(), "true".to_string());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_key() {
+        assert_eq!(validate_key(" abc "), Ok("abc".to_string()));
+        assert_eq!(validate_key("\"\""), Err(KeyError::Empty));
+        assert_eq!(validate_key("\""), Err(KeyError::InvalidCharacter('"')));
+        assert_eq!(validate_key("å"), Err(KeyError::InvalidCharacter('å')));
+        assert_eq!(
+            validate_key(&"a".repeat(256)),
+            Err(KeyError::TooLong {
+                limit: 255,
+                seen: 256
+            })
+        );
+        assert!(validate_key(&"a".repeat(255)).is_ok());
+    }
+
+    #[test]
+    fn test_get_key() {
+        let request =
+            request::Message::from_tcp_stream(b"POST / HTTP/1.1\r\n\r\n").expect("A request");
+        assert_eq!(get_key(&request), Ok(None));
+        let request =
+            request::Message::from_tcp_stream(b"POST / HTTP/1.1\r\nidempotency-key: \"k\"\r\n\r\n")
+                .expect("A request");
+        assert_eq!(get_key(&request), Ok(Some("k".to_string())));
+    }
+
+    #[test]
+    fn test_violation_to_response() {
+        let response = Violation::Invalid(KeyError::Empty).to_response();
+        assert_eq!(response.status, "400 Bad Request".to_string());
+        assert_eq!(
+            response.body,
+            b"Invalid Idempotency-Key: idempotency key is empty".to_vec()
+        );
+        assert_eq!(
+            Violation::PayloadMismatch.to_response().status,
+            "422 Unprocessable Content".to_string()
+        );
+        assert_eq!(
+            Violation::Missing
+                .to_response()
+                .headers
+                .get("Content-Length"),
+            Some(&"38".to_string())
+        );
+    }
+}
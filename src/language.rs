@@ -0,0 +1,118 @@
+//! # Handles language tags of Content-Language headers (RFC 5646).
+
+use std::fmt;
+
+/// # Is tag a well-formed language tag like en, sv-SE or zh-Hant-TW?
+/// Tags are subtags of one to eight letters and digits joined by dashes, starting with a primary
+/// language of two to eight letters, or x or i followed by at least one subtag.
+/// ## Usage
+/// ```rust
+/// use milstian_http::language::is_valid_language_tag;
+/// assert!(is_valid_language_tag("en"));
+/// assert!(is_valid_language_tag("sv-SE"));
+/// assert!(is_valid_language_tag("x-klingon"));
+/// assert!(!is_valid_language_tag("*"));
+/// assert!(!is_valid_language_tag("en_US"));
+/// assert!(!is_valid_language_tag("e"));
+/// assert!(!is_valid_language_tag("en-"));
+/// ```
+pub fn is_valid_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or("");
+    let is_valid_primary = if primary.len() == 1 {
+        (primary.eq_ignore_ascii_case("x") || primary.eq_ignore_ascii_case("i")) && tag.len() > 2
+    } else {
+        (2..=8).contains(&primary.len())
+            && primary
+                .chars()
+                .all(|character| character.is_ascii_alphabetic())
+    };
+    is_valid_primary
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len())
+                && subtag
+                    .chars()
+                    .all(|character| character.is_ascii_alphanumeric())
+        })
+}
+
+/// # The languages of the intended audience of a representation
+/// ## Usage
+/// ```rust
+/// use milstian_http::language::ContentLanguage;
+/// let language = ContentLanguage::new().tag("sv").tag("en-GB").tag("SV");
+/// assert!(language.is_valid());
+/// assert_eq!(language.to_string(), "sv, en-GB".to_string());
+/// assert!(language.contains("en-gb"));
+/// assert_eq!(ContentLanguage::parse(" sv ,, en-GB"), language);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ContentLanguage {
+    pub tags: Vec<String>, // Language tags in order
+}
+
+impl ContentLanguage {
+    /// # Create a header without languages
+    pub fn new() -> ContentLanguage {
+        ContentLanguage::default()
+    }
+
+    /// # Add a language tag, tags already added are not repeated
+    pub fn tag(mut self, tag: &str) -> ContentLanguage {
+        if !self.contains(tag) {
+            self.tags.push(tag.to_string());
+        }
+        self
+    }
+
+    /// # Parse a comma-separated Content-Language value, invalid tags are skipped
+    pub fn parse(value: &str) -> ContentLanguage {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| is_valid_language_tag(tag))
+            .fold(ContentLanguage::new(), ContentLanguage::tag)
+    }
+
+    /// # Is tag one of the languages, case-insensitive?
+    pub fn contains(&self, tag: &str) -> bool {
+        self.tags
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(tag))
+    }
+
+    /// # Are there languages and are all of them well-formed tags?
+    pub fn is_valid(&self) -> bool {
+        !self.tags.is_empty() && self.tags.iter().all(|tag| is_valid_language_tag(tag))
+    }
+}
+
+impl fmt::Display for ContentLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.tags.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_language() {
+        assert!(is_valid_language_tag("zh-Hant-TW"));
+        assert!(is_valid_language_tag("de-CH-1996"));
+        assert!(is_valid_language_tag("i-klingon"));
+        assert!(!is_valid_language_tag(""));
+        assert!(!is_valid_language_tag("x"));
+        assert!(!is_valid_language_tag("x-"));
+        assert!(!is_valid_language_tag("abcdefghi"));
+        assert!(!is_valid_language_tag("en-abcdefghi"));
+        assert!(!is_valid_language_tag("e1"));
+        assert!(!is_valid_language_tag("en--US"));
+
+        let language = ContentLanguage::parse("mi, en, *, en_US, MI");
+        assert_eq!(language.tags, vec!["mi".to_string(), "en".to_string()]);
+        assert!(!ContentLanguage::parse("*").is_valid());
+        assert!(!ContentLanguage::new().tag("en US").is_valid());
+    }
+}
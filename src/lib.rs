@@ -51,8 +51,85 @@
 //! );
 //! ```
 
+#[cfg(all(unix, feature = "sendfile"))]
+extern crate libc;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "quickcheck")]
+extern crate quickcheck;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+/// Emit a tracing event, nothing without the tracing feature
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($level:ident, $($argument:tt)+) => {
+        ::tracing::$level!($($argument)+)
+    };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($level:ident, $($argument:tt)+) => {{}};
+}
+
+/// Enter a tracing span until the end of the current block, nothing without the tracing feature
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($argument:tt)+) => {
+        let _span = ::tracing::debug_span!($($argument)+).entered();
+    };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($argument:tt)+) => {};
+}
+
+#[cfg(feature = "quickcheck")]
+pub mod arbitrary;
+pub mod auth;
+pub mod cache;
+pub mod cgi;
+pub mod chunked;
+pub mod client;
+pub mod cookie;
+pub mod crypto;
+pub mod date;
+#[cfg(feature = "serde")]
+pub mod deserialize;
+pub mod fastcgi;
+pub mod file;
+pub mod framing;
+pub mod headers;
+pub mod hints;
+pub mod hook;
+pub mod host;
+pub mod idempotency;
+pub mod language;
+pub mod media;
+pub mod multipart;
+pub mod negotiation;
+pub mod prefer;
+pub mod proxy;
+pub mod range;
+pub mod record;
 pub mod request;
 pub mod response;
+pub mod route;
+pub mod security;
+pub mod server;
+pub mod signing;
+pub mod stream;
+pub mod timing;
+pub mod transaction;
+pub mod uri;
+#[cfg(feature = "xml")]
+pub mod xml;
 
 /// # Capitalize key, used for http header keys
 /// ## Usage
@@ -87,6 +164,31 @@ pub fn capitalize_key(word: &str) -> String {
     converted
 }
 
+/// # How header names are written when parsed and serialized
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum HeaderNameCase {
+    #[default]
+    Canonical, // Like Content-Type
+    Lowercase, // Like content-type, as in HTTP/2
+    Preserve,  // As received or inserted
+}
+
+/// # Normalize a header name according to case
+/// ## Usage
+/// ```rust
+/// use milstian_http::{normalize_key, HeaderNameCase};
+/// assert_eq!(normalize_key("content-TYPE", &HeaderNameCase::Canonical), "Content-Type".to_string());
+/// assert_eq!(normalize_key("Content-Type", &HeaderNameCase::Lowercase), "content-type".to_string());
+/// assert_eq!(normalize_key("content-TYPE", &HeaderNameCase::Preserve), "content-TYPE".to_string());
+/// ```
+pub fn normalize_key(word: &str, case: &HeaderNameCase) -> String {
+    match case {
+        HeaderNameCase::Canonical => capitalize_key(word),
+        HeaderNameCase::Lowercase => word.to_ascii_lowercase(),
+        HeaderNameCase::Preserve => word.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,4 +199,20 @@ mod tests {
         assert_eq!("Content-Type".to_string(), capitalize_key("CONTENT-TYPE"));
         assert_eq!("Accept".to_string(), capitalize_key("acCept"));
     }
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(
+            normalize_key("x-REQUEST-id", &HeaderNameCase::default()),
+            "X-Request-Id".to_string()
+        );
+        assert_eq!(
+            normalize_key("X-Request-Id", &HeaderNameCase::Lowercase),
+            "x-request-id".to_string()
+        );
+        assert_eq!(
+            normalize_key("X-REQUEST-id", &HeaderNameCase::Preserve),
+            "X-REQUEST-id".to_string()
+        );
+    }
 }
@@ -0,0 +1,253 @@
+//! # Handles media types of Content-Type headers (RFC 6838).
+
+use std::fmt;
+
+use multipart;
+
+/// # A media type with its parameters
+/// Type, subtype and parameter names are lowercase, parameter values are unquoted and kept as is.
+/// ## Usage
+/// ```rust
+/// use milstian_http::media::MediaType;
+/// let media_type = MediaType::parse("Multipart/Form-Data; Boundary=\"a:b\"").expect("A media type");
+/// assert!(media_type.is_multipart_form_data());
+/// assert_eq!(media_type.essence(), "multipart/form-data".to_string());
+/// assert_eq!(media_type.boundary(), Some("a:b"));
+/// assert_eq!(media_type.to_string(), "multipart/form-data; boundary=\"a:b\"".to_string());
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MediaType {
+    pub parameters: Vec<(String, String)>, // Names and values in order
+    pub subtype: String,
+    pub top_level: String, // Like text or application
+}
+
+impl MediaType {
+    /// # Parse a Content-Type value, returns None without a type/subtype pair of tokens
+    /// Parameters without a name or value are skipped, like values that are neither tokens nor
+    /// quoted strings, and the first of repeated names is kept.
+    pub fn parse(value: &str) -> Option<MediaType> {
+        let mut items = split_unquoted(value, ';').into_iter();
+        let essence = items.next()?.trim();
+        let (top_level, subtype) = essence.split_once('/')?;
+        if !is_token(top_level) || !is_token(subtype) {
+            return None;
+        }
+        let mut parameters: Vec<(String, String)> = Vec::new();
+        for item in items {
+            let (name, value) = match item.split_once('=') {
+                Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+            let value = if value.len() > 1 && value.starts_with('"') && value.ends_with('"') {
+                unquote(value)
+            } else if is_token(value) {
+                value.to_string()
+            } else {
+                continue;
+            };
+            if !is_token(&name)
+                || value.is_empty()
+                || parameters.iter().any(|(existing, _)| *existing == name)
+            {
+                continue;
+            }
+            parameters.push((name, value));
+        }
+        Some(MediaType {
+            parameters,
+            subtype: subtype.to_lowercase(),
+            top_level: top_level.to_lowercase(),
+        })
+    }
+
+    /// # Get the type and subtype without parameters, like text/html
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.top_level, self.subtype)
+    }
+
+    /// # Does the type and subtype match essence case-insensitively?
+    pub fn is(&self, essence: &str) -> bool {
+        essence.split_once('/').is_some_and(|(top_level, subtype)| {
+            self.top_level.eq_ignore_ascii_case(top_level.trim())
+                && self.subtype.eq_ignore_ascii_case(subtype.trim())
+        })
+    }
+
+    /// # Get a parameter by name, case-insensitive
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// # Get the boundary parameter of a multi-part type if it is valid by RFC 2046
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::media::MediaType;
+    /// let boundary = |value: &str| {
+    ///     MediaType::parse(value).and_then(|media_type| media_type.boundary().map(str::to_string))
+    /// };
+    /// assert_eq!(boundary("multipart/mixed; boundary=\"a:b c\""), Some("a:b c".to_string()));
+    /// assert_eq!(boundary("multipart/mixed; boundary=a:b"), None);
+    /// assert_eq!(boundary("multipart/mixed; boundary=\"a{b\""), None);
+    /// assert_eq!(boundary("text/plain; boundary=abc"), None);
+    /// ```
+    pub fn boundary(&self) -> Option<&str> {
+        if self.is_multipart() {
+            self.parameter("boundary")
+                .filter(|boundary| multipart::is_valid_boundary(boundary))
+        } else {
+            None
+        }
+    }
+
+    /// # Get the charset parameter
+    pub fn charset(&self) -> Option<&str> {
+        self.parameter("charset")
+    }
+
+    /// # Is it form data from application/x-www-form-urlencoded?
+    pub fn is_form(&self) -> bool {
+        self.is("application/x-www-form-urlencoded")
+    }
+
+    /// # Is it application/json or a structured syntax like application/problem+json?
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::media::MediaType;
+    /// let is_json = |value: &str| MediaType::parse(value).is_some_and(|media_type| media_type.is_json());
+    /// assert!(is_json("application/json; charset=utf-8"));
+    /// assert!(is_json("application/problem+json"));
+    /// assert!(!is_json("text/plain"));
+    /// ```
+    pub fn is_json(&self) -> bool {
+        self.is("application/json") || self.subtype.ends_with("+json")
+    }
+
+    /// # Is it any multipart type?
+    pub fn is_multipart(&self) -> bool {
+        self.top_level == "multipart"
+    }
+
+    /// # Is it multipart/form-data?
+    pub fn is_multipart_form_data(&self) -> bool {
+        self.is("multipart/form-data")
+    }
+}
+
+impl fmt::Display for MediaType {
+    /// # Render the media type with parameter values quoted when they are not tokens
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.top_level, self.subtype)?;
+        for (name, value) in self.parameters.iter() {
+            if is_token(value) {
+                write!(f, "; {}={}", name, value)?;
+            } else {
+                write!(
+                    f,
+                    "; {}=\"{}\"",
+                    name,
+                    value.replace('\\', "\\\\").replace('"', "\\\"")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value.chars().all(|character| {
+            character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(character)
+        })
+}
+
+/// Split value by separator outside of quoted strings
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut start = 0;
+    let mut is_quoted = false;
+    let mut is_escaped = false;
+    for (index, character) in value.char_indices() {
+        if is_escaped {
+            is_escaped = false;
+        } else if is_quoted && character == '\\' {
+            is_escaped = true;
+        } else if character == '"' {
+            is_quoted = !is_quoted;
+        } else if character == separator && !is_quoted {
+            parts.push(&value[start..index]);
+            start = index + 1;
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Remove the quotes and escapes of a quoted string, tokens are returned as is
+fn unquote(value: &str) -> String {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return value.to_string();
+    }
+    let mut unquoted = String::new();
+    let mut is_escaped = false;
+    for character in value[1..value.len() - 1].chars() {
+        if character == '\\' && !is_escaped {
+            is_escaped = true;
+        } else {
+            unquoted.push(character);
+            is_escaped = false;
+        }
+    }
+    unquoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let media_type = MediaType::parse(
+            " Text/HTML ; Charset=UTF-8; charset=latin1; empty=; novalue; a=b/c; q=\"a \\\"b\\\"\"",
+        )
+        .expect("A media type");
+        assert_eq!(
+            media_type,
+            MediaType {
+                parameters: vec![
+                    ("charset".to_string(), "UTF-8".to_string()),
+                    ("q".to_string(), "a \"b\"".to_string()),
+                ],
+                subtype: "html".to_string(),
+                top_level: "text".to_string(),
+            }
+        );
+        assert!(media_type.is("TEXT/html"));
+        assert!(!media_type.is("text"));
+        assert_eq!(media_type.charset(), Some("UTF-8"));
+        assert_eq!(media_type.parameter("Q"), Some("a \"b\""));
+        assert_eq!(media_type.boundary(), None);
+        assert_eq!(
+            MediaType::parse(&media_type.to_string()),
+            Some(media_type.clone())
+        );
+        assert!(!media_type.is_json());
+        assert!(!media_type.is_form());
+
+        assert!(MediaType::parse("application/x-www-form-urlencoded")
+            .expect("A media type")
+            .is_form());
+        let mixed = MediaType::parse("multipart/mixed; boundary=abc").expect("A media type");
+        assert!(mixed.is_multipart());
+        assert!(!mixed.is_multipart_form_data());
+        assert_eq!(mixed.boundary(), Some("abc"));
+
+        assert_eq!(MediaType::parse(""), None);
+        assert_eq!(MediaType::parse("text"), None);
+        assert_eq!(MediaType::parse("text/"), None);
+        assert_eq!(MediaType::parse("te xt/html"), None);
+    }
+}
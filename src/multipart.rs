@@ -0,0 +1,516 @@
+//! # Handles multipart bodies.
+
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static BOUNDARY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+static SPOOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+const BOUNDARY_ALPHABET: &[u8; 62] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BOUNDARY_PREFIX: &str = "milstian-";
+const BOUNDARY_RANDOM_LENGTH: usize = 32;
+
+/// # Is boundary allowed by the bchars grammar of RFC 2046?
+/// ## Usage
+/// ```rust
+/// use milstian_http::multipart::is_valid_boundary;
+/// assert!(is_valid_boundary("----abc123"));
+/// assert!(is_valid_boundary("a b"));
+/// assert!(!is_valid_boundary("a "));
+/// assert!(!is_valid_boundary("a;b"));
+/// assert!(!is_valid_boundary(&"a".repeat(71)));
+/// ```
+pub fn is_valid_boundary(boundary: &str) -> bool {
+    !boundary.is_empty()
+        && boundary.len() <= 70
+        && !boundary.ends_with(' ')
+        && boundary.chars().all(|character| {
+            character.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(character)
+        })
+}
+
+/// Random bits from the randomly keyed hasher of the standard library
+fn get_random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(BOUNDARY_COUNTER.fetch_add(1, Ordering::SeqCst));
+    if let Ok(duration) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.write_u128(duration.as_nanos());
+    }
+    hasher.finish()
+}
+
+/// # Generate a random boundary of alphanumeric characters
+/// ## Usage
+/// ```rust
+/// use milstian_http::multipart::{generate_boundary, is_valid_boundary};
+/// let boundary = generate_boundary();
+/// assert!(is_valid_boundary(&boundary));
+/// assert_ne!(boundary, generate_boundary());
+/// ```
+pub fn generate_boundary() -> String {
+    let mut boundary = String::from(BOUNDARY_PREFIX);
+    let mut random = 0;
+    for index in 0..BOUNDARY_RANDOM_LENGTH {
+        // Each random number provides ten characters
+        if index % 10 == 0 {
+            random = get_random_u64();
+        }
+        boundary.push(BOUNDARY_ALPHABET[(random % 62) as usize] as char);
+        random /= 62;
+    }
+    boundary
+}
+
+/// # Does the delimiter of boundary not occur in any of the parts?
+/// ## Usage
+/// ```rust
+/// use milstian_http::multipart::is_boundary_safe;
+/// assert!(is_boundary_safe("abc", &[b"hello", b"--ab"]));
+/// assert!(!is_boundary_safe("abc", &[b"hello", b"x\r\n--abc"]));
+/// ```
+pub fn is_boundary_safe(boundary: &str, parts: &[&[u8]]) -> bool {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+    parts.iter().all(|part| {
+        part.len() < delimiter.len()
+            || !part
+                .windows(delimiter.len())
+                .any(|window| window == delimiter)
+    })
+}
+
+/// # Generate a random boundary that does not occur in any of the parts
+/// ## Usage
+/// ```rust
+/// use milstian_http::multipart::{generate_boundary_for, is_boundary_safe};
+/// let parts: Vec<&[u8]> = vec![b"first", b"second"];
+/// let boundary = generate_boundary_for(&parts);
+/// assert!(is_boundary_safe(&boundary, &parts));
+/// ```
+pub fn generate_boundary_for(parts: &[&[u8]]) -> String {
+    loop {
+        let boundary = generate_boundary();
+        if is_boundary_safe(&boundary, parts) {
+            return boundary;
+        }
+    }
+}
+
+/// # The section of a multipart body that a parser has reached
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+    Epilogue,   // After the close delimiter
+    Incomplete, // The body ended before a close delimiter
+    Part,       // After a delimiter, looking for the next one
+    Preamble,   // Before the first delimiter
+}
+
+/// # Splits a multipart body into its parts by the delimiters of RFC 2046
+/// Delimiters are a CRLF followed by two dashes and the boundary, only the first may come without
+/// the CRLF at the start of the body. A delimiter followed by two more dashes closes the body,
+/// otherwise it may be followed by spaces or tabs before its CRLF. Parts are yielded with their
+/// header fields, the CRLF before a delimiter belongs to the delimiter and not to the part.
+/// The preamble before the first delimiter and the epilogue after the close delimiter are skipped
+/// but can be inspected once the parser has passed them.
+/// ## Usage
+/// ```rust
+/// use milstian_http::multipart::{Parser, State};
+/// let mut parser = Parser::new("ab", b"--ab\r\nName: 1\r\n\r\nx\r\n--abc\r\n--ab \r\n\r\ny\r\n--ab--\r\n");
+/// assert_eq!(parser.next(), Some(&b"Name: 1\r\n\r\nx\r\n--abc"[..]));
+/// assert_eq!(parser.next(), Some(&b"\r\ny"[..]));
+/// assert_eq!(parser.next(), None);
+/// assert_eq!(parser.state(), State::Epilogue);
+///
+/// let mut parser = Parser::new("ab", b"Ignored\r\n--ab\r\n\r\nx\r\n--ab--\r\nAlso ignored");
+/// assert_eq!(parser.preamble(), None);
+/// assert_eq!(parser.next(), Some(&b"\r\nx"[..]));
+/// assert_eq!(parser.preamble(), Some(&b"Ignored"[..]));
+/// assert_eq!(parser.epilogue(), Some(&b"Also ignored"[..]));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Parser<'a> {
+    body: &'a [u8],
+    delimiter: Vec<u8>,            // Two dashes followed by the boundary
+    epilogue_start: Option<usize>, // After the line of the close delimiter
+    position: usize,               // Where the search for the next delimiter starts
+    preamble_end: Option<usize>,   // At the first delimiter
+    state: State,
+}
+
+/// A delimiter line found in a body
+struct Delimiter {
+    end: usize, // After the line, or after the dashes of a close delimiter
+    is_close: bool,
+    start: usize, // At the CRLF before the dashes, if any
+}
+
+impl<'a> Parser<'a> {
+    /// # Create a parser for the parts of body separated by boundary
+    pub fn new(boundary: &str, body: &'a [u8]) -> Parser<'a> {
+        let mut delimiter = b"--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+        Parser {
+            body,
+            delimiter,
+            epilogue_start: None,
+            position: 0,
+            preamble_end: None,
+            state: State::Preamble,
+        }
+    }
+
+    /// # Get the section the parser has reached
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// # Get the bytes before the first delimiter once it has been found
+    pub fn preamble(&self) -> Option<&'a [u8]> {
+        self.preamble_end.map(|end| &self.body[..end])
+    }
+
+    /// # Get the bytes after the close delimiter once it has been found
+    pub fn epilogue(&self) -> Option<&'a [u8]> {
+        self.epilogue_start.map(|start| &self.body[start..])
+    }
+
+    /// Find the next delimiter at or after from, looking past the boundary to tell it apart
+    /// from longer strings that start with it
+    fn find_delimiter(&self, from: usize) -> Option<Delimiter> {
+        if from == 0 && self.state == State::Preamble && self.body.starts_with(&self.delimiter) {
+            if let Some(delimiter) = self.delimiter_at(0, self.delimiter.len()) {
+                return Some(delimiter);
+            }
+        }
+        let length = self.delimiter.len() + 2;
+        let mut start = from;
+        while start + length <= self.body.len() {
+            if &self.body[start..start + 2] == b"\r\n"
+                && self.body[start + 2..start + length] == self.delimiter[..]
+            {
+                if let Some(delimiter) = self.delimiter_at(start, start + length) {
+                    return Some(delimiter);
+                }
+            }
+            start += 1;
+        }
+        None
+    }
+
+    /// Is the boundary that ends at after followed by what ends a delimiter line?
+    /// Bodies that end right after a boundary are treated as closed.
+    fn delimiter_at(&self, start: usize, after: usize) -> Option<Delimiter> {
+        let rest = &self.body[after..];
+        if rest.starts_with(b"--") {
+            return Some(Delimiter {
+                end: after + 2,
+                is_close: true,
+                start,
+            });
+        }
+        let padding = rest
+            .iter()
+            .take_while(|byte| **byte == b' ' || **byte == b'\t')
+            .count();
+        if padding == rest.len() {
+            Some(Delimiter {
+                end: self.body.len(),
+                is_close: true,
+                start,
+            })
+        } else if rest[padding..].starts_with(b"\r\n") {
+            Some(Delimiter {
+                end: after + padding + 2,
+                is_close: false,
+                start,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            let delimiter = match self.state {
+                State::Epilogue | State::Incomplete => return None,
+                State::Part | State::Preamble => self.find_delimiter(self.position),
+            };
+            let delimiter = match delimiter {
+                Some(delimiter) => delimiter,
+                None => {
+                    self.state = State::Incomplete;
+                    return None;
+                }
+            };
+            let part = &self.body[self.position..delimiter.start];
+            let state = self.state;
+            if state == State::Preamble {
+                self.preamble_end = Some(delimiter.start);
+            }
+            self.position = delimiter.end;
+            self.state = if delimiter.is_close {
+                // The rest of the close delimiter line is not part of the epilogue
+                let rest = &self.body[delimiter.end..];
+                let padding = rest
+                    .iter()
+                    .take_while(|byte| **byte == b' ' || **byte == b'\t')
+                    .count();
+                let line_end = if rest[padding..].starts_with(b"\r\n") {
+                    padding + 2
+                } else {
+                    0
+                };
+                self.epilogue_start = Some(delimiter.end + line_end);
+                State::Epilogue
+            } else {
+                State::Part
+            };
+            if state == State::Part {
+                return Some(part);
+            }
+        }
+    }
+}
+
+/// Removes the file when the last handle is dropped
+#[derive(Debug, Eq, PartialEq)]
+struct TemporaryPath(PathBuf);
+
+impl Drop for TemporaryPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// # A multipart body spooled to a temporary file, removed when the last clone is dropped
+/// Requests read by `stream::Parser` with a Content-Length body are spooled while they arrive, so
+/// an upload never lives in memory as a whole. Requests parsed from a slice are spooled after the
+/// fact and only keep the parsed parts small.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpooledFile {
+    pub content_type: Option<String>,
+    pub filename: Option<String>,
+    path: Arc<TemporaryPath>,
+    pub size: usize,
+}
+
+impl SpooledFile {
+    /// # Write body to a new uniquely named file in directory
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::multipart::SpooledFile;
+    /// use std::env;
+    /// use std::fs;
+    /// let file = SpooledFile::create(&env::temp_dir(), b"abc", None, Some("a.txt".to_string()))
+    ///     .expect("A spooled file");
+    /// assert_eq!(file.size, 3);
+    /// assert_eq!(fs::read(file.path()).expect("File contents"), b"abc".to_vec());
+    /// let path = file.path().to_path_buf();
+    /// drop(file);
+    /// assert!(!path.exists());
+    /// ```
+    pub fn create(
+        directory: &Path,
+        body: &[u8],
+        content_type: Option<String>,
+        filename: Option<String>,
+    ) -> io::Result<SpooledFile> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        let path = directory.join(format!(
+            "milstian-{}-{}-{}.part",
+            process::id(),
+            SPOOL_COUNTER.fetch_add(1, Ordering::SeqCst),
+            nanos
+        ));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let path = TemporaryPath(path);
+        file.write_all(body)?;
+        Ok(SpooledFile {
+            content_type,
+            filename,
+            path: Arc::new(path),
+            size: body.len(),
+        })
+    }
+
+    /// # Append bytes to the end of the temporary file
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::multipart::SpooledFile;
+    /// use std::env;
+    /// use std::fs;
+    /// let mut file = SpooledFile::create(&env::temp_dir(), b"ab", None, None).expect("A file");
+    /// file.append(b"c").expect("An appended body");
+    /// assert_eq!(file.size, 3);
+    /// assert_eq!(fs::read(file.path()).expect("File contents"), b"abc".to_vec());
+    /// ```
+    pub fn append(&mut self, bytes: &[u8]) -> io::Result<()> {
+        OpenOptions::new()
+            .append(true)
+            .open(self.path())?
+            .write_all(bytes)?;
+        self.size += bytes.len();
+        Ok(())
+    }
+
+    /// # Get the path of the temporary file
+    pub fn path(&self) -> &Path {
+        &self.path.0
+    }
+
+    /// # Open the temporary file for reading
+    pub fn open(&self) -> io::Result<File> {
+        File::open(self.path())
+    }
+
+    /// # Copy the contents to destination, the temporary file is still removed on drop
+    pub fn persist(&self, destination: &Path) -> io::Result<()> {
+        fs::copy(self.path(), destination).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Read;
+
+    #[test]
+    fn test_generate_boundary() {
+        let mut boundaries: Vec<String> = (0..100).map(|_| generate_boundary()).collect();
+        for boundary in boundaries.iter() {
+            assert!(is_valid_boundary(boundary));
+            assert_eq!(
+                boundary.len(),
+                BOUNDARY_PREFIX.len() + BOUNDARY_RANDOM_LENGTH
+            );
+        }
+        boundaries.sort();
+        boundaries.dedup();
+        assert_eq!(boundaries.len(), 100);
+    }
+
+    #[test]
+    fn test_is_boundary_safe() {
+        assert!(is_boundary_safe("a", &[]));
+        assert!(is_boundary_safe("a", &[b"", b"-"]));
+        assert!(!is_boundary_safe("a", &[b"--a"]));
+        assert!(!is_boundary_safe("a", &[b"x", b"--a--"]));
+        assert!(is_valid_boundary("'()+_,-./:=?"));
+        assert!(!is_valid_boundary(""));
+        assert!(!is_valid_boundary("å"));
+    }
+
+    #[test]
+    fn test_parser() {
+        let parts = |boundary: &str, body: &[u8]| -> (Vec<Vec<u8>>, State) {
+            let mut parser = Parser::new(boundary, body);
+            let parts = parser.by_ref().map(|part| part.to_vec()).collect();
+            (parts, parser.state())
+        };
+
+        // Preamble and epilogue are skipped, transport padding is allowed after delimiters
+        let body = b"preamble\r\n--a\t \r\n\r\n1\r\n--a\r\nB: 2\r\n\r\n\r\n--a-- \r\n--a\r\n\r\n3";
+        assert_eq!(
+            parts("a", body),
+            (
+                vec![b"\r\n1".to_vec(), b"B: 2\r\n\r\n".to_vec()],
+                State::Epilogue
+            )
+        );
+        let mut parser = Parser::new("a", body);
+        assert_eq!((parser.preamble(), parser.epilogue()), (None, None));
+        parser.next();
+        assert_eq!(parser.preamble(), Some(&b"preamble"[..]));
+        assert_eq!(parser.epilogue(), None);
+        parser.next();
+        assert_eq!(parser.epilogue(), Some(&b"--a\r\n\r\n3"[..]));
+
+        // Bodies that start with a delimiter have an empty preamble
+        let mut parser = Parser::new("a", b"--a\r\n\r\n1\r\n--a--");
+        assert_eq!(parser.by_ref().count(), 1);
+        assert_eq!(parser.preamble(), Some(&b""[..]));
+        assert_eq!(parser.epilogue(), Some(&b""[..]));
+        let mut parser = Parser::new("a", b"--a\r\n\r\n1");
+        assert_eq!(parser.by_ref().count(), 0);
+        assert_eq!(parser.preamble(), Some(&b""[..]));
+        assert_eq!(parser.epilogue(), None);
+
+        // Boundaries are only matched after a CRLF and when followed by a line end or dashes
+        assert_eq!(
+            parts("a", b"x--a\r\n--ab\r\n--a-\r\n--a\r\n\r\n1\r\n--a--"),
+            (vec![b"\r\n1".to_vec()], State::Epilogue)
+        );
+        assert_eq!(
+            parts("a", b"--a\r\n\r\n1\n--a\r\n\r\n2\r\n--a"),
+            (vec![b"\r\n1\n--a\r\n\r\n2".to_vec()], State::Epilogue)
+        );
+
+        // Parts without a delimiter after them are incomplete
+        assert_eq!(
+            parts("a", b"--a\r\n\r\n1\r\n--"),
+            (Vec::new(), State::Incomplete)
+        );
+        assert_eq!(parts("a", b"--a--"), (Vec::new(), State::Epilogue));
+        assert_eq!(parts("a", b""), (Vec::new(), State::Incomplete));
+        assert_eq!(
+            parts("-", b"---\r\n\r\n---"),
+            (vec![Vec::new()], State::Epilogue)
+        );
+    }
+
+    #[test]
+    fn test_spooled_file() {
+        let file = SpooledFile::create(
+            &env::temp_dir(),
+            b"hello",
+            Some("text/plain".to_string()),
+            None,
+        )
+        .expect("A spooled file");
+        let copy = file.clone();
+        assert_eq!(copy, file);
+        let path = file.path().to_path_buf();
+        drop(file);
+
+        // Clones keep the file alive
+        let mut contents = String::new();
+        copy.open()
+            .expect("An open file")
+            .read_to_string(&mut contents)
+            .expect("File contents");
+        assert_eq!(contents, "hello".to_string());
+
+        let destination = env::temp_dir().join(format!("milstian-persist-{}", process::id()));
+        copy.persist(&destination).expect("A persisted file");
+        drop(copy);
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read(&destination).expect("File contents"),
+            b"hello".to_vec()
+        );
+        fs::remove_file(&destination).expect("A removed file");
+
+        assert!(SpooledFile::create(Path::new("/nonexistent/directory"), b"", None, None).is_err());
+    }
+}
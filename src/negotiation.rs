@@ -0,0 +1,867 @@
+//! # Server-driven content negotiation over the Accept headers of a request.
+//!
+//! Every negotiation merges the consulted request header into the `Vary` header of the response,
+//! since the response depends on it whether or not a representation was acceptable.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use language::{is_valid_language_tag, ContentLanguage};
+use request;
+use response;
+
+/// # A representation of a resource the server can produce
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Representation {
+    pub encoding: Option<String>, // None means identity
+    pub language: Option<String>, // None means not language specific
+    pub media_type: String,
+}
+
+impl fmt::Display for Representation {
+    /// # Describe the representation like text/html, language en, encoding gzip
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.media_type)?;
+        if let Some(ref language) = self.language {
+            write!(f, ", language {}", language)?;
+        }
+        if let Some(ref encoding) = self.encoding {
+            write!(f, ", encoding {}", encoding)?;
+        }
+        Ok(())
+    }
+}
+
+/// # A representation available at its own URI, listed for agent-driven negotiation
+/// ## Usage
+/// ```rust
+/// use milstian_http::negotiation::{Representation, Variant};
+/// let variant = Variant {
+///     representation: Representation {
+///         encoding: None,
+///         language: Some("sv".to_string()),
+///         media_type: "text/html".to_string(),
+///     },
+///     uri: "/about.sv.html".to_string(),
+/// };
+/// assert!(variant.is_valid());
+/// assert_eq!(
+///     variant.to_link(),
+///     "</about.sv.html>; rel=\"alternate\"; type=\"text/html\"; hreflang=\"sv\"".to_string()
+/// );
+/// assert_eq!(
+///     variant.to_alternate(),
+///     "{\"/about.sv.html\" 1.0 {type text/html} {language sv}}".to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Variant {
+    pub representation: Representation,
+    pub uri: String, // Where the representation can be requested
+}
+
+impl Variant {
+    /// # Is the URI non-empty without whitespace, quotes or angle brackets?
+    pub fn is_valid(&self) -> bool {
+        !self.uri.is_empty()
+            && !self.uri.chars().any(|character| {
+                character.is_whitespace() || character.is_control() || "<>\"".contains(character)
+            })
+            && !self.representation.media_type.contains('"')
+            && self
+                .representation
+                .language
+                .as_deref()
+                .is_none_or(is_valid_language_tag)
+    }
+
+    /// # Render the variant as an alternate link with type and hreflang (RFC 8288)
+    pub fn to_link(&self) -> String {
+        let mut link = format!(
+            "<{}>; rel=\"alternate\"; type=\"{}\"",
+            self.uri, self.representation.media_type
+        );
+        if let Some(ref language) = self.representation.language {
+            link.push_str(&format!("; hreflang=\"{}\"", language));
+        }
+        link
+    }
+
+    /// # Render the variant as a description of the Alternates header (RFC 2295)
+    pub fn to_alternate(&self) -> String {
+        let mut alternate = format!(
+            "{{\"{}\" 1.0 {{type {}}}",
+            self.uri, self.representation.media_type
+        );
+        if let Some(ref language) = self.representation.language {
+            alternate.push_str(&format!(" {{language {}}}", language));
+        }
+        alternate.push('}');
+        alternate
+    }
+}
+
+/// # The representation chosen by negotiate and the headers describing it
+#[derive(Debug, Eq, PartialEq)]
+pub struct Negotiated<'a> {
+    pub headers: HashMap<String, String>,
+    pub representation: &'a Representation,
+}
+
+impl<'a> Negotiated<'a> {
+    /// # Set the headers on response, Vary is merged with existing entries
+    pub fn apply(&self, response: &mut response::Message) {
+        for (key, value) in self.headers.iter() {
+            if key == "Vary" {
+                for name in value.split(',') {
+                    response.add_vary(name.trim());
+                }
+            } else {
+                response.headers.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+}
+
+/// # No representation satisfies a request header, suggests a 406 Not Acceptable response
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NotAcceptable {
+    pub header: String, // The request header that excluded every representation
+}
+
+impl NotAcceptable {
+    /// # Build the suggested 406 Not Acceptable response, varying on the header
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::negotiation::NotAcceptable;
+    /// let not_acceptable = NotAcceptable {
+    ///     header: "Accept-Encoding".to_string(),
+    /// };
+    /// let response = not_acceptable.to_response();
+    /// assert_eq!(response.status, "406 Not Acceptable".to_string());
+    /// assert_eq!(
+    ///     response.headers.get("Vary"),
+    ///     Some(&"Accept-Encoding".to_string())
+    /// );
+    /// ```
+    pub fn to_response(&self) -> response::Message {
+        let mut response = response::Message::error_page(406, None);
+        response.add_vary(&self.header);
+        response
+    }
+}
+
+impl fmt::Display for NotAcceptable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no representation is acceptable by {}", self.header)
+    }
+}
+
+impl error::Error for NotAcceptable {}
+
+/// # A value from an Accept header with its quality in thousandths
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QualityValue {
+    pub value: String,
+    pub quality: u16, // 0 to 1000 where 1000 is q=1
+}
+
+fn get_quality(parameter: &str) -> Option<u16> {
+    let (key, value) = parameter.split_once('=')?;
+    if !key.trim().eq_ignore_ascii_case("q") {
+        return None;
+    }
+    let quality: f32 = value.trim().parse().ok()?;
+    if !(0.0..=1.0).contains(&quality) {
+        return Some(0);
+    }
+    Some((quality * 1000.0).round() as u16)
+}
+
+/// # Parse a comma-separated list of values with optional q parameters
+/// ## Usage
+/// ```rust
+/// use milstian_http::negotiation::{parse_quality_values, QualityValue};
+/// assert_eq!(
+///     parse_quality_values("text/html, application/json;q=0.5"),
+///     vec![
+///         QualityValue {
+///             value: "text/html".to_string(),
+///             quality: 1000
+///         },
+///         QualityValue {
+///             value: "application/json".to_string(),
+///             quality: 500
+///         },
+///     ]
+/// );
+/// ```
+pub fn parse_quality_values(header: &str) -> Vec<QualityValue> {
+    let mut values: Vec<QualityValue> = Vec::new();
+    for item in header.split(',') {
+        let mut parameters = item.split(';');
+        let value = parameters.next().unwrap_or("").trim();
+        if value.is_empty() {
+            continue;
+        }
+        let quality = parameters.find_map(get_quality).unwrap_or(1000);
+        values.push(QualityValue {
+            value: value.to_string(),
+            quality,
+        });
+    }
+    values
+}
+
+fn match_media_type(range: &str, candidate: &str) -> Option<usize> {
+    if range == "*/*" {
+        return Some(0);
+    }
+    let candidate = candidate.split(';').next().unwrap_or("").trim();
+    if range.eq_ignore_ascii_case(candidate) {
+        return Some(2);
+    }
+    let (range_type, range_subtype) = range.split_once('/')?;
+    let (candidate_type, _) = candidate.split_once('/')?;
+    if range_subtype == "*" && range_type.eq_ignore_ascii_case(candidate_type) {
+        return Some(1);
+    }
+    None
+}
+
+fn match_language(range: &str, candidate: &str) -> Option<usize> {
+    if range == "*" {
+        return Some(0);
+    }
+    let range = range.to_lowercase();
+    let candidate = candidate.to_lowercase();
+    if candidate == range || candidate.starts_with(&format!("{}-", range)) {
+        return Some(range.len());
+    }
+    None
+}
+
+fn match_encoding(range: &str, candidate: &str) -> Option<usize> {
+    if range == "*" {
+        return Some(0);
+    }
+    if range.eq_ignore_ascii_case(candidate) {
+        return Some(1);
+    }
+    None
+}
+
+/// Get the quality and specificity of a content coding by the rules of Accept-Encoding
+/// Named codings are the most specific, then *, identity is acceptable unless it is excluded.
+fn get_encoding_quality(ranges: &[QualityValue], coding: &str) -> Option<(u16, usize)> {
+    let named = ranges
+        .iter()
+        .find(|range| match_encoding(&range.value, coding) == Some(1));
+    let wildcard = ranges.iter().find(|range| range.value == "*");
+    match (named, wildcard) {
+        (Some(range), _) => Some((range.quality, 2)),
+        (None, Some(range)) => Some((range.quality, 1)),
+        (None, None) if coding.eq_ignore_ascii_case("identity") => Some((1000, 0)),
+        (None, None) => None,
+    }
+}
+
+/// Pick the content coding from available that the Accept-Encoding header prefers
+/// Codings are ranked by quality, then named codings rank over those matched by *, then server
+/// order breaks ties. Identity is always available and is chosen when nothing in the header is
+/// acceptable, unless identity;q=0 or *;q=0 excludes it. An empty header only accepts identity.
+fn select_encoding<'a>(header: Option<&str>, available: &[&'a str]) -> Option<&'a str> {
+    let header = match header {
+        Some(header) => header,
+        None => return Some(available.first().cloned().unwrap_or("identity")),
+    };
+    let ranges = parse_quality_values(header);
+    let mut best: Option<(&'a str, (u16, usize))> = None;
+    let mut identity: Option<&'a str> = None;
+    for candidate in available.iter().chain(["identity"].iter()) {
+        let rank = match get_encoding_quality(&ranges, candidate) {
+            Some(rank) if rank.0 > 0 => rank,
+            _ => continue,
+        };
+        if rank.1 == 0 {
+            identity = identity.or(Some(candidate));
+        } else if best.map(|(_, best)| rank > best).unwrap_or(true) {
+            best = Some((candidate, rank));
+        }
+    }
+    best.map(|(candidate, _)| candidate).or(identity)
+}
+
+/// # Choose a transfer coding from available using the TE header
+/// Chunked is always acceptable and is not negotiated, like the trailers member that only
+/// announces that trailer fields are accepted. Without a TE header no other coding is acceptable.
+/// ## Usage
+/// ```rust
+/// use milstian_http::negotiation::negotiate_transfer_coding;
+/// use milstian_http::request;
+/// let request = request::Message::from_tcp_stream(
+///     b"GET / HTTP/1.1\r\nTE: trailers, deflate;q=0.5, gzip;q=0\r\n\r\n",
+/// )
+/// .expect("A request");
+/// assert_eq!(negotiate_transfer_coding(&request, &["gzip", "deflate"]), Some("deflate"));
+/// assert_eq!(negotiate_transfer_coding(&request, &["gzip"]), None);
+/// ```
+pub fn negotiate_transfer_coding<'a>(
+    request: &request::Message,
+    available: &[&'a str],
+) -> Option<&'a str> {
+    let ranges = get_header(request, "TE")?;
+    let mut best: Option<(&'a str, u16)> = None;
+    for candidate in available {
+        if candidate.eq_ignore_ascii_case("chunked") || candidate.eq_ignore_ascii_case("trailers") {
+            continue;
+        }
+        let quality = ranges
+            .iter()
+            .find(|range| match_encoding(&range.value, candidate) == Some(1))
+            .map(|range| range.quality)
+            .unwrap_or(0);
+        if quality > 0 && best.map(|(_, best)| quality > best).unwrap_or(true) {
+            best = Some((candidate, quality));
+        }
+    }
+    best.map(|(candidate, _)| candidate)
+}
+
+/// # Does the TE header announce that trailer fields are accepted?
+/// ## Usage
+/// ```rust
+/// use milstian_http::negotiation::accepts_trailers;
+/// use milstian_http::request;
+/// let request = request::Message::from_tcp_stream(b"GET / HTTP/1.1\r\nTE: Trailers\r\n\r\n")
+///     .expect("A request");
+/// assert!(accepts_trailers(&request));
+/// ```
+pub fn accepts_trailers(request: &request::Message) -> bool {
+    get_header(request, "TE").is_some_and(|ranges| {
+        ranges
+            .iter()
+            .any(|range| range.value.eq_ignore_ascii_case("trailers"))
+    })
+}
+
+/// The most specific matching range decides the quality of candidate
+fn get_candidate_quality(
+    ranges: &[QualityValue],
+    candidate: &str,
+    matcher: fn(&str, &str) -> Option<usize>,
+) -> u16 {
+    ranges
+        .iter()
+        .filter_map(|range| {
+            matcher(&range.value, candidate).map(|specificity| (specificity, range.quality))
+        })
+        .max_by_key(|(specificity, _)| *specificity)
+        .map(|(_, quality)| quality)
+        .unwrap_or(0)
+}
+
+/// Pick the available value with the highest quality, server order breaks ties
+fn select<'a>(
+    header: Option<&str>,
+    available: &[&'a str],
+    matcher: fn(&str, &str) -> Option<usize>,
+) -> Option<&'a str> {
+    let header = match header {
+        Some(header) => header,
+        None => return available.first().cloned(),
+    };
+    let ranges = parse_quality_values(header);
+    let mut best: Option<(&'a str, u16)> = None;
+    for candidate in available {
+        let quality = get_candidate_quality(&ranges, candidate, matcher);
+        if quality > 0 && best.map(|(_, best)| quality > best).unwrap_or(true) {
+            best = Some((candidate, quality));
+        }
+    }
+    best.map(|(candidate, _)| candidate)
+}
+
+fn negotiate_header<'a>(
+    request: &request::Message,
+    response: &mut response::Message,
+    header_name: &str,
+    available: &[&'a str],
+    matcher: fn(&str, &str) -> Option<usize>,
+) -> Option<&'a str> {
+    response.add_vary(header_name);
+    let header = request.header(header_name).map(|value| value.to_string());
+    select(header.as_deref(), available, matcher)
+}
+
+/// # Choose a media type from available using the Accept header
+/// ## Usage
+/// ```rust
+/// use milstian_http::negotiation::negotiate_media_type;
+/// use milstian_http::request;
+/// use milstian_http::response;
+/// let request = request::Message::from_tcp_stream(
+///     b"GET / HTTP/1.1\r\nAccept: text/html;q=0.8, application/*\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let mut response = response::Message::default();
+/// assert_eq!(
+///     negotiate_media_type(&request, &mut response, &["text/html", "application/json"]),
+///     Some("application/json")
+/// );
+/// assert_eq!(response.headers.get("Vary"), Some(&"Accept".to_string()));
+/// ```
+pub fn negotiate_media_type<'a>(
+    request: &request::Message,
+    response: &mut response::Message,
+    available: &[&'a str],
+) -> Option<&'a str> {
+    negotiate_header(request, response, "Accept", available, match_media_type)
+}
+
+/// # Choose a language tag from available using the Accept-Language header
+/// The chosen language is declared in the Content-Language header of response when it is a
+/// well-formed language tag.
+/// ## Usage
+/// ```rust
+/// use milstian_http::negotiation::negotiate_language;
+/// use milstian_http::request;
+/// use milstian_http::response;
+/// let request = request::Message::from_tcp_stream(
+///     b"GET / HTTP/1.1\r\nAccept-Language: sv, en;q=0.5\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let mut response = response::Message::default();
+/// assert_eq!(
+///     negotiate_language(&request, &mut response, &["en-US", "sv-SE"]),
+///     Some("sv-SE")
+/// );
+/// assert_eq!(
+///     response.headers.get("Vary"),
+///     Some(&"Accept-Language".to_string())
+/// );
+/// assert_eq!(
+///     response.headers.get("Content-Language"),
+///     Some(&"sv-SE".to_string())
+/// );
+/// ```
+pub fn negotiate_language<'a>(
+    request: &request::Message,
+    response: &mut response::Message,
+    available: &[&'a str],
+) -> Option<&'a str> {
+    let language = negotiate_header(
+        request,
+        response,
+        "Accept-Language",
+        available,
+        match_language,
+    )?;
+    let _ = response.set_content_language(&ContentLanguage::new().tag(language));
+    Some(language)
+}
+
+/// # Choose a content coding from available using the Accept-Encoding header
+/// Codings are ranked by quality, then codings named in the header rank over those matched by
+/// `*`, then the order of available breaks ties. A quality of 0 makes a coding not acceptable.
+/// Identity, the representation without a content coding, is always available and is chosen when
+/// no coding in the header is acceptable, unless `identity;q=0` or `*;q=0` excludes it. Without
+/// the header the first available coding is chosen. When nothing is acceptable the error
+/// suggests a 406 Not Acceptable response.
+/// ## Usage
+/// ```rust
+/// use milstian_http::negotiation::negotiate_encoding;
+/// use milstian_http::request;
+/// use milstian_http::response;
+/// let request = request::Message::from_tcp_stream(
+///     b"GET / HTTP/1.1\r\nAccept-Encoding: gzip, br;q=0.9\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let mut response = response::Message::default();
+/// assert_eq!(
+///     negotiate_encoding(&request, &mut response, &["br", "gzip"]),
+///     Ok("gzip")
+/// );
+/// assert_eq!(negotiate_encoding(&request, &mut response, &["zstd"]), Ok("identity"));
+///
+/// let request = request::Message::from_tcp_stream(
+///     b"GET / HTTP/1.1\r\nAccept-Encoding: br;q=0, *;q=0\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let not_acceptable = negotiate_encoding(&request, &mut response, &["br", "identity"])
+///     .expect_err("Nothing acceptable");
+/// assert_eq!(not_acceptable.to_response().status_code(), Some(406));
+/// ```
+pub fn negotiate_encoding<'a>(
+    request: &request::Message,
+    response: &mut response::Message,
+    available: &[&'a str],
+) -> Result<&'a str, NotAcceptable> {
+    response.add_vary("Accept-Encoding");
+    let header = request
+        .header("Accept-Encoding")
+        .map(|value| value.to_string());
+    select_encoding(header.as_deref(), available).ok_or_else(|| NotAcceptable {
+        header: "Accept-Encoding".to_string(),
+    })
+}
+
+fn get_header(request: &request::Message, name: &str) -> Option<Vec<QualityValue>> {
+    request
+        .header(name)
+        .map(|value| parse_quality_values(&value.to_string()))
+}
+
+/// # Choose the representation that best satisfies all Accept headers together
+/// When none is acceptable `response::Message::not_acceptable` lists the representations.
+/// ## Usage
+/// ```rust
+/// use milstian_http::negotiation::{negotiate, Representation};
+/// use milstian_http::request;
+/// let request = request::Message::from_tcp_stream(
+///     b"GET / HTTP/1.1\r\nAccept: text/html\r\nAccept-Language: sv;q=0.9, en;q=0.8\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let representations = vec![
+///     Representation {
+///         encoding: None,
+///         language: Some("en".to_string()),
+///         media_type: "text/html".to_string(),
+///     },
+///     Representation {
+///         encoding: None,
+///         language: Some("sv".to_string()),
+///         media_type: "text/html".to_string(),
+///     },
+/// ];
+/// let negotiated = negotiate(&representations, &request).expect("A representation");
+/// assert_eq!(negotiated.representation, &representations[1]);
+/// assert_eq!(
+///     negotiated.headers.get("Content-Language"),
+///     Some(&"sv".to_string())
+/// );
+/// assert_eq!(
+///     negotiated.headers.get("Vary"),
+///     Some(&"Accept-Language".to_string())
+/// );
+/// ```
+pub fn negotiate<'a>(
+    representations: &'a [Representation],
+    request: &request::Message,
+) -> Option<Negotiated<'a>> {
+    let accept = get_header(request, "Accept");
+    let accept_language = get_header(request, "Accept-Language");
+    let accept_encoding = get_header(request, "Accept-Encoding");
+
+    let mut best: Option<(&'a Representation, u64)> = None;
+    for representation in representations {
+        let media_quality = match accept {
+            Some(ref ranges) => {
+                get_candidate_quality(ranges, &representation.media_type, match_media_type)
+            }
+            None => 1000,
+        };
+        let language_quality = match (&accept_language, &representation.language) {
+            (Some(ranges), Some(language)) => {
+                get_candidate_quality(ranges, language, match_language)
+            }
+            _ => 1000,
+        };
+        let encoding_quality = match accept_encoding {
+            Some(ref ranges) => get_encoding_quality(
+                ranges,
+                representation.encoding.as_deref().unwrap_or("identity"),
+            )
+            .map(|(quality, _)| quality)
+            .unwrap_or(0),
+            None => 1000,
+        };
+        let quality =
+            u64::from(media_quality) * u64::from(language_quality) * u64::from(encoding_quality);
+        if quality > 0 && best.map(|(_, best)| quality > best).unwrap_or(true) {
+            best = Some((representation, quality));
+        }
+    }
+    let representation = best?.0;
+
+    let mut headers: HashMap<String, String> = HashMap::new();
+    headers.insert(
+        "Content-Type".to_string(),
+        representation.media_type.to_string(),
+    );
+    if let Some(ref language) = representation.language {
+        if is_valid_language_tag(language) {
+            headers.insert("Content-Language".to_string(), language.to_string());
+        }
+    }
+    if let Some(ref encoding) = representation.encoding {
+        if !encoding.eq_ignore_ascii_case("identity") {
+            headers.insert("Content-Encoding".to_string(), encoding.to_string());
+        }
+    }
+
+    // Only dimensions where the representations differ affect the response
+    let mut vary: Vec<&str> = Vec::new();
+    if representations.iter().any(|other| {
+        !other
+            .media_type
+            .eq_ignore_ascii_case(&representation.media_type)
+    }) {
+        vary.push("Accept");
+    }
+    if representations
+        .iter()
+        .any(|other| other.language != representation.language)
+    {
+        vary.push("Accept-Language");
+    }
+    if representations
+        .iter()
+        .any(|other| other.encoding != representation.encoding)
+    {
+        vary.push("Accept-Encoding");
+    }
+    if !vary.is_empty() {
+        headers.insert("Vary".to_string(), vary.join(", "));
+    }
+
+    Some(Negotiated {
+        headers,
+        representation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quality_values() {
+        let values = parse_quality_values("a;q=0.123, , b;level=1;q=2, c;q=x, d;Q=0");
+        let values: Vec<(&str, u16)> = values
+            .iter()
+            .map(|value| (value.value.as_str(), value.quality))
+            .collect();
+        assert_eq!(values, vec![("a", 123), ("b", 0), ("c", 1000), ("d", 0)]);
+    }
+
+    #[test]
+    fn test_select() {
+        // Missing headers accept the first available value
+        assert_eq!(select(None, &["a/b", "c/d"], match_media_type), Some("a/b"));
+        assert_eq!(select(None, &[], match_media_type), None);
+
+        // Specific ranges override wildcards
+        assert_eq!(
+            select(
+                Some("*/*;q=0.9, text/*;q=0.1, text/plain"),
+                &["text/html", "image/png", "text/plain"],
+                match_media_type
+            ),
+            Some("text/plain")
+        );
+        assert_eq!(
+            select(
+                Some("*/*, text/*;q=0"),
+                &["text/html", "image/png"],
+                match_media_type
+            ),
+            Some("image/png")
+        );
+        assert_eq!(
+            select(Some("text/html"), &["image/png"], match_media_type),
+            None
+        );
+
+        // Server order breaks ties
+        assert_eq!(
+            select(Some("en, sv"), &["sv", "en"], match_language),
+            Some("sv")
+        );
+        assert_eq!(
+            select(Some("en-gb, en;q=0.5"), &["en-US", "en-GB"], match_language),
+            Some("en-GB")
+        );
+        assert_eq!(select(Some("en-GB"), &["en"], match_language), None);
+        assert_eq!(
+            select(Some("*;q=0.1, GZIP"), &["br", "gzip"], match_encoding),
+            Some("gzip")
+        );
+    }
+
+    #[test]
+    fn test_select_encoding() {
+        let available = ["br", "gzip", "identity"];
+
+        // Missing headers accept the first coding, empty headers only identity
+        assert_eq!(select_encoding(None, &available), Some("br"));
+        assert_eq!(select_encoding(None, &[]), Some("identity"));
+        assert_eq!(select_encoding(Some(""), &available), Some("identity"));
+
+        // Named codings rank over * at the same quality, then server order breaks ties
+        assert_eq!(select_encoding(Some("*, gzip"), &available), Some("gzip"));
+        assert_eq!(select_encoding(Some("gzip, BR"), &available), Some("br"));
+        assert_eq!(
+            select_encoding(Some("*;q=0.5, gzip;q=0.4"), &available),
+            Some("br")
+        );
+
+        // Identity is only chosen when nothing named is acceptable
+        assert_eq!(
+            select_encoding(Some("gzip;q=0.1"), &available),
+            Some("gzip")
+        );
+        assert_eq!(select_encoding(Some("zstd"), &available), Some("identity"));
+        assert_eq!(select_encoding(Some("zstd"), &["br"]), Some("identity"));
+        assert_eq!(
+            select_encoding(Some("identity;q=0.5, gzip;q=0.4"), &available),
+            Some("identity")
+        );
+
+        // A quality of 0 is not acceptable, *;q=0 also excludes identity unless it is named
+        assert_eq!(select_encoding(Some("br;q=0, *"), &available), Some("gzip"));
+        assert_eq!(select_encoding(Some("*;q=0"), &available), None);
+        assert_eq!(select_encoding(Some("identity;q=0"), &["identity"]), None);
+        assert_eq!(
+            select_encoding(Some("*;q=0, identity"), &available),
+            Some("identity")
+        );
+    }
+
+    #[test]
+    fn test_transfer_coding() {
+        let request = request::Message::from_tcp_stream(
+            b"GET / HTTP/1.1\r\nTE: gzip;q=0.5, deflate;q=0.9, chunked\r\n\r\n",
+        )
+        .expect("A request");
+        assert_eq!(
+            negotiate_transfer_coding(&request, &["chunked", "gzip", "deflate"]),
+            Some("deflate")
+        );
+        assert!(!accepts_trailers(&request));
+        let request =
+            request::Message::from_tcp_stream(b"GET / HTTP/1.1\r\n\r\n").expect("A request");
+        assert_eq!(negotiate_transfer_coding(&request, &["gzip"]), None);
+        assert!(!accepts_trailers(&request));
+    }
+
+    fn get_representation(
+        media_type: &str,
+        language: Option<&str>,
+        encoding: Option<&str>,
+    ) -> Representation {
+        Representation {
+            encoding: encoding.map(|encoding| encoding.to_string()),
+            language: language.map(|language| language.to_string()),
+            media_type: media_type.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let representations = vec![
+            get_representation("application/json", Some("en"), None),
+            get_representation("text/html", Some("en"), None),
+            get_representation("text/html", Some("en"), Some("gzip")),
+            get_representation("text/html", Some("sv"), None),
+        ];
+
+        // Without Accept headers the first representation is chosen
+        let request =
+            request::Message::from_tcp_stream(b"GET / HTTP/1.1\r\n\r\n").expect("A request");
+        let negotiated = negotiate(&representations, &request).expect("A representation");
+        assert_eq!(negotiated.representation, &representations[0]);
+        assert_eq!(
+            negotiated.headers.get("Vary"),
+            Some(&"Accept, Accept-Language, Accept-Encoding".to_string())
+        );
+
+        // Qualities of all headers are combined
+        let request = request::Message::from_tcp_stream(
+            b"GET / HTTP/1.1\r\nAccept: application/json;q=0.5, text/*\r\nAccept-Language: sv;q=0.4, en\r\nAccept-Encoding: gzip, identity;q=0.5\r\n\r\n",
+        )
+        .expect("A request");
+        let negotiated = negotiate(&representations, &request).expect("A representation");
+        assert_eq!(negotiated.representation, &representations[2]);
+        assert_eq!(
+            negotiated.headers.get("Content-Encoding"),
+            Some(&"gzip".to_string())
+        );
+        let mut response = response::Message::default();
+        response
+            .headers
+            .insert("Vary".to_string(), "Accept".to_string());
+        negotiated.apply(&mut response);
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some(&"text/html".to_string())
+        );
+        assert_eq!(
+            response.headers.get("Vary"),
+            Some(&"Accept, Accept-Language, Accept-Encoding".to_string())
+        );
+
+        // Nothing acceptable
+        let request =
+            request::Message::from_tcp_stream(b"GET / HTTP/1.1\r\nAccept: image/png\r\n\r\n")
+                .expect("A request");
+        assert!(negotiate(&representations, &request).is_none());
+        let request = request::Message::from_tcp_stream(
+            b"GET / HTTP/1.1\r\nAccept: text/html\r\nAccept-Encoding: br, identity;q=0\r\n\r\n",
+        )
+        .expect("A request");
+        assert!(negotiate(&representations, &request).is_none());
+    }
+
+    #[test]
+    fn test_variant() {
+        let variant = |uri: &str, language: Option<&str>| Variant {
+            representation: get_representation("application/json", language, Some("gzip")),
+            uri: uri.to_string(),
+        };
+        assert_eq!(
+            variant("/a", None).to_alternate(),
+            "{\"/a\" 1.0 {type application/json}}".to_string()
+        );
+        assert_eq!(
+            variant("/a", None).to_link(),
+            "</a>; rel=\"alternate\"; type=\"application/json\"".to_string()
+        );
+        assert_eq!(
+            variant("/a", None).representation.to_string(),
+            "application/json, encoding gzip".to_string()
+        );
+        assert!(!variant("", None).is_valid());
+        assert!(!variant("/a b", None).is_valid());
+        assert!(!variant("/a>", None).is_valid());
+        assert!(!variant("/a", Some("en_US")).is_valid());
+
+        let response =
+            response::Message::multiple_choices(&[variant("/a\"", None), variant("/b", None)]);
+        assert_eq!(
+            response.headers.get("Alternates"),
+            Some(&"{\"/b\" 1.0 {type application/json}}".to_string())
+        );
+        let response = response::Message::multiple_choices(&[]);
+        assert_eq!(response.status_code(), Some(300));
+        assert!(response.headers.get("Link").is_none());
+    }
+
+    #[test]
+    fn test_vary_is_merged() {
+        let request =
+            request::Message::from_tcp_stream(b"GET / HTTP/1.1\r\n\r\n").expect("A request");
+        let mut response = response::Message::default();
+        response
+            .headers
+            .insert("Vary".to_string(), "Cookie".to_string());
+        negotiate_media_type(&request, &mut response, &["text/html"]);
+        negotiate_language(&request, &mut response, &["en"]);
+        negotiate_media_type(&request, &mut response, &["text/html"]);
+        assert_eq!(
+            response.headers.get("Vary"),
+            Some(&"Cookie, Accept, Accept-Language".to_string())
+        );
+    }
+}
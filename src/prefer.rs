@@ -0,0 +1,178 @@
+//! # Handles the Prefer and Preference-Applied headers (RFC 7240).
+
+use std::fmt;
+
+use request;
+
+/// # The return preference
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Return {
+    Minimal,
+    Representation,
+}
+
+/// # The handling preference
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Handling {
+    Lenient,
+    Strict,
+}
+
+/// # Preferences from a Prefer header or applied by a response
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Preferences {
+    pub handling: Option<Handling>,
+    pub other: Vec<(String, Option<String>)>, // Unrecognized preferences in order
+    pub respond_async: bool,
+    pub return_preference: Option<Return>,
+    pub wait: Option<u64>, // Seconds
+}
+
+impl Preferences {
+    /// # Parse a Prefer header value, the first occurrence of a preference wins
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::prefer::{Handling, Preferences, Return};
+    /// let preferences = Preferences::parse("return=minimal, wait=10, handling=strict, respond-async");
+    /// assert_eq!(preferences.return_preference, Some(Return::Minimal));
+    /// assert_eq!(preferences.wait, Some(10));
+    /// assert_eq!(preferences.handling, Some(Handling::Strict));
+    /// assert!(preferences.respond_async);
+    /// ```
+    pub fn parse(header: &str) -> Preferences {
+        let mut preferences = Preferences::default();
+        let mut seen: Vec<String> = Vec::new();
+        for preference in header.split(',') {
+            // Parameters after ';' are not used by any recognized preference
+            let preference = preference.split(';').next().unwrap_or("").trim();
+            if preference.is_empty() {
+                continue;
+            }
+            let (name, value) = match preference.split_once('=') {
+                Some((name, value)) => (
+                    name.trim().to_lowercase(),
+                    Some(value.trim().trim_matches('"').to_string()),
+                ),
+                None => (preference.to_lowercase(), None),
+            };
+            if seen.contains(&name) {
+                continue;
+            }
+            seen.push(name.clone());
+
+            let lowercase_value = value.as_ref().map(|value| value.to_lowercase());
+            match (name.as_str(), lowercase_value.as_deref()) {
+                ("handling", Some("lenient")) => preferences.handling = Some(Handling::Lenient),
+                ("handling", Some("strict")) => preferences.handling = Some(Handling::Strict),
+                ("respond-async", _) => preferences.respond_async = true,
+                ("return", Some("minimal")) => {
+                    preferences.return_preference = Some(Return::Minimal)
+                }
+                ("return", Some("representation")) => {
+                    preferences.return_preference = Some(Return::Representation)
+                }
+                ("wait", Some(wait)) => preferences.wait = wait.parse().ok(),
+                _ => preferences.other.push((name, value)),
+            }
+        }
+        preferences
+    }
+
+    /// # Get the preferences of a request, empty if it has no Prefer header
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::prefer::{Preferences, Return};
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\nPrefer: return=representation\r\n\r\n")
+    ///     .expect("A request");
+    /// assert_eq!(
+    ///     Preferences::from_request(&request).return_preference,
+    ///     Some(Return::Representation)
+    /// );
+    /// ```
+    pub fn from_request(request: &request::Message) -> Preferences {
+        match request.header("Prefer") {
+            Some(value) => Preferences::parse(&value.to_string()),
+            None => Preferences::default(),
+        }
+    }
+
+    /// # Are there no preferences?
+    pub fn is_empty(&self) -> bool {
+        *self == Preferences::default()
+    }
+}
+
+impl fmt::Display for Preferences {
+    /// # Format preferences as a Prefer or Preference-Applied header value
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::prefer::{Preferences, Return};
+    /// let applied = Preferences {
+    ///     return_preference: Some(Return::Minimal),
+    ///     ..Preferences::default()
+    /// };
+    /// assert_eq!(applied.to_string(), "return=minimal".to_string());
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut values: Vec<String> = Vec::new();
+        if let Some(return_preference) = self.return_preference {
+            values.push(match return_preference {
+                Return::Minimal => String::from("return=minimal"),
+                Return::Representation => String::from("return=representation"),
+            });
+        }
+        if self.respond_async {
+            values.push(String::from("respond-async"));
+        }
+        if let Some(wait) = self.wait {
+            values.push(format!("wait={}", wait));
+        }
+        if let Some(handling) = self.handling {
+            values.push(match handling {
+                Handling::Lenient => String::from("handling=lenient"),
+                Handling::Strict => String::from("handling=strict"),
+            });
+        }
+        for (name, value) in self.other.iter() {
+            values.push(match value {
+                Some(value) => format!("{}={}", name, value),
+                None => name.to_string(),
+            });
+        }
+        write!(f, "{}", values.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let preferences = Preferences::parse(
+            "RETURN=Minimal; foo=bar, return=representation, wait=x, priority=\"5\", lenient",
+        );
+        assert_eq!(preferences.return_preference, Some(Return::Minimal));
+        assert_eq!(preferences.wait, None);
+        assert_eq!(
+            preferences.other,
+            vec![
+                ("priority".to_string(), Some("5".to_string())),
+                ("lenient".to_string(), None)
+            ]
+        );
+        assert!(Preferences::parse("").is_empty());
+        assert!(Preferences::parse(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_to_string() {
+        let preferences = Preferences::parse("handling=lenient, respond-async, wait=5, a=b, c");
+        assert_eq!(
+            preferences.to_string(),
+            "respond-async, wait=5, handling=lenient, a=b, c".to_string()
+        );
+        assert_eq!(Preferences::parse(&preferences.to_string()), preferences);
+    }
+}
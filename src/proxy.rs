@@ -0,0 +1,591 @@
+//! # Adjusts messages passing through a reverse proxy.
+//!
+//! Requests are forwarded to the upstream with Forwarded and X-Forwarded-* headers describing the
+//! original request. Upstream responses refer to the upstream origin and mount path in Location
+//! headers and cookie attributes, these are rewritten to the public origin and path before the
+//! response is re-served.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use headers::Headers;
+use request;
+use request::{HeaderValuePart, HeaderValueParts};
+use response;
+use uri::{Uri, UriError};
+
+/// # Headers that only apply to a single connection and are never forwarded
+pub const HOP_BY_HOP: [&str; 8] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// # Remove hop-by-hop headers and the headers listed in Connection
+/// ## Usage
+/// ```rust
+/// use milstian_http::headers::Headers;
+/// use milstian_http::proxy::strip_hop_by_hop;
+/// let mut headers = Headers::new();
+/// headers.insert("Connection".to_string(), "close, X-Debug".to_string());
+/// headers.insert("X-Debug".to_string(), "1".to_string());
+/// headers.insert("Content-Type".to_string(), "text/html".to_string());
+/// strip_hop_by_hop(&mut headers);
+/// assert_eq!(headers.keys().collect::<Vec<&String>>(), vec!["Content-Type"]);
+/// ```
+pub fn strip_hop_by_hop(headers: &mut Headers) {
+    let names = get_hop_by_hop(
+        headers
+            .iter()
+            .map(|(name, value)| (name, value.to_string())),
+    );
+    headers.retain(|name, _| !names.iter().any(|hop| hop.eq_ignore_ascii_case(name)));
+}
+
+/// Get the hop-by-hop header names, including those listed in Connection
+fn get_hop_by_hop<'a, I>(headers: I) -> Vec<String>
+where
+    I: Iterator<Item = (&'a String, String)>,
+{
+    let mut names: Vec<String> = HOP_BY_HOP.iter().map(|name| name.to_string()).collect();
+    for (name, value) in headers {
+        if name.eq_ignore_ascii_case("Connection") {
+            names.extend(value.split(',').map(|name| name.trim().to_string()));
+        }
+    }
+    names
+}
+
+/// # Serialize a request for forwarding to upstream_host
+/// Like `forwarded_request_for` without a known client address.
+/// ## Usage
+/// ```rust
+/// use milstian_http::proxy::forwarded_request;
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(
+///     b"GET /a HTTP/1.1\r\nHost: example.com\r\nConnection: keep-alive\r\n\r\n",
+/// )
+/// .expect("A request");
+/// assert_eq!(
+///     forwarded_request(&request, "10.0.0.2:8080"),
+///     b"GET /a HTTP/1.1\r\nForwarded: host=example.com;proto=http\r\nHost: 10.0.0.2:8080\r\nX-Forwarded-Host: example.com\r\nX-Forwarded-Proto: http\r\n\r\n"
+///         .to_vec()
+/// );
+/// ```
+pub fn forwarded_request(original: &request::Message, upstream_host: &str) -> Vec<u8> {
+    forwarded_request_for(original, upstream_host, None)
+}
+
+/// # Serialize a request from client for forwarding to upstream_host
+/// The Host header is replaced, hop-by-hop headers are removed and the original client, host and
+/// protocol are appended to Forwarded and X-Forwarded-For. X-Forwarded-Host and
+/// X-Forwarded-Proto are replaced by the host and protocol this proxy received, since a client
+/// can send any value. The body is forwarded as received and framed by Content-Length.
+/// ## Usage
+/// ```rust
+/// use milstian_http::proxy::forwarded_request_for;
+/// use milstian_http::request::Message;
+/// use std::net::{IpAddr, Ipv4Addr};
+/// let request = Message::from_tcp_stream(
+///     b"GET / HTTP/1.1\r\nHost: example.com\r\nX-Forwarded-For: 192.0.2.1\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let forwarded = forwarded_request_for(
+///     &request,
+///     "backend",
+///     Some(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7))),
+/// );
+/// let forwarded = String::from_utf8(forwarded).expect("A UTF-8 request");
+/// assert!(forwarded.contains("\r\nForwarded: for=198.51.100.7;host=example.com;proto=http\r\n"));
+/// assert!(forwarded.contains("\r\nX-Forwarded-For: 192.0.2.1, 198.51.100.7\r\n"));
+/// ```
+pub fn forwarded_request_for(
+    original: &request::Message,
+    upstream_host: &str,
+    client: Option<IpAddr>,
+) -> Vec<u8> {
+    let mut request = original.clone();
+    let host = original.header("Host").map(|host| host.to_string());
+    let mut names = get_hop_by_hop(
+        request
+            .headers
+            .iter()
+            .map(|(name, value)| (name, value.to_string())),
+    );
+    names.push(String::from("Host"));
+    names.push(String::from("Content-Length"));
+    request.headers.retain(|name, _| {
+        !names
+            .iter()
+            .any(|removed| removed.eq_ignore_ascii_case(name))
+    });
+    request
+        .headers
+        .insert("Host".to_string(), header_value(upstream_host));
+
+    let mut forwarded: Vec<String> = Vec::new();
+    if let Some(client) = client {
+        let node = match client {
+            IpAddr::V4(address) => address.to_string(),
+            IpAddr::V6(address) => format!("[{}]", address),
+        };
+        forwarded.push(format!("for={}", forwarded_value(&node)));
+        append_header(&mut request.headers, "X-Forwarded-For", &client.to_string());
+    }
+    request
+        .headers
+        .retain(|name, _| !name.eq_ignore_ascii_case("X-Forwarded-Host"));
+    if let Some(ref host) = host {
+        forwarded.push(format!("host={}", forwarded_value(host)));
+        set_header(&mut request.headers, "X-Forwarded-Host", host);
+    }
+    forwarded.push(String::from("proto=http"));
+    set_header(&mut request.headers, "X-Forwarded-Proto", "http");
+    append_header(&mut request.headers, "Forwarded", &forwarded.join(";"));
+
+    request.to_bytes()
+}
+
+/// # Maps the origin and mount path of an upstream to those the public reaches it at
+/// ## Usage
+/// ```rust
+/// use milstian_http::proxy::Rewrite;
+/// use milstian_http::response::Message;
+/// let rewrite = Rewrite::new("http://10.0.0.2:8080/", "https://example.com/api/")
+///     .expect("A rewrite");
+/// let mut response = Message::with_status(302);
+/// response
+///     .headers
+///     .insert("Location".to_string(), "http://10.0.0.2:8080/login?next=%2F".to_string());
+/// response.headers.insert(
+///     "Set-Cookie".to_string(),
+///     "session=abc; Domain=10.0.0.2; Path=/".to_string(),
+/// );
+/// response
+///     .headers
+///     .insert("Transfer-Encoding".to_string(), "chunked".to_string());
+/// rewrite.response(&mut response);
+/// assert_eq!(
+///     response.headers.get("Location"),
+///     Some(&"https://example.com/api/login?next=%2F".to_string())
+/// );
+/// assert_eq!(
+///     response.headers.get("Set-Cookie"),
+///     Some(&"session=abc; Domain=example.com; Path=/api/".to_string())
+/// );
+/// assert_eq!(response.headers.get("Transfer-Encoding"), None);
+/// assert_eq!(response.headers.get("Content-Length"), Some(&"0".to_string()));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rewrite {
+    pub public: Uri,   // Normalized scheme, authority and mount path seen by clients
+    pub upstream: Uri, // Normalized scheme, authority and path of the upstream
+}
+
+impl Rewrite {
+    /// # Create a rewrite between two absolute URIs, their paths are the mount points
+    pub fn new(upstream: &str, public: &str) -> Result<Rewrite, UriError> {
+        let parse = |input: &str| -> Result<Uri, UriError> {
+            let uri = Uri::parse(input)?.normalized();
+            if uri.scheme.is_none() || uri.authority.is_none() {
+                return Err(UriError::MissingHost);
+            }
+            Ok(Uri {
+                query: None,
+                fragment: None,
+                ..uri
+            })
+        };
+        Ok(Rewrite {
+            public: parse(public)?,
+            upstream: parse(upstream)?,
+        })
+    }
+
+    /// # Map a path below the upstream mount path to the public mount path
+    /// Other paths are returned unchanged.
+    pub fn path(&self, path: &str) -> String {
+        let upstream = self.upstream.path.trim_end_matches('/');
+        let is_below = path == upstream
+            || path
+                .strip_prefix(upstream)
+                .is_some_and(|rest| rest.starts_with('/'));
+        if !is_below {
+            return path.to_string();
+        }
+        let path = format!(
+            "{}{}",
+            self.public.path.trim_end_matches('/'),
+            &path[upstream.len()..]
+        );
+        if path.is_empty() {
+            String::from("/")
+        } else {
+            path
+        }
+    }
+
+    /// # Map a Location URI of the upstream to the public origin and path
+    /// Absolute URIs of other origins and relative paths are returned unchanged.
+    pub fn location(&self, location: &str) -> String {
+        let uri = match Uri::parse(location) {
+            Ok(uri) => uri,
+            Err(_) => return location.to_string(),
+        };
+        let normalized = uri.normalized();
+        let is_upstream = match normalized.authority {
+            Some(_) => {
+                normalized.scheme == self.upstream.scheme
+                    && normalized.authority == self.upstream.authority
+            }
+            None => uri.scheme.is_none() && uri.path.starts_with('/'),
+        };
+        if !is_upstream {
+            return location.to_string();
+        }
+        let uri = Uri {
+            scheme: if uri.authority.is_some() {
+                self.public.scheme.clone()
+            } else {
+                None
+            },
+            authority: if uri.authority.is_some() {
+                self.public.authority.clone()
+            } else {
+                None
+            },
+            path: self.path(&uri.path),
+            ..uri
+        };
+        uri.to_string()
+    }
+
+    /// # Map the Domain and Path attributes of a Set-Cookie value
+    /// Domains of the upstream host become the public host, other domains are kept.
+    pub fn set_cookie(&self, value: &str) -> String {
+        let attributes: Vec<String> = value
+            .split(';')
+            .enumerate()
+            .map(|(index, attribute)| {
+                let (name, attribute_value) = match attribute.split_once('=') {
+                    Some(pair) if index > 0 => pair,
+                    _ => return attribute.to_string(),
+                };
+                if name.trim().eq_ignore_ascii_case("Domain") {
+                    match self.domain(attribute_value.trim()) {
+                        Some(domain) => format!("{}={}", name, domain),
+                        None => attribute.to_string(),
+                    }
+                } else if name.trim().eq_ignore_ascii_case("Path") {
+                    format!("{}={}", name, self.path(attribute_value.trim()))
+                } else {
+                    attribute.to_string()
+                }
+            })
+            .collect();
+        attributes.join(";")
+    }
+
+    /// # Adjust an upstream response for re-serving to the public
+    /// Hop-by-hop headers are removed, Location, Content-Location and cookies are rewritten and
+    /// the framing is recomputed from the decoded body. Empty bodies keep their Content-Length,
+    /// like responses to HEAD requests.
+    pub fn response(&self, response: &mut response::Message) {
+        strip_hop_by_hop(&mut response.headers);
+        // Fields are found in any case so no upstream URI is passed on
+        response.headers.retain(|name, value| {
+            if name.eq_ignore_ascii_case("Location")
+                || name.eq_ignore_ascii_case("Content-Location")
+            {
+                *value = self.location(value);
+            } else if name.eq_ignore_ascii_case("Set-Cookie") {
+                let cookies: Vec<String> = split_set_cookie(value)
+                    .iter()
+                    .map(|cookie| self.set_cookie(cookie))
+                    .collect();
+                *value = cookies.join(", ");
+            }
+            true
+        });
+        for cookie in response.cookies.iter_mut() {
+            if let Some(domain) = cookie
+                .domain
+                .as_ref()
+                .and_then(|domain| self.domain(domain))
+            {
+                cookie.domain = Some(domain);
+            }
+            if let Some(path) = cookie.path.as_ref().map(|path| self.path(path)) {
+                cookie.path = Some(path);
+            }
+        }
+
+        let status_code = response.status_code().unwrap_or(200);
+        let is_bodiless = status_code < 200 || status_code == 204;
+        if is_bodiless || !response.body.is_empty() || response.header("Content-Length").is_none() {
+            response
+                .headers
+                .retain(|name, _| !name.eq_ignore_ascii_case("Content-Length"));
+            if !is_bodiless {
+                let length = response.body.len();
+                response.set_content_length(length);
+            }
+        }
+    }
+
+    /// Get the public host for a cookie domain of the upstream host
+    fn domain(&self, domain: &str) -> Option<String> {
+        let host = |uri: &Uri| -> String {
+            let authority = uri.authority.clone().unwrap_or_default();
+            let authority = match authority.rfind('@') {
+                Some(index) => authority[index + 1..].to_string(),
+                None => authority,
+            };
+            if authority.starts_with('[') {
+                return authority
+                    .split_once(']')
+                    .map(|(host, _)| format!("{}]", host))
+                    .unwrap_or(authority);
+            }
+            authority.split(':').next().unwrap_or("").to_string()
+        };
+        if domain
+            .trim_start_matches('.')
+            .eq_ignore_ascii_case(&host(&self.upstream))
+        {
+            Some(host(&self.public))
+        } else {
+            None
+        }
+    }
+}
+
+/// Append value to the comma-separated list of a header, found case-insensitively
+fn append_header(headers: &mut HashMap<String, HeaderValueParts>, name: &str, value: &str) {
+    let key = headers
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case(name))
+        .cloned();
+    let value = match key.and_then(|key| headers.remove(&key)) {
+        Some(existing) => format!("{}, {}", existing, value),
+        None => value.to_string(),
+    };
+    headers.insert(name.to_string(), header_value(&value));
+}
+
+/// Replace every field named name, in any case, by one with value
+fn set_header(headers: &mut HashMap<String, HeaderValueParts>, name: &str, value: &str) {
+    headers.retain(|key, _| !key.eq_ignore_ascii_case(name));
+    headers.insert(name.to_string(), header_value(value));
+}
+
+/// A header value serialized as given
+fn header_value(value: &str) -> HeaderValueParts {
+    HeaderValueParts {
+        parts: vec![vec![HeaderValuePart::Single(value.to_string())]],
+    }
+}
+
+/// Quote a Forwarded parameter value unless it is a token
+fn forwarded_value(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value.chars().all(|character| {
+            character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(character)
+        });
+    if is_token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Split Set-Cookie values that were combined with commas, commas in Expires dates are kept
+fn split_set_cookie(value: &str) -> Vec<&str> {
+    let mut cookies: Vec<&str> = Vec::new();
+    let mut start = 0;
+    for (index, _) in value.match_indices(',') {
+        let next = &value[index + 1..];
+        let next = next.split(';').next().unwrap_or("");
+        let starts_cookie = next
+            .split_once('=')
+            .is_some_and(|(name, _)| !name.trim().is_empty() && !name.contains(','));
+        if starts_cookie {
+            cookies.push(value[start..index].trim());
+            start = index + 1;
+        }
+    }
+    cookies.push(value[start..].trim());
+    cookies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cookie::SetCookie;
+
+    #[test]
+    fn test_forwarded_request() {
+        let request = request::Message::from_tcp_stream(
+            b"POST /form HTTP/1.1\r\nhost: example.com:8443\r\nConnection: Upgrade, X-Secret\r\nUpgrade: websocket\r\nX-Secret: 1\r\nForwarded: for=192.0.2.1\r\nX-Forwarded-Proto: https\r\nx-forwarded-host: evil.example\r\nContent-Length: 3\r\n\r\na=b",
+        )
+        .expect("A request");
+        let forwarded = forwarded_request_for(
+            &request,
+            "backend",
+            Some("2001:db8::1".parse().expect("An address")),
+        );
+        let forwarded = request::Message::from_tcp_stream(&forwarded).expect("A forwarded request");
+        let header = |name: &str| forwarded.header(name).map(|value| value.to_string());
+        assert_eq!(header("Host"), Some("backend".to_string()));
+        assert_eq!(
+            forwarded
+                .headers
+                .get("Forwarded")
+                .map(|value| value.to_string()),
+            request::Message::get_header_field(
+                "Forwarded: for=192.0.2.1, for=\"[2001:db8::1]\";host=\"example.com:8443\";proto=http"
+            )
+            .map(|(_, value)| value.to_string())
+        );
+        assert_eq!(header("X-Forwarded-For"), Some("2001:db8::1".to_string()));
+        assert_eq!(
+            header("X-Forwarded-Host"),
+            Some("example.com:8443".to_string())
+        );
+        assert_eq!(header("X-Forwarded-Proto"), Some("http".to_string()));
+        assert_eq!(header("Content-Length"), Some("3".to_string()));
+        assert_eq!(header("Connection"), None);
+        assert_eq!(header("Upgrade"), None);
+        assert_eq!(header("X-Secret"), None);
+        assert_eq!(forwarded.form_value("a"), Some("b"));
+        assert_eq!(forwarded.headers.len(), 6);
+
+        // Bodies are forwarded as received
+        let request = request::Message::from_tcp_stream(
+            b"POST /api HTTP/1.1\r\nHost: example.com\r\nContent-Type: application/json\r\nContent-Length: 13\r\n\r\n{\"a\":\"b&c=d\"}",
+        )
+        .expect("A request");
+        let forwarded = forwarded_request(&request, "backend");
+        assert!(forwarded.ends_with(b"\r\n\r\n{\"a\":\"b&c=d\"}"));
+        let forwarded = request::Message::from_tcp_stream(&forwarded).expect("A forwarded request");
+        assert_eq!(
+            forwarded
+                .header("Content-Length")
+                .map(|value| value.to_string()),
+            Some("13".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rewrite_paths() {
+        let rewrite =
+            Rewrite::new("http://backend:8080/app", "https://Example.com:443").expect("A rewrite");
+        assert_eq!(rewrite.public.authority, Some("example.com".to_string()));
+        assert_eq!(rewrite.path("/app/a"), "/a".to_string());
+        assert_eq!(rewrite.path("/app"), "/".to_string());
+        assert_eq!(rewrite.path("/application"), "/application".to_string());
+        assert_eq!(
+            rewrite.location("HTTP://BACKEND:8080/app/a?b#c"),
+            "https://example.com/a?b#c".to_string()
+        );
+        assert_eq!(rewrite.location("/app/a"), "/a".to_string());
+        assert_eq!(
+            rewrite.location("http://other/app/a"),
+            "http://other/app/a".to_string()
+        );
+        assert_eq!(rewrite.location("a/b"), "a/b".to_string());
+
+        assert!(Rewrite::new("/relative", "https://example.com").is_err());
+    }
+
+    #[test]
+    fn test_rewrite_response() {
+        let rewrite =
+            Rewrite::new("http://backend/", "https://example.com/shop/").expect("A rewrite");
+        let mut response = response::Message::with_status(200);
+        response.body = b"abc".to_vec();
+        response.headers.insert(
+            "Connection".to_string(),
+            "keep-alive, X-Internal".to_string(),
+        );
+        response
+            .headers
+            .insert("X-Internal".to_string(), "1".to_string());
+        response
+            .headers
+            .insert("Content-Length".to_string(), "10".to_string());
+        response.headers.insert(
+            "Set-Cookie".to_string(),
+            "a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Path=/cart, b=2; domain=.BACKEND; HttpOnly, c=3; Domain=cdn.example"
+                .to_string(),
+        );
+        let mut cookie = SetCookie::new("d", "4");
+        cookie.domain = Some("backend".to_string());
+        cookie.path = Some("/".to_string());
+        response.cookies.push(cookie);
+        rewrite.response(&mut response);
+
+        assert_eq!(
+            response.headers.get("Set-Cookie"),
+            Some(&"a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Path=/shop/cart, b=2; domain=example.com; HttpOnly, c=3; Domain=cdn.example".to_string())
+        );
+        assert_eq!(response.cookies[0].domain, Some("example.com".to_string()));
+        assert_eq!(response.cookies[0].path, Some("/shop/".to_string()));
+        assert_eq!(
+            response.headers.get("Content-Length"),
+            Some(&"3".to_string())
+        );
+        assert_eq!(response.headers.get("Connection"), None);
+        assert_eq!(response.headers.get("X-Internal"), None);
+
+        // Responses to HEAD requests keep their length, responses without content lose it
+        let mut response = response::Message::with_status(200);
+        response
+            .headers
+            .insert("Content-Length".to_string(), "10".to_string());
+        rewrite.response(&mut response);
+        assert_eq!(
+            response.headers.get("Content-Length"),
+            Some(&"10".to_string())
+        );
+        let mut response = response::Message::with_status(204);
+        response
+            .headers
+            .insert("Content-Length".to_string(), "0".to_string());
+        rewrite.response(&mut response);
+        assert_eq!(response.headers.get("Content-Length"), None);
+
+        // Fields are rewritten whatever the case of their names
+        let mut response = response::Message::with_status(302);
+        for (name, value) in [
+            ("location", "http://backend/login"),
+            ("content-location", "/item"),
+            ("set-cookie", "a=1; Domain=backend"),
+            ("content-length", "0"),
+        ] {
+            response.headers.insert(name.to_string(), value.to_string());
+        }
+        response.body = b"ab".to_vec();
+        rewrite.response(&mut response);
+        assert_eq!(
+            response.headers.iter().collect::<Vec<(&String, &String)>>(),
+            vec![
+                (
+                    &"location".to_string(),
+                    &"https://example.com/shop/login".to_string()
+                ),
+                (&"content-location".to_string(), &"/shop/item".to_string()),
+                (
+                    &"set-cookie".to_string(),
+                    &"a=1; Domain=example.com".to_string()
+                ),
+                (&"Content-Length".to_string(), &"2".to_string()),
+            ]
+        );
+    }
+}
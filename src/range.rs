@@ -0,0 +1,295 @@
+//! # Handles byte range requests.
+
+use request;
+
+/// # An inclusive range of byte positions in a representation
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ByteRange {
+    pub first: u64,
+    pub last: u64,
+}
+
+impl ByteRange {
+    /// # Get the number of bytes in range
+    /// Inverted ranges are empty and sizes that don't fit a u64 saturate.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::range::ByteRange;
+    /// assert_eq!(ByteRange { first: 5, last: 9 }.size(), 5);
+    /// assert_eq!(ByteRange { first: 9, last: 5 }.size(), 0);
+    /// assert_eq!(ByteRange { first: 0, last: u64::MAX }.size(), u64::MAX);
+    /// ```
+    pub fn size(&self) -> u64 {
+        self.last
+            .checked_sub(self.first)
+            .map_or(0, |span| span.saturating_add(1))
+    }
+
+    /// # Get the Content-Range header value for a representation of length
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::range::ByteRange;
+    /// assert_eq!(ByteRange { first: 0, last: 9 }.content_range(100), "bytes 0-9/100".to_string());
+    /// ```
+    pub fn content_range(&self, length: u64) -> String {
+        format!("bytes {}-{}/{}", self.first, self.last, length)
+    }
+}
+
+/// # Limits protecting against range amplification
+#[derive(Clone, Debug)]
+pub struct RangeLimits {
+    pub max_ranges: usize,           // Served ranges after coalescing
+    pub max_requested_ranges: usize, // Ranges in the header before coalescing
+}
+
+impl Default for RangeLimits {
+    fn default() -> RangeLimits {
+        RangeLimits {
+            max_ranges: 16,
+            max_requested_ranges: 64,
+        }
+    }
+}
+
+/// # How a request for a representation should be answered
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Ranges {
+    Full,                    // 200 with the complete representation
+    Partial(Vec<ByteRange>), // 206 with sorted, non-overlapping ranges
+    Unsatisfiable,           // 416 with Content-Range: bytes */length
+}
+
+/// # Parse a Range header value for a representation of length
+/// Invalid headers and headers exceeding the limits are ignored by serving the full representation.
+/// ## Usage
+/// ```rust
+/// use milstian_http::range::{evaluate_header, ByteRange, RangeLimits, Ranges};
+/// let limits = RangeLimits::default();
+/// assert_eq!(
+///     evaluate_header("bytes=0-4, 3-9, -10", 100, &limits),
+///     Ranges::Partial(vec![
+///         ByteRange { first: 0, last: 9 },
+///         ByteRange { first: 90, last: 99 }
+///     ])
+/// );
+/// assert_eq!(evaluate_header("bytes=200-", 100, &limits), Ranges::Unsatisfiable);
+/// assert_eq!(evaluate_header("lines=1-2", 100, &limits), Ranges::Full);
+/// ```
+pub fn evaluate_header(value: &str, length: u64, limits: &RangeLimits) -> Ranges {
+    let value = value.trim();
+    let specs = match value.get(..6) {
+        Some(unit) if unit.eq_ignore_ascii_case("bytes=") => &value[6..],
+        _ => return Ranges::Full,
+    };
+
+    let mut requested: usize = 0;
+    let mut ranges: Vec<ByteRange> = Vec::new();
+    for spec in specs.split(',').map(|spec| spec.trim()) {
+        if spec.is_empty() {
+            continue;
+        }
+        requested += 1;
+        if requested > limits.max_requested_ranges {
+            return Ranges::Full;
+        }
+        let (first, last) = match spec.split_once('-') {
+            Some(positions) => positions,
+            None => return Ranges::Full,
+        };
+        if first.is_empty() {
+            // A suffix of the representation
+            let suffix = match parse_position(last) {
+                Some(suffix) => suffix,
+                None => return Ranges::Full,
+            };
+            if suffix > 0 && length > 0 {
+                ranges.push(ByteRange {
+                    first: length.saturating_sub(suffix),
+                    last: length - 1,
+                });
+            }
+            continue;
+        }
+        let first = match parse_position(first) {
+            Some(first) => first,
+            None => return Ranges::Full,
+        };
+        let last = if last.is_empty() {
+            u64::MAX
+        } else {
+            match parse_position(last) {
+                Some(last) if last >= first => last,
+                _ => return Ranges::Full,
+            }
+        };
+        if first < length {
+            ranges.push(ByteRange {
+                first,
+                last: last.min(length - 1),
+            });
+        }
+    }
+    if requested == 0 {
+        return Ranges::Full;
+    }
+
+    let ranges = coalesce(ranges);
+    if ranges.is_empty() {
+        return Ranges::Unsatisfiable;
+    }
+    if ranges.len() > limits.max_ranges {
+        return Ranges::Full;
+    }
+    Ranges::Partial(ranges)
+}
+
+/// Parse a byte position, which is only digits unlike the integers u64 parses
+fn parse_position(text: &str) -> Option<u64> {
+    if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+/// # Evaluate the Range header of a GET request for a representation of length
+/// ## Usage
+/// ```rust
+/// use milstian_http::range::{evaluate, ByteRange, RangeLimits, Ranges};
+/// use milstian_http::request::Message;
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nRange: bytes=5-\r\n")
+///     .expect("A request");
+/// assert_eq!(
+///     evaluate(&request, 10, &RangeLimits::default()),
+///     Ranges::Partial(vec![ByteRange { first: 5, last: 9 }])
+/// );
+/// ```
+pub fn evaluate(request: &request::Message, length: u64, limits: &RangeLimits) -> Ranges {
+    if request.request_line.method != request::Method::Get {
+        return Ranges::Full;
+    }
+    match request.header("Range") {
+        Some(value) => evaluate_header(&value.to_string(), length, limits),
+        None => Ranges::Full,
+    }
+}
+
+/// # Sort ranges and merge those that overlap or are adjacent
+/// ## Usage
+/// ```rust
+/// use milstian_http::range::{coalesce, ByteRange};
+/// assert_eq!(
+///     coalesce(vec![
+///         ByteRange { first: 10, last: 19 },
+///         ByteRange { first: 0, last: 4 },
+///         ByteRange { first: 5, last: 6 }
+///     ]),
+///     vec![ByteRange { first: 0, last: 6 }, ByteRange { first: 10, last: 19 }]
+/// );
+/// ```
+pub fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.first);
+    let mut coalesced: Vec<ByteRange> = Vec::new();
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(previous) if range.first <= previous.last.saturating_add(1) => {
+                previous.last = previous.last.max(range.last);
+            }
+            _ => coalesced.push(range),
+        }
+    }
+    coalesced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_header() {
+        let limits = RangeLimits::default();
+        assert_eq!(
+            evaluate_header("bytes=0-0", 10, &limits),
+            Ranges::Partial(vec![ByteRange { first: 0, last: 0 }])
+        );
+        assert_eq!(
+            evaluate_header("BYTES= 2-100 ,", 10, &limits),
+            Ranges::Partial(vec![ByteRange { first: 2, last: 9 }])
+        );
+        assert_eq!(
+            evaluate_header("bytes=-100", 10, &limits),
+            Ranges::Partial(vec![ByteRange { first: 0, last: 9 }])
+        );
+        assert_eq!(
+            evaluate_header("bytes=-0", 10, &limits),
+            Ranges::Unsatisfiable
+        );
+        assert_eq!(
+            evaluate_header("bytes=0-", 0, &limits),
+            Ranges::Unsatisfiable
+        );
+        assert_eq!(
+            evaluate_header("bytes=10-20", 10, &limits),
+            Ranges::Unsatisfiable
+        );
+
+        // Unsatisfiable specs are dropped when others are satisfiable
+        assert_eq!(
+            evaluate_header("bytes=20-30, 0-1", 10, &limits),
+            Ranges::Partial(vec![ByteRange { first: 0, last: 1 }])
+        );
+
+        // Invalid syntax ignores the header
+        assert_eq!(evaluate_header("bytes=", 10, &limits), Ranges::Full);
+        assert_eq!(evaluate_header("bytes=5-1", 10, &limits), Ranges::Full);
+        assert_eq!(evaluate_header("bytes=a-1", 10, &limits), Ranges::Full);
+        assert_eq!(evaluate_header("bytes=1", 10, &limits), Ranges::Full);
+        assert_eq!(evaluate_header("bytes", 10, &limits), Ranges::Full);
+        assert_eq!(evaluate_header("bytes=+1-2", 10, &limits), Ranges::Full);
+        assert_eq!(evaluate_header("bytes=1-+2", 10, &limits), Ranges::Full);
+        assert_eq!(evaluate_header("bytes=-+2", 10, &limits), Ranges::Full);
+    }
+
+    #[test]
+    fn test_evaluate_header_limits() {
+        let limits = RangeLimits {
+            max_ranges: 2,
+            max_requested_ranges: 4,
+        };
+
+        // Overlapping ranges are coalesced before counting
+        assert_eq!(
+            evaluate_header("bytes=0-5, 1-5, 2-5, 3-9", 100, &limits),
+            Ranges::Partial(vec![ByteRange { first: 0, last: 9 }])
+        );
+        assert_eq!(
+            evaluate_header("bytes=0-5, 1-5, 2-5, 3-9, 4-9", 100, &limits),
+            Ranges::Full
+        );
+        assert_eq!(
+            evaluate_header("bytes=0-1, 10-11, 20-21", 100, &limits),
+            Ranges::Full
+        );
+        assert_eq!(
+            evaluate_header("bytes=0-1, 2-3, 20-21", 100, &limits),
+            Ranges::Partial(vec![
+                ByteRange { first: 0, last: 3 },
+                ByteRange {
+                    first: 20,
+                    last: 21
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let limits = RangeLimits::default();
+        let request = request::Message::from_tcp_stream(b"HEAD / HTTP/1.1\r\nRange: bytes=0-1\r\n")
+            .expect("A request");
+        assert_eq!(evaluate(&request, 10, &limits), Ranges::Full);
+        let request = request::Message::from_tcp_stream(b"GET / HTTP/1.1\r\n").expect("A request");
+        assert_eq!(evaluate(&request, 10, &limits), Ranges::Full);
+        assert_eq!(ByteRange { first: 3, last: 3 }.size(), 1);
+    }
+}
@@ -0,0 +1,373 @@
+//! # Records requests as received so they can be replayed against later parser versions.
+//!
+//! A recording holds the raw bytes of a request and a deterministic summary of how they were
+//! parsed. Recordings are written as self-contained binary records that can be appended to one
+//! file: the magic bytes MHR1, the raw bytes and then the outcome, where numbers are 32-bit big
+//! endian and strings are UTF-8 prefixed by their length.
+
+use std::io;
+use std::io::{Read, Write};
+
+use request::{BodyContentType, Message, ParserConfig, WireSize};
+
+/// # Bytes that start each record
+pub const MAGIC: &[u8; 4] = b"MHR1";
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// # A parsed request summarized independent of hash map order
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Summary {
+    pub body_fields: Vec<(String, Vec<u8>)>, // Form or multi-part fields sorted by name
+    pub chunked: bool,                       // Was the body received chunked?
+    pub fields: Vec<(String, String)>,       // Sorted header names and values
+    pub query: Vec<(String, String)>,        // Sorted query argument names and values
+    pub request_line: String,                // Like GET / HTTP/1.1
+    pub wire_size: WireSize,
+}
+
+impl<'a> From<&'a Message> for Summary {
+    fn from(message: &'a Message) -> Summary {
+        // Repeated form fields keep their order, spooled parts are read from their file
+        let mut body_fields: Vec<(String, Vec<u8>)> = match message.body {
+            BodyContentType::SinglePart(ref values) => values
+                .iter()
+                .flat_map(|(name, values)| {
+                    values
+                        .iter()
+                        .map(move |value| (name.clone(), value.clone().into_bytes()))
+                })
+                .collect(),
+            BodyContentType::MultiPart(ref values) => values
+                .iter()
+                .map(|(name, value)| {
+                    let mut body = value.body.clone();
+                    if let Some(ref file) = value.file {
+                        let _ = file.open().and_then(|mut file| file.read_to_end(&mut body));
+                    }
+                    (name.clone(), body)
+                })
+                .collect(),
+            BodyContentType::Raw(_) => Vec::new(),
+        };
+        body_fields.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut fields: Vec<(String, String)> = message
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect();
+        fields.sort();
+        let mut query: Vec<(String, String)> = message
+            .request_line
+            .query_arguments
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        query.sort();
+        Summary {
+            body_fields,
+            chunked: message.is_chunked(),
+            fields,
+            query,
+            request_line: format!(
+                "{} {} {}",
+                Message::get_method_text(&message.request_line.method),
+                message.request_line.request_uri,
+                Message::get_protocol_text(&message.request_line.protocol)
+            ),
+            wire_size: message.wire_size,
+        }
+    }
+}
+
+/// # How the parser handled the raw bytes of a recording
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Parsed(Summary),
+    Rejected(String), // The status line and error, like 400 Bad Request: invalid request line
+}
+
+impl Outcome {
+    /// # Parse bytes with config and summarize the result
+    pub fn of(bytes: &[u8], config: &ParserConfig) -> Outcome {
+        match Message::from_tcp_stream_with_config(bytes, config) {
+            Ok(message) => Outcome::Parsed(Summary::from(&message)),
+            Err(error) => Outcome::Rejected(format!("{}: {}", error.status(), error)),
+        }
+    }
+}
+
+/// # Raw request bytes and how they were parsed when recorded
+/// ## Usage
+/// ```rust
+/// use milstian_http::record::{read, write, Outcome, Recording};
+/// use milstian_http::request::ParserConfig;
+/// let config = ParserConfig::default();
+/// let mut file: Vec<u8> = Vec::new();
+/// write(&mut file, &Recording::new(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n", &config)).expect("A write");
+/// write(&mut file, &Recording::new(b"GET / HTTP/9.9\r\n\r\n", &config)).expect("A write");
+///
+/// let mut reader = file.as_slice();
+/// let first = read(&mut reader).expect("A read").expect("A recording");
+/// match first.outcome {
+///     Outcome::Parsed(ref summary) => assert_eq!(summary.request_line, "GET / HTTP/1.1".to_string()),
+///     Outcome::Rejected(_) => panic!("Expected a parsed request"),
+/// }
+/// assert!(first.is_reproduced(&config));
+/// let second = read(&mut reader).expect("A read").expect("A recording");
+/// assert_eq!(
+///     second.outcome,
+///     Outcome::Rejected(
+///         "505 HTTP Version Not Supported: protocol HTTP/9.9 is not supported".to_string()
+///     )
+/// );
+/// assert!(read(&mut reader).expect("A read").is_none());
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Recording {
+    pub bytes: Vec<u8>, // The request as received
+    pub outcome: Outcome,
+}
+
+impl Recording {
+    /// # Record bytes and how they parse with config
+    pub fn new(bytes: &[u8], config: &ParserConfig) -> Recording {
+        Recording {
+            bytes: bytes.to_vec(),
+            outcome: Outcome::of(bytes, config),
+        }
+    }
+
+    /// # Parse the recorded bytes again with config
+    pub fn replay(&self, config: &ParserConfig) -> Outcome {
+        Outcome::of(&self.bytes, config)
+    }
+
+    /// # Does parsing the recorded bytes with config give the recorded outcome?
+    pub fn is_reproduced(&self, config: &ParserConfig) -> bool {
+        self.replay(config) == self.outcome
+    }
+}
+
+fn write_length<W: Write>(writer: &mut W, length: usize) -> io::Result<()> {
+    if length > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "length does not fit a record",
+        ));
+    }
+    writer.write_all(&(length as u32).to_be_bytes())
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_length(writer, bytes.len())?;
+    writer.write_all(bytes)
+}
+
+fn write_pairs<W: Write>(writer: &mut W, pairs: &[(String, String)]) -> io::Result<()> {
+    write_length(writer, pairs.len())?;
+    for (name, value) in pairs.iter() {
+        write_bytes(writer, name.as_bytes())?;
+        write_bytes(writer, value.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_length<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes) as usize)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let length = read_length(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "record ends early",
+        ));
+    }
+    Ok(bytes)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| invalid_data("string is not UTF-8"))
+}
+
+fn read_pairs<R: Read>(reader: &mut R) -> io::Result<Vec<(String, String)>> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for _ in 0..read_length(reader)? {
+        pairs.push((read_string(reader)?, read_string(reader)?));
+    }
+    Ok(pairs)
+}
+
+/// # Append a recording to writer
+pub fn write<W: Write>(writer: &mut W, recording: &Recording) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_bytes(writer, &recording.bytes)?;
+    match recording.outcome {
+        Outcome::Parsed(ref summary) => {
+            writer.write_all(&[0])?;
+            write_bytes(writer, summary.request_line.as_bytes())?;
+            write_length(writer, summary.wire_size.request_line)?;
+            write_length(writer, summary.wire_size.header)?;
+            write_length(writer, summary.wire_size.body)?;
+            write_pairs(writer, &summary.fields)?;
+            write_pairs(writer, &summary.query)?;
+            write_length(writer, summary.body_fields.len())?;
+            for (name, value) in summary.body_fields.iter() {
+                write_bytes(writer, name.as_bytes())?;
+                write_bytes(writer, value)?;
+            }
+            writer.write_all(&[summary.chunked as u8])?;
+        }
+        Outcome::Rejected(ref reason) => {
+            writer.write_all(&[1])?;
+            write_bytes(writer, reason.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// # Read the next recording from reader, returns None at the end of input
+pub fn read<R: Read>(reader: &mut R) -> io::Result<Option<Recording>> {
+    let mut magic = [0; 4];
+    let mut filled = 0;
+    while filled < magic.len() {
+        match reader.read(&mut magic[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "record ends early",
+                ))
+            }
+            Ok(read) => filled += read,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    if &magic != MAGIC {
+        return Err(invalid_data("not a recording"));
+    }
+    let bytes = read_bytes(reader)?;
+    let mut tag = [0; 1];
+    reader.read_exact(&mut tag)?;
+    let outcome = match tag[0] {
+        0 => {
+            let request_line = read_string(reader)?;
+            let wire_size = WireSize {
+                request_line: read_length(reader)?,
+                header: read_length(reader)?,
+                body: read_length(reader)?,
+            };
+            let fields = read_pairs(reader)?;
+            let query = read_pairs(reader)?;
+            let mut body_fields: Vec<(String, Vec<u8>)> = Vec::new();
+            for _ in 0..read_length(reader)? {
+                body_fields.push((read_string(reader)?, read_bytes(reader)?));
+            }
+            let mut chunked = [0; 1];
+            reader.read_exact(&mut chunked)?;
+            let chunked = match chunked[0] {
+                0 => false,
+                1 => true,
+                _ => return Err(invalid_data("unknown chunked flag")),
+            };
+            Outcome::Parsed(Summary {
+                body_fields,
+                chunked,
+                fields,
+                query,
+                request_line,
+                wire_size,
+            })
+        }
+        1 => Outcome::Rejected(read_string(reader)?),
+        _ => return Err(invalid_data("unknown outcome")),
+    };
+    Ok(Some(Recording { bytes, outcome }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let config = ParserConfig::default();
+        let recording = Recording::new(
+            b"POST /a?y=2&x=1 HTTP/1.1\r\nHost: a\r\nContent-Length: 11\r\n\r\nb=c&a=d&a=e",
+            &config,
+        );
+        assert_eq!(
+            recording.outcome,
+            Outcome::Parsed(Summary {
+                body_fields: vec![
+                    ("a".to_string(), b"d".to_vec()),
+                    ("a".to_string(), b"e".to_vec()),
+                    ("b".to_string(), b"c".to_vec()),
+                ],
+                chunked: false,
+                fields: vec![
+                    ("Content-Length".to_string(), "11".to_string()),
+                    ("Host".to_string(), "a".to_string()),
+                ],
+                query: vec![
+                    ("x".to_string(), "1".to_string()),
+                    ("y".to_string(), "2".to_string()),
+                ],
+                request_line: "POST /a?y=2&x=1 HTTP/1.1".to_string(),
+                wire_size: WireSize {
+                    body: 11,
+                    header: 31,
+                    request_line: 26,
+                },
+            })
+        );
+        let mut file: Vec<u8> = Vec::new();
+        write(&mut file, &recording).expect("A write");
+        assert_eq!(
+            read(&mut file.as_slice()).expect("A read"),
+            Some(recording.clone())
+        );
+
+        // Bodies that differ only in field values or framing are told apart
+        let chunked = Recording::new(
+            b"POST /a?y=2&x=1 HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\nb\r\nb=c&a=d&a=f\r\n0\r\n\r\n",
+            &config,
+        );
+        match chunked.outcome {
+            Outcome::Parsed(ref summary) => {
+                assert!(summary.chunked);
+                assert_eq!(summary.body_fields[1], ("a".to_string(), b"f".to_vec()));
+            }
+            Outcome::Rejected(_) => panic!("Expected a parsed request"),
+        }
+        assert_ne!(chunked.outcome, recording.outcome);
+        let mut file: Vec<u8> = Vec::new();
+        write(&mut file, &chunked).expect("A write");
+        assert_eq!(read(&mut file.as_slice()).expect("A read"), Some(chunked));
+
+        // A stricter parser diverges from the recording
+        let strict = ParserConfig {
+            max_body_size: 4,
+            ..ParserConfig::default()
+        };
+        assert!(recording.is_reproduced(&config));
+        assert!(!recording.is_reproduced(&strict));
+
+        // Truncated and foreign input are errors
+        let error = read(&mut &file[..file.len() - 1]).expect_err("A truncated record");
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let error = read(&mut &b"MHR2"[..]).expect_err("A foreign record");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = read(&mut &b"MH"[..]).expect_err("A truncated magic");
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
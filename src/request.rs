@@ -1,25 +1,88 @@
 //! # Handles everything related to HTTP requests.
 
 use std::collections::HashMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::path::PathBuf;
 use std::str;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use capitalize_key;
-
-#[derive(Debug)]
+use chunked;
+#[cfg(feature = "serde")]
+use deserialize;
+use language::ContentLanguage;
+use media::MediaType;
+use multipart;
+use multipart::SpooledFile;
+use normalize_key;
+use uri;
+#[cfg(feature = "xml")]
+use xml;
+use HeaderNameCase;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum BodyContentType {
-    SinglePart(HashMap<String, String>),
+    SinglePart(HashMap<String, Vec<String>>), // Values of repeated fields in order
     MultiPart(HashMap<String, MultiPartValue>),
+    Raw(Vec<u8>), // Bodies kept as received, like XML with the xml feature
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Message {
     pub body: BodyContentType,
+    pub chunked: bool, // Was the body received with chunked Transfer-Encoding?
     pub headers: HashMap<String, HeaderValueParts>,
+    pub raw_body: Option<Vec<u8>>, // The body as received after any chunked decoding, None when built or spooled
+    pub raw_header: Option<Vec<u8>>, // Request line and header fields as received, None when built
     pub request_line: Line,
+    pub wire_size: WireSize, // Bytes of each part as received
 }
 
-#[derive(Debug)]
+/// # Sizes in bytes of the parts of a request as received, before any decoding
+/// The body is counted as framed, by its Content-Length or up to the end of its chunked coding,
+/// so bytes of a pipelined request are not included. A body without either runs to the end of
+/// input or to the null bytes padding it.
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::{Message, WireSize};
+/// let request = Message::from_tcp_stream(
+///     b"POST /a HTTP/1.1\r\nHost: example.com\r\nContent-Length: 3\r\n\r\na=bGET / HTTP/1.1\r\n\r\n",
+/// )
+/// .expect("A request");
+/// assert_eq!(
+///     request.wire_size,
+///     WireSize {
+///         body: 3,
+///         header: 40,
+///         request_line: 18,
+///     }
+/// );
+/// assert_eq!(request.wire_size.total(), 61);
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WireSize {
+    pub body: usize,
+    pub header: usize,       // Header fields and the empty line ending them
+    pub request_line: usize, // Including its line break
+}
+
+impl WireSize {
+    /// # Get the size of the whole request
+    pub fn total(&self) -> usize {
+        self.request_line + self.header + self.body
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Line {
+    pub fragment: Option<String>,
     pub method: Method,
     pub protocol: Protocol,
     pub raw: String,
@@ -29,10 +92,84 @@ pub struct Line {
     pub query_string: String,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+impl Default for BodyContentType {
+    fn default() -> BodyContentType {
+        BodyContentType::SinglePart(HashMap::new())
+    }
+}
+
+impl Default for Line {
+    /// # A GET request for the root over HTTP/1.1
+    fn default() -> Line {
+        Line {
+            fragment: None,
+            method: Method::Get,
+            protocol: Protocol::V1_1,
+            raw: String::from("GET / HTTP/1.1"),
+            request_uri: String::from("/"),
+            request_uri_base: String::from("/"),
+            query_arguments: HashMap::new(),
+            query_string: String::new(),
+        }
+    }
+}
+
+impl Line {
+    /// # Iterate over the percent-decoded, non-empty segments of the request URI base
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let line = Message::get_request_line("GET /users//john%20doe/?page=1 HTTP/1.1")
+    ///     .expect("A request line");
+    /// let segments: Vec<String> = line.path_segments().collect();
+    /// assert_eq!(segments, vec!["users".to_string(), "john doe".to_string()]);
+    /// ```
+    pub fn path_segments<'a>(&'a self) -> impl Iterator<Item = String> + 'a {
+        self.request_uri_base
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(uri::percent_decode)
+    }
+
+    /// # Get a query argument converted into a type
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{ConversionError, Message};
+    /// let line = Message::get_request_line("GET /?page=2&debug&size=big HTTP/1.1")
+    ///     .expect("A request line");
+    /// assert_eq!(line.query_get_as::<i64>("page"), Ok(2));
+    /// assert_eq!(line.query_get_as::<bool>("debug"), Ok(true));
+    /// assert_eq!(
+    ///     line.query_get_as::<u32>("size"),
+    ///     Err(ConversionError::Invalid {
+    ///         expected: "an unsigned integer",
+    ///         name: "size".to_string(),
+    ///         value: "big".to_string()
+    ///     })
+    /// );
+    /// assert_eq!(
+    ///     line.query_get_as::<u32>("limit"),
+    ///     Err(ConversionError::Missing("limit".to_string()))
+    /// );
+    /// ```
+    pub fn query_get_as<T: FromQueryValue>(&self, name: &str) -> Result<T, ConversionError> {
+        let value = self
+            .query_arguments
+            .get(name)
+            .ok_or_else(|| ConversionError::Missing(name.to_string()))?;
+        T::from_query_value(value).ok_or_else(|| ConversionError::Invalid {
+            expected: T::EXPECTED,
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 pub enum Method {
     Connect,
     Delete,
+    #[default]
     Get,
     Head,
     Invalid,
@@ -43,24 +180,101 @@ pub enum Method {
     Trace,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum HeaderContentType {
     MultiPart(String), // String is multi-part boundary string
     SinglePart,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum HeaderValuePart {
     Single(String),
     KeyValue(String, String),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct HeaderValueParts {
     pub parts: Vec<Vec<HeaderValuePart>>,
 }
 
 impl HeaderValueParts {
+    /// # Iterate over the ';'-separated blocks, each holding its ','-separated items
+    pub fn blocks(&self) -> impl Iterator<Item = &[HeaderValuePart]> {
+        self.parts.iter().map(|block| block.as_slice())
+    }
+
+    /// # Iterate over all items in order
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{HeaderValuePart, Message};
+    /// let (_, value) = Message::get_header_field("Accept-Encoding: gzip, br").expect("A header");
+    /// let items: Vec<&HeaderValuePart> = value.iter().collect();
+    /// assert_eq!(
+    ///     items,
+    ///     vec![
+    ///         &HeaderValuePart::Single("gzip".to_string()),
+    ///         &HeaderValuePart::Single("br".to_string())
+    ///     ]
+    /// );
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = &HeaderValuePart> {
+        self.parts.iter().flat_map(|block| block.iter())
+    }
+
+    /// # Get the first item if it is a plain value, like the media type of a Content-Type
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let (_, value) = Message::get_header_field("Content-Type: text/html; charset=utf-8")
+    ///     .expect("A header");
+    /// assert_eq!(value.first(), Some("text/html"));
+    /// ```
+    pub fn first(&self) -> Option<&str> {
+        match self.iter().next()? {
+            HeaderValuePart::Single(value) => Some(value),
+            HeaderValuePart::KeyValue(_, _) => None,
+        }
+    }
+
+    /// # Does any plain item match token case-insensitively?
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let (_, value) = Message::get_header_field("Connection: Keep-Alive, Upgrade")
+    ///     .expect("A header");
+    /// assert!(value.contains_token("upgrade"));
+    /// assert!(!value.contains_token("close"));
+    /// ```
+    pub fn contains_token(&self, token: &str) -> bool {
+        self.iter().any(|part| match part {
+            HeaderValuePart::Single(value) => value.eq_ignore_ascii_case(token),
+            HeaderValuePart::KeyValue(_, _) => false,
+        })
+    }
+
+    /// # Get all key=value parameters with lowercase keys and unquoted values
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let (_, value) = Message::get_header_field(
+    ///     "Content-Disposition: form-data; Name=\"file\"; filename=\"a.txt\"",
+    /// )
+    /// .expect("A header");
+    /// let params = value.params();
+    /// assert_eq!(params.get("name"), Some(&"file".to_string()));
+    /// assert_eq!(params.get("filename"), Some(&"a.txt".to_string()));
+    /// ```
+    pub fn params(&self) -> HashMap<String, String> {
+        self.iter()
+            .filter_map(|part| match part {
+                HeaderValuePart::KeyValue(key, value) => {
+                    Some((key.to_lowercase(), value.trim_matches('"').to_string()))
+                }
+                HeaderValuePart::Single(_) => None,
+            })
+            .collect()
+    }
+
     pub fn get_key_value(&self, key: &str) -> Option<String> {
         for params_block in self.parts.iter() {
             for params_subblock in params_block.iter() {
@@ -73,67 +287,738 @@ impl HeaderValueParts {
         }
         None
     }
+}
 
-    pub fn to_string(&self) -> String {
-        let mut output = String::new();
-        let mut params_block_count = 0;
-        for params_block in self.parts.iter() {
+impl fmt::Display for HeaderValueParts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (params_block_count, params_block) in self.parts.iter().enumerate() {
             if params_block_count > 0 {
-                output.push_str("; ");
+                write!(f, "; ")?;
             }
-            let mut params_subblock_count = 0;
-            for params_subblock in params_block.iter() {
+            for (params_subblock_count, params_subblock) in params_block.iter().enumerate() {
                 if params_subblock_count > 0 {
-                    output.push_str(", ");
+                    write!(f, ", ")?;
                 }
                 match params_subblock {
-                    HeaderValuePart::Single(string) => {
-                        output.push_str(&string);
-                    }
-                    HeaderValuePart::KeyValue(key, value) => {
-                        output.push_str(&format!("{}={}", key, value).to_string());
-                    }
+                    HeaderValuePart::Single(string) => write!(f, "{}", string)?,
+                    HeaderValuePart::KeyValue(key, value) => write!(f, "{}={}", key, value)?,
                 }
-                params_subblock_count = params_subblock_count + 1;
             }
-            params_block_count = params_block_count + 1;
         }
-        output
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct MultiPartValue {
-    pub body: Vec<u8>,
+    pub body: Vec<u8>,             // Empty when spooled to a file
+    pub file: Option<SpooledFile>, // Set when body exceeded the spool threshold
+    pub filename: Option<String>,  // From the Content-Disposition, empty when no file was chosen
     pub headers: HashMap<String, HeaderValueParts>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+impl MultiPartValue {
+    /// # Get the size of the body whether it is in memory or spooled
+    pub fn size(&self) -> usize {
+        match self.file {
+            Some(ref file) => file.size,
+            None => self.body.len(),
+        }
+    }
+
+    /// # Is it a file input submitted without a chosen file?
+    /// Browsers send these as parts with an empty filename and body.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{BodyContentType, Message};
+    /// let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=a\r\n\r\n--a\r\nContent-Disposition: form-data; name=\"file\"; filename=\"\"\r\nContent-Type: application/octet-stream\r\n\r\n\r\n--a--\r\n")
+    ///     .expect("A request");
+    /// match request.body {
+    ///     BodyContentType::MultiPart(values) => {
+    ///         let file = values.get("file").expect("A part");
+    ///         assert_eq!(file.filename, Some(String::new()));
+    ///         assert!(file.is_empty_upload());
+    ///         assert!(!values.contains_key("other"));
+    ///     }
+    ///     _ => panic!("Expected a multi-part body"),
+    /// }
+    /// ```
+    pub fn is_empty_upload(&self) -> bool {
+        self.filename.as_deref() == Some("") && self.size() == 0
+    }
+}
+
+/// # Splits the multi-part body of a request into values while it is received
+/// Part bodies larger than `multipart_spool_threshold` are written to their files as the bytes
+/// arrive, only header fields, smaller parts and bytes that may start a delimiter are kept in
+/// memory. Parts are delimited like by `multipart::Parser`.
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::{BodyContentType, MultiPartReceiver, ParserConfig};
+/// let config = ParserConfig {
+///     multipart_spool_threshold: Some(2),
+///     ..ParserConfig::default()
+/// };
+/// let mut receiver = MultiPartReceiver::new(
+///     b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=ab\r\nContent-Length: 65\r\n\r\n",
+///     &config,
+/// )
+/// .expect("A valid head")
+/// .expect("A multi-part body");
+/// receiver
+///     .push(b"--ab\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nhel")
+///     .expect("A part");
+/// receiver.push(b"lo\r\n--ab--\r\n").expect("The rest");
+/// let request = receiver.finish().expect("A request");
+/// match request.body {
+///     BodyContentType::MultiPart(values) => {
+///         assert_eq!(values["a"].size(), 5);
+///         assert!(values["a"].file.is_some());
+///     }
+///     _ => panic!("Expected a multi-part body"),
+/// }
+/// assert_eq!(request.raw_body, None);
+/// assert_eq!(request.wire_size.body, 65);
+/// ```
+#[derive(Debug)]
+pub struct MultiPartReceiver {
+    config: ParserConfig,
+    delimiter: Vec<u8>, // CRLF, two dashes and the boundary
+    message: Message,   // Request line and header fields
+    part: ReceivedPart,
+    pending: Vec<u8>, // Received bytes that may start a delimiter
+    received: usize,
+    started: Instant,
+    state: multipart::State,
+}
+
+/// A part of a multi-part body that is being received
+#[derive(Debug)]
+enum ReceivedPart {
+    Ignored,                         // Outside of parts or without a name
+    Memory(Vec<u8>),                 // Header fields and body
+    Spooled(String, MultiPartValue), // Name and value with the body in its file
+}
+
+/// Where the next delimiter is in the received bytes of a multi-part body
+enum Search {
+    Found(usize, usize, bool), // Start, end and whether it closes the body
+    Partial(usize),            // Bytes from here may still start a delimiter
+}
+
+impl MultiPartReceiver {
+    /// # Start receiving the body of the request with head, None unless it is multi-part
+    /// The head is the request line and header fields up to and including the empty line.
+    pub fn new(
+        head: &[u8],
+        config: &ParserConfig,
+    ) -> Result<Option<MultiPartReceiver>, ParseError> {
+        let started = Instant::now();
+        let mut message = Message::parse_tcp_stream(head, config, true)?;
+        if message.header("Transfer-Encoding").is_some() {
+            return Ok(None);
+        }
+        let boundary = match message.content_type() {
+            Some(ref media_type) if media_type.is_multipart() => match media_type.boundary() {
+                Some(boundary) => boundary.to_string(),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        message.raw_body = None;
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+        Ok(Some(MultiPartReceiver {
+            config: config.clone(),
+            delimiter,
+            message,
+            part: ReceivedPart::Ignored,
+            // A delimiter at the start of the body comes without its CRLF
+            pending: b"\r\n".to_vec(),
+            received: 0,
+            started,
+            state: multipart::State::Preamble,
+        }))
+    }
+
+    /// # Split the next received bytes of the body
+    pub fn push(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        self.received += bytes.len();
+        let result = if self.received > self.config.max_body_size {
+            Err(ParseError::BodyTooLarge {
+                limit: self.config.max_body_size,
+                seen: self.received,
+            })
+        } else {
+            self.pending.extend_from_slice(bytes);
+            self.split(false)
+        };
+        if let Err(ref error) = result {
+            Message::record_statistics(
+                &self.config,
+                &Err(error.clone()),
+                self.get_size(),
+                self.started,
+            );
+        }
+        result
+    }
+
+    /// # Split the rest of the body and get the request with its values
+    /// A part without a delimiter after it is incomplete and left out.
+    pub fn finish(mut self) -> Result<Message, ParseError> {
+        let result = self.split(true).and_then(|_| {
+            if let Some(expected) = self.message.content_length() {
+                if self.received < expected {
+                    return Err(ParseError::IncompleteBody {
+                        expected,
+                        received: self.received,
+                    });
+                }
+            }
+            self.message.wire_size.body = self.received;
+            Ok(mem::take(&mut self.message))
+        });
+        Message::record_statistics(&self.config, &result, self.get_size(), self.started);
+        result
+    }
+
+    /// Get the number of bytes received with the head
+    fn get_size(&self) -> usize {
+        self.message.wire_size.request_line + self.message.wire_size.header + self.received
+    }
+
+    /// Hand the pending bytes that can't start a delimiter to the parts they belong to
+    fn split(&mut self, is_final: bool) -> Result<(), ParseError> {
+        loop {
+            if self.state == multipart::State::Epilogue {
+                self.pending.clear();
+                return Ok(());
+            }
+            match MultiPartReceiver::find_delimiter(&self.pending, &self.delimiter, is_final) {
+                Search::Found(start, end, is_close) => {
+                    self.part.write(&self.pending[..start], &self.config)?;
+                    let part = mem::replace(&mut self.part, ReceivedPart::Ignored);
+                    if self.state == multipart::State::Part {
+                        if let Some((name, value)) = part.into_value(&self.config) {
+                            if let BodyContentType::MultiPart(ref mut values) = self.message.body {
+                                values.insert(name, value);
+                            }
+                        }
+                    }
+                    self.pending.drain(..end);
+                    if is_close {
+                        self.state = multipart::State::Epilogue;
+                    } else {
+                        self.state = multipart::State::Part;
+                        self.part = ReceivedPart::Memory(Vec::new());
+                    }
+                }
+                Search::Partial(start) => {
+                    self.part.write(&self.pending[..start], &self.config)?;
+                    self.pending.drain(..start);
+                    if is_final {
+                        self.state = multipart::State::Incomplete;
+                        self.part = ReceivedPart::Ignored;
+                    } else if self.pending.len()
+                        > self.delimiter.len() + self.config.max_header_line_length
+                    {
+                        // Only the padding of a delimiter line can keep growing
+                        return Err(ParseError::HeaderTooLarge {
+                            limit: self.config.max_header_line_length,
+                            seen: self.pending.len() - self.delimiter.len(),
+                        });
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Find the next delimiter in bytes, bytes that end in the middle of one can't be told apart
+    /// from a longer string that starts with it until more bytes arrive or the body ends
+    fn find_delimiter(bytes: &[u8], delimiter: &[u8], is_final: bool) -> Search {
+        for start in 0..bytes.len() {
+            let rest = &bytes[start..];
+            if rest.len() < delimiter.len() {
+                if !is_final && delimiter.starts_with(rest) {
+                    return Search::Partial(start);
+                }
+                continue;
+            }
+            if !rest.starts_with(delimiter) {
+                continue;
+            }
+            let after = &rest[delimiter.len()..];
+            if after.starts_with(b"--") {
+                return Search::Found(start, start + delimiter.len() + 2, true);
+            }
+            let padding = after
+                .iter()
+                .take_while(|byte| **byte == b' ' || **byte == b'\t')
+                .count();
+            let line = &after[padding..];
+            if line.starts_with(b"\r\n") {
+                return Search::Found(start, start + delimiter.len() + padding + 2, false);
+            }
+            if is_final && line.is_empty() {
+                return Search::Found(start, bytes.len(), true);
+            }
+            if !is_final && (line.is_empty() || line == b"\r" || (padding == 0 && line == b"-")) {
+                return Search::Partial(start);
+            }
+        }
+        Search::Partial(bytes.len())
+    }
+}
+
+impl ReceivedPart {
+    /// Add bytes to the part, its body is spooled once it is larger than the threshold
+    fn write(&mut self, bytes: &[u8], config: &ParserConfig) -> Result<(), ParseError> {
+        match *self {
+            ReceivedPart::Ignored => {}
+            ReceivedPart::Memory(ref mut data) => {
+                data.extend_from_slice(bytes);
+                let threshold = match config.multipart_spool_threshold {
+                    Some(threshold) => threshold,
+                    None => return Ok(()),
+                };
+                let (headers, body_start, is_complete) = Message::get_multipart_head(data);
+                if !is_complete {
+                    if data.len() > config.max_header_size {
+                        return Err(ParseError::HeaderTooLarge {
+                            limit: config.max_header_size,
+                            seen: data.len(),
+                        });
+                    }
+                    return Ok(());
+                }
+                if data.len() - body_start <= threshold {
+                    return Ok(());
+                }
+                let (name, filename) = match Message::get_multipart_disposition(&headers) {
+                    Some(disposition) => disposition,
+                    None => {
+                        *self = ReceivedPart::Ignored;
+                        return Ok(());
+                    }
+                };
+
+                // Parts that can't be spooled are kept in memory
+                if let Ok(file) = Message::spool_multipart_body(
+                    &headers,
+                    &data[body_start..],
+                    filename.clone(),
+                    config,
+                ) {
+                    *self = ReceivedPart::Spooled(
+                        name,
+                        MultiPartValue {
+                            body: Vec::new(),
+                            file: Some(file),
+                            filename,
+                            headers,
+                        },
+                    );
+                }
+            }
+            ReceivedPart::Spooled(_, ref mut value) => {
+                // A file that can't be appended to is read back to keep the part in memory
+                if let Some(mut file) = value.file.take() {
+                    if file.append(bytes).is_ok() {
+                        value.file = Some(file);
+                        return Ok(());
+                    }
+                    match fs::read(file.path()) {
+                        Ok(mut body) => {
+                            body.truncate(file.size);
+                            value.body = body;
+                        }
+                        Err(_) => {
+                            *self = ReceivedPart::Ignored;
+                            return Ok(());
+                        }
+                    }
+                }
+                value.body.extend_from_slice(bytes);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the name and value of a part that has been received in full
+    fn into_value(self, config: &ParserConfig) -> Option<(String, MultiPartValue)> {
+        match self {
+            ReceivedPart::Ignored => None,
+            ReceivedPart::Memory(data) => {
+                Message::get_query_args_from_multipart_blob(&data, config)
+            }
+            ReceivedPart::Spooled(name, value) => Some((name, value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 pub enum Protocol {
     Invalid,
     V1_0,
+    #[default]
     V1_1,
     V2_0,
     V0_9,
 }
 
+/// # Characters separating arguments in query strings and form bodies
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QuerySeparator {
+    Ampersand,
+    AmpersandAndSemicolon,
+    Semicolon,
+}
+
+/// # Measurements of a single parsed message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseStatistics {
+    pub body_size: usize,
+    pub bytes: usize, // Length of the parsed input
+    pub duration: Duration,
+    pub error: Option<ParseError>, // Set when the message was rejected
+    pub header_count: usize,
+}
+
+/// # Receives statistics of every message parsed with a configuration
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::{Message, ParseStatistics, ParserConfig, ParserMetrics};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct Counter {
+///     headers: AtomicUsize,
+/// }
+///
+/// impl ParserMetrics for Counter {
+///     fn record(&self, statistics: &ParseStatistics) {
+///         self.headers
+///             .fetch_add(statistics.header_count, Ordering::SeqCst);
+///     }
+/// }
+///
+/// let counter = Arc::new(Counter::default());
+/// let config = ParserConfig {
+///     metrics: Some(counter.clone()),
+///     ..ParserConfig::default()
+/// };
+/// Message::from_tcp_stream_with_config(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n", &config)
+///     .expect("A request");
+/// assert_eq!(counter.headers.load(Ordering::SeqCst), 2);
+/// ```
+pub trait ParserMetrics: Send + Sync {
+    fn record(&self, statistics: &ParseStatistics);
+}
+
+/// # Limits and options applied while parsing a request
+#[derive(Clone)]
+pub struct ParserConfig {
+    pub allowed_methods: Option<Vec<Method>>, // Reject other methods right after the request line
+    pub header_name_case: HeaderNameCase,
+    pub max_body_size: usize,
+    pub max_header_line_length: usize, // Bytes of a single header field line
+    pub max_header_size: usize,
+    pub max_request_uri_length: usize,
+    pub metrics: Option<Arc<dyn ParserMetrics>>, // Called after each parsed message
+    pub multipart_spool_directory: PathBuf,
+    pub multipart_spool_threshold: Option<usize>, // Spool larger parts to files
+    pub query_separator: QuerySeparator,
+    pub strict: bool, // Reject input that lenient parsing would repair
+}
+
+impl Default for ParserConfig {
+    fn default() -> ParserConfig {
+        ParserConfig {
+            allowed_methods: None,
+            header_name_case: HeaderNameCase::Canonical,
+            max_body_size: 8 * 1024 * 1024,
+            max_header_line_length: 8 * 1024,
+            max_header_size: 64 * 1024,
+            max_request_uri_length: 8 * 1024,
+            metrics: None,
+            multipart_spool_directory: env::temp_dir(),
+            multipart_spool_threshold: None,
+            query_separator: QuerySeparator::Ampersand,
+            strict: false,
+        }
+    }
+}
+
+impl ParserConfig {
+    /// # Check method against the allowed methods, unrecognized methods are left to the parser
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{Method, ParseError, ParserConfig};
+    /// let config = ParserConfig {
+    ///     allowed_methods: Some(vec![Method::Get, Method::Head]),
+    ///     ..ParserConfig::default()
+    /// };
+    /// assert_eq!(config.check_method(&Method::Head), Ok(()));
+    /// assert_eq!(
+    ///     config.check_method(&Method::Post),
+    ///     Err(ParseError::MethodNotAllowed {
+    ///         allowed: vec![Method::Get, Method::Head],
+    ///         method: Method::Post
+    ///     })
+    /// );
+    /// ```
+    pub fn check_method(&self, method: &Method) -> Result<(), ParseError> {
+        match &self.allowed_methods {
+            Some(allowed) if *method != Method::Invalid && !allowed.contains(method) => {
+                Err(ParseError::MethodNotAllowed {
+                    allowed: allowed.clone(),
+                    method: *method,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for ParserConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ParserConfig")
+            .field("allowed_methods", &self.allowed_methods)
+            .field("header_name_case", &self.header_name_case)
+            .field("max_body_size", &self.max_body_size)
+            .field("max_header_line_length", &self.max_header_line_length)
+            .field("max_header_size", &self.max_header_size)
+            .field("max_request_uri_length", &self.max_request_uri_length)
+            .field("metrics", &self.metrics.is_some())
+            .field("multipart_spool_directory", &self.multipart_spool_directory)
+            .field("multipart_spool_threshold", &self.multipart_spool_threshold)
+            .field("query_separator", &self.query_separator)
+            .field("strict", &self.strict)
+            .finish()
+    }
+}
+
+/// # Reasons why a byte stream could not be decoded into a request
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    BodyTooLarge {
+        limit: usize,
+        seen: usize,
+    },
+    HeaderTooLarge {
+        limit: usize,
+        seen: usize,
+    },
+    IncompleteBody {
+        expected: usize, // From Content-Length
+        received: usize,
+    },
+    InvalidChunkedBody, // Chunk framing that can't be decoded or ends early
+    InvalidHeader,
+    InvalidRequestLine,
+    MethodNotAllowed {
+        allowed: Vec<Method>,
+        method: Method,
+    },
+    UnsupportedProtocol(String), // A well-formed HTTP version that is not supported
+    UnsupportedTransferCoding(String), // A transfer coding other than chunked
+    UriTooLong {
+        limit: usize,
+        seen: usize,
+    },
+}
+
+impl ParseError {
+    /// # Get the status code a server should respond with for this error
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::ParseError;
+    /// assert_eq!(ParseError::BodyTooLarge { limit: 10, seen: 20 }.status_code(), 413);
+    /// assert_eq!(ParseError::UriTooLong { limit: 10, seen: 20 }.status_code(), 414);
+    /// assert_eq!(ParseError::HeaderTooLarge { limit: 10, seen: 20 }.status_code(), 431);
+    /// assert_eq!(ParseError::UnsupportedProtocol("HTTP/3.0".to_string()).status_code(), 505);
+    /// assert_eq!(ParseError::UnsupportedTransferCoding("gzip".to_string()).status_code(), 501);
+    /// ```
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ParseError::BodyTooLarge { .. } => 413,
+            ParseError::HeaderTooLarge { .. } => 431,
+            ParseError::IncompleteBody { .. } => 400,
+            ParseError::InvalidChunkedBody => 400,
+            ParseError::InvalidHeader => 400,
+            ParseError::InvalidRequestLine => 400,
+            ParseError::MethodNotAllowed { .. } => 405,
+            ParseError::UnsupportedProtocol(_) => 505,
+            ParseError::UnsupportedTransferCoding(_) => 501,
+            ParseError::UriTooLong { .. } => 414,
+        }
+    }
+
+    /// # Get the status line a server should respond with for this error
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::ParseError;
+    /// assert_eq!(
+    ///     ParseError::BodyTooLarge { limit: 10, seen: 20 }.status(),
+    ///     "413 Payload Too Large".to_string()
+    /// );
+    /// ```
+    pub fn status(&self) -> String {
+        match self {
+            ParseError::BodyTooLarge { .. } => String::from("413 Payload Too Large"),
+            ParseError::HeaderTooLarge { .. } => {
+                String::from("431 Request Header Fields Too Large")
+            }
+            ParseError::IncompleteBody { .. }
+            | ParseError::InvalidChunkedBody
+            | ParseError::InvalidHeader
+            | ParseError::InvalidRequestLine => String::from("400 Bad Request"),
+            ParseError::MethodNotAllowed { .. } => String::from("405 Method Not Allowed"),
+            ParseError::UnsupportedProtocol(_) => String::from("505 HTTP Version Not Supported"),
+            ParseError::UnsupportedTransferCoding(_) => String::from("501 Not Implemented"),
+            ParseError::UriTooLong { .. } => String::from("414 URI Too Long"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::BodyTooLarge { limit, seen } => write!(
+                f,
+                "message body of {} bytes exceeds limit of {} bytes",
+                seen, limit
+            ),
+            ParseError::HeaderTooLarge { limit, seen } => write!(
+                f,
+                "header fields of {} bytes exceed limit of {} bytes",
+                seen, limit
+            ),
+            ParseError::IncompleteBody { expected, received } => write!(
+                f,
+                "message body of {} bytes is shorter than Content-Length of {} bytes",
+                received, expected
+            ),
+            ParseError::InvalidChunkedBody => write!(f, "invalid chunked message body"),
+            ParseError::InvalidHeader => write!(f, "invalid header field"),
+            ParseError::InvalidRequestLine => write!(f, "invalid request line"),
+            ParseError::MethodNotAllowed { method, .. } => write!(
+                f,
+                "method {} is not allowed",
+                Message::get_method_text(method)
+            ),
+            ParseError::UnsupportedProtocol(version) => {
+                write!(f, "protocol {} is not supported", version)
+            }
+            ParseError::UnsupportedTransferCoding(coding) => {
+                write!(f, "transfer coding {} is not supported", coding)
+            }
+            ParseError::UriTooLong { limit, seen } => write!(
+                f,
+                "request URI of {} bytes exceeds limit of {} bytes",
+                seen, limit
+            ),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// # Reasons why a query argument could not be converted into a type
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConversionError {
+    Invalid {
+        expected: &'static str,
+        name: String,
+        value: String,
+    },
+    Missing(String), // Name of the absent query argument
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::Invalid {
+                expected,
+                name,
+                value,
+            } => write!(
+                f,
+                "query argument {} has value {:?}, expected {}",
+                name, value, expected
+            ),
+            ConversionError::Missing(name) => write!(f, "query argument {} is missing", name),
+        }
+    }
+}
+
+impl error::Error for ConversionError {}
+
+/// # Types a query argument value can be converted into
+pub trait FromQueryValue: Sized {
+    /// Description of the expected value used in errors
+    const EXPECTED: &'static str;
+
+    fn from_query_value(value: &str) -> Option<Self>;
+}
+
+macro_rules! from_query_value_parsed {
+    ($($type:ty => $expected:expr,)*) => {
+        $(
+            impl FromQueryValue for $type {
+                const EXPECTED: &'static str = $expected;
+
+                fn from_query_value(value: &str) -> Option<$type> {
+                    value.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+from_query_value_parsed! {
+    char => "a character",
+    f32 => "a number",
+    f64 => "a number",
+    i8 => "an integer",
+    i16 => "an integer",
+    i32 => "an integer",
+    i64 => "an integer",
+    isize => "an integer",
+    String => "a string",
+    u8 => "an unsigned integer",
+    u16 => "an unsigned integer",
+    u32 => "an unsigned integer",
+    u64 => "an unsigned integer",
+    usize => "an unsigned integer",
+}
+
+impl FromQueryValue for bool {
+    const EXPECTED: &'static str = "a boolean";
+
+    /// Flags without a value are decoded as "1"
+    fn from_query_value(value: &str) -> Option<bool> {
+        match value {
+            "1" | "on" | "true" => Some(true),
+            "0" | "off" | "false" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+const DUMP_BODY_LIMIT: usize = 256;
+
 enum ParserSection {
     Line,
     HeaderFields,
     MessageBody,
 }
 
-enum MultiPartSection {
-    End,
-    EndSecondary,
-    EndBoundary,
-    Skipping,
-    Start,
-    StartSuffix,
-}
-
 enum ParserMode {
-    Boundaries(Vec<u8>),
+    Boundaries(String),
     Lines,
 }
 
@@ -220,18 +1105,18 @@ impl Message {
         }
     }
 
-    fn get_query_args_from_multipart_blob(data: &[u8]) -> Option<(String, MultiPartValue)> {
+    /// Get the header fields of a part, where its body starts and whether the empty line after
+    /// the header fields was found
+    fn get_multipart_head(data: &[u8]) -> (HashMap<String, HeaderValueParts>, usize, bool) {
         let mut headers: HashMap<String, HeaderValueParts> = HashMap::new();
         let mut last_was_carriage_return = false;
-        let mut index = 0;
         let mut start = 0;
-        for byte in data.iter() {
+        for (index, byte) in data.iter().enumerate() {
             if byte == &10 && last_was_carriage_return {
                 last_was_carriage_return = false;
                 if let Ok(utf8_line) = str::from_utf8(&data[start..index]) {
                     if utf8_line.trim().is_empty() {
-                        start = index + 1;
-                        break;
+                        return (headers, index + 1, true);
                     } else {
                         if let Some((header_key, header_value)) =
                             Message::get_header_field(utf8_line)
@@ -241,49 +1126,152 @@ impl Message {
                     }
                     start = index + 1;
                 }
-            } else if byte == &13 {
-                last_was_carriage_return = true;
             } else {
-                last_was_carriage_return = false;
+                last_was_carriage_return = byte == &13;
             }
-            index = index + 1;
         }
+        (headers, start, false)
+    }
 
-        // Did we find a name within the content-disposition header?
-        let mut name = String::new();
-        if let Some(content_disposition) = headers.get("Content-Disposition") {
-            if let Some(content_disposition_name) = content_disposition.get_key_value("name") {
-                name = content_disposition_name.trim_matches('"').to_string();
-            }
+    /// Get the name and filename of a part from its Content-Disposition, None without a name
+    fn get_multipart_disposition(
+        headers: &HashMap<String, HeaderValueParts>,
+    ) -> Option<(String, Option<String>)> {
+        let content_disposition = headers.get("Content-Disposition")?;
+        let name = content_disposition
+            .get_key_value("name")?
+            .trim_matches('"')
+            .to_string();
+        if name.is_empty() {
+            return None;
         }
-        if !name.is_empty() {
-            let body = data[start..].to_vec();
-            if !body.is_empty() {
-                return Some((name, MultiPartValue { body, headers }));
+        let filename = content_disposition
+            .get_key_value("filename")
+            .map(|filename| filename.trim_matches('"').to_string());
+        Some((name, filename))
+    }
+
+    /// Write the body of a part to a new file in the spool directory
+    fn spool_multipart_body(
+        headers: &HashMap<String, HeaderValueParts>,
+        body: &[u8],
+        filename: Option<String>,
+        config: &ParserConfig,
+    ) -> io::Result<SpooledFile> {
+        let content_type = headers
+            .get("Content-Type")
+            .map(|content_type| content_type.to_string());
+        SpooledFile::create(
+            &config.multipart_spool_directory,
+            body,
+            content_type,
+            filename,
+        )
+    }
+
+    fn get_query_args_from_multipart_blob(
+        data: &[u8],
+        config: &ParserConfig,
+    ) -> Option<(String, MultiPartValue)> {
+        let (headers, start, _) = Message::get_multipart_head(data);
+
+        // Did we find a name within the content-disposition header?
+        let (name, filename) = Message::get_multipart_disposition(&headers)?;
+
+        // Parts with empty bodies are kept so empty fields and unchosen files are not missing
+        let body = &data[start.min(data.len())..];
+
+        // Large bodies are spooled to a file, or kept in memory if spooling fails
+        if config
+            .multipart_spool_threshold
+            .map(|threshold| body.len() > threshold)
+            .unwrap_or(false)
+        {
+            if let Ok(file) =
+                Message::spool_multipart_body(&headers, body, filename.clone(), config)
+            {
+                return Some((
+                    name,
+                    MultiPartValue {
+                        body: Vec::new(),
+                        file: Some(file),
+                        filename,
+                        headers,
+                    },
+                ));
             }
         }
-        None
+        Some((
+            name,
+            MultiPartValue {
+                body: body.to_vec(),
+                file: None,
+                filename,
+                headers,
+            },
+        ))
+    }
+
+    fn get_query_args_from_string(
+        subject: &str,
+        separator: &QuerySeparator,
+    ) -> Option<HashMap<String, String>> {
+        let values =
+            Message::get_query_values_from_string(subject, separator, uri::percent_decode)?;
+        Some(
+            values
+                .into_iter()
+                .filter_map(|(key, mut values)| Some((key, values.pop()?)))
+                .collect(),
+        )
     }
 
-    fn get_query_args_from_string(subject: &str) -> Option<HashMap<String, String>> {
-        let mut args: HashMap<String, String> = HashMap::new();
+    /// Split subject into fields, keys and values are decoded with decode
+    fn get_query_values_from_string(
+        subject: &str,
+        separator: &QuerySeparator,
+        decode: fn(&str) -> String,
+    ) -> Option<HashMap<String, Vec<String>>> {
+        let mut args: HashMap<String, Vec<String>> = HashMap::new();
         if !subject.is_empty() {
-            let subject_arguments: Vec<&str> = subject.split("&").collect();
+            let subject_arguments: Vec<&str> = match separator {
+                QuerySeparator::Ampersand => subject.split('&').collect(),
+                QuerySeparator::AmpersandAndSemicolon => subject.split(&['&', ';'][..]).collect(),
+                QuerySeparator::Semicolon => subject.split(';').collect(),
+            };
             for item in subject_arguments {
                 let query_arg: Vec<&str> = item.split("=").collect();
-                if query_arg.len() == 2 {
-                    args.insert(query_arg.get(0)?.to_string(), query_arg.get(1)?.to_string());
+                let value = if query_arg.len() == 2 {
+                    decode(query_arg.get(1)?)
                 } else {
-                    args.insert(query_arg.get(0)?.to_string(), String::from("1"));
-                }
+                    String::from("1")
+                };
+                args.entry(decode(query_arg.first()?))
+                    .or_default()
+                    .push(value);
             }
         }
-        if args.len() > 0 {
+        if !args.is_empty() {
             return Some(args);
         }
         None
     }
 
+    pub fn get_method_text(method: &Method) -> String {
+        match method {
+            Method::Connect => String::from("CONNECT"),
+            Method::Delete => String::from("DELETE"),
+            Method::Get => String::from("GET"),
+            Method::Head => String::from("HEAD"),
+            Method::Options => String::from("OPTIONS"),
+            Method::Patch => String::from("PATCH"),
+            Method::Post => String::from("POST"),
+            Method::Put => String::from("PUT"),
+            Method::Trace => String::from("TRACE"),
+            Method::Invalid => String::from("INVALID"),
+        }
+    }
+
     pub fn get_protocol_text(protocol: &Protocol) -> String {
         match protocol {
             Protocol::V0_9 => String::from("HTTP/0.9"),
@@ -295,18 +1283,66 @@ impl Message {
     }
 
     pub fn get_message_body(body: &str) -> Option<BodyContentType> {
-        if let Some(body) = Message::get_query_args_from_string(body) {
+        Message::get_message_body_with_config(body, &ParserConfig::default())
+    }
+
+    /// # Parse a form body using the query separator of a configuration
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{BodyContentType, Message, ParserConfig, QuerySeparator};
+    /// let config = ParserConfig {
+    ///     query_separator: QuerySeparator::Semicolon,
+    ///     ..ParserConfig::default()
+    /// };
+    /// if let Some(BodyContentType::SinglePart(body)) =
+    ///     Message::get_message_body_with_config("a=1;b=2", &config)
+    /// {
+    ///     assert_eq!(body.get("b"), Some(&vec!["2".to_string()]));
+    /// } else {
+    ///     panic!("Expected a single-part body");
+    /// }
+    /// ```
+    pub fn get_message_body_with_config(
+        body: &str,
+        config: &ParserConfig,
+    ) -> Option<BodyContentType> {
+        // Plus signs only mean spaces in form bodies, not in query strings
+        if let Some(body) =
+            Message::get_query_values_from_string(body, &config.query_separator, uri::form_decode)
+        {
             return Some(BodyContentType::SinglePart(body));
         }
         None
     }
 
     pub fn get_header_field(line: &str) -> Option<(String, HeaderValueParts)> {
+        Message::get_header_field_with_config(line, &ParserConfig::default())
+    }
+
+    /// # Parse a header field, the name is normalized by the header name case of config
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{Message, ParserConfig};
+    /// use milstian_http::HeaderNameCase;
+    /// let config = ParserConfig {
+    ///     header_name_case: HeaderNameCase::Lowercase,
+    ///     ..ParserConfig::default()
+    /// };
+    /// let (name, value) =
+    ///     Message::get_header_field_with_config("Content-Type: text/html", &config)
+    ///         .expect("A header field");
+    /// assert_eq!(name, "content-type".to_string());
+    /// assert_eq!(value.to_string(), "text/html".to_string());
+    /// ```
+    pub fn get_header_field_with_config(
+        line: &str,
+        config: &ParserConfig,
+    ) -> Option<(String, HeaderValueParts)> {
         let line = line.trim();
         if !line.is_empty() {
             let parts: Vec<&str> = line.splitn(2, ":").collect();
             if parts.len() == 2 {
-                let header_key = capitalize_key(&parts.get(0)?.trim().to_string());
+                let header_key = normalize_key(parts.first()?.trim(), &config.header_name_case);
                 let header_value = parts.get(1)?.trim().to_string();
                 let mut header_parts: Vec<Vec<HeaderValuePart>> = Vec::new();
 
@@ -315,11 +1351,9 @@ impl Message {
                     let mut header_value_part: Vec<HeaderValuePart> = Vec::new();
                     let params_subblocks: Vec<&str> = params_block.split(",").collect();
                     for params_subblock in params_subblocks.iter() {
-                        let params_subblock_clone = params_subblock.clone();
-                        let params_key_pair: Vec<&str> =
-                            params_subblock_clone.splitn(2, "=").collect();
+                        let params_key_pair: Vec<&str> = params_subblock.splitn(2, "=").collect();
                         if params_key_pair.len() == 2 {
-                            let param_key = params_key_pair.get(0)?.trim().to_string();
+                            let param_key = params_key_pair.first()?.trim().to_string();
                             let param_value = params_key_pair.get(1)?.trim().to_string();
                             header_value_part
                                 .push(HeaderValuePart::KeyValue(param_key, param_value));
@@ -342,14 +1376,49 @@ impl Message {
         None
     }
 
-    pub fn get_request_line(line: &str) -> Option<Line> {
-        let line = line.trim();
-        let parts: Vec<&str> = line.split(" ").collect();
-        if parts.len() == 3 {
-            // Request line has three parts (> HTTP 0.9)
-
+    fn get_fragment_from_uri(uri: &str) -> (String, Option<String>) {
+        let uri_parts: Vec<&str> = uri.splitn(2, "#").collect();
+        if uri_parts.len() == 2 {
+            return (uri_parts[0].to_string(), Some(uri_parts[1].to_string()));
+        }
+        (uri.to_string(), None)
+    }
+
+    fn has_whitespace_before_colon(line: &str) -> bool {
+        if let Some(colon) = line.find(':') {
+            return line[..colon].ends_with(|character: char| character.is_whitespace());
+        }
+        false
+    }
+
+    pub fn get_request_line(line: &str) -> Option<Line> {
+        Message::get_request_line_with_config(line, &ParserConfig::default())
+    }
+
+    /// # Parse a request line, in lenient mode repeated spaces and tabs are accepted as separators
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{Message, ParserConfig};
+    /// let config = ParserConfig::default();
+    /// assert!(Message::get_request_line_with_config("GET  /  HTTP/1.1", &config).is_some());
+    /// let config = ParserConfig {
+    ///     strict: true,
+    ///     ..ParserConfig::default()
+    /// };
+    /// assert!(Message::get_request_line_with_config("GET  /  HTTP/1.1", &config).is_none());
+    /// ```
+    pub fn get_request_line_with_config(line: &str, config: &ParserConfig) -> Option<Line> {
+        let line = line.trim();
+        let parts: Vec<&str> = if config.strict {
+            line.split(" ").collect()
+        } else {
+            line.split_whitespace().collect()
+        };
+        if parts.len() == 3 {
+            // Request line has three parts (> HTTP 0.9)
+
             // Get method
-            let method = match parts.get(0)?.as_ref() {
+            let method = match *parts.first()? {
                 "CONNECT" => Method::Connect,
                 "DELETE" => Method::Delete,
                 "GET" => Method::Get,
@@ -359,11 +1428,11 @@ impl Message {
                 "PUT" => Method::Put,
                 "POST" => Method::Post,
                 "TRACE" => Method::Trace,
-                __ => Method::Invalid,
+                _ => Method::Invalid,
             };
 
-            // Parse request URI
-            let request_uri = parts.get(1)?.to_string();
+            // Parse request URI and strip fragment
+            let (request_uri, fragment) = Message::get_fragment_from_uri(parts.get(1)?);
 
             // Parse query string, query arguments as base request URI
             let request_uri_copy = request_uri.clone();
@@ -372,15 +1441,17 @@ impl Message {
             let mut query_arguments: HashMap<String, String> = HashMap::new();
             let uri_parts: Vec<&str> = request_uri_copy.splitn(2, "?").collect();
             if uri_parts.len() == 2 {
-                request_uri_base = uri_parts.get(0)?.to_string();
+                request_uri_base = uri_parts.first()?.to_string();
                 query_string = uri_parts.get(1)?.to_string();
-                if let Some(query_args) = Message::get_query_args_from_string(&query_string) {
+                if let Some(query_args) =
+                    Message::get_query_args_from_string(&query_string, &config.query_separator)
+                {
                     query_arguments = query_args;
                 }
             };
 
             // Parse protocol
-            let protocol = match parts.get(2)?.as_ref() {
+            let protocol = match *parts.get(2)? {
                 "HTTP/0.9" => Protocol::V0_9,
                 "HTTP/1.0" => Protocol::V1_0,
                 "HTTP/1.1" => Protocol::V1_1,
@@ -391,6 +1462,7 @@ impl Message {
             // Did we find a valid method and protocol?
             if method != Method::Invalid && protocol != Protocol::Invalid {
                 return Some(Line {
+                    fragment,
                     method,
                     protocol,
                     raw: line.to_string(),
@@ -407,8 +1479,9 @@ impl Message {
             // HTTP 0.9 only supports GET requests
             let method = Method::Get;
 
-            // Parse request URI
-            let request_uri = parts.get(0)?.trim_matches(char::from(0)).to_string();
+            // Parse request URI and strip fragment
+            let (request_uri, fragment) =
+                Message::get_fragment_from_uri(parts.first()?.trim_matches(char::from(0)));
             if !request_uri.is_empty() {
                 // Protocol is always HTTP 0.9
                 let protocol = Protocol::V0_9;
@@ -420,14 +1493,17 @@ impl Message {
                 let mut query_arguments: HashMap<String, String> = HashMap::new();
                 let uri_parts: Vec<&str> = request_uri_copy.splitn(2, "?").collect();
                 if uri_parts.len() == 2 {
-                    request_uri_base = uri_parts.get(0)?.to_string();
+                    request_uri_base = uri_parts.first()?.to_string();
                     query_string = uri_parts.get(1)?.to_string();
-                    if let Some(query_args) = Message::get_query_args_from_string(&query_string) {
+                    if let Some(query_args) =
+                        Message::get_query_args_from_string(&query_string, &config.query_separator)
+                    {
                         query_arguments = query_args;
                     }
                 }
 
                 return Some(Line {
+                    fragment,
                     method,
                     protocol,
                     raw: line.to_string(),
@@ -452,11 +1528,100 @@ impl Message {
     /// assert_eq!(response_unwrapped.request_line.protocol, Protocol::V2_0);
     /// ```
     pub fn from_tcp_stream(request: &[u8]) -> Option<Message> {
+        Message::try_from_tcp_stream(request).ok()
+    }
+
+    /// Try to decode a byte stream into a HTTP Message, returns why it could not be decoded
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{Message, ParseError};
+    /// assert_eq!(
+    ///     Message::try_from_tcp_stream(b"GET / HTTP/3.0\r\n\r\n").expect_err("A version"),
+    ///     ParseError::UnsupportedProtocol("HTTP/3.0".to_string())
+    /// );
+    /// let error = Message::try_from_tcp_stream(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\na=b")
+    ///     .expect_err("A truncated body");
+    /// assert_eq!(
+    ///     error,
+    ///     ParseError::IncompleteBody {
+    ///         expected: 5,
+    ///         received: 3
+    ///     }
+    /// );
+    /// assert_eq!(error.status_code(), 400);
+    /// assert_eq!(
+    ///     Message::try_from_tcp_stream(b"GET\t/ HTTP/1.1 x\r\n\r\n").expect_err("A line"),
+    ///     ParseError::InvalidRequestLine
+    /// );
+    /// ```
+    pub fn try_from_tcp_stream(request: &[u8]) -> Result<Message, ParseError> {
+        Message::from_tcp_stream_with_config(request, &ParserConfig::default())
+    }
+
+    /// Try to decode a byte stream into a HTTP Message while enforcing the limits of a configuration
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{Message, ParseError, ParserConfig};
+    /// let config = ParserConfig {
+    ///     max_body_size: 4,
+    ///     ..ParserConfig::default()
+    /// };
+    /// let response =
+    ///     Message::from_tcp_stream_with_config(b"POST / HTTP/1.1\r\n\r\ntest=abc", &config);
+    /// assert_eq!(
+    ///     response.expect_err("A too large body"),
+    ///     ParseError::BodyTooLarge { limit: 4, seen: 8 }
+    /// );
+    /// ```
+    pub fn from_tcp_stream_with_config(
+        request: &[u8],
+        config: &ParserConfig,
+    ) -> Result<Message, ParseError> {
+        trace_span!("parse_request", bytes = request.len());
+        let started = Instant::now();
+        let result = Message::parse_tcp_stream(request, config, false);
+        Message::record_statistics(config, &result, request.len(), started);
+        result
+    }
+
+    /// Report the outcome of parsing bytes of input since started to the metrics of config
+    fn record_statistics(
+        config: &ParserConfig,
+        result: &Result<Message, ParseError>,
+        bytes: usize,
+        started: Instant,
+    ) {
+        if let Some(ref metrics) = config.metrics {
+            let (body_size, header_count) = match *result {
+                Ok(ref message) => (message.get_body_size(), message.headers.len()),
+                Err(_) => (0, 0),
+            };
+            metrics.record(&ParseStatistics {
+                body_size,
+                bytes,
+                duration: started.elapsed(),
+                error: result.as_ref().err().cloned(),
+                header_count,
+            });
+        }
+    }
+
+    /// Parse request, unless is_decoded a chunked body is decoded and parsed again and a body
+    /// shorter than its Content-Length is rejected
+    fn parse_tcp_stream(
+        request: &[u8],
+        config: &ParserConfig,
+        is_decoded: bool,
+    ) -> Result<Message, ParseError> {
         // Temporary message
         let mut message = Message {
             body: BodyContentType::SinglePart(HashMap::new()),
+            chunked: false,
             headers: HashMap::new(),
+            raw_body: None,
+            raw_header: None,
             request_line: Line {
+                fragment: None,
                 method: Method::Invalid,
                 protocol: Protocol::Invalid,
                 raw: String::new(),
@@ -465,204 +1630,78 @@ impl Message {
                 query_arguments: HashMap::new(),
                 query_string: String::new(),
             },
+            wire_size: WireSize::default(),
         };
 
         // Parsing variables
         let mut start = 0;
-        let mut start_boundary = 0;
-        let mut start_data = 0;
         let mut section = ParserSection::Line;
-        let mut end = 0;
-        let mut end_data = 0;
-        let last_index = match request.len() {
-            0 => 0,
-            _ => request.len() - 1,
-        };
         let mut last_was_carriage_return = false;
         let mut parser_mode = ParserMode::Lines;
-        let mut multipart_section = MultiPartSection::Start;
+        let mut header_size = 0;
+        let mut body_start: Option<usize> = None;
+        let mut request_line_end: Option<usize> = None;
+
+        // Input ends at the first null byte until a Content-Length frames the body
+        let mut input_end = request
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(request.len());
+
+        for (end, byte) in request.iter().enumerate() {
+            // Did we reach the end of the input?
+            if end >= input_end {
+                if let Ok(utf8_line) = str::from_utf8(&request[start..input_end]) {
+                    if let Err(error) = Message::parse_line(
+                        utf8_line,
+                        &mut section,
+                        &mut message,
+                        &mut parser_mode,
+                        config,
+                    ) {
+                        trace_event!(debug, offset = start, error = %error, "Rejected line");
+                        return Err(error);
+                    }
+                }
+                break;
+            }
+
+            // Is the message body larger than allowed?
+            if let Some(body_start) = body_start {
+                if end - body_start >= config.max_body_size {
+                    let error = ParseError::BodyTooLarge {
+                        limit: config.max_body_size,
+                        seen: input_end - body_start,
+                    };
+                    trace_event!(debug, offset = end, error = %error, "Rejected message body");
+                    return Err(error);
+                }
+            }
 
-        for byte in request.iter() {
             match parser_mode {
                 // Are we parsing boundaries?
                 ParserMode::Boundaries(ref boundary) => {
-                    match multipart_section {
-                        // Stay here until we encounter \n\r
-                        MultiPartSection::Skipping => {
-                            if byte == &13 {
-                                last_was_carriage_return = true;
-                            } else if byte == &10 && last_was_carriage_return {
-                                multipart_section = MultiPartSection::Start;
-                                eprintln!("Going from 'skipping' -> 'start'");
-                                start_boundary = end + 1;
-                                last_was_carriage_return = false;
-                            } else if byte == &0 {
-                                break;
-                            } else {
-                                last_was_carriage_return = false;
-                            }
-                        }
-
-                        // Stay here until we encounter the boundary with optionally appending - characters
-                        MultiPartSection::Start => {
-                            // Does byte match next byte in boundary?
-                            if let Some(boundary_byte) = boundary.get(end - start_boundary) {
-                                if boundary_byte == byte {
-                                    // Was it the last character of boundary?
-                                    if end - start_boundary + 1 == boundary.len() {
-                                        multipart_section = MultiPartSection::StartSuffix;
-                                        eprintln!("Going from 'start' -> 'start suffix'");
-                                    }
-                                } else if byte == &45 && start_boundary < end {
-                                    if let Some(boundary_byte) =
-                                        boundary.get(end - start_boundary - 1)
-                                    {
-                                        if boundary_byte == byte {
-                                            start_boundary = start_boundary + 1;
-                                        } else {
-                                            multipart_section = MultiPartSection::Skipping;
-                                            eprintln!("Going from 'start' -> 'skipping'");
-                                        }
-                                    } else {
-                                        multipart_section = MultiPartSection::Skipping;
-                                        eprintln!("Going from 'start' -> 'skipping'");
-                                    }
-                                } else {
-                                    multipart_section = MultiPartSection::Skipping;
-                                    eprintln!("Going from 'start' -> 'skipping'");
-                                }
-                            } else if byte == &0 {
-                                break;
-                            } else {
-                                multipart_section = MultiPartSection::Skipping;
-                                eprintln!("Going from 'start' -> 'skipping'");
-                            }
-                        }
-
-                        // Stay here until we encounter \r\n after boundary
-                        MultiPartSection::StartSuffix => {
-                            if byte == &13 {
-                                last_was_carriage_return = true;
-                            } else if byte == &10 && last_was_carriage_return {
-                                multipart_section = MultiPartSection::End;
-                                eprintln!("Going from 'start suffix' -> 'end'");
-                                last_was_carriage_return = false;
-                                start_data = end;
-                            } else if byte == &0 {
-                                break;
-                            } else {
-                                last_was_carriage_return = false;
-                                multipart_section = MultiPartSection::Skipping;
-                                eprintln!("Going from 'start suffix' -> 'skipping'");
-                            }
-                        }
-
-                        // Stay here until we encounter \r\n
-                        MultiPartSection::End => {
-                            // Is it a carriage return?
-                            if byte == &13 {
-                                last_was_carriage_return = true;
-
-                            // Is it a new-line?
-                            } else if byte == &10 && last_was_carriage_return {
-                                multipart_section = MultiPartSection::EndSecondary;
-                                last_was_carriage_return = false;
-                                end_data = end - 1;
-                                start_boundary = end + 1;
-                                eprintln!("Going from 'end' -> 'end secondary'");
-                            } else if byte == &0 {
-                                break;
-                            }
-                        }
-
-                        // Stay here until we encounter \r\n
-                        MultiPartSection::EndSecondary => {
-                            // Is it a carriage return?
-                            if byte == &13 {
-                                last_was_carriage_return = true;
-
-                            // Is it a new-line?
-                            } else if byte == &10 && last_was_carriage_return {
-                                multipart_section = MultiPartSection::EndBoundary;
-                                last_was_carriage_return = false;
-                                eprintln!("Going from 'end secondary' -> 'end boundary'");
-                            } else if byte == &0 {
-                                break;
-                            } else {
-                                multipart_section = MultiPartSection::EndBoundary;
-                                last_was_carriage_return = false;
-                            }
-                        }
-
-                        // Stay here until we can't find boundary or find the full boundary
-                        MultiPartSection::EndBoundary => {
-                            // Does byte match next byte in boundary?
-                            if let Some(boundary_byte) = boundary.get(end - start_boundary) {
-                                if boundary_byte == byte {
-                                    eprintln!(
-                                        "Byte matched boundary byte {}",
-                                        *boundary_byte as char
-                                    );
-                                    // Was it the last character of boundary?
-                                    if end - start_boundary + 1 == boundary.len() {
-                                        multipart_section = MultiPartSection::StartSuffix;
-                                        eprintln!("Going from 'end boundary' -> 'start suffix'");
-
-                                        if start_data > 0
-                                            && start_data < end_data
-                                            && end_data < request.len()
-                                        {
-                                            let data = &request[start_data..end_data];
-                                            eprintln!(
-                                                "Trying to get query arg from {:?}",
-                                                str::from_utf8(&data)
-                                            );
-                                            if let Some((query_key, query_value)) =
-                                                Message::get_query_args_from_multipart_blob(&data)
-                                            {
-                                                if let BodyContentType::MultiPart(ref mut values) =
-                                                    message.body
-                                                {
-                                                    values.insert(query_key, query_value);
-                                                }
-                                            }
-                                        }
-                                    }
-
-                                // Was the character a '-' and does the start of boundary occur before the current position?
-                                } else if byte == &45 && start_boundary < end {
-                                    if let Some(boundary_byte) =
-                                        boundary.get(end - start_boundary - 1)
-                                    {
-                                        if boundary_byte == byte {
-                                            start_boundary = start_boundary + 1;
-                                            eprintln!(
-                                                "Character matches boundary byte '{}'",
-                                                *byte as char
-                                            );
-                                        } else {
-                                            multipart_section = MultiPartSection::End;
-                                            eprintln!("Going from 'end boundary' -> 'end'. Byte didnt match boundary {} vs {}", *boundary_byte as char, *byte as char);
-                                        }
-                                    } else {
-                                        multipart_section = MultiPartSection::End;
-                                        eprintln!("Going from 'end boundary' -> 'end'. Failed to find boundary byte");
-                                    }
-                                } else {
-                                    multipart_section = MultiPartSection::End;
-                                    eprintln!("Going from 'end boundary' -> 'end'. Not matching character was not a '-' but {:?}", *byte as char);
-                                    if byte == &13 {
-                                        last_was_carriage_return = true;
-                                    }
-                                }
-                            } else if byte == &0 {
-                                break;
-                            } else {
-                                multipart_section = MultiPartSection::End;
-                                eprintln!("Going from 'end boundary' -> 'end'");
+                    let body_start = body_start.unwrap_or(end);
+                    if input_end - body_start > config.max_body_size {
+                        let error = ParseError::BodyTooLarge {
+                            limit: config.max_body_size,
+                            seen: input_end - body_start,
+                        };
+                        trace_event!(debug, offset = end, error = %error, "Rejected message body");
+                        return Err(error);
+                    }
+                    let mut parser = multipart::Parser::new(boundary, &request[end..input_end]);
+                    for part in parser.by_ref() {
+                        if let Some((query_key, query_value)) =
+                            Message::get_query_args_from_multipart_blob(part, config)
+                        {
+                            if let BodyContentType::MultiPart(ref mut values) = message.body {
+                                values.insert(query_key, query_value);
                             }
                         }
                     }
+                    trace_event!(trace, state = ?parser.state(), "Parsed multi-part body");
+                    break;
                 }
 
                 // Are we parsing lines?
@@ -672,104 +1711,1056 @@ impl Message {
 
                     // Did we find a \r\n sequence?
                     } else if byte == &10 && last_was_carriage_return {
+                        // Are the header fields larger than allowed?
+                        if let ParserSection::HeaderFields = section {
+                            header_size += end + 1 - start;
+                            if header_size > config.max_header_size {
+                                let error = ParseError::HeaderTooLarge {
+                                    limit: config.max_header_size,
+                                    seen: header_size,
+                                };
+                                trace_event!(debug, offset = end, error = %error, "Rejected header fields");
+                                return Err(error);
+                            }
+                        }
+
                         let clean_end = end - 1;
                         if let Ok(utf8_line) = str::from_utf8(&request[start..clean_end]) {
-                            Message::parse_line(
-                                &utf8_line,
+                            if let Err(error) = Message::parse_line(
+                                utf8_line,
                                 &mut section,
                                 &mut message,
                                 &mut parser_mode,
-                            );
+                                config,
+                            ) {
+                                trace_event!(debug, offset = start, error = %error, "Rejected line");
+                                return Err(error);
+                            }
                             start = end + 1;
-                            start_boundary = end + 1;
+                            if request_line_end.is_none() && !matches!(section, ParserSection::Line)
+                            {
+                                request_line_end = Some(start);
+                            }
+                            if let ParserSection::MessageBody = section {
+                                if body_start.is_none() {
+                                    body_start = Some(start);
+                                    if message.header("Transfer-Encoding").is_some() {
+                                        if !is_decoded {
+                                            return Message::parse_chunked_body(
+                                                request,
+                                                start,
+                                                request.len(),
+                                                config,
+                                            );
+                                        }
+                                    } else if let Some(length) = message.content_length() {
+                                        // Bodies framed by Content-Length may hold null bytes
+                                        input_end = request.len().min(start.saturating_add(length));
+                                    }
+                                }
+                            }
                         }
                         last_was_carriage_return = false;
 
-                    // When we get null bytes we are done or if we reach last index
-                    } else if byte == &0 || end == last_index {
-                        let clean_end = match byte {
-                            &0 => end,
-                            _ => end + 1,
-                        };
-                        if let Ok(utf8_line) = str::from_utf8(&request[start..clean_end]) {
-                            Message::parse_line(
-                                &utf8_line,
+                    // Is it the last byte of the input?
+                    } else if end + 1 == input_end {
+                        if let Ok(utf8_line) = str::from_utf8(&request[start..input_end]) {
+                            if let Err(error) = Message::parse_line(
+                                utf8_line,
                                 &mut section,
                                 &mut message,
                                 &mut parser_mode,
-                            );
+                                config,
+                            ) {
+                                trace_event!(debug, offset = start, error = %error, "Rejected line");
+                                return Err(error);
+                            }
                         }
                         break;
                     } else {
                         last_was_carriage_return = false;
+
+                        // Is the header field line longer than allowed?
+                        if let ParserSection::HeaderFields = section {
+                            let seen = end + 1 - start;
+                            if seen > config.max_header_line_length {
+                                let error = ParseError::HeaderTooLarge {
+                                    limit: config.max_header_line_length,
+                                    seen,
+                                };
+                                trace_event!(debug, offset = start, error = %error, "Rejected header field line");
+                                return Err(error);
+                            }
+                        }
                     }
                 }
             }
+        }
 
-            // Increment byte position
-            end = end + 1;
+        let line_end = request_line_end.unwrap_or(input_end).min(input_end);
+        let header_end = body_start.unwrap_or(input_end).clamp(line_end, input_end);
+        message.wire_size = WireSize {
+            body: input_end - header_end,
+            header: header_end - line_end,
+            request_line: line_end,
+        };
+        message.raw_header = Some(request[..header_end].to_vec());
+        message.raw_body = Some(request[header_end..input_end].to_vec());
+
+        // XML bodies are kept as received instead of being decoded as a form
+        #[cfg(feature = "xml")]
+        {
+            if let Some(body_start) = body_start {
+                if message.is_xml() {
+                    message.body = BodyContentType::Raw(
+                        request[body_start.min(input_end)..input_end].to_vec(),
+                    );
+                }
+            }
         }
 
         // Did we find a valid method and protocol?
         if message.request_line.method != Method::Invalid
             && message.request_line.protocol != Protocol::Invalid
         {
-            return Some(message);
+            // Did the body end before its Content-Length?
+            if !is_decoded && message.header("Transfer-Encoding").is_none() {
+                if let Some(expected) = message.content_length() {
+                    if message.wire_size.body < expected {
+                        let error = ParseError::IncompleteBody {
+                            expected,
+                            received: message.wire_size.body,
+                        };
+                        trace_event!(debug, offset = header_end, error = %error, "Rejected message body");
+                        return Err(error);
+                    }
+                }
+            }
+            return Ok(message);
+        }
+
+        trace_event!(debug, offset = 0, "Rejected request line");
+        Err(ParseError::InvalidRequestLine)
+    }
+
+    /// # Whether the connection should stay open after this request
+    /// HTTP/1.1 connections persist unless closed, older protocols need Connection: keep-alive.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\n\r\n").expect("A request");
+    /// assert!(request.is_keep_alive());
+    /// let request = Message::from_tcp_stream(b"GET / HTTP/1.0\r\n\r\n").expect("A request");
+    /// assert!(!request.is_keep_alive());
+    /// ```
+    pub fn is_keep_alive(&self) -> bool {
+        match self.header("Connection") {
+            Some(connection) if connection.contains_token("close") => false,
+            Some(connection) if connection.contains_token("keep-alive") => true,
+            _ => self.request_line.protocol == Protocol::V1_1,
         }
+    }
 
+    /// # Get a header by name, case-insensitive
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"GET / HTTP/1.1\r\nContent-Type: text/html\r\n")
+    ///     .expect("A request");
+    /// assert_eq!(
+    ///     request.header("content-type").expect("A header").to_string(),
+    ///     "text/html".to_string()
+    /// );
+    /// assert!(request.header("accept").is_none());
+    /// ```
+    pub fn header(&self, name: &str) -> Option<&HeaderValueParts> {
+        if let Some(header) = self.headers.get(&capitalize_key(name)) {
+            return Some(header);
+        }
+        for (key, value) in self.headers.iter() {
+            if key.eq_ignore_ascii_case(name) {
+                return Some(value);
+            }
+        }
         None
     }
 
+    /// # Get a query argument by name
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"GET /?page=2 HTTP/1.1\r\n").expect("A request");
+    /// assert_eq!(request.query("page"), Some("2"));
+    /// assert_eq!(request.query("size"), None);
+    /// ```
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.request_line
+            .query_arguments
+            .get(name)
+            .map(|value| value.as_str())
+    }
+
+    /// # Deserialize the query arguments into a type, repeated keys fill sequence fields
+    /// ## Usage
+    /// ```rust
+    /// extern crate milstian_http;
+    /// #[macro_use]
+    /// extern crate serde_derive;
+    /// use milstian_http::request::Message;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Search {
+    ///     page: u32,
+    ///     tag: Vec<String>,
+    /// }
+    ///
+    /// fn main() {
+    ///     let request = Message::from_tcp_stream(b"GET /?page=2&tag=a&tag=b%20c HTTP/1.1\r\n")
+    ///         .expect("A request");
+    ///     let search: Search = request.query_as().expect("A search");
+    ///     assert_eq!(search.page, 2);
+    ///     assert_eq!(search.tag, vec!["a".to_string(), "b c".to_string()]);
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn query_as<T: ::serde::de::DeserializeOwned>(
+        &self,
+    ) -> Result<T, deserialize::DeserializeError> {
+        self.query_as_with_config(&ParserConfig::default())
+    }
+
+    /// # Deserialize the query arguments into a type, split by the separator of config
+    #[cfg(feature = "serde")]
+    pub fn query_as_with_config<T: ::serde::de::DeserializeOwned>(
+        &self,
+        config: &ParserConfig,
+    ) -> Result<T, deserialize::DeserializeError> {
+        deserialize::from_values(
+            Message::get_query_values_from_string(
+                &self.request_line.query_string,
+                &config.query_separator,
+                uri::percent_decode,
+            )
+            .unwrap_or_default(),
+        )
+    }
+
+    /// # Deserialize a single-part or multi-part form body into a type
+    /// Multi-part bodies are deserialized with [`deserialize::from_multipart`], so file parts can
+    /// fill `Vec<u8>` and [`deserialize::Upload`] fields.
+    /// ## Usage
+    /// ```rust
+    /// extern crate milstian_http;
+    /// #[macro_use]
+    /// extern crate serde_derive;
+    /// use milstian_http::request::Message;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Login {
+    ///     name: String,
+    ///     remember: bool,
+    /// }
+    ///
+    /// fn main() {
+    ///     let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\n\r\nname=ada&remember=on")
+    ///         .expect("A request");
+    ///     let login: Login = request.form_as().expect("A login");
+    ///     assert_eq!(login.name, "ada".to_string());
+    ///     assert!(login.remember);
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn form_as<T: ::serde::de::DeserializeOwned>(
+        &self,
+    ) -> Result<T, deserialize::DeserializeError> {
+        match self.body {
+            BodyContentType::SinglePart(ref values) => deserialize::from_values(values.clone()),
+            BodyContentType::MultiPart(ref values) => deserialize::from_multipart(values),
+            BodyContentType::Raw(_) => Err(deserialize::DeserializeError::Custom(
+                "the body is not a form".to_string(),
+            )),
+        }
+    }
+
+    /// # Get a form value by name from a single-part or multi-part body
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\n\r\nname=abc")
+    ///     .expect("A request");
+    /// assert_eq!(request.form_value("name"), Some("abc"));
+    /// ```
+    pub fn form_value(&self, name: &str) -> Option<&str> {
+        match self.body {
+            BodyContentType::SinglePart(ref values) => {
+                values.get(name)?.first().map(|value| value.as_str())
+            }
+            BodyContentType::MultiPart(ref values) => {
+                let value = values.get(name)?;
+                if value.file.is_some() {
+                    return None;
+                }
+                str::from_utf8(&value.body).ok()
+            }
+            BodyContentType::Raw(_) => None,
+        }
+    }
+
+    /// # Get all values of a repeated single-part form field in order
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\n\r\ntag=a&tag=b")
+    ///     .expect("A request");
+    /// assert_eq!(request.form_values("tag"), &["a".to_string(), "b".to_string()]);
+    /// assert_eq!(request.form_value("tag"), Some("a"));
+    /// assert!(request.form_values("name").is_empty());
+    /// ```
+    pub fn form_values(&self, name: &str) -> &[String] {
+        match self.body {
+            BodyContentType::SinglePart(ref values) => match values.get(name) {
+                Some(values) => values,
+                None => &[],
+            },
+            BodyContentType::MultiPart(_) | BodyContentType::Raw(_) => &[],
+        }
+    }
+
+    /// # Get the value of the Content-Length header
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\nContent-Length: 8\r\n\r\ntest=abc")
+    ///     .expect("A request");
+    /// assert_eq!(request.content_length(), Some(8));
+    /// ```
+    pub fn content_length(&self) -> Option<usize> {
+        self.header("Content-Length")?
+            .to_string()
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// # Was the body received with chunked Transfer-Encoding?
+    /// The reassembled body is available in `raw_body` and parsed like any other body.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(
+    ///     b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\na=bc\r\n2\r\n&d\r\n0\r\n\r\n",
+    /// )
+    /// .expect("A request");
+    /// assert!(request.is_chunked());
+    /// assert_eq!(request.raw_body, Some(b"a=bc&d".to_vec()));
+    /// assert_eq!(request.form_value("a"), Some("bc"));
+    /// assert_eq!(request.wire_size.body, 21);
+    /// ```
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
+    /// # Parse a Content-Length field value, a list of identical lengths is collapsed to one
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// assert_eq!(Message::get_content_length("42"), Some(42));
+    /// assert_eq!(Message::get_content_length("42, 42"), Some(42));
+    /// assert_eq!(Message::get_content_length("42, 43"), None);
+    /// assert_eq!(Message::get_content_length("+42"), None);
+    /// ```
+    pub fn get_content_length(value: &str) -> Option<usize> {
+        let mut content_length: Option<usize> = None;
+        for length in value.split(',').map(|length| length.trim()) {
+            if length.is_empty() || !length.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            let length: usize = length.parse().ok()?;
+            if content_length.is_some_and(|content_length| content_length != length) {
+                return None;
+            }
+            content_length = Some(length);
+        }
+        content_length
+    }
+
+    /// # Get the parsed media type of the Content-Type header
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(
+    ///     b"POST / HTTP/1.1\r\nContent-Type: Text/HTML; charset=utf-8\r\n"
+    /// ).expect("A request");
+    /// let media_type = request.content_type().expect("A media type");
+    /// assert_eq!(media_type.essence(), "text/html".to_string());
+    /// assert_eq!(media_type.charset(), Some("utf-8"));
+    /// ```
+    pub fn content_type(&self) -> Option<MediaType> {
+        MediaType::parse(&self.header("Content-Type")?.to_string())
+    }
+
+    /// # Get the well-formed language tags of the Content-Language header
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(
+    ///     b"POST / HTTP/1.1\r\nContent-Language: mi, en-NZ\r\n"
+    /// ).expect("A request");
+    /// let language = request.content_language().expect("A language");
+    /// assert_eq!(language.tags, vec!["mi".to_string(), "en-NZ".to_string()]);
+    /// ```
+    pub fn content_language(&self) -> Option<ContentLanguage> {
+        let language = ContentLanguage::parse(&self.header("Content-Language")?.to_string());
+        if language.tags.is_empty() {
+            None
+        } else {
+            Some(language)
+        }
+    }
+
+    /// # Whether the Content-Type is an XML media type
+    #[cfg(feature = "xml")]
+    pub fn is_xml(&self) -> bool {
+        self.content_type()
+            .is_some_and(|media_type| xml::is_xml_media_type(&media_type.essence()))
+    }
+
+    /// # Get an XML body decoded with its charset, None when the body is not XML
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(
+    ///     b"POST / HTTP/1.1\r\nContent-Type: text/xml; charset=iso-8859-1\r\n\r\n<a>\xe5 &amp; b=c</a>",
+    /// )
+    /// .expect("A request");
+    /// assert_eq!(request.xml_text(), Some(Ok("<a>\u{e5} &amp; b=c</a>".to_string())));
+    /// ```
+    #[cfg(feature = "xml")]
+    pub fn xml_text(&self) -> Option<Result<String, xml::XmlError>> {
+        match self.body {
+            BodyContentType::Raw(ref bytes) if self.is_xml() => {
+                let media_type = self.content_type();
+                let charset = media_type
+                    .as_ref()
+                    .and_then(|media_type| media_type.charset());
+                Some(xml::decode(bytes, charset))
+            }
+            _ => None,
+        }
+    }
+
+    /// # Get an XML body parsed into its root element, None when the body is not XML
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(
+    ///     b"POST / HTTP/1.1\r\nContent-Type: application/xml\r\n\r\n<order>\r\n<id>7</id>\r\n</order>",
+    /// )
+    /// .expect("A request");
+    /// let order = request.xml_body().expect("An XML body").expect("Valid XML");
+    /// assert_eq!(order.child("id").map(|id| id.text()), Some("7".to_string()));
+    /// ```
+    #[cfg(feature = "xml")]
+    pub fn xml_body(&self) -> Option<Result<xml::Element, xml::XmlError>> {
+        self.xml_text()
+            .map(|text| text.and_then(|text| xml::parse(&text)))
+    }
+
+    /// # Encode message into bytes, the body is framed by the Content-Length of the written bytes
+    /// Received bodies are written as received, built bodies are encoded from `body`. Any
+    /// Content-Length and Transfer-Encoding fields are replaced by that one length.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"POST /a HTTP/1.1\r\nHost: localhost\r\n\r\nb=c")
+    ///     .expect("A request");
+    /// assert_eq!(
+    ///     request.to_bytes(),
+    ///     b"POST /a HTTP/1.1\r\nContent-Length: 3\r\nHost: localhost\r\n\r\nb=c".to_vec()
+    /// );
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body = self.get_body_bytes();
+
+        let is_framing = |key: &str| {
+            key.eq_ignore_ascii_case("Content-Length")
+                || key.eq_ignore_ascii_case("Transfer-Encoding")
+        };
+        let has_declared_body = self.headers.keys().any(|key| is_framing(key));
+        let mut headers: Vec<(String, String)> = self
+            .headers
+            .iter()
+            .filter(|(key, _)| !is_framing(key))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        if !body.is_empty() || has_declared_body {
+            headers.push(("Content-Length".to_string(), body.len().to_string()));
+        }
+        headers.sort();
+
+        let mut output = format!(
+            "{} {} {}\r\n",
+            Message::get_method_text(&self.request_line.method),
+            &self.request_line.request_uri,
+            Message::get_protocol_text(&self.request_line.protocol)
+        )
+        .into_bytes();
+        for (key, value) in headers {
+            output.extend_from_slice(format!("{}: {}\r\n", key, value).as_bytes());
+        }
+        output.extend_from_slice(b"\r\n");
+        output.extend_from_slice(&body);
+        output
+    }
+
+    fn get_body_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        // An unreadable spooled file ends the body where it would start
+        let _ = self.body_reader().read_to_end(&mut output);
+        output
+    }
+
+    /// # Stream the message body
+    /// Received bodies are read as received after any chunked decoding, built bodies are encoded
+    /// from `body` and spooled multi-part files are read from disk. A spooled file that can't be
+    /// opened fails the read.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// use std::io::Read;
+    /// let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\n\r\nb=2&a=1")
+    ///     .expect("A request");
+    /// let mut body = String::new();
+    /// request
+    ///     .body_reader()
+    ///     .read_to_string(&mut body)
+    ///     .expect("A body");
+    /// assert_eq!(body, "b=2&a=1".to_string());
+    ///
+    /// let built = Message {
+    ///     raw_body: None,
+    ///     ..request
+    /// };
+    /// let mut body = String::new();
+    /// built.body_reader().read_to_string(&mut body).expect("A body");
+    /// assert_eq!(body, "a=1&b=2".to_string());
+    /// ```
+    pub fn body_reader<'a>(&'a self) -> impl Read + 'a {
+        if let Some(ref raw_body) = self.raw_body {
+            return Box::new(&raw_body[..]) as Box<dyn Read + 'a>;
+        }
+        let reader: Box<dyn Read + 'a> = match self.body {
+            BodyContentType::SinglePart(ref values) => Box::new(io::Cursor::new(
+                Message::get_form_pairs(values).join("&").into_bytes(),
+            )),
+            BodyContentType::MultiPart(ref values) => {
+                let boundary = match self
+                    .content_type()
+                    .and_then(|media_type| media_type.boundary().map(str::to_string))
+                {
+                    Some(boundary) => boundary,
+                    None => return Box::new(io::empty()) as Box<dyn Read + 'a>,
+                };
+                let mut values: Vec<(&String, &MultiPartValue)> = values.iter().collect();
+                values.sort_by(|a, b| a.0.cmp(b.0));
+                let mut reader: Box<dyn Read + 'a> = Box::new(io::empty());
+                for (_, value) in values.iter() {
+                    let mut head = format!("--{}\r\n", boundary);
+                    let mut headers: Vec<(&String, &HeaderValueParts)> =
+                        value.headers.iter().collect();
+                    headers.sort_by(|a, b| a.0.cmp(b.0));
+                    for (key, header_value) in headers {
+                        head.push_str(&format!("{}: {}\r\n", key, header_value));
+                    }
+                    head.push_str("\r\n");
+                    let body: Box<dyn Read + 'a> = match value.file {
+                        Some(ref file) => match file.open() {
+                            Ok(file) => Box::new(file),
+                            Err(error) => Box::new(FailedRead {
+                                kind: error.kind(),
+                                message: error.to_string(),
+                            }),
+                        },
+                        None => Box::new(&value.body[..]),
+                    };
+                    reader = Box::new(
+                        reader
+                            .chain(io::Cursor::new(head.into_bytes()))
+                            .chain(body)
+                            .chain(&b"\r\n"[..]),
+                    );
+                }
+                if !values.is_empty() {
+                    reader = Box::new(reader.chain(io::Cursor::new(
+                        format!("--{}--\r\n", boundary).into_bytes(),
+                    )));
+                }
+                reader
+            }
+            BodyContentType::Raw(ref bytes) => Box::new(&bytes[..]),
+        };
+        reader
+    }
+
+    /// Form fields as key=value pairs sorted by key, repeated fields keep their order
+    fn get_form_pairs(values: &HashMap<String, Vec<String>>) -> Vec<String> {
+        let mut keys: Vec<&String> = values.keys().collect();
+        keys.sort();
+        keys.iter()
+            .flat_map(|key| {
+                values[*key].iter().map(move |value| {
+                    format!("{}={}", uri::form_encode(key), uri::form_encode(value))
+                })
+            })
+            .collect()
+    }
+
+    fn get_body_size(&self) -> usize {
+        match self.body {
+            BodyContentType::SinglePart(ref values) => {
+                let pairs = Message::get_form_pairs(values);
+                pairs
+                    .iter()
+                    .map(|pair| pair.len())
+                    .sum::<usize>()
+                    .saturating_add(pairs.len())
+                    .saturating_sub(1)
+            }
+            BodyContentType::MultiPart(ref values) => {
+                values.values().map(MultiPartValue::size).sum()
+            }
+            BodyContentType::Raw(ref bytes) => bytes.len(),
+        }
+    }
+
+    /// # Get a multi-line description of request line, headers and a truncated body
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"POST /a HTTP/1.1\r\nAgent: Random\r\n\r\nb=c")
+    ///     .expect("A request");
+    /// assert_eq!(request.dump(), "POST /a HTTP/1.1\nAgent: Random\n\nb=c\n".to_string());
+    /// ```
+    pub fn dump(&self) -> String {
+        let mut output = format!(
+            "{} {} {}\n",
+            Message::get_method_text(&self.request_line.method),
+            &self.request_line.request_uri,
+            Message::get_protocol_text(&self.request_line.protocol)
+        );
+
+        let mut headers: Vec<(&String, &HeaderValueParts)> = self.headers.iter().collect();
+        headers.sort_by(|a, b| a.0.cmp(b.0));
+        for (key, value) in headers {
+            output.push_str(&format!("{}: {}\n", key, value));
+        }
+        output.push('\n');
+
+        match self.body {
+            BodyContentType::SinglePart(ref values) => {
+                if !values.is_empty() {
+                    let body = Message::get_form_pairs(values);
+                    output.push_str(&Message::truncate(body.join("&").as_bytes()));
+                    output.push('\n');
+                }
+            }
+            BodyContentType::MultiPart(ref values) => {
+                let mut values: Vec<(&String, &MultiPartValue)> = values.iter().collect();
+                values.sort_by(|a, b| a.0.cmp(b.0));
+                for (name, value) in values {
+                    output.push_str(&format!("[{}] {} bytes\n", name, value.size()));
+                    match value.file {
+                        Some(ref file) => {
+                            output.push_str(&format!("(spooled to {})", file.path().display()))
+                        }
+                        None => output.push_str(&Message::truncate(&value.body)),
+                    }
+                    output.push('\n');
+                }
+            }
+            BodyContentType::Raw(ref bytes) => {
+                if !bytes.is_empty() {
+                    output.push_str(&Message::truncate(bytes));
+                    output.push('\n');
+                }
+            }
+        }
+
+        output
+    }
+
+    fn truncate(body: &[u8]) -> String {
+        if body.len() > DUMP_BODY_LIMIT {
+            return format!(
+                "{}... ({} more bytes)",
+                String::from_utf8_lossy(&body[..DUMP_BODY_LIMIT]),
+                body.len() - DUMP_BODY_LIMIT
+            );
+        }
+        String::from_utf8_lossy(body).into_owned()
+    }
+
+    /// Collapse a Content-Length field with those already parsed, conflicting lengths are invalid
+    fn merge_content_length(
+        message: &Message,
+        value: &HeaderValueParts,
+    ) -> Result<HeaderValueParts, ParseError> {
+        let content_length = match Message::get_content_length(&value.to_string()) {
+            Some(content_length) => content_length,
+            None => {
+                trace_event!(debug, value = %value, "Rejected invalid Content-Length");
+                return Err(ParseError::InvalidHeader);
+            }
+        };
+        if let Some(existing) = message.header("Content-Length") {
+            if Message::get_content_length(&existing.to_string()) != Some(content_length) {
+                trace_event!(
+                    debug,
+                    existing = %existing,
+                    value = %value,
+                    "Rejected conflicting Content-Length"
+                );
+                return Err(ParseError::InvalidHeader);
+            }
+        }
+        Ok(HeaderValueParts {
+            parts: vec![vec![HeaderValuePart::Single(content_length.to_string())]],
+        })
+    }
+
+    /// Decode the chunked body starting at body_start and parse the request with it in place
+    fn parse_chunked_body(
+        request: &[u8],
+        body_start: usize,
+        input_end: usize,
+        config: &ParserConfig,
+    ) -> Result<Message, ParseError> {
+        let body = &request[body_start..input_end];
+        if body.len() > config.max_body_size {
+            return Err(ParseError::BodyTooLarge {
+                limit: config.max_body_size,
+                seen: body.len(),
+            });
+        }
+        let (decoded, used) = match chunked::decode(body) {
+            chunked::Status::Complete(decoded, used) => (decoded, used),
+            chunked::Status::Incomplete | chunked::Status::Invalid => {
+                let error = ParseError::InvalidChunkedBody;
+                trace_event!(debug, offset = body_start, error = %error, "Rejected message body");
+                return Err(error);
+            }
+        };
+        trace_event!(debug, bytes = decoded.len(), "Decoded chunked body");
+        let mut decoded_request = request[..body_start].to_vec();
+        decoded_request.extend_from_slice(&decoded);
+        let mut message = Message::parse_tcp_stream(&decoded_request, config, true)?;
+        message.chunked = true;
+        message.wire_size.body = used;
+        Ok(message)
+    }
+
+    /// Get the version of a request line with a known method and a well-formed but unsupported
+    /// HTTP version
+    fn get_unsupported_protocol(line: &str) -> Option<String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (method, version) = match parts.as_slice() {
+            [method, _, version] => (*method, *version),
+            _ => return None,
+        };
+        let methods = [
+            Method::Connect,
+            Method::Delete,
+            Method::Get,
+            Method::Head,
+            Method::Options,
+            Method::Patch,
+            Method::Post,
+            Method::Put,
+            Method::Trace,
+        ];
+        let protocols = [
+            Protocol::V0_9,
+            Protocol::V1_0,
+            Protocol::V1_1,
+            Protocol::V2_0,
+        ];
+        if !methods
+            .iter()
+            .any(|known| Message::get_method_text(known) == method)
+            || protocols
+                .iter()
+                .any(|known| Message::get_protocol_text(known) == version)
+        {
+            return None;
+        }
+        let number = version.strip_prefix("HTTP/")?.as_bytes();
+        if number.len() == 3
+            && number[0].is_ascii_digit()
+            && number[1] == b'.'
+            && number[2].is_ascii_digit()
+        {
+            Some(version.to_string())
+        } else {
+            None
+        }
+    }
+
     fn parse_line(
         line: &str,
         section: &mut ParserSection,
         message: &mut Message,
         parser_mode: &mut ParserMode,
-    ) {
+        config: &ParserConfig,
+    ) -> Result<(), ParseError> {
         match section {
             ParserSection::Line => {
-                if let Some(request_line_temp) = Message::get_request_line(line) {
+                trace_span!("request_line");
+                if let Some(request_line_temp) = Message::get_request_line_with_config(line, config)
+                {
+                    // Is the request URI longer than allowed?
+                    if request_line_temp.request_uri.len() > config.max_request_uri_length {
+                        return Err(ParseError::UriTooLong {
+                            limit: config.max_request_uri_length,
+                            seen: request_line_temp.request_uri.len(),
+                        });
+                    }
+                    config.check_method(&request_line_temp.method)?;
+                    trace_event!(
+                        debug,
+                        method = ?request_line_temp.method,
+                        uri = %request_line_temp.request_uri,
+                        "Parsed request line"
+                    );
                     message.request_line = request_line_temp;
                     *section = ParserSection::HeaderFields;
+                } else if let Some(version) = Message::get_unsupported_protocol(line) {
+                    return Err(ParseError::UnsupportedProtocol(version));
                 }
             }
             ParserSection::HeaderFields => {
+                trace_span!("header_field");
                 // Is it the last line of the headers?
                 if line.trim().is_empty() {
                     // Check if we have a multi-part body
-                    if let Some(content_type_header) = message.headers.get("Content-Type") {
-                        if let Some(boundary) = content_type_header.get_key_value("boundary") {
-                            *parser_mode = ParserMode::Boundaries(boundary.as_bytes().to_vec());
+                    // Multi-part bodies can't be delimited without a valid boundary
+                    if let Some(media_type) = message.content_type() {
+                        if media_type.is_multipart() {
+                            let boundary = match media_type.boundary() {
+                                Some(boundary) => boundary,
+                                None => {
+                                    trace_event!(
+                                        debug,
+                                        content_type = %media_type,
+                                        "Rejected invalid multi-part boundary"
+                                    );
+                                    return Err(ParseError::InvalidHeader);
+                                }
+                            };
+                            *parser_mode = ParserMode::Boundaries(boundary.to_string());
                             message.body = BodyContentType::MultiPart(HashMap::new());
-                            eprintln!("Found boundary start: '{}'", &boundary);
+                            trace_event!(debug, boundary = %boundary, "Found multi-part boundary");
                         }
                     }
 
+                    // Framing fields delimit a body whatever the method
                     if Message::method_has_request_body(&message.request_line.method)
                         != SettingValence::No
+                        || message.header("Content-Length").is_some()
+                        || message.header("Transfer-Encoding").is_some()
                     {
                         *section = ParserSection::MessageBody;
                     }
                 } else {
-                    if let Some((header_key, header_value)) = Message::get_header_field(line) {
+                    // Whitespace between field name and colon is only trimmed in lenient mode
+                    if config.strict && Message::has_whitespace_before_colon(line) {
+                        return Err(ParseError::InvalidHeader);
+                    }
+                    if let Some((header_key, mut header_value)) =
+                        Message::get_header_field_with_config(line, config)
+                    {
+                        trace_event!(trace, name = %header_key, "Parsed header field");
+                        // Bodies must be delimited the same way as by the stream parser
+                        if header_key.eq_ignore_ascii_case("Transfer-Encoding")
+                            && (message.header("Transfer-Encoding").is_some()
+                                || !chunked::is_valid_transfer_encoding(&header_value.to_string()))
+                        {
+                            trace_event!(debug, value = %header_value, "Rejected Transfer-Encoding");
+                            return Err(ParseError::InvalidHeader);
+                        }
+                        if header_key.eq_ignore_ascii_case("Transfer-Encoding") {
+                            if let Some(coding) =
+                                chunked::get_unsupported_coding(&header_value.to_string())
+                            {
+                                return Err(ParseError::UnsupportedTransferCoding(
+                                    coding.to_string(),
+                                ));
+                            }
+                        }
+                        if header_key.eq_ignore_ascii_case("Content-Length") {
+                            header_value = Message::merge_content_length(message, &header_value)?;
+                            message
+                                .headers
+                                .retain(|key, _| !key.eq_ignore_ascii_case("Content-Length"));
+                        }
                         message.headers.insert(header_key, header_value);
                     }
                 }
             }
             ParserSection::MessageBody => {
+                trace_span!("message_body", bytes = line.len());
                 if !line.is_empty() {
-                    if let Some(body_args) = Message::get_message_body(line) {
+                    if let Some(body_args) = Message::get_message_body_with_config(line, config) {
                         message.body = body_args;
                     }
                 }
             }
         }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Message {
+    /// # Summarize request like method, target, protocol, header count and body size
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// let request = Message::from_tcp_stream(b"POST /a HTTP/1.1\r\nAgent: Random\r\n\r\nb=c")
+    ///     .expect("A request");
+    /// assert_eq!(
+    ///     request.to_string(),
+    ///     "POST /a HTTP/1.1 (1 headers, 3 bytes body)".to_string()
+    /// );
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} ({} headers, {} bytes body)",
+            Message::get_method_text(&self.request_line.method),
+            &self.request_line.request_uri,
+            Message::get_protocol_text(&self.request_line.protocol),
+            self.headers.len(),
+            self.get_body_size()
+        )
+    }
+}
+
+/// Fails every read with the error of a spooled file that couldn't be opened
+struct FailedRead {
+    kind: io::ErrorKind,
+    message: String,
+}
+
+impl Read for FailedRead {
+    fn read(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(self.kind, self.message.clone()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
+    use stream;
+
+    #[test]
+    fn test_duplicate_content_length() {
+        let request = Message::from_tcp_stream(
+            b"POST / HTTP/1.1\r\nContent-Length: 3\r\ncontent-length: 3, 3\r\n\r\na=b",
+        )
+        .expect("A request");
+        assert_eq!(request.content_length(), Some(3));
+        assert_eq!(request.headers.len(), 1);
+        assert_eq!(request.form_value("a"), Some("b"));
+
+        assert_eq!(
+            Message::from_tcp_stream_with_config(
+                b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\na=b",
+                &ParserConfig::default()
+            ),
+            Err(ParseError::InvalidHeader)
+        );
+        assert_eq!(
+            Message::from_tcp_stream_with_config(
+                b"POST / HTTP/1.1\r\nContent-Length: 3, 4\r\n\r\na=b",
+                &ParserConfig::default()
+            ),
+            Err(ParseError::InvalidHeader)
+        );
+        assert_eq!(
+            Message::from_tcp_stream_with_config(
+                b"POST / HTTP/1.1\r\nContent-Length: -3\r\n\r\na=b",
+                &ParserConfig::default()
+            ),
+            Err(ParseError::InvalidHeader)
+        );
+    }
+
+    #[cfg(feature = "xml")]
+    #[test]
+    fn test_xml_body() {
+        let request = Message::from_tcp_stream(
+            b"PUT /a HTTP/1.1\r\nContent-Type: application/atom+xml\r\n\r\n<feed>\r\n<title>a&amp;b</title>\r\n</feed>\0\0",
+        )
+        .expect("A request");
+        assert_eq!(
+            request.body,
+            BodyContentType::Raw(b"<feed>\r\n<title>a&amp;b</title>\r\n</feed>".to_vec())
+        );
+        assert_eq!(request.form_value("title"), None);
+        let feed = request.xml_body().expect("An XML body").expect("Valid XML");
+        assert_eq!(feed.text(), "a&b".to_string());
+        let mut body = String::new();
+        request
+            .body_reader()
+            .read_to_string(&mut body)
+            .expect("A body");
+        assert_eq!(
+            body,
+            "<feed>\r\n<title>a&amp;b</title>\r\n</feed>".to_string()
+        );
+
+        let request = Message::from_tcp_stream(
+            b"POST / HTTP/1.1\r\nContent-Type: text/xml\r\n\r\n<a>\xff</a>",
+        )
+        .expect("A request");
+        assert_eq!(
+            request.xml_body(),
+            Some(Err(xml::XmlError::InvalidEncoding))
+        );
+
+        let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\n\r\na=b").expect("A request");
+        assert_eq!(request.xml_text(), None);
+    }
+
+    #[test]
+    fn test_query_get_as() {
+        let line =
+            Message::get_request_line("GET /?a=-5&b=on&c=0&d=1.5&e=x&f=300&g=%20hello HTTP/1.1")
+                .expect("A request line");
+        assert_eq!(line.query_get_as::<i8>("a"), Ok(-5));
+        assert_eq!(line.query_get_as::<isize>("a"), Ok(-5));
+        assert!(line.query_get_as::<usize>("a").is_err());
+        assert_eq!(line.query_get_as::<bool>("b"), Ok(true));
+        assert_eq!(line.query_get_as::<bool>("c"), Ok(false));
+        assert_eq!(line.query_get_as::<u8>("c"), Ok(0));
+        assert_eq!(line.query_get_as::<f64>("d"), Ok(1.5));
+        assert_eq!(line.query_get_as::<char>("e"), Ok('x'));
+        assert!(line.query_get_as::<bool>("e").is_err());
+        assert!(line.query_get_as::<u8>("f").is_err());
+        assert_eq!(line.query_get_as::<u16>("f"), Ok(300));
+        assert_eq!(line.query_get_as::<String>("g"), Ok(" hello".to_string()));
+        assert_eq!(
+            line.query_get_as::<f32>("e")
+                .expect_err("An error")
+                .to_string(),
+            "query argument e has value \"x\", expected a number".to_string()
+        );
+        assert_eq!(
+            line.query_get_as::<f32>("h")
+                .expect_err("An error")
+                .to_string(),
+            "query argument h is missing".to_string()
+        );
+    }
 
     #[test]
     fn test_get_message_body_single_part() {
@@ -779,37 +2770,66 @@ mod tests {
         let response_unwrapped = response.unwrap();
         if let BodyContentType::SinglePart(response_unwrapped) = response_unwrapped {
             assert_eq!(
-                response_unwrapped
-                    .get(&"random".to_string())
-                    .unwrap()
-                    .to_string(),
-                "abc".to_string()
+                response_unwrapped.get("random").unwrap(),
+                &vec!["abc".to_string()]
             );
             assert_eq!(
-                response_unwrapped
-                    .get(&"hej".to_string())
-                    .unwrap()
-                    .to_string(),
-                "def".to_string()
+                response_unwrapped.get("hej").unwrap(),
+                &vec!["def".to_string()]
             );
             assert_eq!(
-                response_unwrapped
-                    .get(&"def".to_string())
-                    .unwrap()
-                    .to_string(),
-                "1".to_string()
+                response_unwrapped.get("def").unwrap(),
+                &vec!["1".to_string()]
             );
-            assert!(response_unwrapped.get(&"defs".to_string()).is_none());
+            assert!(!response_unwrapped.contains_key("defs"));
+        }
+
+        let response = Message::get_message_body("");
+        assert!(response.is_none());
+
+        // Semicolons are only separators when configured
+        let response = Message::get_message_body("a;b=2&c=3");
+        if let Some(BodyContentType::SinglePart(body)) = response {
+            assert_eq!(body.get("a;b"), Some(&vec!["2".to_string()]));
+            assert_eq!(body.get("c"), Some(&vec!["3".to_string()]));
+        } else {
+            panic!("Expected single-part body");
+        }
+        let config = ParserConfig {
+            query_separator: QuerySeparator::AmpersandAndSemicolon,
+            ..ParserConfig::default()
+        };
+        let response = Message::get_message_body_with_config("a=1;b=2&c=3", &config);
+        if let Some(BodyContentType::SinglePart(body)) = response {
+            assert_eq!(body.get("a"), Some(&vec!["1".to_string()]));
+            assert_eq!(body.get("b"), Some(&vec!["2".to_string()]));
+            assert_eq!(body.get("c"), Some(&vec!["3".to_string()]));
+        } else {
+            panic!("Expected single-part body");
         }
-
-        let response = Message::get_message_body("");
-        assert!(response.is_none());
+        let config = ParserConfig {
+            query_separator: QuerySeparator::Semicolon,
+            ..ParserConfig::default()
+        };
+        let response = Message::get_message_body_with_config("a=1;b&c=3", &config);
+        if let Some(BodyContentType::SinglePart(body)) = response {
+            assert_eq!(body.get("a"), Some(&vec!["1".to_string()]));
+            assert_eq!(body.get("b&c"), Some(&vec!["3".to_string()]));
+        } else {
+            panic!("Expected single-part body");
+        }
+        let response = Message::get_request_line_with_config("GET /?a=1;b=2 HTTP/1.1", &config);
+        assert_eq!(
+            response.expect("A request line").query_arguments.get("b"),
+            Some(&"2".to_string())
+        );
     }
 
     #[test]
     fn test_get_query_args_from_multipart_blob() {
         let response = Message::get_query_args_from_multipart_blob(
             b"Content-Disposition: form-data; name=\"losen\"\r\n\r\nabc\n123",
+            &ParserConfig::default(),
         );
         assert!(response.is_some());
         if let Some((query_key, query_value)) = response {
@@ -840,7 +2860,7 @@ lOrHXWTsK+8ayLNZ0dCcP054srAtMmJHscPiuUYPfvKSgLxl+JxkPC147EktCCzv
 BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
 =LO1E
 -----END PGP SIGNATURE-----
-");
+", &ParserConfig::default());
 
         assert!(response.is_some());
         if let Some((query_key, query_value)) = response {
@@ -859,8 +2879,211 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
 
         let response = Message::get_query_args_from_multipart_blob(
             b"okasdokadsokasd oa skoasdk\r\nokadsokasdokoadskods\r\n123123",
+            &ParserConfig::default(),
         );
         assert!(response.is_none());
+
+        // Empty fields and file inputs without a chosen file are kept
+        let (_, value) = Message::get_query_args_from_multipart_blob(
+            b"Content-Disposition: form-data; name=\"a\"\r\n\r\n",
+            &ParserConfig::default(),
+        )
+        .expect("An empty field");
+        assert_eq!((value.body, value.filename), (Vec::new(), None));
+        let (_, value) = Message::get_query_args_from_multipart_blob(
+            b"Content-Disposition: form-data; name=\"b\"; filename=\"\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+            &ParserConfig {
+                multipart_spool_threshold: Some(0),
+                ..ParserConfig::default()
+            },
+        )
+        .expect("An empty upload");
+        assert!(value.file.is_none());
+        assert!(value.is_empty_upload());
+        let (_, value) = Message::get_query_args_from_multipart_blob(
+            b"Content-Disposition: form-data; name=\"c\"; filename=\"c.txt\"\r\n\r\n",
+            &ParserConfig::default(),
+        )
+        .expect("An empty file");
+        assert_eq!(value.filename, Some("c.txt".to_string()));
+        assert!(!value.is_empty_upload());
+
+        // Bodies above the spool threshold are written to a temporary file
+        let config = ParserConfig {
+            multipart_spool_threshold: Some(2),
+            ..ParserConfig::default()
+        };
+        let (_, value) = Message::get_query_args_from_multipart_blob(
+            b"Content-Disposition: form-data; name=\"a\"; filename=\"a.txt\"\r\nContent-Type: text/plain\r\n\r\nabc",
+            &config,
+        )
+        .expect("A spooled part");
+        assert!(value.body.is_empty());
+        let file = value.file.expect("A spooled file");
+        assert_eq!(file.size, 3);
+        assert_eq!(file.filename, Some("a.txt".to_string()));
+        assert_eq!(file.content_type, Some("text/plain".to_string()));
+        assert_eq!(
+            fs::read(file.path()).expect("File contents"),
+            b"abc".to_vec()
+        );
+        let (_, value) = Message::get_query_args_from_multipart_blob(
+            b"Content-Disposition: form-data; name=\"a\"\r\n\r\nab",
+            &config,
+        )
+        .expect("A part");
+        assert_eq!(value.body, b"ab".to_vec());
+        assert!(value.file.is_none());
+    }
+
+    #[test]
+    fn test_multipart_receiver() {
+        let config = ParserConfig {
+            multipart_spool_threshold: Some(3),
+            ..ParserConfig::default()
+        };
+        let contents = |request: &Message| -> Vec<(String, Vec<u8>, bool)> {
+            let mut contents = Vec::new();
+            if let BodyContentType::MultiPart(ref values) = request.body {
+                for (name, value) in values.iter() {
+                    let body = match value.file {
+                        Some(ref file) => fs::read(file.path()).expect("File contents"),
+                        None => value.body.clone(),
+                    };
+                    contents.push((name.clone(), body, value.file.is_some()));
+                }
+            }
+            contents.sort();
+            contents
+        };
+
+        // Bodies are split like when the request is parsed at once, however the bytes arrive
+        let bodies: [&[u8]; 6] = [
+            b"preamble\r\n--ab \t\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nhello\r\n--abc\r\n--ab\r\nContent-Disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\r\nxy\r\n--ab--\r\nepilogue",
+            b"--ab\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n\r\n-\r\n--a\r\n--ab-\r\n--ab",
+            b"--ab\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nlong value\r\n--ab\r\nNo-Name: 1\r\n\r\nlong value\r\n--ab\r\n\r\n",
+            b"--ab\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nlong value\r\n--ab  ",
+            b"--ab--\r\n--ab\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--ab--",
+            b"",
+        ];
+        for body in bodies.iter() {
+            let head = format!(
+                "POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=ab\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            );
+            let mut request = head.clone().into_bytes();
+            request.extend_from_slice(body);
+            let expected =
+                Message::from_tcp_stream_with_config(&request, &config).expect("A request");
+            for size in 1..body.len().max(1) + 1 {
+                let mut receiver = MultiPartReceiver::new(head.as_bytes(), &config)
+                    .expect("A valid head")
+                    .expect("A multi-part body");
+                for bytes in body.chunks(size) {
+                    receiver.push(bytes).expect("Parts");
+                }
+                let received = receiver.finish().expect("A request");
+                assert_eq!(contents(&received), contents(&expected));
+                assert_eq!(received.wire_size, expected.wire_size);
+                assert_eq!(received.raw_body, None);
+            }
+        }
+
+        // Parts are spooled while they arrive
+        let mut receiver = MultiPartReceiver::new(
+            b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=ab\r\n\r\n",
+            &config,
+        )
+        .expect("A valid head")
+        .expect("A multi-part body");
+        receiver
+            .push(b"--ab\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nhello")
+            .expect("A part");
+        match receiver.part {
+            ReceivedPart::Spooled(ref name, ref value) => {
+                assert_eq!(name, "a");
+                assert_eq!(value.size(), 5);
+            }
+            _ => panic!("Expected a spooled part"),
+        }
+        assert!(receiver.pending.len() < receiver.delimiter.len());
+
+        // Other bodies are not received as parts
+        assert!(MultiPartReceiver::new(b"POST / HTTP/1.1\r\n\r\n", &config)
+            .expect("A valid head")
+            .is_none());
+        assert_eq!(
+            MultiPartReceiver::new(b"POST / HTTP/9.9\r\n\r\n", &config).expect_err("A version"),
+            ParseError::UnsupportedProtocol("HTTP/9.9".to_string())
+        );
+
+        // Limits apply to the received body
+        let head = b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=ab\r\nContent-Length: 8\r\n\r\n";
+        let mut receiver = MultiPartReceiver::new(
+            head,
+            &ParserConfig {
+                max_body_size: 4,
+                ..config.clone()
+            },
+        )
+        .expect("A valid head")
+        .expect("A multi-part body");
+        assert_eq!(
+            receiver
+                .push(b"--ab\r\n\r\n")
+                .expect_err("A too large body"),
+            ParseError::BodyTooLarge { limit: 4, seen: 8 }
+        );
+        let mut receiver = MultiPartReceiver::new(head, &config)
+            .expect("A valid head")
+            .expect("A multi-part body");
+        receiver.push(b"--ab").expect("A delimiter");
+        assert_eq!(
+            receiver.finish().expect_err("A truncated body"),
+            ParseError::IncompleteBody {
+                expected: 8,
+                received: 4
+            }
+        );
+        let mut receiver = MultiPartReceiver::new(
+            b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=ab\r\n\r\n",
+            &ParserConfig {
+                max_header_size: 64,
+                ..config.clone()
+            },
+        )
+        .expect("A valid head")
+        .expect("A multi-part body");
+        let mut part = b"--ab\r\nA: ".to_vec();
+        part.extend_from_slice(&[b'a'; 70]);
+        assert_eq!(
+            receiver
+                .push(&part)
+                .expect_err("Too large part header fields"),
+            ParseError::HeaderTooLarge {
+                limit: 64,
+                seen: 73
+            }
+        );
+    }
+
+    #[test]
+    fn test_header_value_parts_iteration() {
+        let (_, value) =
+            Message::get_header_field("X-Test: a, b; q=1, C; Key=\"v\"").expect("A header");
+        assert_eq!(value.blocks().count(), 3);
+        assert_eq!(value.iter().count(), 5);
+        assert_eq!(value.first(), Some("a"));
+        assert!(value.contains_token("c"));
+        assert!(!value.contains_token("q"));
+        let params = value.params();
+        assert_eq!(params.len(), 2);
+        assert_eq!(params.get("q"), Some(&"1".to_string()));
+        assert_eq!(params.get("key"), Some(&"v".to_string()));
+
+        let (_, value) = Message::get_header_field("X-Test: q=1").expect("A header");
+        assert_eq!(value.first(), None);
+        assert_eq!(HeaderValueParts::default().first(), None);
     }
 
     #[test]
@@ -919,7 +3142,7 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         assert_eq!(
             response_unpacked
                 .query_arguments
-                .get(&"abc".to_string())
+                .get("abc")
                 .unwrap()
                 .to_string(),
             String::from("test")
@@ -934,6 +3157,7 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         assert_eq!(response_unpacked.request_uri, String::from("/"));
         assert_eq!(response_unpacked.request_uri_base, String::from("/"));
         assert_eq!(response_unpacked.query_string, String::from(""));
+        assert_eq!(response_unpacked.fragment, None);
         assert_eq!(response_unpacked.protocol, Protocol::V1_0);
 
         let response = Message::get_request_line("HEAD /moradish.html?test&abc=def HTTP/1.1\r\n");
@@ -953,7 +3177,7 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         assert_eq!(
             response_unpacked
                 .query_arguments
-                .get(&"test".to_string())
+                .get("test")
                 .unwrap()
                 .to_string(),
             String::from("1")
@@ -961,7 +3185,7 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         assert_eq!(
             response_unpacked
                 .query_arguments
-                .get(&"abc".to_string())
+                .get("abc")
                 .unwrap()
                 .to_string(),
             String::from("def")
@@ -981,6 +3205,49 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
 
         let response = Message::get_request_line("GET / HTTP/2.2\r\n");
         assert!(response.is_none());
+
+        let response = Message::get_request_line("GET /random?abc=test#top HTTP/1.1\r\n");
+        assert!(response.is_some());
+        let response_unpacked = response.unwrap();
+        assert_eq!(
+            response_unpacked.request_uri,
+            String::from("/random?abc=test")
+        );
+        assert_eq!(response_unpacked.request_uri_base, String::from("/random"));
+        assert_eq!(response_unpacked.query_string, String::from("abc=test"));
+        assert_eq!(response_unpacked.fragment, Some(String::from("top")));
+
+        let response = Message::get_request_line("GET /random#top?abc=test HTTP/1.1\r\n");
+        let response_unpacked = response.unwrap();
+        assert_eq!(response_unpacked.request_uri_base, String::from("/random"));
+        assert_eq!(response_unpacked.query_string, String::from(""));
+        assert_eq!(
+            response_unpacked.fragment,
+            Some(String::from("top?abc=test"))
+        );
+
+        let response = Message::get_request_line("GET  /random \t HTTP/1.1\r\n");
+        assert!(response.is_some());
+        let response_unpacked = response.unwrap();
+        assert_eq!(response_unpacked.method, Method::Get);
+        assert_eq!(response_unpacked.request_uri, String::from("/random"));
+        assert_eq!(response_unpacked.protocol, Protocol::V1_1);
+
+        let response = Message::get_request_line("GET /a%2Fb//c/ HTTP/1.1\r\n");
+        let segments: Vec<String> = response.expect("A request line").path_segments().collect();
+        assert_eq!(segments, vec!["a/b".to_string(), "c".to_string()]);
+        let response = Message::get_request_line("GET / HTTP/1.1\r\n");
+        assert_eq!(response.expect("A request line").path_segments().count(), 0);
+
+        let config = ParserConfig {
+            strict: true,
+            ..ParserConfig::default()
+        };
+        let response =
+            Message::get_request_line_with_config("GET  /random \t HTTP/1.1\r\n", &config);
+        assert!(response.is_none());
+        let response = Message::get_request_line_with_config("GET /random HTTP/1.1\r\n", &config);
+        assert!(response.is_some());
     }
 
     #[test]
@@ -1015,15 +3282,15 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         assert_eq!(
             response_unwrapped
                 .headers
-                .get(&"Agent".to_string())
+                .get("Agent")
                 .expect("Agent")
                 .to_string(),
             "Random browser".to_string()
         );
         if let BodyContentType::SinglePart(body) = response_unwrapped.body {
             assert_eq!(
-                body.get(&"test".to_string()).expect("test-abc").to_string(),
-                "abc".to_string()
+                body.get("test").expect("test-abc"),
+                &vec!["abc".to_string()]
             );
         }
 
@@ -1039,7 +3306,7 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         let response_unwrapped = response.expect("multipart");
         if let BodyContentType::MultiPart(body) = response_unwrapped.body {
             assert_eq!(
-                String::from_utf8(body.get(&"file".to_string()).expect("expecting file data").body.clone()).expect("expecting utf-8 file data"),
+                String::from_utf8(body.get("file").expect("expecting file data").body.clone()).expect("expecting utf-8 file data"),
                 "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEEweTLo61406/YlPngt6ZvA7WQdqgFAlqfE5MACgkQt6ZvA7WQ\ndqgnEAgAjtdbsMPaULGXKX6H+fcsYeGEN8OjiUTNz+StwNDkDxhxB4MT0N0lYZ4L\nxUv86kwMdWAaxp8pvVWo6gWXTEM5gWmN302bBxkpbhBl9fnq6WdcCCDGs4GM5vHX\nlOrHXWTsK+8ayLNZ0dCcP054srAtMmJHscPiuUYPfvKSgLxl+JxkPC147EktCCzv\n5O+2AtQPwIEPuaMewFqP9KjaGOhWgAc0nauIKa0ASt9FXXrexq1EoZnoZ3ZQ0p/w\n/otAB2D27yQ4kv+X2Rn94Ky9W0lMT2MYEF+/tQH4aEKsdMBQ7REQtfLGFlEzTMB/\nBNUI5YCF3PV9MKr3N53vEVYvkbXLbw==\n=LO1E\n-----END PGP SIGNATURE-----\n".to_string()
             );
         } else {
@@ -1059,16 +3326,16 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         }
 
         // Multi-part data with two data
-        let response = Message::from_tcp_stream(b"POST /?test=abcdef HTTP/1.1\r\nHost: localhost:8888\r\nUser-Agent: Mozilla/5.0 (Macintosh; Intel Mac OS X 10.13; rv:63.0) Gecko/20100101 Firefox/63.0\r\nAccept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\nAccept-Language: en-US,en;q=0.5\r\nAccept-Encoding: gzip, deflate\r\nReferer: http://localhost:8888/?test=abcdef\r\nContent-Type: multipart/form-data; boundary=-----------------------------3204198641555151219403070096\r\nContent-Length: 733\r\nDNT: 1\r\nConnection: keep-alive\r\nUpgrade-Insecure-Requests: 1\r\nPragma: no-cache\r\nCache-Control: no-cache\r\n\r\n-----------------------------3204198641555151219403070096\r\nContent-Disposition: form-data; name=\"file\"; filename=\"KeePassXC-2.3.3.dmg.DIGEST\"\r\nContent-Type: application/octet-stream\r\n\r\n1219dd686aee2549ef8fe688aeef22e85272a8ccbefdbbb64c0e5601db17fbdb  KeePassXC-2.3.3.dmg\r\n\r\n-----------------------------3204198641555151219403070096\r\nContent-Disposition: form-data; name=\"file2\"; filename=\"KeePassXC-2.3.3.dmg.sig\"\r\nContent-Type: application/octet-stream\r\n\r\n-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEEweTLo61406/YlPngt6ZvA7WQdqgFAlrzMl4ACgkQt6ZvA7WQ\ndqhkrQf9G3r5thluX7Ogx9BCnot2L17nH7DFcwcWe2k1gHyC7ttkbdYSXQXaCDGN\nYmedemyvdE7d/TZxbbPuo09LYvj/+5WAUx8KBJHsE6xMK7kwbZJ5i3BBO2NY7p2b\no68XU+Emg6VuynjoW9xDTQO/2PUSSzJeU9Jql7RXPY2RpJp0+BbGkC356vavZk9a\n8oX8/abn1iZgzfY1lyC4aBNHFf7ycalEbOgGAfw/iT5qtDIihLf4QwFqCKO0/stn\nB118cEtpnKmAQuQMoAqKXlPg8f3xxVf2plJZkRMaynX39ykf3gAeRDnkCoQWx0GN\nFr5IBrP1bBbAWAKn2C4TqKb9QyMwJw==\n=icrk\n-----END PGP SIGNATURE-----\r\n\r\n-----------------------------3204198641555151219403070096--\r\n");
+        let response = Message::from_tcp_stream(b"POST /?test=abcdef HTTP/1.1\r\nHost: localhost:8888\r\nUser-Agent: Mozilla/5.0 (Macintosh; Intel Mac OS X 10.13; rv:63.0) Gecko/20100101 Firefox/63.0\r\nAccept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\nAccept-Language: en-US,en;q=0.5\r\nAccept-Encoding: gzip, deflate\r\nReferer: http://localhost:8888/?test=abcdef\r\nContent-Type: multipart/form-data; boundary=---------------------------3204198641555151219403070096\r\nContent-Length: 1009\r\nDNT: 1\r\nConnection: keep-alive\r\nUpgrade-Insecure-Requests: 1\r\nPragma: no-cache\r\nCache-Control: no-cache\r\n\r\n-----------------------------3204198641555151219403070096\r\nContent-Disposition: form-data; name=\"file\"; filename=\"KeePassXC-2.3.3.dmg.DIGEST\"\r\nContent-Type: application/octet-stream\r\n\r\n1219dd686aee2549ef8fe688aeef22e85272a8ccbefdbbb64c0e5601db17fbdb  KeePassXC-2.3.3.dmg\r\n\r\n-----------------------------3204198641555151219403070096\r\nContent-Disposition: form-data; name=\"file2\"; filename=\"KeePassXC-2.3.3.dmg.sig\"\r\nContent-Type: application/octet-stream\r\n\r\n-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEEweTLo61406/YlPngt6ZvA7WQdqgFAlrzMl4ACgkQt6ZvA7WQ\ndqhkrQf9G3r5thluX7Ogx9BCnot2L17nH7DFcwcWe2k1gHyC7ttkbdYSXQXaCDGN\nYmedemyvdE7d/TZxbbPuo09LYvj/+5WAUx8KBJHsE6xMK7kwbZJ5i3BBO2NY7p2b\no68XU+Emg6VuynjoW9xDTQO/2PUSSzJeU9Jql7RXPY2RpJp0+BbGkC356vavZk9a\n8oX8/abn1iZgzfY1lyC4aBNHFf7ycalEbOgGAfw/iT5qtDIihLf4QwFqCKO0/stn\nB118cEtpnKmAQuQMoAqKXlPg8f3xxVf2plJZkRMaynX39ykf3gAeRDnkCoQWx0GN\nFr5IBrP1bBbAWAKn2C4TqKb9QyMwJw==\n=icrk\n-----END PGP SIGNATURE-----\r\n\r\n-----------------------------3204198641555151219403070096--\r\n");
         let response_unwrapped = response.expect("multipart");
         if let BodyContentType::MultiPart(body) = response_unwrapped.body {
             assert_eq!(
-                String::from_utf8(body.get(&"file".to_string()).expect("expecting file data").body.clone()).expect("expecting utf-8 file data"),
-                "1219dd686aee2549ef8fe688aeef22e85272a8ccbefdbbb64c0e5601db17fbdb  KeePassXC-2.3.3.dmg".to_string()
+                String::from_utf8(body.get("file").expect("expecting file data").body.clone()).expect("expecting utf-8 file data"),
+                "1219dd686aee2549ef8fe688aeef22e85272a8ccbefdbbb64c0e5601db17fbdb  KeePassXC-2.3.3.dmg\r\n".to_string()
             );
             assert_eq!(
-                String::from_utf8(body.get(&"file2".to_string()).expect("expecting file data").body.clone()).expect("expecting utf-8 file data"),
-                "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEEweTLo61406/YlPngt6ZvA7WQdqgFAlrzMl4ACgkQt6ZvA7WQ\ndqhkrQf9G3r5thluX7Ogx9BCnot2L17nH7DFcwcWe2k1gHyC7ttkbdYSXQXaCDGN\nYmedemyvdE7d/TZxbbPuo09LYvj/+5WAUx8KBJHsE6xMK7kwbZJ5i3BBO2NY7p2b\no68XU+Emg6VuynjoW9xDTQO/2PUSSzJeU9Jql7RXPY2RpJp0+BbGkC356vavZk9a\n8oX8/abn1iZgzfY1lyC4aBNHFf7ycalEbOgGAfw/iT5qtDIihLf4QwFqCKO0/stn\nB118cEtpnKmAQuQMoAqKXlPg8f3xxVf2plJZkRMaynX39ykf3gAeRDnkCoQWx0GN\nFr5IBrP1bBbAWAKn2C4TqKb9QyMwJw==\n=icrk\n-----END PGP SIGNATURE-----".to_string()
+                String::from_utf8(body.get("file2").expect("expecting file data").body.clone()).expect("expecting utf-8 file data"),
+                "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEEweTLo61406/YlPngt6ZvA7WQdqgFAlrzMl4ACgkQt6ZvA7WQ\ndqhkrQf9G3r5thluX7Ogx9BCnot2L17nH7DFcwcWe2k1gHyC7ttkbdYSXQXaCDGN\nYmedemyvdE7d/TZxbbPuo09LYvj/+5WAUx8KBJHsE6xMK7kwbZJ5i3BBO2NY7p2b\no68XU+Emg6VuynjoW9xDTQO/2PUSSzJeU9Jql7RXPY2RpJp0+BbGkC356vavZk9a\n8oX8/abn1iZgzfY1lyC4aBNHFf7ycalEbOgGAfw/iT5qtDIihLf4QwFqCKO0/stn\nB118cEtpnKmAQuQMoAqKXlPg8f3xxVf2plJZkRMaynX39ykf3gAeRDnkCoQWx0GN\nFr5IBrP1bBbAWAKn2C4TqKb9QyMwJw==\n=icrk\n-----END PGP SIGNATURE-----\r\n".to_string()
             );
         } else {
             eprintln!(
@@ -1091,10 +3358,7 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         assert!(response.is_some());
         let response_unwrapped = response.unwrap();
         if let BodyContentType::SinglePart(body) = response_unwrapped.body {
-            assert_eq!(
-                body.get(&"abc".to_string()).unwrap().to_string(),
-                "123".to_string()
-            );
+            assert_eq!(body.get("abc").unwrap(), &vec!["123".to_string()]);
         }
 
         // HEAD requests should not get their message body parsed
@@ -1102,7 +3366,7 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         assert!(response.is_some());
         let response_unwrapped = response.unwrap();
         if let BodyContentType::SinglePart(body) = response_unwrapped.body {
-            assert!(body.get(&"abc".to_string()).is_none());
+            assert!(!body.contains_key("abc"));
         }
 
         let response = Message::from_tcp_stream(b"html/index.html\r\n");
@@ -1119,4 +3383,645 @@ BNUI5YCF3PV9MKr3N53vEVYvkbXLbw==
         assert!(response.is_none());
     }
 
+    #[test]
+    fn test_wire_size() {
+        let wire_size = |request: &[u8]| {
+            Message::from_tcp_stream(request)
+                .expect("A request")
+                .wire_size
+        };
+        assert_eq!(
+            wire_size(b"GET / HTTP/1.1"),
+            WireSize {
+                body: 0,
+                header: 0,
+                request_line: 14,
+            }
+        );
+        assert_eq!(
+            wire_size(b"GET / HTTP/1.1\r\nHost: a\r\n"),
+            WireSize {
+                body: 0,
+                header: 9,
+                request_line: 16,
+            }
+        );
+
+        // Framed bodies hold null bytes and end before a pipelined request
+        let next = b"GET / HTTP/1.1\r\n\r\n";
+        let mut request = b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\n\x00a\x00b".to_vec();
+        request.extend_from_slice(next);
+        assert_eq!(wire_size(&request).body, 4);
+        let mut request =
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n\x00a\r\n0\r\n\r\n"
+                .to_vec();
+        request.extend_from_slice(next);
+        assert_eq!(wire_size(&request).body, 12);
+
+        // Null bytes pad the input after the request
+        let mut request = b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=-x\r\n\r\n---x\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nb\r\n---x--\r\n".to_vec();
+        let size = request.len();
+        request.extend_from_slice(&[0; 16]);
+        let wire_size = wire_size(&request);
+        assert_eq!(wire_size.request_line, 17);
+        assert_eq!(wire_size.header, 50);
+        assert_eq!(wire_size.total(), size);
+    }
+
+    #[test]
+    fn test_default_and_clone() {
+        let message = Message::default();
+        assert_eq!(message.request_line.method, Method::Get);
+        assert_eq!(message.request_line.protocol, Protocol::V1_1);
+        assert_eq!(message.request_line.request_uri, "/".to_string());
+        assert_eq!(message.body, BodyContentType::SinglePart(HashMap::new()));
+        assert_eq!(
+            Message::get_request_line(&message.request_line.raw),
+            Some(message.request_line.clone())
+        );
+
+        let message = Message::from_tcp_stream(b"POST /a HTTP/1.1\r\nAgent: Random\r\n\r\nb=c")
+            .expect("A request");
+        let mut copy = message.clone();
+        assert_eq!(copy, message);
+        copy.headers.remove("Agent");
+        assert_ne!(copy, message);
+    }
+
+    #[test]
+    fn test_display_and_dump() {
+        let request = Message::from_tcp_stream(b"GET /?a=b HTTP/1.0\r\nB: 2\r\nA: 1\r\n\r\n")
+            .expect("A request");
+        assert_eq!(
+            request.to_string(),
+            "GET /?a=b HTTP/1.0 (2 headers, 0 bytes body)".to_string()
+        );
+        assert_eq!(
+            request.dump(),
+            "GET /?a=b HTTP/1.0\nA: 1\nB: 2\n\n".to_string()
+        );
+
+        let mut request = Message::default();
+        let mut values = HashMap::new();
+        values.insert(
+            "file".to_string(),
+            MultiPartValue {
+                body: vec![b'x'; 300],
+                file: None,
+                filename: None,
+                headers: HashMap::new(),
+            },
+        );
+        request.body = BodyContentType::MultiPart(values);
+        assert_eq!(
+            request.to_string(),
+            "GET / HTTP/1.1 (0 headers, 300 bytes body)".to_string()
+        );
+        assert_eq!(
+            request.dump(),
+            format!(
+                "GET / HTTP/1.1\n\n[file] 300 bytes\n{}... (44 more bytes)\n",
+                "x".repeat(256)
+            )
+        );
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let request = Message::from_tcp_stream(b"GET /?a=b HTTP/1.0\r\nB: 2\r\nA: 1\r\n\r\n")
+            .expect("A request");
+        assert_eq!(
+            request.to_bytes(),
+            b"GET /?a=b HTTP/1.0\r\nA: 1\r\nB: 2\r\n\r\n".to_vec()
+        );
+
+        // Multi-part bodies are encoded with the boundary of the content-type header
+        let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=----abc\r\n\r\n------abc\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHello\r\n------abc--\r\n")
+            .expect("A multi-part request");
+        let bytes = request.to_bytes();
+        assert_eq!(
+            String::from_utf8(bytes.clone()).expect("UTF-8"),
+            "POST / HTTP/1.1\r\nContent-Length: 79\r\nContent-Type: multipart/form-data; boundary=----abc\r\n\r\n------abc\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHello\r\n------abc--\r\n".to_string()
+        );
+        assert_eq!(
+            Message::from_tcp_stream(&bytes)
+                .expect("A decoded request")
+                .form_value("title"),
+            Some("Hello")
+        );
+
+        // Received framing is replaced by the length of the written body
+        let request = Message::from_tcp_stream(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\na=b\r\n0\r\n\r\n",
+        )
+        .expect("A chunked request");
+        assert_eq!(
+            request.to_bytes(),
+            b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\na=b".to_vec()
+        );
+        let mut request =
+            Message::from_tcp_stream(b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\na=b")
+                .expect("A request");
+        request.raw_body = None;
+        request.body = BodyContentType::SinglePart(HashMap::new());
+        assert_eq!(
+            request.to_bytes(),
+            b"POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_body_reader() {
+        let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\n\r\n").expect("A request");
+        let mut body = Vec::new();
+        request
+            .body_reader()
+            .read_to_end(&mut body)
+            .expect("A body");
+        assert!(body.is_empty());
+
+        // Spooled parts are streamed from their temporary file
+        let config = ParserConfig {
+            multipart_spool_threshold: Some(3),
+            ..ParserConfig::default()
+        };
+        let mut request = Message::from_tcp_stream_with_config(b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=----abc\r\n\r\n------abc\r\nContent-Disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\r\nlarge\r\n------abc\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nab\r\n------abc--\r\n", &config)
+            .expect("A multi-part request");
+        match request.body {
+            BodyContentType::MultiPart(ref values) => {
+                assert!(values.get("b").expect("A part").file.is_some())
+            }
+            _ => panic!("Expected a multi-part body"),
+        }
+        let received = b"------abc\r\nContent-Disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\r\nlarge\r\n------abc\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nab\r\n------abc--\r\n".to_vec();
+        let mut body = Vec::new();
+        request
+            .body_reader()
+            .read_to_end(&mut body)
+            .expect("A body");
+        assert_eq!(body, received);
+        assert_eq!(request.get_body_bytes(), received);
+
+        // Without the received body parts are encoded in order of name
+        request.raw_body = None;
+        let mut body = String::new();
+        request
+            .body_reader()
+            .read_to_string(&mut body)
+            .expect("A body");
+        assert_eq!(body, "------abc\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nab\r\n------abc\r\nContent-Disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\r\nlarge\r\n------abc--\r\n".to_string());
+        assert_eq!(body.into_bytes(), request.get_body_bytes());
+
+        // A spooled file that can't be opened fails the read
+        if let BodyContentType::MultiPart(ref values) = request.body {
+            let file = values["b"].file.as_ref().expect("A spooled file");
+            fs::remove_file(file.path()).expect("A removed file");
+        }
+        let error = request
+            .body_reader()
+            .read_to_end(&mut Vec::new())
+            .expect_err("A missing spooled file");
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_accessors() {
+        let response = Message::from_tcp_stream(b"POST /?page=3&sort HTTP/1.1\r\nCONTENT-TYPE: application/x-www-form-urlencoded\r\ncontent-length: 12\r\n\r\nname=abc&x=1");
+        let request = response.expect("A request");
+        assert_eq!(
+            request
+                .header("Content-type")
+                .expect("A header")
+                .to_string(),
+            "application/x-www-form-urlencoded".to_string()
+        );
+        assert_eq!(request.query("page"), Some("3"));
+        assert_eq!(request.query("sort"), Some("1"));
+        assert_eq!(request.form_value("name"), Some("abc"));
+        assert_eq!(request.form_value("missing"), None);
+        assert_eq!(request.content_length(), Some(12));
+        assert!(request
+            .content_type()
+            .is_some_and(|media_type| media_type.is_form()));
+
+        // Repeated fields keep every value in order and serialize back
+        let repeated =
+            Message::from_tcp_stream(b"POST /?tag=x&tag=y HTTP/1.1\r\n\r\ntag=b&name=c&tag=a")
+                .expect("A request");
+        assert_eq!(
+            repeated.form_values("tag"),
+            &["b".to_string(), "a".to_string()]
+        );
+        assert_eq!(repeated.query("tag"), Some("y"));
+        assert!(repeated.to_bytes().ends_with(b"\r\n\r\ntag=b&name=c&tag=a"));
+        let built = Message {
+            raw_body: None,
+            ..repeated.clone()
+        };
+        assert!(built.to_bytes().ends_with(b"\r\n\r\nname=c&tag=b&tag=a"));
+
+        // Plus signs are spaces in form bodies but literal in query strings
+        let encoded =
+            Message::from_tcp_stream(b"POST /?q=a+b%20c HTTP/1.1\r\n\r\nq=a+b%2Bc&full+name=x")
+                .expect("A request");
+        assert_eq!(encoded.query("q"), Some("a+b c"));
+        assert_eq!(encoded.form_value("q"), Some("a b+c"));
+        assert_eq!(encoded.form_value("full name"), Some("x"));
+        let built = Message {
+            raw_body: None,
+            ..encoded
+        };
+        assert!(built.to_bytes().ends_with(b"\r\n\r\nfull+name=x&q=a+b%2Bc"));
+
+        let response = Message::from_tcp_stream(b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=----abc\r\n\r\n------abc\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHello\r\n------abc--\r\n");
+        let request = response.expect("A multi-part request");
+        assert_eq!(request.form_value("title"), Some("Hello"));
+        assert!(request.form_values("title").is_empty());
+        assert_eq!(request.content_length(), None);
+        assert_eq!(
+            request
+                .content_type()
+                .and_then(|media_type| media_type.boundary().map(str::to_string)),
+            Some("----abc".to_string())
+        );
+
+        // Multi-part bodies without a valid boundary are rejected, quoted boundaries are unquoted
+        for content_type in [
+            "multipart/form-data",
+            "multipart/form-data; boundary=a:b",
+            "multipart/form-data; boundary=\"a{b\"",
+            "multipart/mixed; boundary=\"-------------------------------------------------------------------------\"",
+        ]
+        .iter()
+        {
+            let request = format!(
+                "POST / HTTP/1.1\r\nContent-Type: {}\r\n\r\n--a\r\n",
+                content_type
+            );
+            assert_eq!(
+                Message::from_tcp_stream_with_config(request.as_bytes(), &ParserConfig::default()),
+                Err(ParseError::InvalidHeader)
+            );
+        }
+        let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=\"--a:b\"\r\n\r\n----a:b\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHello\r\n----a:b--\r\n")
+            .expect("A multi-part request");
+        assert_eq!(request.form_value("title"), Some("Hello"));
+
+        // Parts in the preamble and epilogue are not fields
+        let request = Message::from_tcp_stream(b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=a\r\n\r\nContent-Disposition: form-data; name=\"preamble\"\r\n\r\nx\r\n--a\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHello\r\n--a--\r\n--a\r\nContent-Disposition: form-data; name=\"epilogue\"\r\n\r\ny\r\n--a--\r\n")
+            .expect("A multi-part request");
+        match request.body {
+            BodyContentType::MultiPart(ref values) => {
+                assert_eq!(values.keys().collect::<Vec<&String>>(), vec!["title"])
+            }
+            _ => panic!("Expected a multi-part body"),
+        }
+    }
+
+    #[test]
+    fn test_from_tcp_stream_malformed_input() {
+        let inputs: Vec<&[u8]> = vec![
+            b"\r\n",
+            b"\n\r\n\r",
+            b"\r\r\r\n\n",
+            b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=\r\n\r\n--\r\n--",
+            b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=-\r\n\r\n---\r\n\r\n---",
+            b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=--a\r\n\r\n-\r\n\r\n\r\n----a--\r\n",
+            b"POST / HTTP/1.1\r\nContent-Type: x; boundary=ab\r\n\r\n\r\n\r\n\r\nab\r\n\r\n\0",
+            b"GET / HTTP/1.1\r\n\xff\xfe: \x00\r\n\r\n",
+        ];
+        for input in inputs {
+            let _ = Message::from_tcp_stream(input);
+        }
+    }
+
+    #[test]
+    fn test_header_name_case() {
+        let request = b"POST / HTTP/1.1\r\nCONTENT-type: multipart/form-data; boundary=----abc\r\nx-ID: 1\r\n\r\n------abc\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nb\r\n------abc--\r\n";
+        for (case, name) in [
+            (HeaderNameCase::Canonical, "X-Id"),
+            (HeaderNameCase::Lowercase, "x-id"),
+            (HeaderNameCase::Preserve, "x-ID"),
+        ]
+        .iter()
+        {
+            let config = ParserConfig {
+                header_name_case: *case,
+                ..ParserConfig::default()
+            };
+            let message =
+                Message::from_tcp_stream_with_config(request, &config).expect("A request");
+            assert!(message.headers.contains_key(*name));
+            assert_eq!(
+                message.header("X-Id").expect("A header").to_string(),
+                "1".to_string()
+            );
+            assert_eq!(message.form_value("a"), Some("b"));
+        }
+    }
+
+    #[test]
+    fn test_parser_metrics() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Recorder {
+            statistics: Mutex<Vec<ParseStatistics>>,
+        }
+
+        impl ParserMetrics for Recorder {
+            fn record(&self, statistics: &ParseStatistics) {
+                self.statistics
+                    .lock()
+                    .expect("A lock")
+                    .push(statistics.clone());
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let config = ParserConfig {
+            max_body_size: 4,
+            metrics: Some(recorder.clone()),
+            ..ParserConfig::default()
+        };
+        let request = b"POST / HTTP/1.1\r\nA: 1\r\n\r\na=bc";
+        Message::from_tcp_stream_with_config(request, &config).expect("A request");
+        Message::from_tcp_stream_with_config(b"POST / HTTP/1.1\r\n\r\na=bcd", &config)
+            .expect_err("A too large body");
+        let statistics = recorder.statistics.lock().expect("A lock");
+        assert_eq!(statistics.len(), 2);
+        assert_eq!(statistics[0].body_size, 4);
+        assert_eq!(statistics[0].bytes, request.len());
+        assert_eq!(statistics[0].header_count, 1);
+        assert_eq!(statistics[0].error, None);
+        assert_eq!(
+            statistics[1].error,
+            Some(ParseError::BodyTooLarge { limit: 4, seen: 5 })
+        );
+        assert!(format!("{:?}", config).contains("metrics: true"));
+    }
+
+    #[test]
+    fn test_from_tcp_stream_with_config() {
+        let config = ParserConfig {
+            allowed_methods: None,
+            header_name_case: HeaderNameCase::Canonical,
+            max_body_size: 8,
+            max_header_line_length: 24,
+            max_header_size: 32,
+            max_request_uri_length: 16,
+            metrics: None,
+            multipart_spool_directory: env::temp_dir(),
+            multipart_spool_threshold: None,
+            query_separator: QuerySeparator::Ampersand,
+            strict: false,
+        };
+
+        // Requests within limits are decoded
+        let response = Message::from_tcp_stream_with_config(
+            b"POST /random HTTP/1.1\r\nAgent: Random\r\n\r\ntest=abc",
+            &config,
+        );
+        assert!(response.is_ok());
+
+        // Disallowed methods are rejected before the header fields
+        let allow_get = ParserConfig {
+            allowed_methods: Some(vec![Method::Get]),
+            ..config.clone()
+        };
+        assert_eq!(
+            Message::from_tcp_stream_with_config(
+                b"POST /random HTTP/1.1\r\nAgent: Random browser\r\nAccept: text/html\r\n\r\n",
+                &allow_get,
+            ),
+            Err(ParseError::MethodNotAllowed {
+                allowed: vec![Method::Get],
+                method: Method::Post
+            })
+        );
+
+        // Too large message body
+        let response = Message::from_tcp_stream_with_config(
+            b"POST /random HTTP/1.1\r\nAgent: Random\r\n\r\ntest=abcdef",
+            &config,
+        );
+        assert_eq!(
+            response.expect_err("A too large body"),
+            ParseError::BodyTooLarge { limit: 8, seen: 11 }
+        );
+        let response = Message::from_tcp_stream_with_config(
+            b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=a\r\nContent-Length: 15\r\n\r\n--a\r\n\r\n1\r\n--a--GET / HTTP/1.1\r\n\r\n",
+            &ParserConfig {
+                max_body_size: 8,
+                ..ParserConfig::default()
+            },
+        );
+        assert_eq!(
+            response.expect_err("A too large multi-part body"),
+            ParseError::BodyTooLarge { limit: 8, seen: 15 }
+        );
+
+        // Too large header fields
+        let response = Message::from_tcp_stream_with_config(
+            b"GET / HTTP/1.1\r\nAgent: Random browser\r\nAccept: text/html\r\n\r\n",
+            &config,
+        );
+        assert_eq!(
+            response.expect_err("Too large headers"),
+            ParseError::HeaderTooLarge {
+                limit: 32,
+                seen: 42
+            }
+        );
+
+        // Too long header field lines are rejected before their line ends
+        let response = Message::from_tcp_stream_with_config(
+            b"GET / HTTP/1.1\r\nUser-Agent: Random browser with a long name",
+            &config,
+        );
+        assert_eq!(
+            response.expect_err("A too long header line"),
+            ParseError::HeaderTooLarge {
+                limit: 24,
+                seen: 25
+            }
+        );
+
+        // Too long request URI
+        let response = Message::from_tcp_stream_with_config(
+            b"GET /a/very/long/request/uri HTTP/1.1\r\n\r\n",
+            &config,
+        );
+        let error = response.expect_err("A too long URI");
+        assert_eq!(
+            error,
+            ParseError::UriTooLong {
+                limit: 16,
+                seen: 24
+            }
+        );
+        assert_eq!(error.status_code(), 414);
+
+        // Whitespace before header colon is only accepted in lenient mode
+        let request = b"GET / HTTP/1.1\r\nAgent : Random\r\n\r\n";
+        let response = Message::from_tcp_stream_with_config(request, &config);
+        assert_eq!(
+            response
+                .expect("A lenient parse")
+                .headers
+                .get("Agent")
+                .expect("Agent")
+                .to_string(),
+            "Random".to_string()
+        );
+        let strict_config = ParserConfig {
+            strict: true,
+            ..ParserConfig::default()
+        };
+        let response = Message::from_tcp_stream_with_config(request, &strict_config);
+        assert_eq!(
+            response.expect_err("A strict parse"),
+            ParseError::InvalidHeader
+        );
+        let response = Message::from_tcp_stream_with_config(
+            b"GET / HTTP/1.1\r\nAgent: Random : browser\r\n\r\n",
+            &strict_config,
+        );
+        assert!(response.is_ok());
+
+        // Invalid request line
+        let response = Message::from_tcp_stream_with_config(b"RANDOM /stuff HTTP/2.5\r\n", &config);
+        assert_eq!(
+            response.expect_err("An invalid request line"),
+            ParseError::InvalidRequestLine
+        );
+
+        // Unsupported protocol and truncated body
+        let response = Message::from_tcp_stream_with_config(b"GET /stuff HTTP/2.5\r\n", &config);
+        assert_eq!(
+            response.expect_err("An unsupported protocol"),
+            ParseError::UnsupportedProtocol("HTTP/2.5".to_string())
+        );
+        let response = Message::from_tcp_stream_with_config(b"GET /stuff HTTP/1.x\r\n", &config);
+        assert_eq!(
+            response.expect_err("An invalid request line"),
+            ParseError::InvalidRequestLine
+        );
+        let response = Message::from_tcp_stream_with_config(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 99\r\n\r\n3\r\na=b\r\n0\r\n\r\n",
+            &ParserConfig::default(),
+        );
+        assert!(response.expect("A chunked request").is_chunked());
+    }
+
+    #[test]
+    fn test_body_with_null_bytes() {
+        let request = Message::try_from_tcp_stream(
+            b"POST /u HTTP/1.1\r\nContent-Type: application/octet-stream\r\nContent-Length: 4\r\n\r\na\x00bc",
+        )
+        .expect("A binary body");
+        assert_eq!(request.wire_size.body, 4);
+
+        let bytes = b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=abc\r\nContent-Length: 64\r\n\r\n--abc\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n\x00\x01\r\n--abc--\r\n";
+        let request = Message::try_from_tcp_stream(bytes).expect("A binary upload");
+        if let BodyContentType::MultiPart(ref values) = request.body {
+            assert_eq!(values.get("a").expect("A part").body, b"\x00\x01".to_vec());
+        } else {
+            panic!("Expected a multi-part body");
+        }
+        match stream::parse(bytes, &ParserConfig::default()) {
+            stream::Status::Complete(_, consumed) => assert_eq!(consumed, bytes.len()),
+            status => panic!("Expected a complete request, got {:?}", status),
+        }
+
+        // Null bytes after an unframed body are padding
+        let request = Message::try_from_tcp_stream(b"POST / HTTP/1.1\r\n\r\na=b\x00\x00")
+            .expect("A padded request");
+        assert_eq!(request.wire_size.body, 3);
+
+        // Content-Length frames a body whatever the method
+        let request =
+            Message::try_from_tcp_stream(b"DELETE /a HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc")
+                .expect("A request with a body");
+        assert_eq!(request.raw_body, Some(b"abc".to_vec()));
+        assert_eq!(request.wire_size.body, 3);
+        assert_eq!(
+            Message::try_from_tcp_stream(b"DELETE /a HTTP/1.1\r\nContent-Length: 4\r\n\r\nabc")
+                .expect_err("A truncated body"),
+            ParseError::IncompleteBody {
+                expected: 4,
+                received: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_chunked_body() {
+        let request = Message::from_tcp_stream(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n3;x=y\r\na=b\r\n0\r\nExpires: never\r\n\r\n",
+        )
+        .expect("A chunked request");
+        assert_eq!(request.raw_body, Some(b"a=b".to_vec()));
+        assert_eq!(request.form_value("a"), Some("b"));
+        assert_eq!(request.wire_size.body, 33);
+
+        // Multi-part bodies are split after decoding
+        let request = Message::from_tcp_stream(
+            b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=abc\r\nTransfer-Encoding: chunked\r\n\r\n1c\r\n--abc\r\nContent-Disposition: \r\n23\r\nform-data; name=\"a\"\r\n\r\nb\r\n--abc--\r\n\r\n0\r\n\r\n",
+        )
+        .expect("A chunked multi-part request");
+        if let BodyContentType::MultiPart(ref values) = request.body {
+            assert_eq!(values.get("a").expect("A part").body, b"b".to_vec());
+        } else {
+            panic!("Expected a multi-part body");
+        }
+
+        // Bodies that can't be delimited like the stream parser does are rejected
+        for bytes in [
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n1\r\na\r\n0\r\n\r\n"[..],
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: identity\r\n\r\n"[..],
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: xchunked\r\nContent-Length: 3\r\n\r\na=b"[..],
+        ] {
+            assert_eq!(
+                Message::try_from_tcp_stream(bytes).expect_err("An undelimited body"),
+                ParseError::InvalidHeader
+            );
+            assert_eq!(
+                stream::parse(bytes, &ParserConfig::default()),
+                stream::Status::Invalid(ParseError::InvalidHeader)
+            );
+        }
+
+        // Only the chunked coding is decoded, other codings are not implemented
+        let bytes =
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n1\r\na\r\n0\r\n\r\n";
+        let error = ParseError::UnsupportedTransferCoding("gzip".to_string());
+        assert_eq!(
+            Message::try_from_tcp_stream(bytes).expect_err("An unsupported coding"),
+            error
+        );
+        assert_eq!(
+            stream::parse(bytes, &ParserConfig::default()),
+            stream::Status::Invalid(error)
+        );
+        assert_eq!(
+            ParseError::UnsupportedTransferCoding("gzip".to_string()).status(),
+            "501 Not Implemented".to_string()
+        );
+
+        assert_eq!(
+            Message::try_from_tcp_stream(
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\na=b\r\n"
+            )
+            .expect_err("A truncated chunked body"),
+            ParseError::InvalidChunkedBody
+        );
+        assert_eq!(
+            Message::try_from_tcp_stream(
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nz\r\na=b\r\n0\r\n\r\n"
+            )
+            .expect_err("An invalid chunk size"),
+            ParseError::InvalidChunkedBody
+        );
+    }
 }
@@ -1,33 +1,1539 @@
 //! # Handles everything related to HTTP responses.
 
 use std::collections::HashMap;
+use std::error;
+use std::fmt;
+#[cfg(any(feature = "mmap", all(unix, feature = "sendfile")))]
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+#[cfg(all(unix, feature = "sendfile"))]
+use std::io::{Seek, SeekFrom};
+#[cfg(all(unix, feature = "sendfile"))]
+use std::net::TcpStream;
+#[cfg(feature = "mmap")]
+use std::ops::Range;
+#[cfg(all(feature = "sendfile", any(target_os = "android", target_os = "linux")))]
+use std::os::unix::io::AsRawFd;
+#[cfg(feature = "mmap")]
+use std::path::Path;
 use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// # A request message
+use cache;
+use cache::{CacheControl, CacheStatus, SurrogateControl};
+use chunked;
+use cookie::{CookieError, SetCookie};
+use date;
+use headers;
+use headers::Headers;
+use hints::AcceptCh;
+use language::ContentLanguage;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use negotiation::{Representation, Variant};
+use normalize_key;
+use prefer::Preferences;
+use range::ByteRange;
+use request;
+use request::Protocol;
+use security::{
+    ClearSiteData, ContentSecurityPolicy, ExpectCt, NetworkErrorLogging, PermissionsPolicy,
+    ReferrerPolicy, ReportTo, ReportingEndpoints,
+};
+use timing;
+use timing::ServerTiming;
+use HeaderNameCase;
+
+const TRACE_SENSITIVE_HEADERS: [&str; 4] = [
+    "Authorization",
+    "Cookie",
+    "Proxy-Authorization",
+    "Set-Cookie",
+];
+
+/// # Get the canonical reason phrase of a status code
+/// ## Usage
+/// ```rust
+/// use milstian_http::response::reason_phrase;
+/// assert_eq!(reason_phrase(404), Some("Not Found"));
+/// assert_eq!(reason_phrase(599), None);
+/// ```
+pub fn reason_phrase(code: u16) -> Option<&'static str> {
+    match code {
+        100 => Some("Continue"),
+        101 => Some("Switching Protocols"),
+        103 => Some("Early Hints"),
+        200 => Some("OK"),
+        201 => Some("Created"),
+        202 => Some("Accepted"),
+        203 => Some("Non-Authoritative Information"),
+        204 => Some("No Content"),
+        205 => Some("Reset Content"),
+        206 => Some("Partial Content"),
+        300 => Some("Multiple Choices"),
+        301 => Some("Moved Permanently"),
+        302 => Some("Found"),
+        303 => Some("See Other"),
+        304 => Some("Not Modified"),
+        305 => Some("Use Proxy"),
+        307 => Some("Temporary Redirect"),
+        308 => Some("Permanent Redirect"),
+        400 => Some("Bad Request"),
+        401 => Some("Unauthorized"),
+        402 => Some("Payment Required"),
+        403 => Some("Forbidden"),
+        404 => Some("Not Found"),
+        405 => Some("Method Not Allowed"),
+        406 => Some("Not Acceptable"),
+        407 => Some("Proxy Authentication Required"),
+        408 => Some("Request Timeout"),
+        409 => Some("Conflict"),
+        410 => Some("Gone"),
+        411 => Some("Length Required"),
+        412 => Some("Precondition Failed"),
+        413 => Some("Payload Too Large"),
+        414 => Some("URI Too Long"),
+        415 => Some("Unsupported Media Type"),
+        416 => Some("Range Not Satisfiable"),
+        417 => Some("Expectation Failed"),
+        421 => Some("Misdirected Request"),
+        422 => Some("Unprocessable Content"),
+        425 => Some("Too Early"),
+        426 => Some("Upgrade Required"),
+        428 => Some("Precondition Required"),
+        429 => Some("Too Many Requests"),
+        431 => Some("Request Header Fields Too Large"),
+        451 => Some("Unavailable For Legal Reasons"),
+        500 => Some("Internal Server Error"),
+        501 => Some("Not Implemented"),
+        502 => Some("Bad Gateway"),
+        503 => Some("Service Unavailable"),
+        504 => Some("Gateway Timeout"),
+        505 => Some("HTTP Version Not Supported"),
+        511 => Some("Network Authentication Required"),
+        _ => None,
+    }
+}
+
+/// # Renders an HTML error body from status code, reason phrase and an unescaped detail
+pub type ErrorPageLayout = fn(u16, &str, Option<&str>) -> String;
+
+/// # Escape text for use in HTML content and quoted attribute values
+/// ## Usage
+/// ```rust
+/// use milstian_http::response::escape_html;
+/// assert_eq!(escape_html("<a href=\"x\">'&'</a>"), "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;".to_string());
+/// ```
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// # Escape text for use inside a JSON string
+/// ## Usage
+/// ```rust
+/// use milstian_http::response::escape_json;
+/// assert_eq!(escape_json("a \"b\"\\\n\u{1}"), "a \\\"b\\\"\\\\\\n\\u0001".to_string());
+/// ```
+pub fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                escaped.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// # The default error page layout
+/// ## Usage
+/// ```rust
+/// use milstian_http::response::render_error_page;
+/// let page = render_error_page(404, "Not Found", Some("No <page> here"));
+/// assert!(page.contains("<title>404 Not Found</title>"));
+/// assert!(page.contains("<p>No &lt;page&gt; here</p>"));
+/// ```
+pub fn render_error_page(code: u16, reason: &str, detail: Option<&str>) -> String {
+    let title = escape_html(&format!("{} {}", code, reason));
+    let detail = match detail {
+        Some(detail) => format!("<p>{}</p>\n", escape_html(detail)),
+        None => String::new(),
+    };
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n{}</body>\n</html>\n",
+        title, title, detail
+    )
+}
+
+/// # A header value rejected by a typed setter
+#[derive(Debug, Eq, PartialEq)]
+pub struct InvalidHeaderValue {
+    pub name: String,
+    pub value: String,
+}
+
+impl fmt::Display for InvalidHeaderValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid value {:?} for header {}", self.value, self.name)
+    }
+}
+
+impl error::Error for InvalidHeaderValue {}
+
+/// # A response message
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Message {
     pub protocol: String,
     pub status: String,
-    pub headers: HashMap<String, String>,
+    pub headers: Headers, // Sent in insertion order after Date and Server
     pub body: Vec<u8>,
+    pub cookies: Vec<SetCookie>, // Each is sent as its own Set-Cookie header
+}
+
+impl Default for Message {
+    /// # An empty HTTP/1.1 200 OK response
+    fn default() -> Message {
+        Message::new(
+            "HTTP/1.1".to_string(),
+            "200 OK".to_string(),
+            HashMap::new(),
+            Vec::new(),
+        )
+    }
 }
 
 impl Message {
     /// # Create a new HTTP Message
-    pub fn new(
+    pub fn new<H: Into<Headers>>(
         protocol: String,
         status: String,
-        headers: HashMap<String, String>,
+        headers: H,
         body: Vec<u8>,
     ) -> Message {
         Message {
             protocol,
             status,
+            headers: headers.into(),
+            body,
+            cookies: Vec::new(),
+        }
+    }
+
+    /// # Create a response with a status code and its canonical reason phrase
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let message = Message::with_status(404);
+    /// assert_eq!(message.status, "404 Not Found".to_string());
+    /// assert_eq!(message.protocol, "HTTP/1.1".to_string());
+    /// ```
+    pub fn with_status(code: u16) -> Message {
+        let mut message = Message::default();
+        message.set_status(code);
+        message
+    }
+
+    /// # Create an error response with an HTML body from the default layout
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let message = Message::error_page(503, Some("Back soon"));
+    /// assert_eq!(message.status, "503 Service Unavailable".to_string());
+    /// assert_eq!(
+    ///     message.headers.get("Content-Type"),
+    ///     Some(&"text/html; charset=utf-8".to_string())
+    /// );
+    /// ```
+    pub fn error_page(code: u16, detail: Option<&str>) -> Message {
+        Message::error_page_with_layout(code, detail, render_error_page)
+    }
+
+    /// # Create an error response with an HTML body from layout
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::{escape_html, Message};
+    /// fn layout(code: u16, reason: &str, detail: Option<&str>) -> String {
+    ///     format!("<h1>{}</h1>{}", code, escape_html(detail.unwrap_or(reason)))
+    /// }
+    /// let message = Message::error_page_with_layout(404, None, layout);
+    /// assert_eq!(message.body, b"<h1>404</h1>Not Found".to_vec());
+    /// ```
+    pub fn error_page_with_layout(
+        code: u16,
+        detail: Option<&str>,
+        layout: ErrorPageLayout,
+    ) -> Message {
+        let mut message = Message::with_status(code);
+        message.body = layout(code, reason_phrase(code).unwrap_or(""), detail).into_bytes();
+        message.headers.insert(
+            "Content-Type".to_string(),
+            "text/html; charset=utf-8".to_string(),
+        );
+        let length = message.body.len();
+        message.set_content_length(length);
+        message
+    }
+
+    /// # Create a 300 Multiple Choices response for agent-driven negotiation
+    /// The variants are listed as links in an HTML body, in the Link header as alternates and in
+    /// the Alternates header, variants with invalid URIs or language tags are left out. A server
+    /// with a preferred choice can add it with `set_location`.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::negotiation::{Representation, Variant};
+    /// use milstian_http::response::Message;
+    /// let variant = |uri: &str, language: &str| Variant {
+    ///     representation: Representation {
+    ///         encoding: None,
+    ///         language: Some(language.to_string()),
+    ///         media_type: "text/html".to_string(),
+    ///     },
+    ///     uri: uri.to_string(),
+    /// };
+    /// let message = Message::multiple_choices(&[variant("/a.en", "en"), variant("/a.sv", "sv")]);
+    /// assert_eq!(message.status, "300 Multiple Choices".to_string());
+    /// assert_eq!(
+    ///     message.headers.get("Link"),
+    ///     Some(&"</a.en>; rel=\"alternate\"; type=\"text/html\"; hreflang=\"en\", </a.sv>; rel=\"alternate\"; type=\"text/html\"; hreflang=\"sv\"".to_string())
+    /// );
+    /// assert_eq!(
+    ///     message.headers.get("Alternates"),
+    ///     Some(&"{\"/a.en\" 1.0 {type text/html} {language en}}, {\"/a.sv\" 1.0 {type text/html} {language sv}}".to_string())
+    /// );
+    /// let body = String::from_utf8(message.body).expect("A body");
+    /// assert!(body.contains("<li><a href=\"/a.sv\">text/html, language sv</a></li>"));
+    /// ```
+    pub fn multiple_choices(variants: &[Variant]) -> Message {
+        let variants: Vec<&Variant> = variants
+            .iter()
+            .filter(|variant| variant.is_valid())
+            .collect();
+        let title = escape_html(&format!("300 {}", reason_phrase(300).unwrap_or("")));
+        let items: String = variants
+            .iter()
+            .map(|variant| {
+                format!(
+                    "<li><a href=\"{}\">{}</a></li>\n",
+                    escape_html(&variant.uri),
+                    escape_html(&variant.representation.to_string())
+                )
+            })
+            .collect();
+        let mut message = Message::with_status(300);
+        message.body = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n<ul>\n{}</ul>\n</body>\n</html>\n",
+            title, title, items
+        )
+        .into_bytes();
+        message.headers.insert(
+            "Content-Type".to_string(),
+            "text/html; charset=utf-8".to_string(),
+        );
+        let length = message.body.len();
+        message.set_content_length(length);
+        if !variants.is_empty() {
+            let links: Vec<String> = variants.iter().map(|variant| variant.to_link()).collect();
+            message.headers.insert("Link".to_string(), links.join(", "));
+            let alternates: Vec<String> = variants
+                .iter()
+                .map(|variant| variant.to_alternate())
+                .collect();
+            message
+                .headers
+                .insert("Alternates".to_string(), alternates.join(", "));
+        }
+        message
+    }
+
+    /// # Create a 400 Bad Request response with an HTML body
+    pub fn bad_request(detail: Option<&str>) -> Message {
+        Message::error_page(400, detail)
+    }
+
+    /// # Create a 403 Forbidden response with an HTML body
+    pub fn forbidden(detail: Option<&str>) -> Message {
+        Message::error_page(403, detail)
+    }
+
+    /// # Create a 404 Not Found response with an HTML body
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// assert_eq!(Message::not_found(None).status_code(), Some(404));
+    /// ```
+    pub fn not_found(detail: Option<&str>) -> Message {
+        Message::error_page(404, detail)
+    }
+
+    /// # Create a 406 Not Acceptable response with an HTML body listing the available variants
+    /// Vary includes Accept, and Accept-Language or Accept-Encoding when variants have languages
+    /// or encodings, since those request headers excluded every variant.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::negotiation::Representation;
+    /// use milstian_http::response::Message;
+    /// let variants = vec![
+    ///     Representation {
+    ///         encoding: None,
+    ///         language: Some("en".to_string()),
+    ///         media_type: "text/html".to_string(),
+    ///     },
+    ///     Representation {
+    ///         encoding: Some("gzip".to_string()),
+    ///         language: None,
+    ///         media_type: "application/json".to_string(),
+    ///     },
+    /// ];
+    /// let message = Message::not_acceptable(&variants);
+    /// assert_eq!(message.status, "406 Not Acceptable".to_string());
+    /// let body = String::from_utf8(message.body).expect("A body");
+    /// assert!(body.contains("<li>text/html, language en</li>"));
+    /// assert!(body.contains("<li>application/json, encoding gzip</li>"));
+    /// assert_eq!(
+    ///     message.headers.get("Vary"),
+    ///     Some(&"Accept, Accept-Language, Accept-Encoding".to_string())
+    /// );
+    /// ```
+    pub fn not_acceptable(variants: &[Representation]) -> Message {
+        let title = escape_html(&format!("406 {}", reason_phrase(406).unwrap_or("")));
+        let items: String = variants
+            .iter()
+            .map(|variant| format!("<li>{}</li>\n", escape_html(&variant.to_string())))
+            .collect();
+        let mut message = Message::with_status(406);
+        message.body = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n<p>Available variants:</p>\n<ul>\n{}</ul>\n</body>\n</html>\n",
+            title, title, items
+        )
+        .into_bytes();
+        message.headers.insert(
+            "Content-Type".to_string(),
+            "text/html; charset=utf-8".to_string(),
+        );
+        let length = message.body.len();
+        message.set_content_length(length);
+        message.add_vary("Accept");
+        if variants.iter().any(|variant| variant.language.is_some()) {
+            message.add_vary("Accept-Language");
+        }
+        if variants.iter().any(|variant| variant.encoding.is_some()) {
+            message.add_vary("Accept-Encoding");
+        }
+        message
+    }
+
+    /// # Create a 500 Internal Server Error response with an HTML body
+    pub fn internal_server_error(detail: Option<&str>) -> Message {
+        Message::error_page(500, detail)
+    }
+
+    /// # Create a 503 Service Unavailable response with an HTML body
+    pub fn service_unavailable(detail: Option<&str>) -> Message {
+        Message::error_page(503, detail)
+    }
+
+    /// # Create an error response with a JSON envelope of status, machine-readable code and message
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let message = Message::json_error(422, "invalid_email", "Email \"a\" is invalid");
+    /// assert_eq!(message.status, "422 Unprocessable Content".to_string());
+    /// assert_eq!(
+    ///     message.body,
+    ///     b"{\"error\":{\"status\":422,\"code\":\"invalid_email\",\"message\":\"Email \\\"a\\\" is invalid\"}}".to_vec()
+    /// );
+    /// assert_eq!(
+    ///     message.headers.get("Content-Type"),
+    ///     Some(&"application/json".to_string())
+    /// );
+    /// ```
+    pub fn json_error(status: u16, code: &str, message: &str) -> Message {
+        let mut response = Message::with_status(status);
+        response.body = format!(
+            "{{\"error\":{{\"status\":{},\"code\":\"{}\",\"message\":\"{}\"}}}}",
+            status,
+            escape_json(code),
+            escape_json(message)
+        )
+        .into_bytes();
+        response
+            .headers
+            .insert("Content-Type".to_string(), "application/json".to_string());
+        let length = response.body.len();
+        response.set_content_length(length);
+        response
+    }
+
+    /// # Create a 206 Partial Content response with range of full_body
+    /// The last position is clamped to the end of the body, ranges starting past the end or ending
+    /// before they start give 416 Range Not Satisfiable with Content-Range: bytes */length.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::range::ByteRange;
+    /// use milstian_http::response::Message;
+    /// let message =
+    ///     Message::partial_content(b"0123456789", &ByteRange { first: 2, last: 4 }, "text/plain");
+    /// assert_eq!(message.status, "206 Partial Content".to_string());
+    /// assert_eq!(message.body, b"234".to_vec());
+    /// assert_eq!(
+    ///     message.headers.get("Content-Range"),
+    ///     Some(&"bytes 2-4/10".to_string())
+    /// );
+    /// assert_eq!(message.headers.get("Content-Length"), Some(&"3".to_string()));
+    /// assert_eq!(message.headers.get("Accept-Ranges"), Some(&"bytes".to_string()));
+    /// ```
+    pub fn partial_content(full_body: &[u8], range: &ByteRange, content_type: &str) -> Message {
+        let length = full_body.len() as u64;
+        let mut message = if range.first > range.last || range.first >= length {
+            let mut message = Message::with_status(416);
+            message
+                .headers
+                .insert("Content-Range".to_string(), format!("bytes */{}", length));
+            message
+        } else {
+            let range = ByteRange {
+                first: range.first,
+                last: range.last.min(length - 1),
+            };
+            let mut message = Message::with_status(206);
+            message.body = full_body[range.first as usize..=range.last as usize].to_vec();
+            message
+                .headers
+                .insert("Content-Range".to_string(), range.content_range(length));
+            message
+                .headers
+                .insert("Content-Type".to_string(), content_type.to_string());
+            message
+        };
+        message
+            .headers
+            .insert("Accept-Ranges".to_string(), "bytes".to_string());
+        let size = message.body.len();
+        message.set_content_length(size);
+        message
+    }
+
+    /// # Create a 101 Switching Protocols response to upgrade, like websocket or h2c
+    /// The response has no body and extra headers may not frame one, so Content-Length and
+    /// Transfer-Encoding are rejected like invalid names and values.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let message = Message::switching_protocols(
+    ///     "websocket",
+    ///     &[("Sec-WebSocket-Accept", "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")],
+    /// )
+    /// .expect("A 101 response");
+    /// assert_eq!(
+    ///     message.to_bytes(),
+    ///     b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n".to_vec()
+    /// );
+    /// assert!(Message::switching_protocols("web socket", &[]).is_err());
+    /// assert!(Message::switching_protocols("h2c", &[("Content-Length", "3")]).is_err());
+    /// ```
+    pub fn switching_protocols(
+        upgrade: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Message, InvalidHeaderValue> {
+        let mut message = Message::with_status(101);
+        let is_valid = upgrade.split(',').all(|protocol| {
+            let mut parts = protocol.trim().splitn(2, '/');
+            parts.all(Message::is_token)
+        });
+        message.set_validated_header("Upgrade", upgrade, is_valid)?;
+        message
+            .headers
+            .insert("Connection".to_string(), "Upgrade".to_string());
+        for (name, value) in headers {
+            let is_valid = Message::is_token(name)
+                && ![
+                    "Connection",
+                    "Content-Length",
+                    "Transfer-Encoding",
+                    "Upgrade",
+                ]
+                .iter()
+                .any(|framing| framing.eq_ignore_ascii_case(name));
+            message.set_validated_header(name, value, is_valid)?;
+        }
+        Ok(message)
+    }
+
+    /// # Create a response rejecting a request that could not be parsed
+    /// The status follows the error, the body is a short plain text description and the
+    /// connection is closed since the rest of the stream can not be trusted.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::{ParseError, Protocol};
+    /// use milstian_http::response::Message;
+    /// let message = Message::from_parse_error(&ParseError::InvalidRequestLine, &Protocol::V1_0);
+    /// assert_eq!(
+    ///     message.to_bytes(),
+    ///     b"HTTP/1.0 400 Bad Request\r\nContent-Type: text/plain; charset=utf-8\r\nConnection: close\r\nContent-Length: 21\r\n\r\ninvalid request line\n".to_vec()
+    /// );
+    /// ```
+    pub fn from_parse_error(error: &request::ParseError, protocol: &Protocol) -> Message {
+        let protocol = match protocol {
+            Protocol::V1_0 => Protocol::V1_0,
+            _ => Protocol::V1_1,
+        };
+        let mut message = Message::with_status(error.status_code());
+        message.protocol = request::Message::get_protocol_text(&protocol);
+        message.body = format!("{}\n", error).into_bytes();
+        message.headers.insert(
+            "Content-Type".to_string(),
+            "text/plain; charset=utf-8".to_string(),
+        );
+        message
+            .headers
+            .insert("Connection".to_string(), "close".to_string());
+        if let request::ParseError::MethodNotAllowed { allowed, .. } = error {
+            let allowed: Vec<String> = allowed
+                .iter()
+                .map(request::Message::get_method_text)
+                .collect();
+            message
+                .headers
+                .insert("Allow".to_string(), allowed.join(", "));
+        }
+        let length = message.body.len();
+        message.set_content_length(length);
+        message
+    }
+
+    /// # Set the status code with its canonical reason phrase, unknown codes get an empty phrase
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// message.set_status(503);
+    /// assert_eq!(message.status, "503 Service Unavailable".to_string());
+    /// message.set_status(599);
+    /// assert_eq!(message.status, "599 ".to_string());
+    /// ```
+    pub fn set_status(&mut self, code: u16) {
+        self.set_status_with_reason(code, reason_phrase(code).unwrap_or(""));
+    }
+
+    /// # Set the status code with a custom reason phrase
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// message.set_status_with_reason(200, "Everything Is Fine");
+    /// assert_eq!(message.status, "200 Everything Is Fine".to_string());
+    /// assert_eq!(message.status_code(), Some(200));
+    /// assert_eq!(message.reason(), "Everything Is Fine");
+    /// ```
+    pub fn set_status_with_reason(&mut self, code: u16, reason: &str) {
+        // Line breaks would end the status line early
+        let reason: String = reason
+            .chars()
+            .filter(|character| *character != '\r' && *character != '\n')
+            .collect();
+        self.status = format!("{} {}", code, reason);
+    }
+
+    /// # Get the numeric status code
+    pub fn status_code(&self) -> Option<u16> {
+        let code = self.status.split(' ').next()?;
+        if code.len() != 3 {
+            return None;
+        }
+        code.parse().ok()
+    }
+
+    /// # Get the reason phrase of the status
+    pub fn reason(&self) -> &str {
+        match self.status.split_once(' ') {
+            Some((_, reason)) => reason,
+            None => "",
+        }
+    }
+
+    fn is_valid_header_value(value: &str) -> bool {
+        !value.is_empty()
+            && value.trim() == value
+            && !value
+                .chars()
+                .any(|character| character.is_control() && character != '\t')
+    }
+
+    fn is_token(value: &str) -> bool {
+        !value.is_empty()
+            && value.chars().all(|character| {
+                character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(character)
+            })
+    }
+
+    fn set_validated_header(
+        &mut self,
+        name: &str,
+        value: &str,
+        is_valid: bool,
+    ) -> Result<(), InvalidHeaderValue> {
+        if !is_valid || !Message::is_valid_header_value(value) {
+            return Err(InvalidHeaderValue {
+                name: name.to_string(),
+                value: value.to_string(),
+            });
+        }
+        self.headers.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// # Get the value of a header, the name is matched case-insensitively
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// message
+    ///     .headers
+    ///     .insert("content-length".to_string(), "0".to_string());
+    /// assert_eq!(message.header("Content-Length"), Some(&"0".to_string()));
+    /// assert_eq!(message.header("Connection"), None);
+    /// ```
+    pub fn header(&self, name: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Get the name a field is stored under, matched case-insensitively, or name itself
+    fn header_name(&self, name: &str) -> String {
+        self.headers
+            .keys()
+            .find(|key| key.eq_ignore_ascii_case(name))
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// # Set a header, replacing all values of the field
+    /// Names are matched case-insensitively and a replaced field keeps its name and position.
+    /// Set-Cookie is rejected since cookies are sent one per field, use `add_cookie` instead.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// assert!(message.set_header("Vary", "Accept").is_ok());
+    /// assert!(message.set_header("vary", "Cookie").is_ok());
+    /// assert_eq!(message.headers.get("Vary"), Some(&"Cookie".to_string()));
+    /// assert!(message.set_header("X Id", "a").is_err());
+    /// assert!(message.set_header("X-Id", "a\r\nb").is_err());
+    /// ```
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), InvalidHeaderValue> {
+        let is_valid = Message::is_token(name) && !name.eq_ignore_ascii_case("Set-Cookie");
+        if !is_valid || !Message::is_valid_header_value(value) {
+            return Err(InvalidHeaderValue {
+                name: name.to_string(),
+                value: value.to_string(),
+            });
+        }
+        let key = self.header_name(name);
+        self.headers
+            .retain(|existing, _| *existing == key || !existing.eq_ignore_ascii_case(name));
+        self.headers.insert(key, value.to_string());
+        Ok(())
+    }
+
+    /// # Append a value to a header, so that layered handlers add to a field instead of replacing it
+    /// List-valued fields like Vary or Cache-Control get the value joined with a comma, entries
+    /// that are already listed are skipped and a Vary of * absorbs everything. Fields stored under
+    /// other letter cases are merged into the first one. Singleton fields
+    /// like Content-Type can't hold two values, appending to one that is set is an error and
+    /// leaves it unchanged. Set-Cookie is rejected like in `set_header`.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// assert!(message.append_header("Vary", "Accept").is_ok());
+    /// assert!(message.append_header("vary", "Accept-Encoding, accept").is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Vary"),
+    ///     Some(&"Accept, Accept-Encoding".to_string())
+    /// );
+    /// assert!(message.append_header("Content-Type", "text/plain").is_ok());
+    /// assert!(message.append_header("Content-Type", "text/html").is_err());
+    /// assert_eq!(
+    ///     message.headers.get("Content-Type"),
+    ///     Some(&"text/plain".to_string())
+    /// );
+    /// ```
+    pub fn append_header(&mut self, name: &str, value: &str) -> Result<(), InvalidHeaderValue> {
+        let existing: Vec<&str> = self
+            .headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect();
+        if existing.is_empty() {
+            return self.set_header(name, value);
+        }
+        let existing = existing.join(", ");
+        let is_valid = Message::is_token(name)
+            && !name.eq_ignore_ascii_case("Set-Cookie")
+            && !headers::is_singleton(name);
+        if !is_valid || !Message::is_valid_header_value(value) {
+            return Err(InvalidHeaderValue {
+                name: name.to_string(),
+                value: value.to_string(),
+            });
+        }
+        // Vary lists field names, which are case-insensitive
+        let is_vary = name.eq_ignore_ascii_case("Vary");
+        let mut entries = split_list(&existing);
+        entries.extend(split_list(value));
+        if is_vary && entries.iter().any(|entry| entry == "*") {
+            entries = vec!["*".to_string()];
+        }
+        let mut merged: Vec<String> = Vec::new();
+        for entry in entries {
+            if !merged
+                .iter()
+                .any(|listed| *listed == entry || (is_vary && listed.eq_ignore_ascii_case(&entry)))
+            {
+                merged.push(entry);
+            }
+        }
+        let key = self.header_name(name);
+        self.headers
+            .retain(|existing, _| *existing == key || !existing.eq_ignore_ascii_case(name));
+        self.headers.insert(key, merged.join(", "));
+        Ok(())
+    }
+
+    /// # Set the Content-Type header, the media type must be a valid type/subtype pair
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use std::collections::HashMap;
+    /// let mut message = Message::new(
+    ///     "HTTP/1.1".to_string(),
+    ///     "200 OK".to_string(),
+    ///     HashMap::new(),
+    ///     Vec::new(),
+    /// );
+    /// assert!(message.set_content_type("text/html; charset=utf-8").is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Content-Type"),
+    ///     Some(&"text/html; charset=utf-8".to_string())
+    /// );
+    /// assert!(message.set_content_type("text").is_err());
+    /// ```
+    pub fn set_content_type(&mut self, mime: &str) -> Result<(), InvalidHeaderValue> {
+        let media_type = mime.split(';').next().unwrap_or("").trim();
+        let parts: Vec<&str> = media_type.splitn(2, '/').collect();
+        let is_valid = parts.len() == 2 && parts.iter().all(|part| Message::is_token(part));
+        self.set_validated_header("Content-Type", mime, is_valid)
+    }
+
+    /// # Set the Content-Language header from well-formed language tags
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::language::ContentLanguage;
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// assert!(message
+    ///     .set_content_language(&ContentLanguage::new().tag("sv").tag("en"))
+    ///     .is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Content-Language"),
+    ///     Some(&"sv, en".to_string())
+    /// );
+    /// assert!(message.set_content_language(&ContentLanguage::new()).is_err());
+    /// assert!(message
+    ///     .set_content_language(&ContentLanguage::new().tag("*"))
+    ///     .is_err());
+    /// ```
+    pub fn set_content_language(
+        &mut self,
+        language: &ContentLanguage,
+    ) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header(
+            "Content-Language",
+            &language.to_string(),
+            language.is_valid(),
+        )
+    }
+
+    /// # Set an XML body with its Content-Type and Content-Length headers
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// message.set_xml_body("<ok/>");
+    /// assert_eq!(
+    ///     message.headers.get("Content-Type"),
+    ///     Some(&"application/xml; charset=utf-8".to_string())
+    /// );
+    /// assert_eq!(message.headers.get("Content-Length"), Some(&"5".to_string()));
+    /// ```
+    #[cfg(feature = "xml")]
+    pub fn set_xml_body(&mut self, xml: &str) {
+        self.body = xml.as_bytes().to_vec();
+        self.headers.insert(
+            "Content-Type".to_string(),
+            "application/xml; charset=utf-8".to_string(),
+        );
+        let length = self.body.len();
+        self.set_content_length(length);
+    }
+
+    /// # Set the Content-Length header
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use std::collections::HashMap;
+    /// let mut message = Message::new(
+    ///     "HTTP/1.1".to_string(),
+    ///     "200 OK".to_string(),
+    ///     HashMap::new(),
+    ///     b"abc".to_vec(),
+    /// );
+    /// let length = message.body.len();
+    /// message.set_content_length(length);
+    /// assert_eq!(message.headers.get("Content-Length"), Some(&"3".to_string()));
+    /// ```
+    pub fn set_content_length(&mut self, length: usize) {
+        self.headers
+            .insert("Content-Length".to_string(), length.to_string());
+    }
+
+    /// # Set the Location header, the URI may not contain whitespace
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use std::collections::HashMap;
+    /// let mut message = Message::new(
+    ///     "HTTP/1.1".to_string(),
+    ///     "302 Found".to_string(),
+    ///     HashMap::new(),
+    ///     Vec::new(),
+    /// );
+    /// assert!(message.set_location("/login?next=%2F").is_ok());
+    /// assert!(message.set_location("/log in").is_err());
+    /// ```
+    pub fn set_location(&mut self, uri: &str) -> Result<(), InvalidHeaderValue> {
+        let is_valid = !uri.chars().any(|character| character.is_whitespace());
+        self.set_validated_header("Location", uri, is_valid)
+    }
+
+    /// # Set the Cache-Control header from typed directives
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::CacheControl;
+    /// use milstian_http::response::Message;
+    /// use std::collections::HashMap;
+    /// let mut message = Message::new(
+    ///     "HTTP/1.1".to_string(),
+    ///     "200 OK".to_string(),
+    ///     HashMap::new(),
+    ///     Vec::new(),
+    /// );
+    /// let cache_control = CacheControl {
+    ///     public: true,
+    ///     max_age: Some(3600),
+    ///     ..CacheControl::default()
+    /// };
+    /// assert!(message.set_cache_control(&cache_control).is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Cache-Control"),
+    ///     Some(&"public, max-age=3600".to_string())
+    /// );
+    /// ```
+    pub fn set_cache_control(
+        &mut self,
+        cache_control: &CacheControl,
+    ) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header(
+            "Cache-Control",
+            &cache_control.to_string(),
+            cache_control.is_valid(),
+        )
+    }
+
+    /// # Set the Surrogate-Control header for gateway caches in front of the server
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::SurrogateControl;
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// let surrogate_control = SurrogateControl {
+    ///     max_age: Some(600),
+    ///     ..SurrogateControl::default()
+    /// };
+    /// assert!(message.set_surrogate_control(&surrogate_control).is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Surrogate-Control"),
+    ///     Some(&"max-age=600".to_string())
+    /// );
+    /// assert!(message.set_surrogate_control(&SurrogateControl::default()).is_err());
+    /// ```
+    pub fn set_surrogate_control(
+        &mut self,
+        surrogate_control: &SurrogateControl,
+    ) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header(
+            "Surrogate-Control",
+            &surrogate_control.to_string(),
+            surrogate_control.is_valid(),
+        )
+    }
+
+    /// # Set the Accept-CH header from the client hints the server wants
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::hints::{AcceptCh, SEC_CH_UA_PLATFORM};
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// assert!(message
+    ///     .set_accept_ch(&AcceptCh::new().hint(SEC_CH_UA_PLATFORM))
+    ///     .is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Accept-CH"),
+    ///     Some(&"Sec-CH-UA-Platform".to_string())
+    /// );
+    /// assert!(message.set_accept_ch(&AcceptCh::new()).is_err());
+    /// ```
+    pub fn set_accept_ch(&mut self, accept: &AcceptCh) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header("Accept-CH", &accept.to_string(), accept.is_valid())
+    }
+
+    /// # Set the Content-Security-Policy header, or its report-only variant, from a typed policy
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::security::{ContentSecurityPolicy, Source};
+    /// let mut message = Message::default();
+    /// let policy = ContentSecurityPolicy::new().default_src(&[Source::SelfOrigin]);
+    /// assert!(message.set_content_security_policy(&policy).is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Content-Security-Policy"),
+    ///     Some(&"default-src 'self'".to_string())
+    /// );
+    /// assert!(message
+    ///     .set_content_security_policy(&ContentSecurityPolicy::new())
+    ///     .is_err());
+    /// ```
+    pub fn set_content_security_policy(
+        &mut self,
+        policy: &ContentSecurityPolicy,
+    ) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header(policy.header_name(), &policy.to_string(), policy.is_valid())
+    }
+
+    /// # Set the Referrer-Policy header
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::security::ReferrerPolicy;
+    /// let mut message = Message::default();
+    /// message.set_referrer_policy(ReferrerPolicy::NoReferrer);
+    /// assert_eq!(
+    ///     message.headers.get("Referrer-Policy"),
+    ///     Some(&"no-referrer".to_string())
+    /// );
+    /// ```
+    pub fn set_referrer_policy(&mut self, policy: ReferrerPolicy) {
+        self.headers
+            .insert("Referrer-Policy".to_string(), policy.to_string());
+    }
+
+    /// # Set the Permissions-Policy header from features and their allowlists
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::security::PermissionsPolicy;
+    /// let mut message = Message::default();
+    /// let policy = PermissionsPolicy::new().deny("interest-cohort");
+    /// assert!(message.set_permissions_policy(&policy).is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Permissions-Policy"),
+    ///     Some(&"interest-cohort=()".to_string())
+    /// );
+    /// ```
+    pub fn set_permissions_policy(
+        &mut self,
+        policy: &PermissionsPolicy,
+    ) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header("Permissions-Policy", &policy.to_string(), policy.is_valid())
+    }
+
+    /// # Set the Clear-Site-Data header, nothing is set without directives
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::security::ClearSiteData;
+    /// let mut message = Message::with_status(303);
+    /// message.set_clear_site_data(&[ClearSiteData::Cookies, ClearSiteData::Storage]);
+    /// assert_eq!(
+    ///     message.headers.get("Clear-Site-Data"),
+    ///     Some(&"\"cookies\", \"storage\"".to_string())
+    /// );
+    /// message.set_clear_site_data(&[]);
+    /// assert_eq!(
+    ///     message.headers.get("Clear-Site-Data"),
+    ///     Some(&"\"cookies\", \"storage\"".to_string())
+    /// );
+    /// ```
+    pub fn set_clear_site_data(&mut self, directives: &[ClearSiteData]) {
+        if directives.is_empty() {
+            return;
+        }
+        let directives: Vec<String> = directives
+            .iter()
+            .map(|directive| directive.to_string())
+            .collect();
+        self.headers
+            .insert("Clear-Site-Data".to_string(), directives.join(", "));
+    }
+
+    /// # Set the Expect-CT header
+    pub fn set_expect_ct(&mut self, expect_ct: &ExpectCt) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header("Expect-CT", &expect_ct.to_string(), expect_ct.is_valid())
+    }
+
+    /// # Set the Reporting-Endpoints header from named endpoints
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::security::ReportingEndpoints;
+    /// let mut message = Message::default();
+    /// let endpoints = ReportingEndpoints::new().endpoint("default", "https://example.com/r");
+    /// assert!(message.set_reporting_endpoints(&endpoints).is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Reporting-Endpoints"),
+    ///     Some(&"default=\"https://example.com/r\"".to_string())
+    /// );
+    /// ```
+    pub fn set_reporting_endpoints(
+        &mut self,
+        endpoints: &ReportingEndpoints,
+    ) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header(
+            "Reporting-Endpoints",
+            &endpoints.to_string(),
+            endpoints.is_valid(),
+        )
+    }
+
+    /// # Set the Report-To header from endpoint groups, there must be at least one group
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::security::{NetworkErrorLogging, ReportTo};
+    /// use std::time::Duration;
+    /// let mut message = Message::default();
+    /// let groups = [
+    ///     ReportTo::new("nel", Duration::from_secs(3600)).endpoint("https://example.com/nel")
+    /// ];
+    /// assert!(message.set_report_to(&groups).is_ok());
+    /// let policy = NetworkErrorLogging::new("nel", Duration::from_secs(3600));
+    /// assert!(message.set_nel(&policy).is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("NEL"),
+    ///     Some(&"{\"report_to\":\"nel\",\"max_age\":3600}".to_string())
+    /// );
+    /// assert!(message.set_report_to(&[]).is_err());
+    /// ```
+    pub fn set_report_to(&mut self, groups: &[ReportTo]) -> Result<(), InvalidHeaderValue> {
+        let value: Vec<String> = groups.iter().map(|group| group.to_string()).collect();
+        self.set_validated_header(
+            "Report-To",
+            &value.join(", "),
+            !groups.is_empty() && groups.iter().all(ReportTo::is_valid),
+        )
+    }
+
+    /// # Set the NEL header from a Network Error Logging policy
+    pub fn set_nel(&mut self, policy: &NetworkErrorLogging) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header("NEL", &policy.to_string(), policy.is_valid())
+    }
+
+    /// # Set the Server-Timing header from accumulated metrics
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::timing::ServerTiming;
+    /// use std::time::Duration;
+    /// let mut timing = ServerTiming::new();
+    /// timing.add("db", Some(Duration::from_millis(12)), None);
+    /// let mut message = Message::default();
+    /// assert!(message.set_server_timing(&timing).is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Server-Timing"),
+    ///     Some(&"db;dur=12".to_string())
+    /// );
+    /// ```
+    pub fn set_server_timing(&mut self, timing: &ServerTiming) -> Result<(), InvalidHeaderValue> {
+        self.set_validated_header("Server-Timing", &timing.to_string(), timing.is_valid())
+    }
+
+    /// # Get the metrics of the Server-Timing header, like of a response read by the client
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// message
+    ///     .headers
+    ///     .insert("Server-Timing".to_string(), "db;dur=12, hit".to_string());
+    /// let metrics = message.server_timing();
+    /// assert_eq!(metrics.len(), 2);
+    /// assert_eq!(metrics[1].name, "hit".to_string());
+    /// ```
+    pub fn server_timing(&self) -> Vec<timing::Metric> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Server-Timing"))
+            .map(|(_, value)| timing::parse(value))
+            .unwrap_or_default()
+    }
+
+    /// # Append how a cache handled the request to the Cache-Status header
+    /// Each cache adds its entry after those of the caches closer to the origin.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cache::{CacheStatus, Forward};
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// assert!(message
+    ///     .add_cache_status(&CacheStatus::forward("Origin", Forward::Miss))
+    ///     .is_ok());
+    /// assert!(message.add_cache_status(&CacheStatus::hit("Edge")).is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Cache-Status"),
+    ///     Some(&"Origin;fwd=miss, Edge;hit".to_string())
+    /// );
+    /// assert!(message.add_cache_status(&CacheStatus::default()).is_err());
+    /// assert_eq!(message.cache_status().len(), 2);
+    /// ```
+    pub fn add_cache_status(&mut self, status: &CacheStatus) -> Result<(), InvalidHeaderValue> {
+        let entry = status.to_string();
+        if !status.is_valid() {
+            return Err(InvalidHeaderValue {
+                name: "Cache-Status".to_string(),
+                value: entry,
+            });
+        }
+        let key = self.header_name("Cache-Status");
+        let value = match self.headers.get(&key) {
+            Some(existing) => format!("{}, {}", existing, entry),
+            None => entry,
+        };
+        self.headers.insert(key, value);
+        Ok(())
+    }
+
+    /// # Get the entries of the Cache-Status header, closest to the origin first
+    pub fn cache_status(&self) -> Vec<CacheStatus> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Cache-Status"))
+            .map(|(_, value)| cache::parse_cache_status(value))
+            .unwrap_or_default()
+    }
+
+    /// # Append a link to the Link header, the target may not contain whitespace or angle brackets
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// assert!(message.add_link("/docs", "help").is_ok());
+    /// assert!(message.add_link("https://example.com/next", "next").is_ok());
+    /// assert_eq!(
+    ///     message.headers.get("Link"),
+    ///     Some(&"</docs>; rel=\"help\", <https://example.com/next>; rel=\"next\"".to_string())
+    /// );
+    /// assert!(message.add_link("/a b", "help").is_err());
+    /// ```
+    pub fn add_link(&mut self, target: &str, relation: &str) -> Result<(), InvalidHeaderValue> {
+        let link = format!("<{}>; rel=\"{}\"", target, relation);
+        let is_valid = !target.is_empty()
+            && !target
+                .chars()
+                .any(|character| character.is_whitespace() || character == '<' || character == '>')
+            && relation.split(' ').all(Message::is_token);
+        if !is_valid || !Message::is_valid_header_value(&link) {
+            return Err(InvalidHeaderValue {
+                name: "Link".to_string(),
+                value: link,
+            });
+        }
+        let value = match self.headers.get("Link") {
+            Some(existing) => format!("{}, {}", existing, link),
+            None => link,
+        };
+        self.headers.insert("Link".to_string(), value);
+        Ok(())
+    }
+
+    /// # Set the Deprecation header to when the resource was or will be deprecated (RFC 9745)
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// let mut message = Message::default();
+    /// message.set_deprecation(UNIX_EPOCH + Duration::from_secs(1688169599));
+    /// assert_eq!(
+    ///     message.headers.get("Deprecation"),
+    ///     Some(&"@1688169599".to_string())
+    /// );
+    /// assert!(message.add_link("https://example.com/deprecation", "deprecation").is_ok());
+    /// ```
+    pub fn set_deprecation(&mut self, time: SystemTime) {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        self.headers
+            .insert("Deprecation".to_string(), format!("@{}", seconds));
+    }
+
+    /// # Set the Sunset header to when the resource will become unavailable (RFC 8594)
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// let mut message = Message::default();
+    /// message.set_sunset(UNIX_EPOCH + Duration::from_secs(1735689600));
+    /// assert_eq!(
+    ///     message.headers.get("Sunset"),
+    ///     Some(&"Wed, 01 Jan 2025 00:00:00 GMT".to_string())
+    /// );
+    /// assert!(message.add_link("https://example.com/sunset", "sunset").is_ok());
+    /// ```
+    pub fn set_sunset(&mut self, time: SystemTime) {
+        self.headers
+            .insert("Sunset".to_string(), date::format_http_date(time));
+    }
+
+    /// # Add a cookie to send with a Set-Cookie header, invalid cookies are rejected
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::cookie::SetCookie;
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// assert!(message.add_cookie(SetCookie::new("a", "1")).is_ok());
+    /// assert!(message.add_cookie(SetCookie::new("b", "2")).is_ok());
+    /// assert!(message.add_cookie(SetCookie::new("c", "a b")).is_err());
+    /// assert_eq!(
+    ///     message.header_to_string(),
+    ///     "HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\n\r\n".to_string()
+    /// );
+    /// ```
+    pub fn add_cookie(&mut self, cookie: SetCookie) -> Result<(), CookieError> {
+        cookie.validate()?;
+        self.cookies.push(cookie);
+        Ok(())
+    }
+
+    /// # Set the Preference-Applied header, nothing is set when no preference was applied
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::prefer::{Preferences, Return};
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// message.set_preference_applied(&Preferences::default());
+    /// assert!(message.headers.get("Preference-Applied").is_none());
+    /// message.set_preference_applied(&Preferences {
+    ///     return_preference: Some(Return::Minimal),
+    ///     ..Preferences::default()
+    /// });
+    /// assert_eq!(
+    ///     message.headers.get("Preference-Applied"),
+    ///     Some(&"return=minimal".to_string())
+    /// );
+    /// ```
+    pub fn set_preference_applied(&mut self, preferences: &Preferences) {
+        if !preferences.is_empty() {
+            self.headers
+                .insert("Preference-Applied".to_string(), preferences.to_string());
+        }
+    }
+
+    /// # Merge a request header name into the Vary header without duplicating entries
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// let mut message = Message::default();
+    /// message.add_vary("Accept");
+    /// message.add_vary("accept");
+    /// message.add_vary("Accept-Encoding");
+    /// assert_eq!(
+    ///     message.headers.get("Vary"),
+    ///     Some(&"Accept, Accept-Encoding".to_string())
+    /// );
+    /// ```
+    pub fn add_vary(&mut self, name: &str) {
+        // Invalid names are left out
+        let _ = self.append_header("Vary", name);
+    }
+
+    /// # Reflect a request back as a message/http body, credentials are removed
+    /// Received requests are echoed with their request line and header fields as received, built
+    /// requests are serialized.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request;
+    /// use milstian_http::response::Message;
+    /// let request = request::Message::from_tcp_stream(
+    ///     b"TRACE /a HTTP/1.1\r\nhost: localhost\r\nCookie: session=1\r\n\r\n",
+    /// )
+    /// .expect("A request");
+    /// let response = Message::trace_echo(&request);
+    /// assert_eq!(response.status, "200 OK".to_string());
+    /// assert_eq!(
+    ///     response.headers.get("Content-Type"),
+    ///     Some(&"message/http".to_string())
+    /// );
+    /// assert_eq!(
+    ///     response.body,
+    ///     b"TRACE /a HTTP/1.1\r\nhost: localhost\r\n\r\n".to_vec()
+    /// );
+    /// ```
+    pub fn trace_echo(request: &request::Message) -> Message {
+        let is_sensitive = |name: &str| {
+            TRACE_SENSITIVE_HEADERS
+                .iter()
+                .any(|sensitive| sensitive.eq_ignore_ascii_case(name.trim()))
+        };
+        let body = match request.raw_header {
+            Some(ref raw_header) => {
+                let mut body = Vec::new();
+                let mut is_skipped = false;
+                for (index, line) in raw_header
+                    .split_inclusive(|byte| *byte == b'\n')
+                    .enumerate()
+                {
+                    // Folded lines belong to the field before them
+                    if index > 0 && !line.starts_with(b" ") && !line.starts_with(b"\t") {
+                        let name = line.split(|byte| *byte == b':').next().unwrap_or(line);
+                        is_skipped = is_sensitive(&String::from_utf8_lossy(name));
+                    }
+                    if !is_skipped {
+                        body.extend_from_slice(line);
+                    }
+                }
+                body
+            }
+            None => {
+                let mut reflected = request.clone();
+                reflected.headers.retain(|name, _| !is_sensitive(name));
+                reflected.to_bytes()
+            }
+        };
+
+        let mut headers: HashMap<String, String> = HashMap::new();
+        headers.insert("Content-Type".to_string(), "message/http".to_string());
+        headers.insert("Content-Length".to_string(), body.len().to_string());
+        Message::new(
+            request::Message::get_protocol_text(&request.request_line.protocol),
+            "200 OK".to_string(),
             headers,
             body,
+        )
+    }
+
+    /// # Convert HTTP/1.1-only mechanisms for a client speaking an older protocol
+    ///
+    /// Bodies whose final transfer coding is chunked are decoded and given a Content-Length, a
+    /// chunked body that can't be decoded is replaced by an empty 500 response rather than sent
+    /// with chunk framing. Bodies with other transfer codings are delimited by closing the
+    /// connection. Persistent connections are only kept when the client asked for them and the
+    /// body length is known. Newer protocols are left untouched.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Protocol;
+    /// use milstian_http::response::Message;
+    /// use std::collections::HashMap;
+    /// let mut headers = HashMap::new();
+    /// headers.insert("Transfer-Encoding".to_string(), "chunked".to_string());
+    /// let mut message = Message::new(
+    ///     "HTTP/1.1".to_string(),
+    ///     "200 OK".to_string(),
+    ///     headers,
+    ///     b"3\r\nabc\r\n0\r\n\r\n".to_vec(),
+    /// );
+    /// message.downgrade(&Protocol::V1_0, false);
+    /// assert_eq!(
+    ///     message.to_bytes(),
+    ///     b"HTTP/1.0 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc".to_vec()
+    /// );
+    /// ```
+    pub fn downgrade(&mut self, protocol: &Protocol, keep_alive: bool) {
+        if *protocol != Protocol::V1_0 && *protocol != Protocol::V0_9 {
+            return;
+        }
+        self.protocol = String::from("HTTP/1.0");
+
+        let mut length_is_known = self
+            .headers
+            .keys()
+            .any(|name| name.eq_ignore_ascii_case("Content-Length"));
+        let mut codings: Vec<String> = Vec::new();
+        self.headers.retain(|name, value| {
+            if name.eq_ignore_ascii_case("Transfer-Encoding") {
+                codings.extend(value.split(',').map(|coding| coding.trim().to_lowercase()));
+                return false;
+            }
+            true
+        });
+        if !codings.is_empty() {
+            length_is_known = false;
+            if codings.last().is_some_and(|coding| coding == "chunked") {
+                match chunked::decode(&self.body) {
+                    chunked::Status::Complete(body, _) => self.body = body,
+                    _ => {
+                        self.set_status(500);
+                        self.body.clear();
+                        self.headers
+                            .retain(|name, _| !name.eq_ignore_ascii_case("Content-Type"));
+                    }
+                }
+                length_is_known = true;
+            }
+            self.headers
+                .retain(|name, _| !name.eq_ignore_ascii_case("Content-Length"));
+            if length_is_known {
+                let length = self.body.len();
+                self.set_content_length(length);
+            }
         }
+        self.headers
+            .retain(|name, _| !name.eq_ignore_ascii_case("Trailer"));
+
+        let connection = if keep_alive && length_is_known {
+            "keep-alive"
+        } else {
+            "close"
+        };
+        self.headers
+            .insert("Connection".to_string(), connection.to_string());
     }
 
     /// # Get the HTTP header as a new string
+    /// Date and Server come first, other fields follow in insertion order unless sorted with
+    /// `headers.sort()`.
     /// ```rust
     /// use milstian_http::response::Message;
     /// use std::collections::HashMap;
@@ -42,21 +1548,36 @@ impl Message {
     /// );    
     /// ```
     pub fn header_to_string(&self) -> String {
+        self.header_to_string_with_case(&HeaderNameCase::Preserve)
+    }
+
+    /// # Get the HTTP header as a new string with header names normalized by case
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::HeaderNameCase;
+    /// let mut message = Message::default();
+    /// message.set_content_length(0);
+    /// assert_eq!(
+    ///     message.header_to_string_with_case(&HeaderNameCase::Lowercase),
+    ///     "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n".to_string()
+    /// );
+    /// ```
+    pub fn header_to_string_with_case(&self, case: &HeaderNameCase) -> String {
         let mut response = format!("{} {}\r\n", &self.protocol, &self.status);
 
-        if !&self.headers.is_empty() {
-            let mut headers: Vec<(&String, &String)> = self.headers.iter().collect();
-            headers.sort_by(|a, b| a.cmp(b));
-            for (key, value) in headers {
-                response.push_str(&format!("{}: {}\r\n", &key, &value));
-            }
+        for (key, value) in self.headers.serialization_order() {
+            response.push_str(&format!("{}: {}\r\n", normalize_key(key, case), value));
+        }
+        let cookie_key = normalize_key("Set-Cookie", case);
+        for cookie in self.cookies.iter() {
+            response.push_str(&format!("{}: {}\r\n", cookie_key, cookie));
         }
         response.push_str("\r\n");
 
         response
     }
 
-    /// # Convert response message into a string
+    /// # Convert message into bytes
     /// ```rust
     /// use milstian_http::response::Message;
     /// use std::collections::HashMap;
@@ -66,32 +1587,28 @@ impl Message {
     ///         "200 OK".to_string(),
     ///         HashMap::new(),
     ///         b"<html><body>Nothing here</body></html>".to_vec()
-    ///     ).to_string(),
-    ///     "HTTP/1.0 200 OK\r\n\r\n<html><body>Nothing here</body></html>".to_string()
+    ///     ).to_bytes(),
+    ///     b"HTTP/1.0 200 OK\r\n\r\n<html><body>Nothing here</body></html>".to_vec()
     /// );
     /// ```
-    pub fn to_string(&mut self) -> String {
-        let mut response = format!("{} {}\r\n", &self.protocol, &self.status);
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with_case(&HeaderNameCase::Preserve)
+    }
 
-        if !&self.headers.is_empty() {
-            let mut headers: Vec<(&String, &String)> = self.headers.iter().collect();
-            headers.sort_by(|a, b| a.cmp(b));
-            for (key, value) in headers {
-                response.push_str(&format!("{}: {}\r\n", &key, &value));
-            }
-        }
-        response.push_str("\r\n");
+    /// # Convert message into bytes with header names normalized by case
+    pub fn to_bytes_with_case(&self, case: &HeaderNameCase) -> Vec<u8> {
+        let mut response = self.header_to_string_with_case(case).into_bytes();
 
         if !&self.body.is_empty() {
-            if let Ok(body_string) = str::from_utf8(&self.body) {
-                response.push_str(body_string);
-            }
+            response.extend_from_slice(&self.body);
         }
 
         response
     }
+}
 
-    /// # Convert message into bytes
+impl fmt::Display for Message {
+    /// # Convert response message into a string
     /// ```rust
     /// use milstian_http::response::Message;
     /// use std::collections::HashMap;
@@ -101,37 +1618,600 @@ impl Message {
     ///         "200 OK".to_string(),
     ///         HashMap::new(),
     ///         b"<html><body>Nothing here</body></html>".to_vec()
-    ///     ).to_bytes(),
-    ///     b"HTTP/1.0 200 OK\r\n\r\n<html><body>Nothing here</body></html>".to_vec()
+    ///     ).to_string(),
+    ///     "HTTP/1.0 200 OK\r\n\r\n<html><body>Nothing here</body></html>".to_string()
     /// );
     /// ```
-    pub fn to_bytes(&mut self) -> Vec<u8> {
-        let mut response = format!("{} {}\r\n", &self.protocol, &self.status).into_bytes();
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.header_to_string())?;
 
-        if !&self.headers.is_empty() {
-            let mut headers: Vec<(&String, &String)> = self.headers.iter().collect();
-            headers.sort_by(|a, b| a.cmp(b));
-            for (key, value) in headers {
-                response.append(&mut format!("{}: {}\r\n", &key, &value).into_bytes());
+        if !&self.body.is_empty() {
+            if let Ok(body_string) = str::from_utf8(&self.body) {
+                write!(f, "{}", body_string)?;
             }
         }
-        response.append(&mut "\r\n".to_string().into_bytes());
 
-        if !&self.body.is_empty() {
-            response.append(&mut self.body);
+        Ok(())
+    }
+}
+
+const STREAM_CHUNK_SIZE: usize = 8192;
+
+/// # The body of a streaming response
+pub enum Body {
+    Reader(Box<dyn Read + Send>, Option<u64>), // Source and its size when known
+    #[cfg(all(unix, feature = "sendfile"))]
+    File {
+        file: File,
+        offset: u64,
+        length: u64,
+    },
+    #[cfg(feature = "mmap")]
+    Mmap(Mmap, Range<usize>), // Mapped file and the range of it that is sent
+}
+
+impl Body {
+    /// # Map a file into memory and send the range of it
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::{Body, Message, StreamingMessage};
+    /// use std::env;
+    /// use std::fs;
+    /// let path = env::temp_dir().join("milstian-mmap-example.txt");
+    /// fs::write(&path, b"Hello world").expect("A file");
+    /// let body = Body::mmap(&path, 6..11).expect("A mapped file");
+    /// assert_eq!(body.size(), Some(5));
+    /// let mut output = Vec::new();
+    /// StreamingMessage::with_body(Message::default(), body)
+    ///     .write_to(&mut output)
+    ///     .expect("A written response");
+    /// assert_eq!(output, b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nworld".to_vec());
+    /// assert!(Body::mmap(&path, 6..12).is_err());
+    /// fs::remove_file(&path).expect("A removed file");
+    /// ```
+    #[cfg(feature = "mmap")]
+    pub fn mmap(path: &Path, range: Range<u64>) -> io::Result<Body> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        if range.start > range.end || range.end > length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "range is outside of the file",
+            ));
+        }
+        // The file must not be truncated while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Body::Mmap(map, range.start as usize..range.end as usize))
+    }
+
+    /// # Get the size of the body when it is known before it is sent
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            Body::Reader(_, size) => *size,
+            #[cfg(all(unix, feature = "sendfile"))]
+            Body::File { length, .. } => Some(*length),
+            #[cfg(feature = "mmap")]
+            Body::Mmap(_, range) => Some(range.len() as u64),
         }
+    }
+}
 
-        response
+/// # A response whose body is read from a source while it is written
+pub struct StreamingMessage {
+    pub message: Message, // Status line and headers, the body of message is not sent
+    body: Body,
+}
+
+impl fmt::Debug for StreamingMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamingMessage")
+            .field("message", &self.message)
+            .field("size", &self.size())
+            .finish()
+    }
+}
+
+impl StreamingMessage {
+    /// # Create a response streaming the body from source
+    pub fn new(
+        message: Message,
+        source: Box<dyn Read + Send>,
+        size: Option<u64>,
+    ) -> StreamingMessage {
+        StreamingMessage::with_body(message, Body::Reader(source, size))
+    }
+
+    /// # Create a response sending length bytes of file starting at offset
+    /// The body is sent with sendfile(2) by write_to_stream where the platform supports it.
+    #[cfg(all(unix, feature = "sendfile"))]
+    pub fn from_file(message: Message, file: File, offset: u64, length: u64) -> StreamingMessage {
+        StreamingMessage::with_body(
+            message,
+            Body::File {
+                file,
+                offset,
+                length,
+            },
+        )
+    }
+
+    /// # Create a response sending body
+    pub fn with_body(message: Message, body: Body) -> StreamingMessage {
+        StreamingMessage { message, body }
+    }
+
+    /// # Get the size of the body, sent as Content-Length instead of chunks when known
+    pub fn size(&self) -> Option<u64> {
+        self.body.size()
+    }
+
+    /// Set the framing headers, returns whether the body is chunked
+    fn prepare_head(&mut self) -> bool {
+        let size = self.size();
+        let chunked = size.is_none() && self.message.protocol != "HTTP/1.0";
+        self.message.headers.remove("Content-Length");
+        self.message.headers.remove("Transfer-Encoding");
+        match size {
+            Some(size) => {
+                self.message
+                    .headers
+                    .insert("Content-Length".to_string(), size.to_string());
+            }
+            None if chunked => {
+                self.message
+                    .headers
+                    .insert("Transfer-Encoding".to_string(), "chunked".to_string());
+            }
+            None => {
+                self.message
+                    .headers
+                    .insert("Connection".to_string(), "close".to_string());
+            }
+        }
+        chunked
+    }
+
+    /// # Write the head and stream the body, returns the number of body bytes sent
+    /// Without a size HTTP/1.1 bodies are chunked and HTTP/1.0 bodies end when the connection closes.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::{Message, StreamingMessage};
+    /// use std::io::Cursor;
+    /// let source = Box::new(Cursor::new(b"abc".to_vec()));
+    /// let mut output = Vec::new();
+    /// StreamingMessage::new(Message::default(), source, None)
+    ///     .write_to(&mut output)
+    ///     .expect("A written response");
+    /// assert_eq!(
+    ///     output,
+    ///     b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n".to_vec()
+    /// );
+    ///
+    /// let source = Box::new(Cursor::new(b"abc".to_vec()));
+    /// let mut output = Vec::new();
+    /// StreamingMessage::new(Message::default(), source, Some(3))
+    ///     .write_to(&mut output)
+    ///     .expect("A written response");
+    /// assert_eq!(output, b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc".to_vec());
+    /// ```
+    pub fn write_to<W: Write>(mut self, writer: &mut W) -> io::Result<u64> {
+        let chunked = self.prepare_head();
+        writer.write_all(self.message.header_to_string().as_bytes())?;
+        let written = match self.body {
+            Body::Reader(source, size) => write_source(source, size, chunked, writer)?,
+            #[cfg(all(unix, feature = "sendfile"))]
+            Body::File {
+                mut file,
+                offset,
+                length,
+            } => {
+                file.seek(SeekFrom::Start(offset))?;
+                write_source(Box::new(file), Some(length), false, writer)?
+            }
+            #[cfg(feature = "mmap")]
+            Body::Mmap(map, range) => {
+                writer.write_all(&map[range.clone()])?;
+                range.len() as u64
+            }
+        };
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// # Write the head and send a file body with sendfile(2), other bodies are written like write_to
+    #[cfg(all(unix, feature = "sendfile"))]
+    pub fn write_to_stream(mut self, stream: &mut TcpStream) -> io::Result<u64> {
+        let (offset, length) = match self.body {
+            Body::File { offset, length, .. } => (offset, length),
+            _ => return self.write_to(stream),
+        };
+        self.prepare_head();
+        stream.write_all(self.message.header_to_string().as_bytes())?;
+        if let Body::File { ref file, .. } = self.body {
+            send_file(stream, file, offset, length)?;
+        }
+        Ok(length)
+    }
+}
+
+/// Split a comma-separated field value into trimmed entries, commas in quoted strings are kept
+fn split_list(value: &str) -> Vec<String> {
+    let mut entries: Vec<String> = Vec::new();
+    let mut entry = String::new();
+    let mut is_quoted = false;
+    let mut is_escaped = false;
+    for character in value.chars() {
+        if is_escaped {
+            is_escaped = false;
+        } else if is_quoted && character == '\\' {
+            is_escaped = true;
+        } else if character == '"' {
+            is_quoted = !is_quoted;
+        } else if character == ',' && !is_quoted {
+            entries.push(entry.trim().to_string());
+            entry.clear();
+            continue;
+        }
+        entry.push(character);
+    }
+    entries.push(entry.trim().to_string());
+    entries.retain(|entry| !entry.is_empty());
+    entries
+}
+
+/// Copy source to writer, as chunks or limited to a known size
+fn write_source<W: Write>(
+    mut source: Box<dyn Read + Send>,
+    size: Option<u64>,
+    chunked: bool,
+    writer: &mut W,
+) -> io::Result<u64> {
+    if let Some(size) = size {
+        let written = io::copy(&mut source.take(size), writer)?;
+        if written < size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "body source ended before its size",
+            ));
+        }
+        return Ok(written);
+    }
+
+    let mut buffer = [0; STREAM_CHUNK_SIZE];
+    let mut written: u64 = 0;
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        if chunked {
+            writer.write_all(&chunked::encode_chunk(&buffer[..read]))?;
+        } else {
+            writer.write_all(&buffer[..read])?;
+        }
+        written += read as u64;
+    }
+    if chunked {
+        writer.write_all(&chunked::encode_chunk(b""))?;
+    }
+    Ok(written)
+}
+
+/// Send length bytes of file from offset without copying them through user space
+#[cfg(all(feature = "sendfile", any(target_os = "android", target_os = "linux")))]
+fn send_file(stream: &mut TcpStream, file: &File, offset: u64, length: u64) -> io::Result<()> {
+    let mut offset = offset as libc::off_t;
+    let mut remaining = length;
+    while remaining > 0 {
+        // Linux transfers at most 0x7ffff000 bytes per call
+        let count = remaining.min(0x7fff_f000) as usize;
+        let sent =
+            unsafe { libc::sendfile(stream.as_raw_fd(), file.as_raw_fd(), &mut offset, count) };
+        if sent < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+        if sent == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file ended before its length",
+            ));
+        }
+        remaining -= sent as u64;
     }
+    Ok(())
+}
+
+/// Send length bytes of file from offset, copied where sendfile(2) is not supported
+#[cfg(all(
+    unix,
+    feature = "sendfile",
+    not(any(target_os = "android", target_os = "linux"))
+))]
+fn send_file(stream: &mut TcpStream, file: &File, offset: u64, length: u64) -> io::Result<()> {
+    let mut file = file;
+    file.seek(SeekFrom::Start(offset))?;
+    if io::copy(&mut file.take(length), stream)? < length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "file ended before its length",
+        ));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_error_page() {
+        let message = Message::bad_request(Some("Missing \"name\""));
+        assert_eq!(message.status, "400 Bad Request".to_string());
+        assert_eq!(
+            String::from_utf8(message.body.clone()).expect("UTF-8"),
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>400 Bad Request</title>\n</head>\n<body>\n<h1>400 Bad Request</h1>\n<p>Missing &quot;name&quot;</p>\n</body>\n</html>\n"
+                .to_string()
+        );
+        assert_eq!(
+            message.headers.get("Content-Length"),
+            Some(&message.body.len().to_string())
+        );
+        assert_eq!(Message::forbidden(None).status_code(), Some(403));
+        assert_eq!(
+            Message::internal_server_error(None).status_code(),
+            Some(500)
+        );
+        assert_eq!(Message::service_unavailable(None).status_code(), Some(503));
+        assert!(!String::from_utf8(Message::not_found(None).body)
+            .expect("UTF-8")
+            .contains("<p>"));
+
+        // Unknown codes have an empty reason phrase
+        assert!(String::from_utf8(Message::error_page(599, None).body)
+            .expect("UTF-8")
+            .contains("<title>599 </title>"));
+    }
+
+    #[test]
+    fn test_json_error() {
+        let message = Message::json_error(404, "not_found", "No\tsuch\u{0}item");
+        assert_eq!(
+            message.body,
+            b"{\"error\":{\"status\":404,\"code\":\"not_found\",\"message\":\"No\\tsuch\\u0000item\"}}"
+                .to_vec()
+        );
+        assert_eq!(
+            message.headers.get("Content-Length"),
+            Some(&message.body.len().to_string())
+        );
+        assert_eq!(message.status, "404 Not Found".to_string());
+    }
+
+    #[test]
+    fn test_switching_protocols() {
+        let message =
+            Message::switching_protocols("HTTP/2.0, h2c", &[("X-Id", "1")]).expect("A response");
+        assert_eq!(message.status_code(), Some(101));
+        assert_eq!(
+            message.headers.get("Upgrade"),
+            Some(&"HTTP/2.0, h2c".to_string())
+        );
+        assert_eq!(message.headers.get("X-Id"), Some(&"1".to_string()));
+        assert!(message.body.is_empty());
+        assert_eq!(message.headers.get("Content-Length"), None);
+
+        assert!(Message::switching_protocols("", &[]).is_err());
+        assert!(Message::switching_protocols("h2c/", &[]).is_err());
+        for name in ["transfer-encoding", "Connection", "Upgrade", "X Id"].iter() {
+            assert_eq!(
+                Message::switching_protocols("h2c", &[(name, "a")]),
+                Err(InvalidHeaderValue {
+                    name: name.to_string(),
+                    value: "a".to_string()
+                })
+            );
+        }
+        assert!(Message::switching_protocols("h2c", &[("X-Id", "a\r\nb")]).is_err());
+    }
+
+    #[test]
+    fn test_partial_content() {
+        let body = b"0123456789";
+        let message = Message::partial_content(body, &ByteRange { first: 8, last: 20 }, "a/b");
+        assert_eq!(message.body, b"89".to_vec());
+        assert_eq!(
+            message.to_bytes(),
+            b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 8-9/10\r\nContent-Type: a/b\r\nAccept-Ranges: bytes\r\nContent-Length: 2\r\n\r\n89".to_vec()
+        );
+        let message = Message::partial_content(body, &ByteRange { first: 0, last: 9 }, "a/b");
+        assert_eq!(message.body, body.to_vec());
+        assert_eq!(message.status_code(), Some(206));
+
+        // Unsatisfiable ranges
+        for range in [
+            ByteRange {
+                first: 10,
+                last: 12,
+            },
+            ByteRange { first: 5, last: 4 },
+        ]
+        .iter()
+        {
+            let message = Message::partial_content(body, range, "a/b");
+            assert_eq!(message.status, "416 Range Not Satisfiable".to_string());
+            assert!(message.body.is_empty());
+            assert_eq!(
+                message.headers.get("Content-Range"),
+                Some(&"bytes */10".to_string())
+            );
+            assert_eq!(message.headers.get("Content-Type"), None);
+        }
+        let message = Message::partial_content(b"", &ByteRange { first: 0, last: 0 }, "a/b");
+        assert_eq!(message.status_code(), Some(416));
+    }
+
+    #[test]
+    fn test_from_parse_error() {
+        let message = Message::from_parse_error(
+            &request::ParseError::BodyTooLarge { limit: 1, seen: 2 },
+            &Protocol::V2_0,
+        );
+        assert_eq!(message.protocol, "HTTP/1.1".to_string());
+        assert_eq!(message.status, "413 Payload Too Large".to_string());
+        assert_eq!(
+            message.headers.get("Content-Length"),
+            Some(&message.body.len().to_string())
+        );
+        assert_eq!(
+            Message::from_parse_error(
+                &request::ParseError::HeaderTooLarge { limit: 1, seen: 2 },
+                &Protocol::V1_1
+            )
+            .status_code(),
+            Some(431)
+        );
+        assert_eq!(
+            Message::from_parse_error(
+                &request::ParseError::UriTooLong { limit: 1, seen: 2 },
+                &Protocol::V1_1
+            )
+            .status_code(),
+            Some(414)
+        );
+        let message = Message::from_parse_error(
+            &request::ParseError::MethodNotAllowed {
+                allowed: vec![request::Method::Get, request::Method::Head],
+                method: request::Method::Post,
+            },
+            &Protocol::V1_1,
+        );
+        assert_eq!(message.status, "405 Method Not Allowed".to_string());
+        assert_eq!(message.headers.get("Allow"), Some(&"GET, HEAD".to_string()));
+        assert_eq!(message.body, b"method POST is not allowed\n".to_vec());
+        assert_eq!(
+            Message::from_parse_error(&request::ParseError::InvalidHeader, &Protocol::V1_1).body,
+            b"invalid header field\n".to_vec()
+        );
+    }
+
+    #[cfg(all(unix, feature = "sendfile"))]
+    #[test]
+    fn test_streaming_message_from_file() {
+        use multipart;
+        use std::env;
+        use std::net::TcpListener;
+
+        let spooled = multipart::SpooledFile::create(&env::temp_dir(), b"0123456789", None, None)
+            .expect("A temporary file");
+
+        // Files are copied when written to other writers
+        let file = spooled.open().expect("An open file");
+        let mut output = Vec::new();
+        let written = StreamingMessage::from_file(Message::default(), file, 2, 5)
+            .write_to(&mut output)
+            .expect("A written response");
+        assert_eq!(written, 5);
+        assert_eq!(
+            output,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n23456".to_vec()
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("A listener");
+        let mut stream =
+            TcpStream::connect(listener.local_addr().expect("An address")).expect("A stream");
+        let (mut accepted, _) = listener.accept().expect("A connection");
+        let file = spooled.open().expect("An open file");
+        let written = StreamingMessage::from_file(Message::default(), file, 7, 3)
+            .write_to_stream(&mut accepted)
+            .expect("A sent response");
+        assert_eq!(written, 3);
+        drop(accepted);
+        let mut output = Vec::new();
+        stream.read_to_end(&mut output).expect("A response");
+        assert_eq!(
+            output,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n789".to_vec()
+        );
+
+        // A range past the end of the file fails
+        let file = spooled.open().expect("An open file");
+        let mut stream =
+            TcpStream::connect(listener.local_addr().expect("An address")).expect("A stream");
+        let (mut accepted, _) = listener.accept().expect("A connection");
+        let error = StreamingMessage::from_file(Message::default(), file, 8, 5)
+            .write_to_stream(&mut accepted)
+            .expect_err("A short file");
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        drop(accepted);
+        stream.read_to_end(&mut Vec::new()).expect("A response");
+    }
+
+    #[test]
+    fn test_streaming_message() {
+        // Bodies larger than a chunk are split and decode back
+        let body: Vec<u8> = (0..20000).map(|index| (index % 251) as u8).collect();
+        let mut message = Message::with_status(201);
+        message.set_content_length(5);
+        let mut output = Vec::new();
+        let written = StreamingMessage::new(message, Box::new(Cursor::new(body.clone())), None)
+            .write_to(&mut output)
+            .expect("A written response");
+        assert_eq!(written, 20000);
+        let head = b"HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert!(output.starts_with(head));
+        match chunked::decode(&output[head.len()..]) {
+            chunked::Status::Complete(decoded, consumed) => {
+                assert_eq!(decoded, body);
+                assert_eq!(consumed, output.len() - head.len());
+            }
+            status => panic!("Expected a complete body, got {:?}", status),
+        }
+
+        // Sizes limit the body and a short source is an error
+        let mut output = Vec::new();
+        let written = StreamingMessage::new(
+            Message::default(),
+            Box::new(Cursor::new(b"abcdef".to_vec())),
+            Some(2),
+        )
+        .write_to(&mut output)
+        .expect("A written response");
+        assert_eq!(written, 2);
+        assert!(output.ends_with(b"\r\n\r\nab"));
+        let error = StreamingMessage::new(
+            Message::default(),
+            Box::new(Cursor::new(b"a".to_vec())),
+            Some(2),
+        )
+        .write_to(&mut Vec::new())
+        .expect_err("A short body");
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        // HTTP/1.0 bodies without a size are delimited by closing the connection
+        let message = Message {
+            protocol: "HTTP/1.0".to_string(),
+            ..Message::default()
+        };
+        let mut output = Vec::new();
+        StreamingMessage::new(message, Box::new(Cursor::new(b"abc".to_vec())), None)
+            .write_to(&mut output)
+            .expect("A written response");
+        assert_eq!(
+            output,
+            b"HTTP/1.0 200 OK\r\nConnection: close\r\n\r\nabc".to_vec()
+        );
+    }
 
     #[test]
     fn test_to_string() {
-        let mut message = Message::new(
+        let message = Message::new(
             "HTTP/1.0".to_string(),
             "200 OK".to_string(),
             HashMap::new(),
@@ -144,8 +2224,261 @@ mod tests {
     }
 
     #[test]
-    fn test_to_bytes() {
+    fn test_default_and_clone() {
+        let message = Message::default();
+        assert_eq!(message.to_bytes(), b"HTTP/1.1 200 OK\r\n\r\n".to_vec());
+        let mut copy = message.clone();
+        assert_eq!(copy, message);
+        copy.body = b"abc".to_vec();
+        assert_ne!(copy, message);
+    }
+
+    #[test]
+    fn test_typed_setters() {
         let mut message = Message::new(
+            "HTTP/1.1".to_string(),
+            "200 OK".to_string(),
+            HashMap::new(),
+            Vec::new(),
+        );
+        assert!(message.set_content_type("application/json").is_ok());
+        assert!(message.set_content_type("application json").is_err());
+        assert!(message
+            .set_content_type("text/html\r\nX-Injected: 1")
+            .is_err());
+        assert_eq!(
+            message.headers.get("Content-Type"),
+            Some(&"application/json".to_string())
+        );
+
+        message.set_content_length(42);
+        assert_eq!(
+            message.headers.get("Content-Length"),
+            Some(&"42".to_string())
+        );
+
+        assert!(message.set_location("https://example.com/a?b=c").is_ok());
+        assert_eq!(
+            message.set_location(""),
+            Err(InvalidHeaderValue {
+                name: "Location".to_string(),
+                value: "".to_string()
+            })
+        );
+
+        let cache_control = CacheControl {
+            public: true,
+            private: true,
+            ..CacheControl::default()
+        };
+        assert!(message.set_cache_control(&cache_control).is_err());
+        assert!(!message.headers.contains_key("Cache-Control"));
+        let cache_control = CacheControl {
+            no_store: true,
+            ..CacheControl::default()
+        };
+        assert!(message.set_cache_control(&cache_control).is_ok());
+        assert_eq!(
+            message.headers.get("Cache-Control"),
+            Some(&"no-store".to_string())
+        );
+    }
+
+    #[test]
+    fn test_append_header() {
+        let mut message = Message::default();
+        message
+            .headers
+            .insert("cache-control".to_string(), "no-cache=\"a, b\"".to_string());
+        message.headers.insert("X-Id".to_string(), "1".to_string());
+        message
+            .headers
+            .insert("CACHE-CONTROL".to_string(), "private".to_string());
+        assert!(message
+            .append_header("Cache-Control", "max-age=0, private")
+            .is_ok());
+        assert_eq!(
+            message.headers.get("cache-control"),
+            Some(&"no-cache=\"a, b\", private, max-age=0".to_string())
+        );
+        assert!(message.set_header("Cache-Control", "no-store").is_ok());
+        assert_eq!(
+            message.headers.iter().collect::<Vec<(&String, &String)>>(),
+            vec![
+                (&"cache-control".to_string(), &"no-store".to_string()),
+                (&"X-Id".to_string(), &"1".to_string())
+            ]
+        );
+
+        assert!(message.append_header("Vary", "Accept").is_ok());
+        assert!(message.append_header("Vary", "*").is_ok());
+        assert!(message.append_header("Vary", "Cookie").is_ok());
+        assert_eq!(message.headers.get("Vary"), Some(&"*".to_string()));
+
+        assert!(message.append_header("Location", "/a").is_ok());
+        assert!(message.append_header("location", "/b").is_err());
+        assert!(message.set_header("Location", "/b").is_ok());
+        assert_eq!(message.headers.get("Location"), Some(&"/b".to_string()));
+
+        assert!(message.set_header("Set-Cookie", "a=1").is_err());
+        assert!(message.append_header("set-cookie", "a=1").is_err());
+        assert!(message.append_header("X-Id", "").is_err());
+        assert!(!message.headers.contains_key("Set-Cookie"));
+    }
+
+    #[test]
+    fn test_lifecycle_headers() {
+        let mut message = Message::default();
+        message.set_deprecation(UNIX_EPOCH);
+        message.set_sunset(UNIX_EPOCH);
+        assert!(message.add_link("/changelog", "deprecation").is_ok());
+        assert!(message.add_link("/policy", "sunset alternate").is_ok());
+        assert_eq!(
+            message.to_bytes(),
+            b"HTTP/1.1 200 OK\r\nDeprecation: @0\r\nSunset: Thu, 01 Jan 1970 00:00:00 GMT\r\nLink: </changelog>; rel=\"deprecation\", </policy>; rel=\"sunset alternate\"\r\n\r\n".to_vec()
+        );
+        assert!(message.add_link("/a>", "next").is_err());
+        assert!(message.add_link("", "next").is_err());
+        assert!(message.add_link("/a", "").is_err());
+        assert!(message.add_link("/a", "ne\"xt").is_err());
+    }
+
+    #[test]
+    fn test_status() {
+        let mut message = Message::with_status(308);
+        assert_eq!(message.status, "308 Permanent Redirect".to_string());
+        assert_eq!(message.status_code(), Some(308));
+        message.set_status_with_reason(400, "Bad\r\nX-Injected: 1");
+        assert_eq!(message.status, "400 BadX-Injected: 1".to_string());
+        message.status = "OK".to_string();
+        assert_eq!(message.status_code(), None);
+        assert_eq!(message.reason(), "");
+        message.status = "2000 OK".to_string();
+        assert_eq!(message.status_code(), None);
+        for code in 100..600 {
+            if let Some(reason) = reason_phrase(code) {
+                assert!(!reason.is_empty());
+                assert_eq!(Message::with_status(code).reason(), reason);
+            }
+        }
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let mut headers = HashMap::new();
+        headers.insert("Transfer-Encoding".to_string(), "chunked".to_string());
+        headers.insert("Trailer".to_string(), "Expires".to_string());
+        let message = Message::new(
+            "HTTP/1.1".to_string(),
+            "200 OK".to_string(),
+            headers,
+            b"3\r\nabc\r\n0\r\nExpires: 0\r\n\r\n".to_vec(),
+        );
+
+        let mut unchanged = message.clone();
+        unchanged.downgrade(&Protocol::V1_1, false);
+        assert_eq!(unchanged, message);
+
+        let mut downgraded = message.clone();
+        downgraded.downgrade(&Protocol::V1_0, true);
+        assert_eq!(
+            downgraded.to_bytes(),
+            b"HTTP/1.0 200 OK\r\nContent-Length: 3\r\nConnection: keep-alive\r\n\r\nabc".to_vec()
+        );
+
+        // Chunked bodies that can't be decoded are replaced by an error
+        let mut downgraded = message.clone();
+        downgraded.body = b"xyz".to_vec();
+        downgraded
+            .headers
+            .insert("Content-Length".to_string(), "3".to_string());
+        downgraded.downgrade(&Protocol::V1_0, true);
+        assert_eq!(
+            downgraded.to_bytes(),
+            b"HTTP/1.0 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: keep-alive\r\n\r\n".to_vec()
+        );
+
+        // Fields are found in any case and only a final chunked coding is decoded
+        let mut headers = HashMap::new();
+        headers.insert("transfer-encoding".to_string(), "chunked, gzip".to_string());
+        headers.insert("content-length".to_string(), "3".to_string());
+        let mut downgraded = Message::new(
+            "HTTP/1.1".to_string(),
+            "200 OK".to_string(),
+            headers,
+            b"xyz".to_vec(),
+        );
+        downgraded.downgrade(&Protocol::V1_0, true);
+        assert_eq!(
+            downgraded.to_bytes(),
+            b"HTTP/1.0 200 OK\r\nConnection: close\r\n\r\nxyz".to_vec()
+        );
+        let mut headers = HashMap::new();
+        headers.insert("transfer-encoding".to_string(), "gzip, Chunked".to_string());
+        let mut downgraded = Message::new(
+            "HTTP/1.1".to_string(),
+            "200 OK".to_string(),
+            headers,
+            b"3\r\nxyz\r\n0\r\n\r\n".to_vec(),
+        );
+        downgraded.downgrade(&Protocol::V1_0, false);
+        assert_eq!(
+            downgraded.to_bytes(),
+            b"HTTP/1.0 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\nxyz".to_vec()
+        );
+
+        // Unknown lengths can't be kept alive
+        let mut downgraded = Message::default();
+        downgraded.downgrade(&Protocol::V0_9, true);
+        assert_eq!(
+            downgraded.headers.get("Connection"),
+            Some(&"close".to_string())
+        );
+    }
+
+    #[test]
+    fn test_trace_echo() {
+        let request = request::Message::from_tcp_stream(
+            b"TRACE / HTTP/1.0\r\nauthorization: Basic YTpi\r\nProxy-Authorization: x\r\nX-Trace: 1\r\n\r\n",
+        )
+        .expect("A request");
+        let response = Message::trace_echo(&request);
+        assert_eq!(response.protocol, "HTTP/1.0".to_string());
+        assert_eq!(
+            response.body,
+            b"TRACE / HTTP/1.0\r\nX-Trace: 1\r\n\r\n".to_vec()
+        );
+        assert_eq!(
+            response.headers.get("Content-Length"),
+            Some(&response.body.len().to_string())
+        );
+
+        // Order, case, repeated and folded fields and framing are kept as received
+        let request = request::Message::from_tcp_stream(
+            b"TRACE / HTTP/1.1\r\nx-b: 1\r\nCookie: a=1;\r\n b=2\r\nX-A: 2\r\nx-b: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        )
+        .expect("A request");
+        assert_eq!(
+            Message::trace_echo(&request).body,
+            b"TRACE / HTTP/1.1\r\nx-b: 1\r\nX-A: 2\r\nx-b: 3\r\nTransfer-Encoding: chunked\r\n\r\n"
+                .to_vec()
+        );
+
+        // Built requests are serialized
+        let built = request::Message {
+            raw_header: None,
+            ..request::Message::from_tcp_stream(b"TRACE / HTTP/1.1\r\nx-b: 1\r\nCookie: a\r\n\r\n")
+                .expect("A request")
+        };
+        assert_eq!(
+            Message::trace_echo(&built).body,
+            b"TRACE / HTTP/1.1\r\nX-B: 1\r\n\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let message = Message::new(
             "HTTP/1.0".to_string(),
             "200 OK".to_string(),
             HashMap::new(),
@@ -155,6 +2488,12 @@ mod tests {
             message.to_bytes(),
             b"HTTP/1.0 200 OK\r\n\r\n<html><body>Nothing here</body></html>".to_vec()
         );
-    }
 
+        // Serializing does not consume the body
+        assert_eq!(message.to_bytes(), message.to_bytes());
+        assert_eq!(
+            message.body,
+            b"<html><body>Nothing here</body></html>".to_vec()
+        );
+    }
 }
@@ -0,0 +1,341 @@
+//! # Handles matching request URIs against route templates.
+
+use std::collections::HashMap;
+
+use request;
+use request::Method;
+use response;
+use uri;
+
+/// # The outcome of checking a request method against the methods a route supports
+#[derive(Debug, PartialEq)]
+pub enum Dispatch {
+    Allowed,
+    Rejected(response::Message), // 404, 405 or 501 response ready to be sent
+}
+
+/// # Check method against the methods of a route, GET implies HEAD
+/// A route without any methods is answered as if it didn't exist, since a 405 must list at least
+/// one allowed method.
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::Method;
+/// use milstian_http::route::{check_method, Dispatch};
+/// let allowed = [Method::Get, Method::Post];
+/// assert_eq!(check_method(&Method::Head, &allowed), Dispatch::Allowed);
+/// match check_method(&Method::Delete, &allowed) {
+///     Dispatch::Rejected(response) => {
+///         assert_eq!(response.status, "405 Method Not Allowed".to_string());
+///         assert_eq!(
+///             response.headers.get("Allow"),
+///             Some(&"GET, HEAD, POST".to_string())
+///         );
+///     }
+///     Dispatch::Allowed => panic!("Expected a rejection"),
+/// }
+/// ```
+pub fn check_method(method: &Method, allowed: &[Method]) -> Dispatch {
+    let is_allowed =
+        allowed.contains(method) || (*method == Method::Head && allowed.contains(&Method::Get));
+    if *method != Method::Invalid && is_allowed {
+        return Dispatch::Allowed;
+    }
+
+    let mut headers: HashMap<String, String> = HashMap::new();
+    headers.insert("Content-Length".to_string(), "0".to_string());
+    let allow = get_allow(allowed);
+    let status = if *method == Method::Invalid {
+        // Extension methods are not implemented by any route
+        String::from("501 Not Implemented")
+    } else if allow.is_empty() {
+        String::from("404 Not Found")
+    } else {
+        headers.insert("Allow".to_string(), allow);
+        String::from("405 Method Not Allowed")
+    };
+    Dispatch::Rejected(response::Message::new(
+        "HTTP/1.1".to_string(),
+        status,
+        headers,
+        Vec::new(),
+    ))
+}
+
+/// # What the server as a whole supports, advertised in response to `OPTIONS *`
+/// Routes answer OPTIONS for their own resources, this describes the server regardless of any
+/// resource.
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::{Message, Method};
+/// use milstian_http::route::{is_server_wide, Capabilities};
+/// let request = Message::from_tcp_stream(b"OPTIONS * HTTP/1.1\r\nHost: example.com\r\n\r\n")
+///     .expect("A request");
+/// assert!(is_server_wide(&request));
+/// let capabilities = Capabilities::new(&[Method::Get, Method::Post, Method::Options])
+///     .protocol("websocket")
+///     .extension("Accept-Ranges", "bytes");
+/// assert_eq!(
+///     capabilities.to_response().to_bytes(),
+///     b"HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nAllow: GET, HEAD, OPTIONS, POST\r\nUpgrade: websocket\r\nConnection: upgrade\r\nContent-Length: 0\r\n\r\n"
+///         .to_vec()
+/// );
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Capabilities {
+    pub extensions: Vec<(String, String)>, // Further capability headers, like Accept-Patch
+    pub methods: Vec<Method>,              // GET implies HEAD
+    pub protocols: Vec<String>,            // Protocols or versions to upgrade to, like HTTP/2.0
+}
+
+impl Capabilities {
+    /// # Create capabilities of the methods the server implements
+    pub fn new(methods: &[Method]) -> Capabilities {
+        Capabilities {
+            extensions: Vec::new(),
+            methods: methods.to_vec(),
+            protocols: Vec::new(),
+        }
+    }
+
+    /// # Advertise a protocol the server upgrades connections to
+    pub fn protocol(mut self, protocol: &str) -> Capabilities {
+        self.protocols.push(protocol.to_string());
+        self
+    }
+
+    /// # Advertise a capability with a header, like Accept-Ranges: bytes
+    pub fn extension(mut self, name: &str, value: &str) -> Capabilities {
+        self.extensions.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// # Build the 200 response to `OPTIONS *`
+    pub fn to_response(&self) -> response::Message {
+        let mut message = response::Message::with_status(200);
+        for (name, value) in self.extensions.iter() {
+            message.headers.insert(name.to_string(), value.to_string());
+        }
+        message
+            .headers
+            .insert("Allow".to_string(), get_allow(&self.methods));
+        if !self.protocols.is_empty() {
+            // Senders of Upgrade must list it as a connection option
+            message
+                .headers
+                .insert("Upgrade".to_string(), self.protocols.join(", "));
+            message
+                .headers
+                .insert("Connection".to_string(), "upgrade".to_string());
+        }
+        message.set_content_length(0);
+        message
+    }
+}
+
+/// # Is request an `OPTIONS *` request about the server as a whole?
+pub fn is_server_wide(request: &request::Message) -> bool {
+    request.request_line.method == Method::Options && request.request_line.request_uri == "*"
+}
+
+/// Get a sorted Allow header value of methods, GET implies HEAD
+fn get_allow(methods: &[Method]) -> String {
+    let mut methods: Vec<Method> = methods.to_vec();
+    if methods.contains(&Method::Get) {
+        methods.push(Method::Head);
+    }
+    let mut names: Vec<String> = methods
+        .iter()
+        .filter(|method| **method != Method::Invalid)
+        .map(request::Message::get_method_text)
+        .collect();
+    names.sort();
+    names.dedup();
+    names.join(", ")
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Segment {
+    Literal(String),
+    Parameter(String),
+}
+
+/// # A compiled route template like `/users/{id}/posts/{slug}`
+#[derive(Debug)]
+pub struct Route {
+    pub pattern: String,
+    segments: Vec<Segment>,
+}
+
+impl Route {
+    /// # Compile a route template, returns None for malformed or duplicate parameters
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::route::Route;
+    /// assert!(Route::new("/users/{id}").is_some());
+    /// assert!(Route::new("/users/{id").is_none());
+    /// assert!(Route::new("/users/{id}/{id}").is_none());
+    /// ```
+    pub fn new(pattern: &str) -> Option<Route> {
+        let mut segments: Vec<Segment> = Vec::new();
+        for segment in pattern.split('/').filter(|segment| !segment.is_empty()) {
+            if segment.starts_with('{') && segment.ends_with('}') && segment.len() > 2 {
+                let name = &segment[1..segment.len() - 1];
+                if name.contains(&['{', '}'][..])
+                    || segments.contains(&Segment::Parameter(name.to_string()))
+                {
+                    return None;
+                }
+                segments.push(Segment::Parameter(name.to_string()));
+            } else if segment.contains(&['{', '}'][..]) {
+                return None;
+            } else {
+                segments.push(Segment::Literal(uri::percent_decode(segment)));
+            }
+        }
+        Some(Route {
+            pattern: pattern.to_string(),
+            segments,
+        })
+    }
+
+    /// # Match a request URI base against route, returns captured parameters on success
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// use milstian_http::route::Route;
+    /// let route = Route::new("/users/{id}/posts/{slug}").expect("A route");
+    /// let request = Message::from_tcp_stream(b"GET /users/12/posts/hello%20world HTTP/1.1\r\n")
+    ///     .expect("A request");
+    /// let parameters = route
+    ///     .matches(&request.request_line.request_uri_base)
+    ///     .expect("A matching route");
+    /// assert_eq!(parameters.get("id"), Some(&"12".to_string()));
+    /// assert_eq!(parameters.get("slug"), Some(&"hello world".to_string()));
+    /// assert!(route.matches("/users/12").is_none());
+    /// ```
+    pub fn matches(&self, request_uri_base: &str) -> Option<HashMap<String, String>> {
+        let mut parameters: HashMap<String, String> = HashMap::new();
+        let mut segments = self.segments.iter();
+        for path_segment in request_uri_base
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(uri::percent_decode)
+        {
+            match segments.next()? {
+                Segment::Literal(literal) => {
+                    if *literal != path_segment {
+                        return None;
+                    }
+                }
+                Segment::Parameter(name) => {
+                    parameters.insert(name.to_string(), path_segment);
+                }
+            }
+        }
+        if segments.next().is_some() {
+            return None;
+        }
+        Some(parameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let route = Route::new("/users/{id}/posts").expect("A route");
+        assert_eq!(
+            route.segments,
+            vec![
+                Segment::Literal("users".to_string()),
+                Segment::Parameter("id".to_string()),
+                Segment::Literal("posts".to_string())
+            ]
+        );
+        assert!(Route::new("/").expect("A root route").segments.is_empty());
+        assert!(Route::new("/users/{}").is_none());
+        assert!(Route::new("/users/a{id}").is_none());
+        assert!(Route::new("/users/{i{d}").is_none());
+    }
+
+    #[test]
+    fn test_matches() {
+        let route = Route::new("/users/{id}/posts/{slug}").expect("A route");
+        let parameters = route.matches("/users/5/posts/abc/").expect("A match");
+        assert_eq!(parameters.len(), 2);
+        assert_eq!(parameters.get("id"), Some(&"5".to_string()));
+        assert_eq!(parameters.get("slug"), Some(&"abc".to_string()));
+        assert!(route.matches("/users/5/comments/abc").is_none());
+        assert!(route.matches("/users/5/posts/abc/def").is_none());
+        assert!(route.matches("/users/5/posts").is_none());
+
+        let route = Route::new("/").expect("A root route");
+        assert!(route.matches("/").expect("A match").is_empty());
+        assert!(route.matches("/index.html").is_none());
+
+        let route = Route::new("/files/my%20file").expect("A route");
+        assert!(route.matches("/files/my%20file").is_some());
+    }
+
+    #[test]
+    fn test_check_method() {
+        assert_eq!(
+            check_method(&Method::Post, &[Method::Post]),
+            Dispatch::Allowed
+        );
+        assert_eq!(
+            check_method(&Method::Get, &[Method::Head]),
+            Dispatch::Rejected(response::Message::new(
+                "HTTP/1.1".to_string(),
+                "405 Method Not Allowed".to_string(),
+                [
+                    ("Allow".to_string(), "HEAD".to_string()),
+                    ("Content-Length".to_string(), "0".to_string())
+                ]
+                .iter()
+                .cloned()
+                .collect::<HashMap<String, String>>(),
+                Vec::new()
+            ))
+        );
+
+        // Unknown methods are not implemented even if listed
+        match check_method(&Method::Invalid, &[Method::Get, Method::Invalid]) {
+            Dispatch::Rejected(response) => {
+                assert_eq!(response.status, "501 Not Implemented".to_string());
+                assert!(!response.headers.contains_key("Allow"));
+            }
+            Dispatch::Allowed => panic!("Expected a rejection"),
+        }
+
+        // An empty method set allows nothing and has no Allow to send
+        for allowed in [&[][..], &[Method::Invalid][..]] {
+            match check_method(&Method::Get, allowed) {
+                Dispatch::Rejected(response) => {
+                    assert_eq!(response.status, "404 Not Found".to_string());
+                    assert!(!response.headers.contains_key("Allow"));
+                }
+                Dispatch::Allowed => panic!("Expected a rejection"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let response = Capabilities::new(&[Method::Head, Method::Invalid]).to_response();
+        assert_eq!(response.status_code(), Some(200));
+        assert_eq!(response.headers.get("Allow"), Some(&"HEAD".to_string()));
+        assert_eq!(response.headers.get("Upgrade"), None);
+        assert_eq!(response.headers.get("Connection"), None);
+        assert_eq!(
+            response.headers.get("Content-Length"),
+            Some(&"0".to_string())
+        );
+
+        let request = |bytes: &[u8]| request::Message::from_tcp_stream(bytes).expect("A request");
+        assert!(!is_server_wide(&request(b"OPTIONS / HTTP/1.1\r\n\r\n")));
+        assert!(!is_server_wide(&request(b"GET * HTTP/1.1\r\n\r\n")));
+    }
+}
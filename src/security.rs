@@ -0,0 +1,950 @@
+//! # Builds security and reporting response headers.
+//!
+//! Policies are assembled from typed parts and rendered with their separators and quoting, so a
+//! stray semicolon or unquoted keyword can't silently change what a browser enforces.
+
+use std::fmt;
+use std::time::Duration;
+
+use host::AllowList;
+use request;
+use response;
+use response::escape_json;
+use uri::Uri;
+
+/// # Algorithms of hash sources
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+/// # A source expression of a Content-Security-Policy directive
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    Hash(HashAlgorithm, String), // Base64 digest of an inline script or style
+    Host(String),                // Like https://cdn.example.com or *.example.com
+    None,                        // Matches nothing, must be the only source of a directive
+    Nonce(String),               // Base64 value that must be fresh for every response
+    Scheme(String),              // Like https or data, without colon
+    SelfOrigin,
+    StrictDynamic,
+    UnsafeEval,
+    UnsafeInline,
+}
+
+impl Source {
+    /// # Check that the source renders as a single source expression
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::security::Source;
+    /// assert!(Source::Host("https://*.example.com:443/js/".to_string()).is_valid());
+    /// assert!(!Source::Host("a; script-src *".to_string()).is_valid());
+    /// assert!(!Source::Nonce("not base64!".to_string()).is_valid());
+    /// ```
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Source::Hash(_, value) | Source::Nonce(value) => is_base64(value),
+            Source::Host(host) => {
+                !host.is_empty()
+                    && host.chars().all(|character| {
+                        character.is_ascii_graphic() && !";,'\"".contains(character)
+                    })
+            }
+            Source::Scheme(scheme) => {
+                scheme
+                    .chars()
+                    .next()
+                    .is_some_and(|character| character.is_ascii_alphabetic())
+                    && scheme.chars().all(|character| {
+                        character.is_ascii_alphanumeric() || "+-.".contains(character)
+                    })
+            }
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Hash(algorithm, digest) => {
+                let algorithm = match algorithm {
+                    HashAlgorithm::Sha256 => "sha256",
+                    HashAlgorithm::Sha384 => "sha384",
+                    HashAlgorithm::Sha512 => "sha512",
+                };
+                write!(f, "'{}-{}'", algorithm, digest)
+            }
+            Source::Host(host) => write!(f, "{}", host),
+            Source::None => write!(f, "'none'"),
+            Source::Nonce(nonce) => write!(f, "'nonce-{}'", nonce),
+            Source::Scheme(scheme) => write!(f, "{}:", scheme),
+            Source::SelfOrigin => write!(f, "'self'"),
+            Source::StrictDynamic => write!(f, "'strict-dynamic'"),
+            Source::UnsafeEval => write!(f, "'unsafe-eval'"),
+            Source::UnsafeInline => write!(f, "'unsafe-inline'"),
+        }
+    }
+}
+
+/// Whether value is non-empty base64 or base64url with optional padding
+fn is_base64(value: &str) -> bool {
+    let data = value.trim_end_matches('=');
+    !data.is_empty()
+        && value.len() - data.len() <= 2
+        && data
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "+/-_".contains(character))
+}
+
+/// # A Content-Security-Policy built from directives in the order they were added
+/// ## Usage
+/// ```rust
+/// use milstian_http::security::{ContentSecurityPolicy, Source};
+/// let policy = ContentSecurityPolicy::new()
+///     .default_src(&[Source::SelfOrigin])
+///     .script_src(&[Source::SelfOrigin, Source::Nonce("rAnd0m".to_string())])
+///     .frame_ancestors(&[Source::None])
+///     .report_uri("/csp-reports");
+/// assert!(policy.is_valid());
+/// assert_eq!(
+///     policy.to_string(),
+///     "default-src 'self'; script-src 'self' 'nonce-rAnd0m'; frame-ancestors 'none'; report-uri /csp-reports"
+///         .to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ContentSecurityPolicy {
+    directives: Vec<(String, Vec<String>)>, // Name and rendered values
+    report_only: bool,
+    valid: bool, // Whether every added source and value was valid
+}
+
+impl Default for ContentSecurityPolicy {
+    fn default() -> ContentSecurityPolicy {
+        ContentSecurityPolicy::new()
+    }
+}
+
+impl ContentSecurityPolicy {
+    /// # Create an empty policy
+    pub fn new() -> ContentSecurityPolicy {
+        ContentSecurityPolicy {
+            directives: Vec::new(),
+            report_only: false,
+            valid: true,
+        }
+    }
+
+    /// # Set a directive to sources, replacing an earlier directive with the same name
+    pub fn directive(self, name: &str, sources: &[Source]) -> ContentSecurityPolicy {
+        let is_valid = sources.iter().all(Source::is_valid)
+            && (sources.len() == 1 || !sources.contains(&Source::None));
+        self.set(
+            name,
+            sources.iter().map(|source| source.to_string()).collect(),
+            is_valid,
+        )
+    }
+
+    /// # Set the default-src directive, the fallback of the other fetch directives
+    pub fn default_src(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("default-src", sources)
+    }
+
+    /// # Set the script-src directive
+    pub fn script_src(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("script-src", sources)
+    }
+
+    /// # Set the style-src directive
+    pub fn style_src(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("style-src", sources)
+    }
+
+    /// # Set the img-src directive
+    pub fn img_src(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("img-src", sources)
+    }
+
+    /// # Set the connect-src directive
+    pub fn connect_src(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("connect-src", sources)
+    }
+
+    /// # Set the font-src directive
+    pub fn font_src(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("font-src", sources)
+    }
+
+    /// # Set the object-src directive
+    pub fn object_src(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("object-src", sources)
+    }
+
+    /// # Set the base-uri directive
+    pub fn base_uri(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("base-uri", sources)
+    }
+
+    /// # Set the form-action directive
+    pub fn form_action(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("form-action", sources)
+    }
+
+    /// # Set the frame-ancestors directive, which pages may embed this one
+    pub fn frame_ancestors(self, sources: &[Source]) -> ContentSecurityPolicy {
+        self.directive("frame-ancestors", sources)
+    }
+
+    /// # Set the report-uri directive that violations are posted to
+    pub fn report_uri(self, uri: &str) -> ContentSecurityPolicy {
+        let is_valid = Source::Host(uri.to_string()).is_valid();
+        self.set("report-uri", vec![uri.to_string()], is_valid)
+    }
+
+    /// # Add the upgrade-insecure-requests directive
+    pub fn upgrade_insecure_requests(self) -> ContentSecurityPolicy {
+        self.set("upgrade-insecure-requests", Vec::new(), true)
+    }
+
+    /// # Only report violations by sending the policy as Content-Security-Policy-Report-Only
+    pub fn report_only(mut self) -> ContentSecurityPolicy {
+        self.report_only = true;
+        self
+    }
+
+    /// # Get the name of the header the policy is sent with
+    pub fn header_name(&self) -> &'static str {
+        if self.report_only {
+            "Content-Security-Policy-Report-Only"
+        } else {
+            "Content-Security-Policy"
+        }
+    }
+
+    /// # Check that the policy has directives and that all of them are valid
+    pub fn is_valid(&self) -> bool {
+        self.valid && !self.directives.is_empty()
+    }
+
+    fn set(mut self, name: &str, values: Vec<String>, is_valid: bool) -> ContentSecurityPolicy {
+        let is_valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|character| character.is_ascii_lowercase() || character == '-');
+        self.valid = self.valid && is_valid && is_valid_name;
+        match self
+            .directives
+            .iter_mut()
+            .find(|(directive, _)| directive == name)
+        {
+            Some(directive) => directive.1 = values,
+            None => self.directives.push((name.to_string(), values)),
+        }
+        self
+    }
+}
+
+impl fmt::Display for ContentSecurityPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let directives: Vec<String> = self
+            .directives
+            .iter()
+            .map(|(name, values)| {
+                let mut directive = name.clone();
+                for value in values {
+                    directive.push(' ');
+                    directive.push_str(value);
+                }
+                directive
+            })
+            .collect();
+        write!(f, "{}", directives.join("; "))
+    }
+}
+
+/// # Values of the Referrer-Policy header
+/// ## Usage
+/// ```rust
+/// use milstian_http::security::ReferrerPolicy;
+/// assert_eq!(
+///     ReferrerPolicy::StrictOriginWhenCrossOrigin.to_string(),
+///     "strict-origin-when-cross-origin".to_string()
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReferrerPolicy {
+    NoReferrer,
+    NoReferrerWhenDowngrade,
+    Origin,
+    OriginWhenCrossOrigin,
+    SameOrigin,
+    StrictOrigin,
+    StrictOriginWhenCrossOrigin, // The default of browsers
+    UnsafeUrl,
+}
+
+impl fmt::Display for ReferrerPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let token = match self {
+            ReferrerPolicy::NoReferrer => "no-referrer",
+            ReferrerPolicy::NoReferrerWhenDowngrade => "no-referrer-when-downgrade",
+            ReferrerPolicy::Origin => "origin",
+            ReferrerPolicy::OriginWhenCrossOrigin => "origin-when-cross-origin",
+            ReferrerPolicy::SameOrigin => "same-origin",
+            ReferrerPolicy::StrictOrigin => "strict-origin",
+            ReferrerPolicy::StrictOriginWhenCrossOrigin => "strict-origin-when-cross-origin",
+            ReferrerPolicy::UnsafeUrl => "unsafe-url",
+        };
+        write!(f, "{}", token)
+    }
+}
+
+/// # A member of a Permissions-Policy allowlist
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Allow {
+    All,            // Every origin, rendered as * on its own
+    Origin(String), // A serialized origin like https://example.com
+    SelfOrigin,
+}
+
+impl Allow {
+    /// # Check that the member is an allowed origin without path, query or quotes
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::security::Allow;
+    /// assert!(Allow::Origin("https://example.com:8443".to_string()).is_valid());
+    /// assert!(!Allow::Origin("https://example.com/path".to_string()).is_valid());
+    /// assert!(!Allow::Origin("example.com".to_string()).is_valid());
+    /// ```
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Allow::Origin(origin) => match origin.split_once("://") {
+                Some((scheme, host)) => {
+                    Source::Scheme(scheme.to_string()).is_valid()
+                        && !host.is_empty()
+                        && host.chars().all(|character| {
+                            character.is_ascii_graphic() && !"\"\\/?#@".contains(character)
+                        })
+                }
+                None => false,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// # A Permissions-Policy of features and their allowlists in the order they were added
+/// Allowlists are serialized as structured field inner lists, an empty allowlist disables a
+/// feature everywhere.
+/// ## Usage
+/// ```rust
+/// use milstian_http::security::{Allow, PermissionsPolicy};
+/// let policy = PermissionsPolicy::new()
+///     .feature("camera", &[])
+///     .feature(
+///         "geolocation",
+///         &[Allow::SelfOrigin, Allow::Origin("https://maps.example".to_string())],
+///     )
+///     .feature("fullscreen", &[Allow::All]);
+/// assert!(policy.is_valid());
+/// assert_eq!(
+///     policy.to_string(),
+///     "camera=(), geolocation=(self \"https://maps.example\"), fullscreen=*".to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PermissionsPolicy {
+    features: Vec<(String, Vec<Allow>)>,
+}
+
+impl PermissionsPolicy {
+    /// # Create an empty policy
+    pub fn new() -> PermissionsPolicy {
+        PermissionsPolicy::default()
+    }
+
+    /// # Set the allowlist of a feature, replacing an earlier allowlist of the same feature
+    pub fn feature(mut self, name: &str, allowlist: &[Allow]) -> PermissionsPolicy {
+        match self
+            .features
+            .iter_mut()
+            .find(|(feature, _)| feature == name)
+        {
+            Some(feature) => feature.1 = allowlist.to_vec(),
+            None => self.features.push((name.to_string(), allowlist.to_vec())),
+        }
+        self
+    }
+
+    /// # Disable a feature for every origin
+    pub fn deny(self, name: &str) -> PermissionsPolicy {
+        self.feature(name, &[])
+    }
+
+    /// # Check that the policy has features with structured field keys and valid allowlists
+    pub fn is_valid(&self) -> bool {
+        !self.features.is_empty()
+            && self
+                .features
+                .iter()
+                .all(|(name, allowlist)| is_key(name) && allowlist.iter().all(Allow::is_valid))
+    }
+}
+
+impl fmt::Display for PermissionsPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let features: Vec<String> = self
+            .features
+            .iter()
+            .map(|(name, allowlist)| {
+                if allowlist.contains(&Allow::All) {
+                    return format!("{}=*", name);
+                }
+                let members: Vec<String> = allowlist
+                    .iter()
+                    .map(|member| match member {
+                        Allow::Origin(origin) => format!("\"{}\"", origin),
+                        _ => String::from("self"),
+                    })
+                    .collect();
+                format!("{}=({})", name, members.join(" "))
+            })
+            .collect();
+        write!(f, "{}", features.join(", "))
+    }
+}
+
+/// # Directives of a Clear-Site-Data header, like for logout responses
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClearSiteData {
+    All, // Every type of data, rendered as "*"
+    Cache,
+    ClientHints,
+    Cookies,
+    ExecutionContexts, // Reloads the browsing contexts of the origin
+    Storage,
+}
+
+impl fmt::Display for ClearSiteData {
+    /// # Render the directive as a quoted string
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let directive = match self {
+            ClearSiteData::All => "*",
+            ClearSiteData::Cache => "cache",
+            ClearSiteData::ClientHints => "clientHints",
+            ClearSiteData::Cookies => "cookies",
+            ClearSiteData::ExecutionContexts => "executionContexts",
+            ClearSiteData::Storage => "storage",
+        };
+        write!(f, "\"{}\"", directive)
+    }
+}
+
+/// # An Expect-CT header asking browsers to check Certificate Transparency
+/// ## Usage
+/// ```rust
+/// use milstian_http::security::ExpectCt;
+/// use std::time::Duration;
+/// let expect_ct = ExpectCt::new(Duration::from_secs(86400))
+///     .enforce()
+///     .report_uri("https://example.com/ct-reports");
+/// assert!(expect_ct.is_valid());
+/// assert_eq!(
+///     expect_ct.to_string(),
+///     "max-age=86400, enforce, report-uri=\"https://example.com/ct-reports\"".to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExpectCt {
+    pub enforce: bool, // Refuse connections that violate the policy instead of only reporting
+    pub max_age: Duration, // Sent in whole seconds
+    pub report_uri: Option<String>,
+}
+
+impl ExpectCt {
+    /// # Create a report-only policy remembered for max_age
+    pub fn new(max_age: Duration) -> ExpectCt {
+        ExpectCt {
+            enforce: false,
+            max_age,
+            report_uri: None,
+        }
+    }
+
+    /// # Enforce the policy
+    pub fn enforce(mut self) -> ExpectCt {
+        self.enforce = true;
+        self
+    }
+
+    /// # Send violation reports to uri
+    pub fn report_uri(mut self, uri: &str) -> ExpectCt {
+        self.report_uri = Some(uri.to_string());
+        self
+    }
+
+    /// # Check that the report URI can be quoted
+    pub fn is_valid(&self) -> bool {
+        self.report_uri.as_ref().is_none_or(|uri| is_url(uri))
+    }
+}
+
+impl fmt::Display for ExpectCt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "max-age={}", self.max_age.as_secs())?;
+        if self.enforce {
+            write!(f, ", enforce")?;
+        }
+        if let Some(ref uri) = self.report_uri {
+            write!(f, ", report-uri=\"{}\"", uri)?;
+        }
+        Ok(())
+    }
+}
+
+/// # Named report endpoints of a Reporting-Endpoints header in the order they were added
+/// Policies refer to the endpoints by name, like the report-to directive of a
+/// Content-Security-Policy.
+/// ## Usage
+/// ```rust
+/// use milstian_http::security::ReportingEndpoints;
+/// let endpoints = ReportingEndpoints::new()
+///     .endpoint("default", "https://example.com/reports")
+///     .endpoint("csp-endpoint", "https://example.com/csp-reports");
+/// assert!(endpoints.is_valid());
+/// assert_eq!(
+///     endpoints.to_string(),
+///     "default=\"https://example.com/reports\", csp-endpoint=\"https://example.com/csp-reports\""
+///         .to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReportingEndpoints {
+    pub endpoints: Vec<(String, String)>, // Names and URLs
+}
+
+impl ReportingEndpoints {
+    /// # Create a header without endpoints
+    pub fn new() -> ReportingEndpoints {
+        ReportingEndpoints::default()
+    }
+
+    /// # Add or replace the endpoint called name
+    pub fn endpoint(mut self, name: &str, url: &str) -> ReportingEndpoints {
+        self.endpoints.retain(|(existing, _)| existing != name);
+        self.endpoints.push((name.to_string(), url.to_string()));
+        self
+    }
+
+    /// # Check that there are endpoints with structured field keys and quotable URLs
+    pub fn is_valid(&self) -> bool {
+        !self.endpoints.is_empty()
+            && self
+                .endpoints
+                .iter()
+                .all(|(name, url)| is_key(name) && is_url(url))
+    }
+}
+
+impl fmt::Display for ReportingEndpoints {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let endpoints: Vec<String> = self
+            .endpoints
+            .iter()
+            .map(|(name, url)| format!("{}=\"{}\"", name, url))
+            .collect();
+        write!(f, "{}", endpoints.join(", "))
+    }
+}
+
+/// # An endpoint group of the legacy Report-To header, rendered as a JSON object
+/// ## Usage
+/// ```rust
+/// use milstian_http::security::ReportTo;
+/// use std::time::Duration;
+/// let group = ReportTo::new("default", Duration::from_secs(10886400))
+///     .endpoint("https://example.com/reports")
+///     .include_subdomains();
+/// assert!(group.is_valid());
+/// assert_eq!(
+///     group.to_string(),
+///     "{\"group\":\"default\",\"max_age\":10886400,\"endpoints\":[{\"url\":\"https://example.com/reports\"}],\"include_subdomains\":true}"
+///         .to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReportTo {
+    pub endpoints: Vec<String>, // URLs tried in order
+    pub group: String,
+    pub include_subdomains: bool,
+    pub max_age: Duration, // Sent in whole seconds
+}
+
+impl ReportTo {
+    /// # Create a group without endpoints remembered for max_age
+    pub fn new(group: &str, max_age: Duration) -> ReportTo {
+        ReportTo {
+            endpoints: Vec::new(),
+            group: group.to_string(),
+            include_subdomains: false,
+            max_age,
+        }
+    }
+
+    /// # Append an endpoint URL
+    pub fn endpoint(mut self, url: &str) -> ReportTo {
+        self.endpoints.push(url.to_string());
+        self
+    }
+
+    /// # Apply the group to subdomains of the origin
+    pub fn include_subdomains(mut self) -> ReportTo {
+        self.include_subdomains = true;
+        self
+    }
+
+    /// # Check that the group is named and has endpoints
+    pub fn is_valid(&self) -> bool {
+        !self.group.is_empty()
+            && !self.endpoints.is_empty()
+            && self.endpoints.iter().all(|url| is_url(url))
+    }
+}
+
+impl fmt::Display for ReportTo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let endpoints: Vec<String> = self
+            .endpoints
+            .iter()
+            .map(|url| format!("{{\"url\":\"{}\"}}", escape_json(url)))
+            .collect();
+        write!(
+            f,
+            "{{\"group\":\"{}\",\"max_age\":{},\"endpoints\":[{}]",
+            escape_json(&self.group),
+            self.max_age.as_secs(),
+            endpoints.join(",")
+        )?;
+        if self.include_subdomains {
+            write!(f, ",\"include_subdomains\":true")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// # A Network Error Logging policy of an NEL header, rendered as a JSON object
+/// Reports go to the Report-To group named by report_to. Sampling fractions are between 0 and 1,
+/// browsers default to reporting all failures and no successes.
+/// ## Usage
+/// ```rust
+/// use milstian_http::security::NetworkErrorLogging;
+/// use std::time::Duration;
+/// let mut policy = NetworkErrorLogging::new("default", Duration::from_secs(2592000));
+/// policy.success_fraction = Some(0.25);
+/// assert!(policy.is_valid());
+/// assert_eq!(
+///     policy.to_string(),
+///     "{\"report_to\":\"default\",\"max_age\":2592000,\"success_fraction\":0.25}".to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkErrorLogging {
+    pub failure_fraction: Option<f64>,
+    pub include_subdomains: bool,
+    pub max_age: Duration, // Sent in whole seconds, zero removes the policy
+    pub report_to: String,
+    pub success_fraction: Option<f64>,
+}
+
+impl NetworkErrorLogging {
+    /// # Create a policy reporting to group for max_age
+    pub fn new(report_to: &str, max_age: Duration) -> NetworkErrorLogging {
+        NetworkErrorLogging {
+            failure_fraction: None,
+            include_subdomains: false,
+            max_age,
+            report_to: report_to.to_string(),
+            success_fraction: None,
+        }
+    }
+
+    /// # Check that the group is named and the fractions are between 0 and 1
+    pub fn is_valid(&self) -> bool {
+        let is_fraction = |fraction: &Option<f64>| {
+            fraction.is_none_or(|fraction| (0.0..=1.0).contains(&fraction))
+        };
+        !self.report_to.is_empty()
+            && is_fraction(&self.success_fraction)
+            && is_fraction(&self.failure_fraction)
+    }
+}
+
+impl fmt::Display for NetworkErrorLogging {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{{\"report_to\":\"{}\",\"max_age\":{}",
+            escape_json(&self.report_to),
+            self.max_age.as_secs()
+        )?;
+        if self.include_subdomains {
+            write!(f, ",\"include_subdomains\":true")?;
+        }
+        if let Some(fraction) = self.success_fraction {
+            write!(f, ",\"success_fraction\":{}", fraction)?;
+        }
+        if let Some(fraction) = self.failure_fraction {
+            write!(f, ",\"failure_fraction\":{}", fraction)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// # Redirect a plain HTTP request that sent Upgrade-Insecure-Requests: 1 to HTTPS
+/// The redirect is a 307 to the same path and query on the default HTTPS port, so the method
+/// and body are kept. Hosts must be in allow_list to keep the redirect from pointing elsewhere.
+/// Responses to requests that are not redirected should still carry
+/// Vary: Upgrade-Insecure-Requests when they may be.
+/// ## Usage
+/// ```rust
+/// use milstian_http::host::AllowList;
+/// use milstian_http::request::Message;
+/// use milstian_http::security::upgrade_insecure_redirect;
+/// let allow_list = AllowList::new(&["example.com"]);
+/// let request = Message::from_tcp_stream(
+///     b"GET /a?b=c HTTP/1.1\r\nHost: example.com:80\r\nUpgrade-Insecure-Requests: 1\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let response = upgrade_insecure_redirect(&request, false, &allow_list).expect("A redirect");
+/// assert_eq!(
+///     response.to_bytes(),
+///     b"HTTP/1.1 307 Temporary Redirect\r\nLocation: https://example.com/a?b=c\r\nVary: Upgrade-Insecure-Requests\r\nContent-Length: 0\r\n\r\n"
+///         .to_vec()
+/// );
+/// assert!(upgrade_insecure_redirect(&request, true, &allow_list).is_none());
+/// ```
+pub fn upgrade_insecure_redirect(
+    request: &request::Message,
+    is_secure: bool,
+    allow_list: &AllowList,
+) -> Option<response::Message> {
+    let is_requested = request
+        .header("Upgrade-Insecure-Requests")
+        .is_some_and(|value| value.to_string().trim() == "1");
+    if is_secure || !is_requested {
+        return None;
+    }
+    let host = allow_list.validate(request).ok()?;
+    let request_uri = &request.request_line.request_uri;
+    let target = if request_uri.starts_with('/') {
+        request_uri.to_string()
+    } else {
+        let uri = Uri::parse(request_uri).ok()?;
+        uri.authority.as_ref()?;
+        let path = if uri.path.is_empty() { "/" } else { &uri.path };
+        match uri.query {
+            Some(ref query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        }
+    };
+
+    let mut message = response::Message::with_status(307);
+    message.headers.insert(
+        "Location".to_string(),
+        format!("https://{}{}", host, target),
+    );
+    message
+        .headers
+        .insert("Vary".to_string(), "Upgrade-Insecure-Requests".to_string());
+    message.set_content_length(0);
+    Some(message)
+}
+
+/// Whether name is a structured field key, like the feature names of a Permissions-Policy
+fn is_key(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|character| character.is_ascii_lowercase() || character == '*')
+        && name.chars().all(|character| {
+            character.is_ascii_lowercase()
+                || character.is_ascii_digit()
+                || "_-.*".contains(character)
+        })
+}
+
+/// Whether url can be sent in a quoted string without escapes
+fn is_url(url: &str) -> bool {
+    !url.is_empty()
+        && url
+            .chars()
+            .all(|character| character.is_ascii_graphic() && character != '"' && character != '\\')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_security_policy() {
+        let policy = ContentSecurityPolicy::new()
+            .default_src(&[Source::None])
+            .script_src(&[
+                Source::StrictDynamic,
+                Source::Hash(
+                    HashAlgorithm::Sha256,
+                    "B2yPHKaXnvFWtRChIbabYmUBFZdVfKKXHbWtWidDVF8=".to_string(),
+                ),
+            ])
+            .img_src(&[Source::SelfOrigin, Source::Scheme("data".to_string())])
+            .default_src(&[Source::SelfOrigin])
+            .upgrade_insecure_requests();
+        assert!(policy.is_valid());
+        assert_eq!(
+            policy.to_string(),
+            "default-src 'self'; script-src 'strict-dynamic' 'sha256-B2yPHKaXnvFWtRChIbabYmUBFZdVfKKXHbWtWidDVF8='; img-src 'self' data:; upgrade-insecure-requests"
+                .to_string()
+        );
+        assert_eq!(policy.header_name(), "Content-Security-Policy");
+        assert_eq!(
+            policy.report_only().header_name(),
+            "Content-Security-Policy-Report-Only"
+        );
+
+        // Invalid policies
+        assert!(!ContentSecurityPolicy::new().is_valid());
+        assert!(ContentSecurityPolicy::default()
+            .object_src(&[Source::None])
+            .is_valid());
+        assert!(!ContentSecurityPolicy::new()
+            .default_src(&[Source::None, Source::SelfOrigin])
+            .is_valid());
+        assert!(!ContentSecurityPolicy::new()
+            .script_src(&[Source::Scheme("1http".to_string())])
+            .is_valid());
+        assert!(!ContentSecurityPolicy::new()
+            .directive("Script Src", &[Source::SelfOrigin])
+            .is_valid());
+        assert!(!ContentSecurityPolicy::new().report_uri("/a;b").is_valid());
+        assert!(!Source::Nonce("abc===".to_string()).is_valid());
+        assert!(Source::Nonce("abc-_==".to_string()).is_valid());
+    }
+
+    #[test]
+    fn test_permissions_policy() {
+        let policy = PermissionsPolicy::new()
+            .feature("camera", &[Allow::All])
+            .deny("microphone")
+            .feature("camera", &[Allow::SelfOrigin, Allow::All]);
+        assert!(policy.is_valid());
+        assert_eq!(policy.to_string(), "camera=*, microphone=()".to_string());
+
+        assert!(!PermissionsPolicy::new().is_valid());
+        assert!(!PermissionsPolicy::new().deny("Camera").is_valid());
+        assert!(!PermissionsPolicy::new().deny("").is_valid());
+        assert!(!PermissionsPolicy::new()
+            .feature("camera", &[Allow::Origin("https://a\"b".to_string())])
+            .is_valid());
+        assert!(PermissionsPolicy::new()
+            .feature("publickey-credentials-get", &[Allow::SelfOrigin])
+            .is_valid());
+    }
+
+    #[test]
+    fn test_clear_site_data() {
+        let directives: Vec<String> = [
+            ClearSiteData::All,
+            ClearSiteData::Cache,
+            ClearSiteData::ClientHints,
+            ClearSiteData::Cookies,
+            ClearSiteData::ExecutionContexts,
+            ClearSiteData::Storage,
+        ]
+        .iter()
+        .map(|directive| directive.to_string())
+        .collect();
+        assert_eq!(
+            directives.join(", "),
+            "\"*\", \"cache\", \"clientHints\", \"cookies\", \"executionContexts\", \"storage\""
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_reporting() {
+        let expect_ct = ExpectCt::new(Duration::from_millis(1500));
+        assert!(expect_ct.is_valid());
+        assert_eq!(expect_ct.to_string(), "max-age=1".to_string());
+        assert!(!expect_ct.report_uri("https://example.com/\"").is_valid());
+
+        assert!(!ReportingEndpoints::new().is_valid());
+        let endpoints = ReportingEndpoints::new()
+            .endpoint("main", "https://a.example/")
+            .endpoint("main", "https://b.example/");
+        assert_eq!(
+            endpoints.to_string(),
+            "main=\"https://b.example/\"".to_string()
+        );
+        assert!(endpoints.is_valid());
+        assert!(!endpoints.endpoint("Main", "https://a.example/").is_valid());
+
+        let group = ReportTo::new("a \"quoted\" group", Duration::from_secs(60));
+        assert!(!group.is_valid());
+        let group = group
+            .endpoint("https://a.example/")
+            .endpoint("https://b.example/");
+        assert!(group.is_valid());
+        assert_eq!(
+            group.to_string(),
+            "{\"group\":\"a \\\"quoted\\\" group\",\"max_age\":60,\"endpoints\":[{\"url\":\"https://a.example/\"},{\"url\":\"https://b.example/\"}]}"
+                .to_string()
+        );
+
+        let mut policy = NetworkErrorLogging::new("default", Duration::from_secs(0));
+        policy.include_subdomains = true;
+        policy.failure_fraction = Some(1.0);
+        assert_eq!(
+            policy.to_string(),
+            "{\"report_to\":\"default\",\"max_age\":0,\"include_subdomains\":true,\"failure_fraction\":1}"
+                .to_string()
+        );
+        policy.success_fraction = Some(1.5);
+        assert!(!policy.is_valid());
+        assert!(!NetworkErrorLogging::new("", Duration::from_secs(1)).is_valid());
+    }
+
+    #[test]
+    fn test_upgrade_insecure_redirect() {
+        let allow_list = AllowList::new(&["example.com"]);
+        let redirect = |bytes: &[u8]| {
+            let request = request::Message::from_tcp_stream(bytes).expect("A request");
+            upgrade_insecure_redirect(&request, false, &allow_list)
+                .and_then(|response| response.headers.get("Location").cloned())
+        };
+        assert_eq!(
+            redirect(b"POST http://Example.com:8080/form HTTP/1.1\r\nHost: example.com\r\nUpgrade-Insecure-Requests: 1\r\n\r\n"),
+            Some("https://example.com/form".to_string())
+        );
+        assert_eq!(
+            redirect(
+                b"GET / HTTP/1.1\r\nHost: evil.example\r\nUpgrade-Insecure-Requests: 1\r\n\r\n"
+            ),
+            None
+        );
+        assert_eq!(
+            redirect(
+                b"GET / HTTP/1.1\r\nHost: example.com\r\nUpgrade-Insecure-Requests: 0\r\n\r\n"
+            ),
+            None
+        );
+        assert_eq!(
+            redirect(
+                b"OPTIONS * HTTP/1.1\r\nHost: example.com\r\nUpgrade-Insecure-Requests: 1\r\n\r\n"
+            ),
+            None
+        );
+    }
+}
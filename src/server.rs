@@ -0,0 +1,312 @@
+//! # A small blocking server built on the streaming parser.
+//!
+//! Every connection is handled on its own thread, requests are passed to a handler and persistent
+//! connections are kept open until the client or the response asks for them to be closed.
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+
+use request;
+use request::{Method, ParserConfig, Protocol};
+use response;
+use stream::{Parser, Status};
+
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// # Bind to address and serve requests with handler forever
+/// ## Usage
+/// ```rust,no_run
+/// use milstian_http::response::Message;
+/// use milstian_http::server;
+/// use std::collections::HashMap;
+/// server::serve("127.0.0.1:8888", |request| {
+///     Message::new(
+///         "HTTP/1.1".to_string(),
+///         "200 OK".to_string(),
+///         HashMap::new(),
+///         request.request_line.request_uri.into_bytes(),
+///     )
+/// })
+/// .expect("A running server");
+/// ```
+pub fn serve<A, H>(address: A, handler: H) -> io::Result<()>
+where
+    A: ToSocketAddrs,
+    H: Fn(request::Message) -> response::Message + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(address)?;
+    serve_listener(listener, ParserConfig::default(), handler)
+}
+
+/// # Serve requests from an already bound listener with handler forever
+pub fn serve_listener<H>(listener: TcpListener, config: ParserConfig, handler: H) -> io::Result<()>
+where
+    H: Fn(request::Message) -> response::Message + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let config = config.clone();
+        let handler = Arc::clone(&handler);
+        thread::spawn(move || {
+            // Failed connections only concern the client, the server keeps accepting
+            if let Err(_error) = handle_connection(&mut stream, &config, &*handler) {
+                trace_event!(warn, error = %_error, "Connection failed");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// # Read requests from stream and write the responses of handler until the connection closes
+/// ## Usage
+/// ```rust,no_run
+/// use milstian_http::request::ParserConfig;
+/// use milstian_http::response::Message;
+/// use milstian_http::server;
+/// use std::net::TcpListener;
+/// let listener = TcpListener::bind("127.0.0.1:0").expect("A listener");
+/// let (mut stream, _) = listener.accept().expect("A connection");
+/// server::handle_connection(&mut stream, &ParserConfig::default(), &|_| Message::default())
+///     .expect("A handled connection");
+/// ```
+pub fn handle_connection<S, H>(stream: &mut S, config: &ParserConfig, handler: &H) -> io::Result<()>
+where
+    S: Read + Write,
+    H: Fn(request::Message) -> response::Message + ?Sized,
+{
+    let mut parser = Parser::with_config(config.clone());
+    let mut chunk = [0; READ_BUFFER_SIZE];
+    loop {
+        match parser.parse() {
+            Status::Complete(request, _) => {
+                let mut keep_alive = request.is_keep_alive();
+                let protocol = request.request_line.protocol;
+                let is_head = request.request_line.method == Method::Head;
+                let mut response = handler(*request);
+                // Informational and 204 responses never have a body to frame
+                let status_code = response.status_code().unwrap_or(200);
+                if status_code >= 200
+                    && status_code != 204
+                    && response.header("Content-Length").is_none()
+                    && response.header("Transfer-Encoding").is_none()
+                {
+                    let length = response.body.len();
+                    response.set_content_length(length);
+                }
+                response.downgrade(&protocol, keep_alive);
+                // Responses to HEAD are framed like those to GET but never carry the body
+                if is_head {
+                    response.body.clear();
+                }
+                if response
+                    .header("Connection")
+                    .map(|value| value.eq_ignore_ascii_case("close"))
+                    .unwrap_or(false)
+                {
+                    keep_alive = false;
+                } else if !keep_alive {
+                    response
+                        .headers
+                        .insert("Connection".to_string(), "close".to_string());
+                }
+                stream.write_all(&response.to_bytes_with_case(&config.header_name_case))?;
+                stream.flush()?;
+                // After 101 the connection speaks another protocol
+                if !keep_alive || status_code == 101 {
+                    return Ok(());
+                }
+            }
+            Status::Incomplete(_) => {
+                let read = stream.read(&mut chunk)?;
+                if read == 0 {
+                    return Ok(());
+                }
+                parser.push(&chunk[..read]);
+            }
+            Status::Invalid(error) => {
+                stream.write_all(
+                    &response::Message::from_parse_error(&error, &Protocol::V1_1)
+                        .to_bytes_with_case(&config.header_name_case),
+                )?;
+                stream.flush()?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use HeaderNameCase;
+
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn echo(request: request::Message) -> response::Message {
+        response::Message::new(
+            "HTTP/1.1".to_string(),
+            "200 OK".to_string(),
+            HashMap::new(),
+            request.request_line.request_uri.into_bytes(),
+        )
+    }
+
+    fn handle(input: &[u8]) -> String {
+        let mut stream = MockStream {
+            input: Cursor::new(input.to_vec()),
+            output: Vec::new(),
+        };
+        handle_connection(&mut stream, &ParserConfig::default(), &echo)
+            .expect("A handled connection");
+        String::from_utf8(stream.output).expect("A UTF-8 response")
+    }
+
+    #[test]
+    fn test_handle_connection_keep_alive() {
+        assert_eq!(
+            handle(b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n/aHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n/b"
+                .to_string()
+        );
+        assert_eq!(
+            handle(b"GET /a HTTP/1.1\r\nConnection: close\r\n\r\nGET /b HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n/a".to_string()
+        );
+        assert_eq!(
+            handle(b"GET /a HTTP/1.0\r\n\r\nGET /b HTTP/1.0\r\n\r\n"),
+            "HTTP/1.0 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n/a".to_string()
+        );
+        assert_eq!(
+            handle(b"GET /a HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /b HTTP/1.0\r\n\r\n"),
+            "HTTP/1.0 200 OK\r\nContent-Length: 2\r\nConnection: keep-alive\r\n\r\n/aHTTP/1.0 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n/b".to_string()
+        );
+    }
+
+    #[test]
+    fn test_handle_connection_framing() {
+        let respond = |request: request::Message| {
+            let mut headers = HashMap::new();
+            match request.request_line.request_uri.as_ref() {
+                "/chunked" => {
+                    headers.insert("transfer-encoding".to_string(), "chunked".to_string());
+                    response::Message::new(
+                        "HTTP/1.1".to_string(),
+                        "200 OK".to_string(),
+                        headers,
+                        b"1\r\na\r\n0\r\n\r\n".to_vec(),
+                    )
+                }
+                "/close" => {
+                    headers.insert("connection".to_string(), "close".to_string());
+                    response::Message::new(
+                        "HTTP/1.1".to_string(),
+                        "200 OK".to_string(),
+                        headers,
+                        b"c".to_vec(),
+                    )
+                }
+                _ => echo(request),
+            }
+        };
+        let handle = |input: &[u8]| {
+            let mut stream = MockStream {
+                input: Cursor::new(input.to_vec()),
+                output: Vec::new(),
+            };
+            handle_connection(&mut stream, &ParserConfig::default(), &respond)
+                .expect("A handled connection");
+            String::from_utf8(stream.output).expect("A UTF-8 response")
+        };
+
+        // Framing fields set by the handler are found in any case
+        assert_eq!(
+            handle(b"GET /chunked HTTP/1.1\r\nConnection: close\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n1\r\na\r\n0\r\n\r\n"
+                .to_string()
+        );
+        assert_eq!(
+            handle(b"GET /close HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 1\r\n\r\nc".to_string()
+        );
+
+        // Responses to HEAD keep their length but not their body
+        assert_eq!(
+            handle(b"HEAD /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n/b"
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_handle_connection_header_name_case() {
+        let mut stream = MockStream {
+            input: Cursor::new(b"GET /a HTTP/1.1\r\nconnection: CLOSE\r\n\r\n".to_vec()),
+            output: Vec::new(),
+        };
+        let config = ParserConfig {
+            header_name_case: HeaderNameCase::Lowercase,
+            ..ParserConfig::default()
+        };
+        handle_connection(&mut stream, &config, &echo).expect("A handled connection");
+        assert_eq!(
+            String::from_utf8(stream.output).expect("A UTF-8 response"),
+            "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n/a".to_string()
+        );
+    }
+
+    #[test]
+    fn test_handle_connection_upgrade() {
+        let mut stream = MockStream {
+            input: Cursor::new(
+                b"GET / HTTP/1.1\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\nGET /b HTTP/1.1\r\n\r\n"
+                    .to_vec(),
+            ),
+            output: Vec::new(),
+        };
+        handle_connection(&mut stream, &ParserConfig::default(), &|_| {
+            response::Message::switching_protocols("h2c", &[]).expect("A 101 response")
+        })
+        .expect("A handled connection");
+        assert_eq!(
+            String::from_utf8(stream.output).expect("A UTF-8 response"),
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: h2c\r\nConnection: Upgrade\r\n\r\n"
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_handle_connection_errors() {
+        assert_eq!(
+            handle(b"FOO / HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain; charset=utf-8\r\nConnection: close\r\nContent-Length: 21\r\n\r\ninvalid request line\n"
+                .to_string()
+        );
+
+        // Connections closed mid-request are dropped without a response
+        assert_eq!(handle(b"GET /a HTTP/1.1\r\n"), String::new());
+    }
+}
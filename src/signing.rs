@@ -0,0 +1,212 @@
+//! # Canonicalizes requests for signature schemes.
+//!
+//! Schemes like AWS Signature Version 4 sign a canonical form of the request so that signer and
+//! verifier agree on the bytes regardless of header order, whitespace or percent-encoding.
+
+use std::fmt;
+use std::io::Read;
+
+use crypto::sha256;
+use request;
+use uri;
+
+/// # Payload hash for requests whose body is not signed
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// # A canonical request over a method, path, query, signed headers and payload hash
+/// The path and query are percent-decoded and re-encoded so only unreserved characters are
+/// left as is, query parameters are sorted by name and value, header names are lowercase and
+/// values are trimmed with inner whitespace collapsed.
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::Message;
+/// use milstian_http::signing::CanonicalRequest;
+/// let request = Message::from_tcp_stream(
+///     b"GET /a%20b/c?z=1&a=x%2By HTTP/1.1\r\nHost: example.com\r\nX-Date:  20150830T123600Z\r\n\r\n",
+/// )
+/// .expect("A request");
+/// let canonical = CanonicalRequest::new(&request, &["X-Date", "Host"]);
+/// assert_eq!(
+///     canonical.to_string(),
+///     "GET\n/a%20b/c\na=x%2By&z=1\nhost:example.com\nx-date:20150830T123600Z\n\nhost;x-date\ne3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+///         .to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CanonicalRequest {
+    pub headers: Vec<(String, String)>, // Lowercase names and canonical values sorted by name
+    pub method: String,
+    pub path: String,
+    pub payload_hash: String, // Lowercase hex SHA-256 of the body or UNSIGNED_PAYLOAD
+    pub query: String,
+}
+
+impl CanonicalRequest {
+    /// # Canonicalize request, signing the named headers that it has
+    pub fn new(request: &request::Message, signed_headers: &[&str]) -> CanonicalRequest {
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for name in signed_headers.iter() {
+            let name = name.to_lowercase();
+            if headers.iter().any(|(existing, _)| *existing == name) {
+                continue;
+            }
+            if let Some(value) = request.header(&name) {
+                let value = value.to_string();
+                let value: Vec<&str> = value.split_whitespace().collect();
+                headers.push((name, value.join(" ")));
+            }
+        }
+        headers.sort();
+
+        let path: Vec<String> = request
+            .request_line
+            .request_uri_base
+            .split('/')
+            .map(|segment| uri_encode(&uri::percent_decode(segment), true))
+            .collect();
+        let path = path.join("/");
+
+        let mut query: Vec<(String, String)> = request
+            .request_line
+            .query_string
+            .split('&')
+            .filter(|parameter| !parameter.is_empty())
+            .map(|parameter| {
+                let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+                (
+                    uri_encode(&uri::percent_decode(name), true),
+                    uri_encode(&uri::percent_decode(value), true),
+                )
+            })
+            .collect();
+        query.sort();
+        let query: Vec<String> = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+
+        // The body is hashed as received, an unreadable spooled file ends it like in to_bytes
+        let mut body: Vec<u8> = Vec::new();
+        let _ = request.body_reader().read_to_end(&mut body);
+
+        CanonicalRequest {
+            headers,
+            method: request::Message::get_method_text(&request.request_line.method),
+            path: if path.is_empty() {
+                String::from("/")
+            } else {
+                path
+            },
+            payload_hash: to_hex(&sha256(&body)),
+            query: query.join("&"),
+        }
+    }
+
+    /// # Leave the body out of the signature
+    pub fn unsigned_payload(mut self) -> CanonicalRequest {
+        self.payload_hash = UNSIGNED_PAYLOAD.to_string();
+        self
+    }
+
+    /// # Get the signed header names joined by semicolons
+    pub fn signed_headers(&self) -> String {
+        let names: Vec<&str> = self.headers.iter().map(|(name, _)| name.as_str()).collect();
+        names.join(";")
+    }
+
+    /// # Get the lowercase hex SHA-256 of the canonical request, as used in a string to sign
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::Message;
+    /// use milstian_http::signing::CanonicalRequest;
+    /// let request = Message::from_tcp_stream(
+    ///     b"GET / HTTP/1.1\r\nHost: example.amazonaws.com\r\nX-Amz-Date: 20150830T123600Z\r\n\r\n",
+    /// )
+    /// .expect("A request");
+    /// assert_eq!(
+    ///     CanonicalRequest::new(&request, &["host", "x-amz-date"]).hash(),
+    ///     "bb579772317eb040ac9ed261061d46c1f17a8133879d6129b6e1c25292927e63".to_string()
+    /// );
+    /// ```
+    pub fn hash(&self) -> String {
+        to_hex(&sha256(self.to_string().as_bytes()))
+    }
+}
+
+impl fmt::Display for CanonicalRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.method)?;
+        writeln!(f, "{}", self.path)?;
+        writeln!(f, "{}", self.query)?;
+        for (name, value) in self.headers.iter() {
+            writeln!(f, "{}:{}", name, value)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{}", self.signed_headers())?;
+        write!(f, "{}", self.payload_hash)
+    }
+}
+
+/// # Percent-encode everything but unreserved characters, slashes are kept unless encode_slash
+/// ## Usage
+/// ```rust
+/// use milstian_http::signing::uri_encode;
+/// assert_eq!(uri_encode("a b/~c*", false), "a%20b/~c%2A".to_string());
+/// assert_eq!(uri_encode("a/b", true), "a%2Fb".to_string());
+/// ```
+pub fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_request() {
+        let request = request::Message::from_tcp_stream(
+            b"POST /%7Euser/a+b/ HTTP/1.1\r\nhost: Example.com\r\nX-Multi:  a   b \r\nX-Unsigned: 1\r\nContent-Length: 3\r\n\r\nc=d",
+        )
+        .expect("A request");
+        let canonical = CanonicalRequest::new(&request, &["X-Multi", "Host", "host", "X-Missing"]);
+        assert_eq!(canonical.method, "POST".to_string());
+        assert_eq!(canonical.path, "/~user/a%2Bb/".to_string());
+        assert_eq!(canonical.query, String::new());
+        assert_eq!(canonical.signed_headers(), "host;x-multi".to_string());
+        assert_eq!(
+            canonical.headers,
+            vec![
+                ("host".to_string(), "Example.com".to_string()),
+                ("x-multi".to_string(), "a b".to_string()),
+            ]
+        );
+        assert_eq!(canonical.payload_hash, to_hex(&sha256(b"c=d")));
+        assert_eq!(
+            canonical.unsigned_payload().payload_hash,
+            UNSIGNED_PAYLOAD.to_string()
+        );
+
+        // Repeated and valueless parameters are sorted by name, then value
+        let request =
+            request::Message::from_tcp_stream(b"GET /?b=2&a&b=1&c=%2f%20 HTTP/1.1\r\n\r\n")
+                .expect("A request");
+        assert_eq!(
+            CanonicalRequest::new(&request, &[]).query,
+            "a=&b=1&b=2&c=%2F%20".to_string()
+        );
+    }
+}
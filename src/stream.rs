@@ -0,0 +1,874 @@
+//! # Incremental parsing of requests from a byte stream.
+//!
+//! Bytes are buffered until a complete request, framed by its header block and Content-Length or
+//! chunked Transfer-Encoding, is available. Multi-part bodies with parts to spool are split as
+//! they arrive instead. A `ConnectionParser` also tracks how fast they arrive.
+
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::str;
+
+use chunked;
+use request::{Message, MultiPartReceiver, ParseError, ParserConfig};
+use response;
+
+/// # The outcome of trying to parse buffered bytes
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Complete(Box<Message>, usize), // Request and number of bytes it consumed
+    Incomplete(Needed),
+    Invalid(ParseError),
+}
+
+/// # How many more bytes an incomplete request needs
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Needed {
+    AtLeast(usize), // Like the rest of CRLF CRLF or the next chunk
+    Exactly(usize), // The rest of a Content-Length body
+}
+
+impl Needed {
+    /// # Get the number of bytes worth reading next
+    pub fn size(&self) -> usize {
+        match self {
+            Needed::AtLeast(size) | Needed::Exactly(size) => *size,
+        }
+    }
+}
+
+/// # A buffering request parser
+#[derive(Debug, Default)]
+pub struct Parser {
+    buffer: Vec<u8>,
+    config: ParserConfig,
+    receiver: Option<(MultiPartReceiver, usize)>, // A body being split and its remaining bytes
+}
+
+impl Parser {
+    /// # Create a parser with the default configuration
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    /// # Create a parser that enforces the limits of a configuration
+    pub fn with_config(config: ParserConfig) -> Parser {
+        Parser {
+            buffer: Vec::new(),
+            config,
+            receiver: None,
+        }
+    }
+
+    /// # Append bytes read from the stream
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// # Get the buffered bytes that are not yet part of a request
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// # Take the buffered bytes that are not yet part of a request
+    pub fn into_buffered(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    /// # Write the 101 response of an upgrade and hand over the bytes buffered after its request
+    /// The bytes belong to the new protocol, like the first WebSocket frames or the HTTP/2
+    /// connection preface, and should be fed to its codec before reading more from the stream.
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::response::Message;
+    /// use milstian_http::stream::{Parser, Status};
+    /// let mut parser = Parser::new();
+    /// parser.push(b"GET / HTTP/1.1\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\nPRI * HTTP/2.0");
+    /// assert!(match parser.parse() {
+    ///     Status::Complete(..) => true,
+    ///     _ => false,
+    /// });
+    /// let mut response = Message::with_status(101);
+    /// response.headers.insert("Connection".to_string(), "Upgrade".to_string());
+    /// response.headers.insert("Upgrade".to_string(), "h2c".to_string());
+    /// let mut stream: Vec<u8> = Vec::new();
+    /// let leftover = parser.upgrade(&mut stream, &response).expect("An upgrade");
+    /// assert_eq!(leftover, b"PRI * HTTP/2.0".to_vec());
+    /// assert!(stream.starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+    /// ```
+    pub fn upgrade<W: Write>(
+        self,
+        stream: &mut W,
+        response: &response::Message,
+    ) -> io::Result<Vec<u8>> {
+        if response.status_code() != Some(101) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an upgrade needs a 101 Switching Protocols response",
+            ));
+        }
+        stream.write_all(&response.to_bytes_with_case(&self.config.header_name_case))?;
+        stream.flush()?;
+        Ok(self.into_buffered())
+    }
+
+    /// # Try to parse the next request, consumed bytes are removed from the buffer
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::stream::{Needed, Parser, Status};
+    /// let mut parser = Parser::new();
+    /// parser.push(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\na=");
+    /// assert_eq!(parser.parse(), Status::Incomplete(Needed::Exactly(2)));
+    /// parser.push(b"bcGET / HTTP/1.1\r\n\r\n");
+    /// match parser.parse() {
+    ///     Status::Complete(request, consumed) => {
+    ///         assert_eq!(request.query("a"), None);
+    ///         assert_eq!(request.form_value("a"), Some("bc"));
+    ///         assert_eq!(consumed, 42);
+    ///     }
+    ///     _ => panic!("Expected a complete request"),
+    /// }
+    /// assert_eq!(parser.buffered(), b"GET / HTTP/1.1\r\n\r\n");
+    /// ```
+    pub fn parse(&mut self) -> Status {
+        if let Some((receiver, remaining)) = self.receiver.take() {
+            return self.receive(receiver, remaining);
+        }
+        let status = parse(&self.buffer, &self.config);
+        match status {
+            Status::Complete(_, consumed) => {
+                self.buffer.drain(..consumed);
+            }
+            // Multi-part bodies with parts to spool are split while they arrive
+            Status::Incomplete(Needed::Exactly(remaining)) => {
+                let threshold = match self.config.multipart_spool_threshold {
+                    Some(threshold) => threshold,
+                    None => return status,
+                };
+                let header_end = match find_header_end(&self.buffer, &self.config) {
+                    Ok(Some(header_end)) => header_end,
+                    _ => return status,
+                };
+                if self.buffer.len() - header_end + remaining <= threshold {
+                    return status;
+                }
+                match MultiPartReceiver::new(&self.buffer[..header_end], &self.config) {
+                    Ok(Some(receiver)) => {
+                        self.buffer.drain(..header_end);
+                        let length = self.buffer.len() + remaining;
+                        return self.receive(receiver, length);
+                    }
+                    Ok(None) => {}
+                    Err(error) => return Status::Invalid(error),
+                }
+            }
+            _ => {}
+        }
+        status
+    }
+
+    /// Hand buffered bytes of the body to receiver, the request is complete after remaining
+    fn receive(&mut self, mut receiver: MultiPartReceiver, remaining: usize) -> Status {
+        let used = remaining.min(self.buffer.len());
+        if let Err(error) = receiver.push(&self.buffer[..used]) {
+            return Status::Invalid(error);
+        }
+        self.buffer.drain(..used);
+        if used < remaining {
+            self.receiver = Some((receiver, remaining - used));
+            return Status::Incomplete(Needed::Exactly(remaining - used));
+        }
+        match receiver.finish() {
+            Ok(message) => {
+                let consumed = message.wire_size.total();
+                Status::Complete(Box::new(message), consumed)
+            }
+            Err(error) => Status::Invalid(error),
+        }
+    }
+}
+
+/// # The part of a request a connection is waiting for
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phase {
+    Body,
+    Headers,
+    RequestLine, // Also while waiting for the next request on a persistent connection
+}
+
+impl Phase {
+    /// Get the phase of the buffered bytes of an incomplete request
+    fn of(buffered: &[u8]) -> Phase {
+        if buffered.windows(4).any(|window| window == b"\r\n\r\n") {
+            Phase::Body
+        } else if buffered.windows(2).any(|window| window == b"\r\n") {
+            Phase::Headers
+        } else {
+            Phase::RequestLine
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let phase = match *self {
+            Phase::Body => "body",
+            Phase::Headers => "headers",
+            Phase::RequestLine => "request line",
+        };
+        write!(f, "{}", phase)
+    }
+}
+
+/// # How much progress a phase has to make
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Deadline {
+    pub checkpoints: Option<u32>, // Checkpoints the phase may last, None for no limit
+    pub min_bytes: usize,         // Bytes to receive between two checkpoints
+}
+
+/// # Deadlines of each phase, measured in checkpoints the server calls at a fixed interval
+/// The defaults suit checkpoints a few seconds apart: the request line may take four of them
+/// without data, the headers four while receiving something every time and the body as long as
+/// data keeps arriving.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProgressPolicy {
+    pub body: Deadline,
+    pub headers: Deadline,
+    pub request_line: Deadline,
+}
+
+impl Default for ProgressPolicy {
+    fn default() -> ProgressPolicy {
+        ProgressPolicy {
+            body: Deadline {
+                checkpoints: None,
+                min_bytes: 1,
+            },
+            headers: Deadline {
+                checkpoints: Some(4),
+                min_bytes: 1,
+            },
+            request_line: Deadline {
+                checkpoints: Some(4),
+                min_bytes: 0,
+            },
+        }
+    }
+}
+
+impl ProgressPolicy {
+    /// # Get the deadline of phase
+    pub fn deadline(&self, phase: Phase) -> &Deadline {
+        match phase {
+            Phase::Body => &self.body,
+            Phase::Headers => &self.headers,
+            Phase::RequestLine => &self.request_line,
+        }
+    }
+}
+
+/// # A connection that missed the deadline of a phase
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Stall {
+    pub checkpoints: u32, // Checkpoints spent in the phase, including the one that failed
+    pub phase: Phase,
+    pub received: usize, // Bytes received since the previous checkpoint
+}
+
+impl Stall {
+    /// # Build the 408 Request Timeout response to send before closing the connection
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::stream::{Phase, Stall};
+    /// let stall = Stall {
+    ///     checkpoints: 5,
+    ///     phase: Phase::Headers,
+    ///     received: 1,
+    /// };
+    /// let response = stall.to_response();
+    /// assert_eq!(response.status, "408 Request Timeout".to_string());
+    /// assert_eq!(response.headers.get("Connection"), Some(&"close".to_string()));
+    /// ```
+    pub fn to_response(&self) -> response::Message {
+        let mut response = response::Message::error_page(408, None);
+        response
+            .headers
+            .insert("Connection".to_string(), "close".to_string());
+        response
+    }
+}
+
+/// # A request parser for one connection that detects clients stalling a request
+/// The server calls `checkpoint` at a fixed interval from its own timer, each call checks the
+/// bytes received since the previous one against the deadline of the current phase, so slow
+/// clients like in a slowloris attack can be answered with 408 and closed.
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::ParserConfig;
+/// use milstian_http::stream::{ConnectionParser, Phase, ProgressPolicy, Status};
+/// let mut parser = ConnectionParser::new(ParserConfig::default(), ProgressPolicy::default());
+/// parser.push(b"GET / HTTP/1.1\r\n");
+/// assert!(parser.checkpoint().is_ok());
+/// assert_eq!(parser.phase(), Phase::Headers);
+/// for _ in 0..3 {
+///     parser.push(b"X");
+///     assert!(parser.checkpoint().is_ok());
+/// }
+/// parser.push(b"X");
+/// let stall = parser.checkpoint().expect_err("A stalled request");
+/// assert_eq!(stall.phase, Phase::Headers);
+/// assert_eq!(stall.to_response().status_code(), Some(408));
+/// ```
+#[derive(Debug, Default)]
+pub struct ConnectionParser {
+    checkpoints: u32, // Checkpoints in the current phase
+    parser: Parser,
+    phase: Option<Phase>, // Phase at the previous checkpoint
+    policy: ProgressPolicy,
+    received: usize, // Bytes since the previous checkpoint
+}
+
+impl ConnectionParser {
+    /// # Create a parser that enforces the limits of a configuration and the deadlines of policy
+    pub fn new(config: ParserConfig, policy: ProgressPolicy) -> ConnectionParser {
+        ConnectionParser {
+            parser: Parser::with_config(config),
+            policy,
+            ..ConnectionParser::default()
+        }
+    }
+
+    /// # Append bytes read from the stream
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.received += bytes.len();
+        self.parser.push(bytes);
+    }
+
+    /// # Get the buffered bytes that are not yet part of a request
+    pub fn buffered(&self) -> &[u8] {
+        self.parser.buffered()
+    }
+
+    /// # Take the parser with the buffered bytes, like for an upgrade
+    pub fn into_parser(self) -> Parser {
+        self.parser
+    }
+
+    /// # Get the phase of the request that is being received
+    pub fn phase(&self) -> Phase {
+        if self.parser.receiver.is_some() {
+            return Phase::Body;
+        }
+        Phase::of(self.parser.buffered())
+    }
+
+    /// # Try to parse the next request, the deadlines start over after a complete one
+    pub fn parse(&mut self) -> Status {
+        let status = self.parser.parse();
+        if let Status::Complete(..) = status {
+            self.checkpoints = 0;
+            self.phase = None;
+        }
+        status
+    }
+
+    /// # Check the progress since the previous checkpoint against the deadline of the phase
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::request::ParserConfig;
+    /// use milstian_http::stream::{ConnectionParser, Deadline, Phase, ProgressPolicy};
+    /// let policy = ProgressPolicy {
+    ///     body: Deadline {
+    ///         checkpoints: None,
+    ///         min_bytes: 1024,
+    ///     },
+    ///     ..ProgressPolicy::default()
+    /// };
+    /// let mut parser = ConnectionParser::new(ParserConfig::default(), policy);
+    /// parser.push(b"POST / HTTP/1.1\r\nContent-Length: 4096\r\n\r\n");
+    /// parser.push(&[b'a'; 1024]);
+    /// assert!(parser.checkpoint().is_ok());
+    /// parser.push(&[b'a'; 512]);
+    /// let stall = parser.checkpoint().expect_err("A too slow body");
+    /// assert_eq!(stall.phase, Phase::Body);
+    /// assert_eq!(stall.received, 512);
+    /// ```
+    pub fn checkpoint(&mut self) -> Result<(), Stall> {
+        let phase = self.phase();
+        if self.phase != Some(phase) {
+            self.phase = Some(phase);
+            self.checkpoints = 0;
+        }
+        self.checkpoints += 1;
+        let received = self.received;
+        self.received = 0;
+        let deadline = self.policy.deadline(phase);
+        if received < deadline.min_bytes
+            || deadline
+                .checkpoints
+                .is_some_and(|checkpoints| self.checkpoints > checkpoints)
+        {
+            return Err(Stall {
+                checkpoints: self.checkpoints,
+                phase,
+                received,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Get the Transfer-Encoding of head, which must be a single field of only chunked
+fn get_transfer_encoding(head: &str) -> Result<Option<&str>, ParseError> {
+    let mut transfer_encoding: Option<&str> = None;
+    for line in head.split("\r\n").skip(1) {
+        let value = match line.split_once(':') {
+            Some((key, value)) if key.trim().eq_ignore_ascii_case("Transfer-Encoding") => {
+                value.trim()
+            }
+            _ => continue,
+        };
+        if transfer_encoding.is_some() || !chunked::is_valid_transfer_encoding(value) {
+            return Err(ParseError::InvalidHeader);
+        }
+        if let Some(coding) = chunked::get_unsupported_coding(value) {
+            return Err(ParseError::UnsupportedTransferCoding(coding.to_string()));
+        }
+        transfer_encoding = Some(value);
+    }
+    Ok(transfer_encoding)
+}
+
+/// Get the Content-Length of head, every Content-Length field must have the same length
+fn get_content_length(head: &str) -> Result<Option<usize>, ParseError> {
+    let mut content_length: Option<usize> = None;
+    for line in head.split("\r\n").skip(1) {
+        let value = match line.split_once(':') {
+            Some((key, value)) if key.trim().eq_ignore_ascii_case("Content-Length") => value,
+            _ => continue,
+        };
+        let length = Message::get_content_length(value).ok_or(ParseError::InvalidHeader)?;
+        if content_length.is_some_and(|content_length| content_length != length) {
+            return Err(ParseError::InvalidHeader);
+        }
+        content_length = Some(length);
+    }
+    Ok(content_length)
+}
+
+/// Find the end of the header block after CRLF CRLF, header field lines longer than allowed are
+/// rejected while scanning instead of buffering up to the limit of the whole header
+fn find_header_end(bytes: &[u8], config: &ParserConfig) -> Result<Option<usize>, ParseError> {
+    let mut line_start = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        if *byte == b'\n' && index > 0 && bytes[index - 1] == b'\r' {
+            if line_start > 0 && index - line_start == 1 {
+                return Ok(Some(index + 1));
+            }
+            line_start = index + 1;
+        } else if line_start > 0 && *byte != b'\r' {
+            let seen = index + 1 - line_start;
+            if seen > config.max_header_line_length {
+                return Err(ParseError::HeaderTooLarge {
+                    limit: config.max_header_line_length,
+                    seen,
+                });
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Get the number of bytes that could complete CRLF CRLF after bytes
+fn get_header_end_remaining(bytes: &[u8]) -> usize {
+    (1..4)
+        .rev()
+        .find(|matched| bytes.ends_with(&b"\r\n\r\n"[..*matched]))
+        .map(|matched| 4 - matched)
+        .unwrap_or(4)
+}
+
+/// # Try to parse one request from the start of bytes
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::ParserConfig;
+/// use milstian_http::stream::{parse, Needed, Status};
+/// let config = ParserConfig::default();
+/// assert_eq!(
+///     parse(b"GET / HTTP/1.1\r\nHost: a\r\n", &config),
+///     Status::Incomplete(Needed::AtLeast(2))
+/// );
+/// if let Status::Complete(request, consumed) = parse(b"GET / HTTP/1.1\r\n\r\n", &config) {
+///     assert_eq!(request.request_line.request_uri, "/".to_string());
+///     assert_eq!(consumed, 18);
+/// } else {
+///     panic!("Expected a complete request");
+/// }
+/// ```
+pub fn parse(bytes: &[u8], config: &ParserConfig) -> Status {
+    // Disallowed methods are rejected before the rest of the head is buffered
+    if config.allowed_methods.is_some() {
+        if let Some(line_end) = bytes.windows(2).position(|window| window == b"\r\n") {
+            if let Some(request_line) = str::from_utf8(&bytes[..line_end])
+                .ok()
+                .and_then(|line| Message::get_request_line_with_config(line, config))
+            {
+                if let Err(error) = config.check_method(&request_line.method) {
+                    return Status::Invalid(error);
+                }
+            }
+        }
+    }
+    let header_end = match find_header_end(bytes, config) {
+        Ok(Some(header_end)) => header_end,
+        Err(error) => return Status::Invalid(error),
+        Ok(None) => {
+            let limit = config.max_header_size + config.max_request_uri_length;
+            if bytes.len() > limit {
+                return Status::Invalid(ParseError::HeaderTooLarge {
+                    limit,
+                    seen: bytes.len(),
+                });
+            }
+            return Status::Incomplete(Needed::AtLeast(get_header_end_remaining(bytes)));
+        }
+    };
+    let head = match str::from_utf8(&bytes[..header_end]) {
+        Ok(head) => head,
+        Err(_) => return Status::Invalid(ParseError::InvalidHeader),
+    };
+    let body = &bytes[header_end..];
+
+    let is_chunked = match get_transfer_encoding(head) {
+        Ok(transfer_encoding) => transfer_encoding.is_some(),
+        Err(error) => return Status::Invalid(error),
+    };
+    let consumed = if is_chunked {
+        match chunked::decode(body) {
+            chunked::Status::Complete(_, used) => header_end + used,
+            chunked::Status::Incomplete => {
+                if body.len() > config.max_body_size {
+                    return Status::Invalid(ParseError::BodyTooLarge {
+                        limit: config.max_body_size,
+                        seen: body.len(),
+                    });
+                }
+                return Status::Incomplete(Needed::AtLeast(1));
+            }
+            chunked::Status::Invalid => return Status::Invalid(ParseError::InvalidChunkedBody),
+        }
+    } else {
+        let content_length = match get_content_length(head) {
+            Ok(content_length) => content_length.unwrap_or(0),
+            Err(error) => return Status::Invalid(error),
+        };
+        if content_length > config.max_body_size {
+            return Status::Invalid(ParseError::BodyTooLarge {
+                limit: config.max_body_size,
+                seen: content_length,
+            });
+        }
+        if body.len() < content_length {
+            return Status::Incomplete(Needed::Exactly(content_length - body.len()));
+        }
+        header_end + content_length
+    };
+
+    match Message::from_tcp_stream_with_config(&bytes[..consumed], config) {
+        Ok(message) => Status::Complete(Box::new(message), consumed),
+        Err(error) => Status::Invalid(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use request::{BodyContentType, Method};
+
+    #[test]
+    fn test_parse() {
+        let config = ParserConfig::default();
+        assert_eq!(parse(b"", &config), Status::Incomplete(Needed::AtLeast(4)));
+        assert_eq!(
+            parse(b"GET / HTTP/1.1\r", &config),
+            Status::Incomplete(Needed::AtLeast(3))
+        );
+        assert_eq!(
+            parse(b"GET / HTTP/1.1\r\n\r", &config),
+            Status::Incomplete(Needed::AtLeast(1))
+        );
+        assert_eq!(
+            parse(b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nab", &config),
+            Status::Incomplete(Needed::Exactly(1))
+        );
+        assert_eq!(
+            parse(
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\na",
+                &config
+            ),
+            Status::Incomplete(Needed::AtLeast(1))
+        );
+        assert_eq!(Needed::Exactly(3).size(), 3);
+        assert_eq!(
+            parse(
+                b"POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nabc",
+                &config
+            ),
+            Status::Invalid(ParseError::InvalidHeader)
+        );
+        assert_eq!(
+            parse(
+                b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3, 3\r\n\r\na",
+                &config
+            ),
+            Status::Incomplete(Needed::Exactly(2))
+        );
+        assert_eq!(
+            parse(b"POST / HTTP/1.1\r\ncontent-length: x\r\n\r\n", &config),
+            Status::Invalid(ParseError::InvalidHeader)
+        );
+        assert_eq!(
+            parse(b"FOO / HTTP/1.1\r\n\r\n", &config),
+            Status::Invalid(ParseError::InvalidRequestLine)
+        );
+
+        if let Status::Complete(request, consumed) = parse(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\na=b\r\n0\r\n\r\nrest",
+            &config,
+        ) {
+            assert_eq!(request.request_line.method, Method::Post);
+            assert_eq!(request.form_value("a"), Some("b"));
+            assert_eq!(consumed, 60);
+        } else {
+            panic!("Expected a complete chunked request");
+        }
+
+        let config = ParserConfig {
+            max_body_size: 4,
+            max_header_size: 16,
+            max_request_uri_length: 16,
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\n", &config),
+            Status::Invalid(ParseError::BodyTooLarge { limit: 4, seen: 5 })
+        );
+        assert_eq!(
+            parse(&[b'a'; 33], &config),
+            Status::Invalid(ParseError::HeaderTooLarge {
+                limit: 32,
+                seen: 33
+            })
+        );
+
+        // A long header line is rejected as soon as it passes the limit
+        let config = ParserConfig {
+            max_header_line_length: 8,
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse(b"GET / HTTP/1.1\r\nA: 1234\r\nB: 12345\r", &config),
+            Status::Incomplete(Needed::AtLeast(3))
+        );
+        assert_eq!(
+            parse(b"GET / HTTP/1.1\r\nA: 1234\r\nB: 123456", &config),
+            Status::Invalid(ParseError::HeaderTooLarge { limit: 8, seen: 9 })
+        );
+        assert_eq!(
+            parse(b"GET /a/long/request/uri HTTP/1.1\r\n\r\n", &config),
+            parse(
+                b"GET /a/long/request/uri HTTP/1.1\r\n\r\n",
+                &ParserConfig::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_allowed_methods() {
+        let config = ParserConfig {
+            allowed_methods: Some(vec![Method::Get]),
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse(b"POST / HTTP/1.1\r\nContent-Le", &config),
+            Status::Invalid(ParseError::MethodNotAllowed {
+                allowed: vec![Method::Get],
+                method: Method::Post
+            })
+        );
+        assert_eq!(
+            parse(b"GET / HTTP/1.1\r\nHost: a", &config),
+            Status::Incomplete(Needed::AtLeast(4))
+        );
+        assert_eq!(
+            parse(b"FOO / HTTP/1.1\r\n\r\n", &config),
+            Status::Invalid(ParseError::InvalidRequestLine)
+        );
+    }
+
+    #[test]
+    fn test_parser_pipelining() {
+        let mut parser = Parser::new();
+        parser.push(b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n");
+        match parser.parse() {
+            Status::Complete(request, consumed) => {
+                assert_eq!(request.request_line.request_uri, "/a".to_string());
+                assert_eq!(consumed, 19);
+            }
+            _ => panic!("Expected a complete request"),
+        }
+        assert_eq!(parser.parse(), Status::Incomplete(Needed::AtLeast(2)));
+        parser.push(b"\r\n");
+        match parser.parse() {
+            Status::Complete(request, _) => {
+                assert_eq!(request.request_line.request_uri, "/b".to_string());
+            }
+            _ => panic!("Expected a complete request"),
+        }
+        assert!(parser.buffered().is_empty());
+    }
+
+    #[test]
+    fn test_parser_spooling() {
+        let config = ParserConfig {
+            multipart_spool_threshold: Some(8),
+            ..ParserConfig::default()
+        };
+        let head = b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=ab\r\nContent-Length: 1060\r\n\r\n";
+        let mut parser = ConnectionParser::new(config.clone(), ProgressPolicy::default());
+        parser.push(head);
+        parser.push(b"--ab\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n");
+        assert_eq!(parser.parse(), Status::Incomplete(Needed::Exactly(1010)));
+
+        // Body bytes are handed to the spooled part instead of being buffered
+        for _ in 0..10 {
+            parser.push(&[b'a'; 100]);
+            assert!(matches!(
+                parser.parse(),
+                Status::Incomplete(Needed::Exactly(_))
+            ));
+            assert!(parser.buffered().is_empty());
+            assert_eq!(parser.phase(), Phase::Body);
+        }
+        parser.push(b"\r\n--ab--\r\nGET / HTTP/1.1\r\n\r\n");
+        match parser.parse() {
+            Status::Complete(request, consumed) => {
+                assert_eq!(consumed, head.len() + 1060);
+                assert_eq!(request.wire_size.body, 1060);
+                match request.body {
+                    BodyContentType::MultiPart(ref values) => {
+                        let file = values["a"].file.as_ref().expect("A spooled file");
+                        assert_eq!(file.size, 1000);
+                    }
+                    _ => panic!("Expected a multi-part body"),
+                }
+            }
+            _ => panic!("Expected a complete request"),
+        }
+        assert_eq!(parser.buffered(), b"GET / HTTP/1.1\r\n\r\n");
+
+        // Small bodies and bodies of other types are buffered as usual
+        let mut parser = Parser::with_config(config.clone());
+        parser.push(b"POST / HTTP/1.1\r\nContent-Length: 20\r\n\r\na=b");
+        assert_eq!(parser.parse(), Status::Incomplete(Needed::Exactly(17)));
+        assert_eq!(parser.buffered().len(), 42);
+        assert!(parser.receiver.is_none());
+
+        // Heads are checked before their body is received
+        let mut parser = Parser::with_config(ParserConfig {
+            strict: true,
+            ..config
+        });
+        parser.push(b"POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=ab\r\nContent-Length: 20\r\nA : 1\r\n\r\n");
+        assert_eq!(parser.parse(), Status::Invalid(ParseError::InvalidHeader));
+    }
+
+    #[test]
+    fn test_connection_parser() {
+        let mut parser = ConnectionParser::new(ParserConfig::default(), ProgressPolicy::default());
+        assert_eq!(parser.phase(), Phase::RequestLine);
+
+        // Idle connections may wait for the request line
+        for _ in 0..4 {
+            assert!(parser.checkpoint().is_ok());
+        }
+        parser.push(b"G");
+        assert_eq!(
+            parser.checkpoint(),
+            Err(Stall {
+                checkpoints: 5,
+                phase: Phase::RequestLine,
+                received: 1,
+            })
+        );
+
+        // A new phase and a complete request start the deadlines over
+        let mut parser = ConnectionParser::new(ParserConfig::default(), ProgressPolicy::default());
+        parser.push(b"POST / HTTP/1.1\r\nContent-Length: 2\r\n");
+        assert!(parser.checkpoint().is_ok());
+        assert_eq!(
+            parser.checkpoint(),
+            Err(Stall {
+                checkpoints: 2,
+                phase: Phase::Headers,
+                received: 0,
+            })
+        );
+        parser.push(b"\r\na");
+        assert_eq!(parser.phase(), Phase::Body);
+        assert!(parser.checkpoint().is_ok());
+        parser.push(b"bGET / HTTP/1.1\r\n");
+        match parser.parse() {
+            Status::Complete(request, _) => assert_eq!(request.wire_size.body, 2),
+            status => panic!("Expected a complete request, got {:?}", status),
+        }
+        assert_eq!(parser.phase(), Phase::Headers);
+        for _ in 0..4 {
+            parser.push(b"X");
+            assert!(parser.checkpoint().is_ok());
+        }
+        assert_eq!(parser.buffered(), b"GET / HTTP/1.1\r\nXXXX");
+        assert_eq!(Phase::RequestLine.to_string(), "request line".to_string());
+    }
+
+    #[test]
+    fn test_parser_upgrade() {
+        let mut parser = Parser::new();
+        parser.push(
+            b"GET /chat HTTP/1.1\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n\x81\x02hi",
+        );
+        match parser.parse() {
+            Status::Complete(request, _) => {
+                assert_eq!(request.request_line.request_uri, "/chat".to_string())
+            }
+            _ => panic!("Expected a complete request"),
+        }
+        let mut response = response::Message::with_status(101);
+        response
+            .headers
+            .insert("Upgrade".to_string(), "websocket".to_string());
+        let mut stream: Vec<u8> = Vec::new();
+        assert_eq!(
+            parser.upgrade(&mut stream, &response).expect("An upgrade"),
+            b"\x81\x02hi".to_vec()
+        );
+        assert_eq!(
+            stream,
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n".to_vec()
+        );
+
+        // Only 101 responses complete an upgrade
+        let mut parser = Parser::new();
+        parser.push(b"abc");
+        let mut stream: Vec<u8> = Vec::new();
+        assert_eq!(
+            parser
+                .upgrade(&mut stream, &response::Message::with_status(200))
+                .expect_err("An error")
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(stream.is_empty());
+    }
+}
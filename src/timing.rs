@@ -0,0 +1,264 @@
+//! # Handles Server-Timing performance metrics.
+//!
+//! Servers accumulate named metrics while handling a request and send them in a Server-Timing
+//! header, clients parse the same header back into metrics.
+
+use std::fmt;
+use std::time::Duration;
+
+/// # A named performance metric
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Metric {
+    pub description: Option<String>,
+    pub duration: Option<Duration>, // Sent in milliseconds with microsecond precision
+    pub name: String,
+}
+
+impl Metric {
+    /// # Check that the name is a token and the description has no control characters
+    pub fn is_valid(&self) -> bool {
+        is_token(&self.name)
+            && self.description.as_ref().is_none_or(|description| {
+                !description
+                    .chars()
+                    .any(|character| character.is_control() && character != '\t')
+            })
+    }
+}
+
+impl fmt::Display for Metric {
+    /// # Render the metric like db;dur=53.2;desc="Primary database"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(duration) = self.duration {
+            let micros = duration.as_micros();
+            let fraction = format!("{:03}", micros % 1000);
+            let fraction = fraction.trim_end_matches('0');
+            if fraction.is_empty() {
+                write!(f, ";dur={}", micros / 1000)?;
+            } else {
+                write!(f, ";dur={}.{}", micros / 1000, fraction)?;
+            }
+        }
+        if let Some(ref description) = self.description {
+            if is_token(description) {
+                write!(f, ";desc={}", description)?;
+            } else {
+                write!(
+                    f,
+                    ";desc=\"{}\"",
+                    description.replace('\\', "\\\\").replace('"', "\\\"")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// # Metrics of a Server-Timing header in the order they were added
+/// ## Usage
+/// ```rust
+/// use milstian_http::timing::ServerTiming;
+/// use std::time::Duration;
+/// let mut timing = ServerTiming::new();
+/// timing.add("db", Some(Duration::from_micros(53_200)), Some("Primary database"));
+/// timing.add("cache", None, Some("hit"));
+/// assert!(timing.is_valid());
+/// assert_eq!(
+///     timing.to_string(),
+///     "db;dur=53.2;desc=\"Primary database\", cache;desc=hit".to_string()
+/// );
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ServerTiming {
+    pub metrics: Vec<Metric>,
+}
+
+impl ServerTiming {
+    /// # Create an empty set of metrics
+    pub fn new() -> ServerTiming {
+        ServerTiming::default()
+    }
+
+    /// # Add a metric with an optional duration and description
+    pub fn add(&mut self, name: &str, duration: Option<Duration>, description: Option<&str>) {
+        self.metrics.push(Metric {
+            description: description.map(|description| description.to_string()),
+            duration,
+            name: name.to_string(),
+        });
+    }
+
+    /// # Check that there are metrics and all of them are valid
+    pub fn is_valid(&self) -> bool {
+        !self.metrics.is_empty() && self.metrics.iter().all(Metric::is_valid)
+    }
+}
+
+impl fmt::Display for ServerTiming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let metrics: Vec<String> = self
+            .metrics
+            .iter()
+            .map(|metric| metric.to_string())
+            .collect();
+        write!(f, "{}", metrics.join(", "))
+    }
+}
+
+/// # Parse the metrics of a Server-Timing header value
+/// Metrics with invalid names are skipped, only the first dur and desc parameters are used and
+/// durations that are not non-negative numbers are ignored.
+/// ## Usage
+/// ```rust
+/// use milstian_http::timing::parse;
+/// use std::time::Duration;
+/// let metrics = parse("miss, db;dur=53.2, app;desc=\"a, b\";dur=47");
+/// assert_eq!(metrics.len(), 3);
+/// assert_eq!(metrics[0].name, "miss".to_string());
+/// assert_eq!(metrics[1].duration, Some(Duration::from_micros(53_200)));
+/// assert_eq!(metrics[2].description, Some("a, b".to_string()));
+/// assert_eq!(metrics[2].duration, Some(Duration::from_millis(47)));
+/// ```
+pub fn parse(value: &str) -> Vec<Metric> {
+    let mut metrics: Vec<Metric> = Vec::new();
+    for metric in split_unquoted(value, ',') {
+        let mut parameters = split_unquoted(metric, ';').into_iter();
+        let name = parameters.next().unwrap_or("").trim();
+        if !is_token(name) {
+            continue;
+        }
+        let mut description: Option<String> = None;
+        let mut duration: Option<Duration> = None;
+        for parameter in parameters {
+            let (key, value) = match parameter.split_once('=') {
+                Some((key, value)) => (key.trim(), unquote(value.trim())),
+                None => continue,
+            };
+            if key.eq_ignore_ascii_case("dur") && duration.is_none() {
+                duration = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|milliseconds| milliseconds.is_finite() && *milliseconds >= 0.0)
+                    .map(|milliseconds| {
+                        Duration::from_micros((milliseconds * 1000.0).round() as u64)
+                    });
+            } else if key.eq_ignore_ascii_case("desc") && description.is_none() {
+                description = Some(value);
+            }
+        }
+        metrics.push(Metric {
+            description,
+            duration,
+            name: name.to_string(),
+        });
+    }
+    metrics
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value.chars().all(|character| {
+            character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(character)
+        })
+}
+
+/// Split value by separator outside of quoted strings
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut start = 0;
+    let mut is_quoted = false;
+    let mut is_escaped = false;
+    for (index, character) in value.char_indices() {
+        if is_escaped {
+            is_escaped = false;
+        } else if is_quoted && character == '\\' {
+            is_escaped = true;
+        } else if character == '"' {
+            is_quoted = !is_quoted;
+        } else if character == separator && !is_quoted {
+            parts.push(&value[start..index]);
+            start = index + 1;
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Remove the quotes and escapes of a quoted string, tokens are returned as is
+fn unquote(value: &str) -> String {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return value.to_string();
+    }
+    let mut unquoted = String::new();
+    let mut is_escaped = false;
+    for character in value[1..value.len() - 1].chars() {
+        if character == '\\' && !is_escaped {
+            is_escaped = true;
+        } else {
+            unquoted.push(character);
+            is_escaped = false;
+        }
+    }
+    unquoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_timing() {
+        let mut timing = ServerTiming::new();
+        assert!(!timing.is_valid());
+        timing.add("total", Some(Duration::from_millis(120)), None);
+        timing.add(
+            "render",
+            Some(Duration::from_nanos(1_500)),
+            Some("say \"hi\""),
+        );
+        timing.add("idle", Some(Duration::from_micros(10)), None);
+        assert!(timing.is_valid());
+        let value = timing.to_string();
+        assert_eq!(
+            value,
+            "total;dur=120, render;dur=0.001;desc=\"say \\\"hi\\\"\", idle;dur=0.01".to_string()
+        );
+
+        // Rendered metrics parse back with microsecond precision
+        let metrics = parse(&value);
+        assert_eq!(metrics[0], timing.metrics[0]);
+        assert_eq!(metrics[1].description, Some("say \"hi\"".to_string()));
+        assert_eq!(metrics[1].duration, Some(Duration::from_micros(1)));
+        assert_eq!(metrics[2], timing.metrics[2]);
+
+        timing.add("a b", None, None);
+        assert!(!timing.is_valid());
+        let mut timing = ServerTiming::new();
+        timing.add("a", None, Some("line\nbreak"));
+        assert!(!timing.is_valid());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(parse("").is_empty());
+        let metrics = parse(
+            " db ; DUR = 2 ; dur=3 ; desc=first ; desc=second, ;dur=1, bad name, cpu;dur=-1;dur=x",
+        );
+        assert_eq!(
+            metrics,
+            vec![
+                Metric {
+                    description: Some("first".to_string()),
+                    duration: Some(Duration::from_millis(2)),
+                    name: "db".to_string(),
+                },
+                Metric {
+                    description: None,
+                    duration: None,
+                    name: "cpu".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! # Correlates a request with its response and timing.
+//!
+//! A transaction follows a request through the handlers of a server, collects Server-Timing
+//! metrics along the way and finalizes the framing headers of the response in one place.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use date;
+use request;
+use request::Method;
+use response;
+use timing::ServerTiming;
+
+/// # A request, its eventual response and timing metadata
+/// ## Usage
+/// ```rust
+/// use milstian_http::request::Message;
+/// use milstian_http::response;
+/// use milstian_http::transaction::Transaction;
+/// let request = Message::from_tcp_stream(b"GET / HTTP/1.0\r\n\r\n").expect("A request");
+/// let mut transaction = Transaction::new(request);
+/// let mut message = response::Message::with_status(200);
+/// message.body = b"Hello".to_vec();
+/// transaction.respond(message);
+/// let response = transaction.finalize();
+/// assert_eq!(response.protocol, "HTTP/1.0".to_string());
+/// assert_eq!(response.headers.get("Content-Length"), Some(&"5".to_string()));
+/// assert_eq!(response.headers.get("Connection"), Some(&"close".to_string()));
+/// assert!(response.headers.contains_key("Date"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    pub request: request::Message,
+    pub response: Option<response::Message>, // Set by the handler that answers the request
+    pub started: Instant,
+    pub timing: ServerTiming, // Sent as Server-Timing unless the response already has one
+}
+
+impl Transaction {
+    /// # Start a transaction for request now
+    pub fn new(request: request::Message) -> Transaction {
+        Transaction {
+            request,
+            response: None,
+            started: Instant::now(),
+            timing: ServerTiming::new(),
+        }
+    }
+
+    /// # Get the time since the transaction started
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// # Answer the request, replacing an earlier response
+    pub fn respond(&mut self, response: response::Message) {
+        self.response = Some(response);
+    }
+
+    /// # Whether the connection stays open after the response
+    /// Both the request and the response may close the connection.
+    pub fn is_keep_alive(&self) -> bool {
+        let is_closed = self.response.as_ref().is_some_and(|response| {
+            response
+                .headers
+                .get("Connection")
+                .is_some_and(|connection| connection.eq_ignore_ascii_case("close"))
+        });
+        !is_closed && self.request.is_keep_alive()
+    }
+
+    /// # Finalize the response for sending, requests nobody answered get 404 Not Found
+    /// Adds Date, Server-Timing and Content-Length when missing, sends no body to HEAD requests
+    /// and downgrades the response to the protocol of the request with a matching Connection.
+    pub fn finalize(&mut self) -> &response::Message {
+        let keep_alive = self.is_keep_alive();
+        let is_head = self.request.request_line.method == Method::Head;
+        let protocol = self.request.request_line.protocol;
+        let timing = if self.timing.is_valid() {
+            Some(self.timing.to_string())
+        } else {
+            None
+        };
+        let response = self
+            .response
+            .get_or_insert_with(|| response::Message::not_found(None));
+
+        response
+            .headers
+            .entry("Date".to_string())
+            .or_insert_with(|| date::format_http_date(SystemTime::now()));
+        if let Some(timing) = timing {
+            response
+                .headers
+                .entry("Server-Timing".to_string())
+                .or_insert(timing);
+        }
+
+        // Informational, 204 and 304 responses never have a body to frame
+        let status_code = response.status_code().unwrap_or(200);
+        if status_code >= 200
+            && status_code != 204
+            && status_code != 304
+            && !response.headers.contains_key("Content-Length")
+            && !response.headers.contains_key("Transfer-Encoding")
+        {
+            let length = response.body.len();
+            response.set_content_length(length);
+        }
+        if is_head || status_code < 200 || status_code == 204 || status_code == 304 {
+            response.body.clear();
+        }
+
+        response.downgrade(&protocol, keep_alive);
+        if !keep_alive {
+            response
+                .headers
+                .insert("Connection".to_string(), "close".to_string());
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(request: &[u8]) -> Transaction {
+        Transaction::new(request::Message::from_tcp_stream(request).expect("A request"))
+    }
+
+    #[test]
+    fn test_finalize() {
+        // Unanswered requests
+        let mut unanswered = transaction(b"GET / HTTP/1.1\r\n\r\n");
+        let response = unanswered.finalize();
+        assert_eq!(response.status_code(), Some(404));
+        assert_eq!(
+            response.headers.get("Content-Length"),
+            Some(&response.body.len().to_string())
+        );
+        assert_eq!(response.headers.get("Connection"), None);
+
+        // Existing headers are kept and HEAD responses have no body
+        let mut head = transaction(b"HEAD / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        head.timing.add("db", Some(Duration::from_millis(3)), None);
+        let mut message = response::Message::with_status(200);
+        message.body = b"abc".to_vec();
+        message.headers.insert(
+            "Date".to_string(),
+            "Thu, 01 Jan 1970 00:00:00 GMT".to_string(),
+        );
+        head.respond(message);
+        assert!(!head.is_keep_alive());
+        assert_eq!(
+            head.finalize().to_bytes(),
+            b"HTTP/1.1 200 OK\r\nDate: Thu, 01 Jan 1970 00:00:00 GMT\r\nServer-Timing: db;dur=3\r\nContent-Length: 3\r\nConnection: close\r\n\r\n"
+                .to_vec()
+        );
+
+        // Responses without a body
+        let mut no_content = transaction(b"DELETE /a HTTP/1.1\r\n\r\n");
+        let mut message = response::Message::with_status(204);
+        message.body = b"ignored".to_vec();
+        no_content.respond(message);
+        let response = no_content.finalize();
+        assert!(response.body.is_empty());
+        assert_eq!(response.headers.get("Content-Length"), None);
+
+        // Responses closing the connection
+        let mut closing = transaction(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n");
+        assert!(closing.is_keep_alive());
+        let mut message = response::Message::with_status(200);
+        message
+            .headers
+            .insert("Connection".to_string(), "Close".to_string());
+        closing.respond(message);
+        assert!(!closing.is_keep_alive());
+        let response = closing.finalize();
+        assert_eq!(response.protocol, "HTTP/1.0".to_string());
+        assert_eq!(
+            response.headers.get("Connection"),
+            Some(&"close".to_string())
+        );
+        assert!(closing.elapsed() < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,530 @@
+//! # A minimal XML reader for request bodies.
+//!
+//! Documents are parsed into a tree of elements and text without namespaces processing or
+//! validation. Document type declarations with an internal subset are rejected, so entity
+//! expansion attacks are not possible.
+
+use std::error;
+use std::fmt;
+
+/// # Reasons why an XML body could not be read
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum XmlError {
+    InvalidEncoding, // The bytes are not valid in the charset
+    Syntax {
+        message: &'static str,
+        offset: usize,
+    },
+    UnsupportedCharset(String),
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XmlError::InvalidEncoding => write!(f, "xml body is not valid in its charset"),
+            XmlError::Syntax { message, offset } => {
+                write!(f, "xml syntax error at offset {}: {}", offset, message)
+            }
+            XmlError::UnsupportedCharset(charset) => {
+                write!(f, "xml charset {:?} is not supported", charset)
+            }
+        }
+    }
+}
+
+impl error::Error for XmlError {}
+
+/// # A node in the children of an element
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String), // Decoded character data, whitespace-only text is dropped
+}
+
+/// # An XML element with its attributes and children in document order
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// # Get the value of an attribute
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// # Get the first child element with name
+    /// ## Usage
+    /// ```rust
+    /// use milstian_http::xml::parse;
+    /// let root = parse("<order id=\"7\"><item>a</item><item>b</item></order>").expect("XML");
+    /// assert_eq!(root.attribute("id"), Some("7"));
+    /// assert_eq!(root.child("item").map(|item| item.text()), Some("a".to_string()));
+    /// assert_eq!(root.children_named("item").count(), 2);
+    /// ```
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find_map(|node| match node {
+            Node::Element(element) if element.name == name => Some(element),
+            _ => None,
+        })
+    }
+
+    /// # Get the child elements with name
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter_map(move |node| match node {
+            Node::Element(element) if element.name == name => Some(element),
+            _ => None,
+        })
+    }
+
+    /// # Get the concatenated text of all descendants
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for node in self.children.iter() {
+            match node {
+                Node::Element(element) => text.push_str(&element.text()),
+                Node::Text(value) => text.push_str(value),
+            }
+        }
+        text
+    }
+}
+
+impl fmt::Display for Element {
+    /// # Serialize the element with escaped text and attribute values
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{}", self.name)?;
+        for (key, value) in self.attributes.iter() {
+            write!(f, " {}=\"{}\"", key, escape(value))?;
+        }
+        if self.children.is_empty() {
+            return write!(f, "/>");
+        }
+        write!(f, ">")?;
+        for node in self.children.iter() {
+            match node {
+                Node::Element(element) => write!(f, "{}", element)?,
+                Node::Text(value) => write!(f, "{}", escape(value))?,
+            }
+        }
+        write!(f, "</{}>", self.name)
+    }
+}
+
+/// # Escape text for XML content and quoted attribute values
+/// ## Usage
+/// ```rust
+/// assert_eq!(milstian_http::xml::escape("a<b & \"c\""), "a&lt;b &amp; &quot;c&quot;".to_string());
+/// ```
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// # Whether a media type is XML, like application/xml, text/xml or application/atom+xml
+/// ## Usage
+/// ```rust
+/// use milstian_http::xml::is_xml_media_type;
+/// assert!(is_xml_media_type("Application/XML"));
+/// assert!(is_xml_media_type("application/atom+xml"));
+/// assert!(!is_xml_media_type("application/json"));
+/// ```
+pub fn is_xml_media_type(media_type: &str) -> bool {
+    let media_type = media_type.trim().to_ascii_lowercase();
+    media_type == "application/xml" || media_type == "text/xml" || media_type.ends_with("+xml")
+}
+
+/// Get the encoding of an XML declaration
+fn get_declared_encoding(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(b"<?xml") {
+        return None;
+    }
+    let end = bytes.windows(2).position(|window| window == b"?>")?;
+    let declaration = String::from_utf8_lossy(&bytes[..end]).into_owned();
+    let start = declaration.find("encoding")? + "encoding".len();
+    let value = declaration[start..]
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start();
+    let quote = value.chars().next()?;
+    let value = &value[1..];
+    Some(value[..value.find(quote)?].to_string())
+}
+
+/// # Decode body bytes with charset, or the XML declaration encoding, or UTF-8
+/// UTF-8, US-ASCII and ISO-8859-1 are supported.
+/// ## Usage
+/// ```rust
+/// use milstian_http::xml::decode;
+/// assert_eq!(decode(b"<a>\xe5</a>", Some("ISO-8859-1")), Ok("<a>å</a>".to_string()));
+/// assert_eq!(decode(b"\xef\xbb\xbf<a/>", None), Ok("<a/>".to_string()));
+/// ```
+pub fn decode(bytes: &[u8], charset: Option<&str>) -> Result<String, XmlError> {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let charset = match charset {
+        Some(charset) => charset.trim_matches('"').to_ascii_lowercase(),
+        None => get_declared_encoding(bytes)
+            .map(|encoding| encoding.to_ascii_lowercase())
+            .unwrap_or_else(|| "utf-8".to_string()),
+    };
+    match charset.as_str() {
+        "utf-8" | "utf8" => {
+            String::from_utf8(bytes.to_vec()).map_err(|_| XmlError::InvalidEncoding)
+        }
+        "us-ascii" | "ascii" => {
+            if bytes.is_ascii() {
+                Ok(String::from_utf8_lossy(bytes).into_owned())
+            } else {
+                Err(XmlError::InvalidEncoding)
+            }
+        }
+        "iso-8859-1" | "latin1" => Ok(bytes.iter().map(|byte| *byte as char).collect()),
+        _ => Err(XmlError::UnsupportedCharset(charset)),
+    }
+}
+
+/// Decode the entity and character references of text
+fn unescape(text: &str, offset: usize) -> Result<String, XmlError> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let end = rest[start..].find(';').ok_or(XmlError::Syntax {
+            message: "unterminated reference",
+            offset,
+        })?;
+        let reference = &rest[start + 1..start + end];
+        let character = match reference {
+            "amp" => Some('&'),
+            "apos" => Some('\''),
+            "gt" => Some('>'),
+            "lt" => Some('<'),
+            "quot" => Some('"'),
+            _ => match reference.strip_prefix('#') {
+                Some(number) => match number.strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => number.parse().ok(),
+                }
+                .and_then(char::from_u32),
+                None => None,
+            },
+        };
+        match character {
+            Some(character) => unescaped.push(character),
+            None => {
+                return Err(XmlError::Syntax {
+                    message: "unknown reference",
+                    offset,
+                })
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+fn is_name_end(character: char) -> bool {
+    character.is_whitespace() || character == '/' || character == '>' || character == '='
+}
+
+/// Get the end of the name starting at position
+fn get_name_end(text: &str, position: usize) -> Result<usize, XmlError> {
+    let end = text[position..]
+        .find(is_name_end)
+        .map(|index| position + index)
+        .unwrap_or(text.len());
+    let name = &text[position..end];
+    match name.chars().next() {
+        Some(first) if first.is_alphabetic() || first == '_' || first == ':' => Ok(end),
+        _ => Err(XmlError::Syntax {
+            message: "invalid name",
+            offset: position,
+        }),
+    }
+}
+
+fn skip_whitespace(text: &str, position: usize) -> usize {
+    text[position..]
+        .find(|character: char| !character.is_whitespace())
+        .map(|index| position + index)
+        .unwrap_or(text.len())
+}
+
+/// Get the position after the next occurrence of delimiter
+fn skip_past(text: &str, position: usize, delimiter: &str) -> Result<usize, XmlError> {
+    text[position..]
+        .find(delimiter)
+        .map(|index| position + index + delimiter.len())
+        .ok_or(XmlError::Syntax {
+            message: "unterminated markup",
+            offset: position,
+        })
+}
+
+/// Parse the attributes and end of a start tag, returns the position after it and if it was empty
+fn parse_start_tag(
+    text: &str,
+    mut position: usize,
+    element: &mut Element,
+) -> Result<(usize, bool), XmlError> {
+    loop {
+        position = skip_whitespace(text, position);
+        let rest = &text[position..];
+        if rest.starts_with("/>") {
+            return Ok((position + 2, true));
+        }
+        if rest.starts_with('>') {
+            return Ok((position + 1, false));
+        }
+        if rest.is_empty() {
+            return Err(XmlError::Syntax {
+                message: "unterminated start tag",
+                offset: position,
+            });
+        }
+
+        let name_end = get_name_end(text, position)?;
+        let name = text[position..name_end].to_string();
+        position = skip_whitespace(text, name_end);
+        if !text[position..].starts_with('=') {
+            return Err(XmlError::Syntax {
+                message: "expected =",
+                offset: position,
+            });
+        }
+        position = skip_whitespace(text, position + 1);
+        let quote = match text[position..].chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => {
+                return Err(XmlError::Syntax {
+                    message: "expected quoted value",
+                    offset: position,
+                })
+            }
+        };
+        let value_end = text[position + 1..]
+            .find(quote)
+            .map(|index| position + 1 + index)
+            .ok_or(XmlError::Syntax {
+                message: "unterminated attribute value",
+                offset: position,
+            })?;
+        let raw_value = &text[position + 1..value_end];
+        if raw_value.contains('<') {
+            return Err(XmlError::Syntax {
+                message: "< in attribute value",
+                offset: position,
+            });
+        }
+        if element.attribute(&name).is_some() {
+            return Err(XmlError::Syntax {
+                message: "duplicate attribute",
+                offset: position,
+            });
+        }
+        element
+            .attributes
+            .push((name, unescape(raw_value, position)?));
+        position = value_end + 1;
+    }
+}
+
+/// # Parse a document into its root element
+/// ## Usage
+/// ```rust
+/// use milstian_http::xml::{parse, Node};
+/// let root = parse("<?xml version=\"1.0\"?>\n<a b='1'>x &amp; <![CDATA[<y>]]><c/></a>").expect("XML");
+/// assert_eq!(root.name, "a".to_string());
+/// assert_eq!(root.text(), "x & <y>".to_string());
+/// assert_eq!(root.to_string(), "<a b=\"1\">x &amp; &lt;y&gt;<c/></a>".to_string());
+/// assert!(parse("<a><b></a>").is_err());
+/// ```
+pub fn parse(text: &str) -> Result<Element, XmlError> {
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root: Option<Element> = None;
+    let mut position = if text.starts_with('\u{feff}') { 3 } else { 0 };
+    while position < text.len() {
+        let rest = &text[position..];
+        if rest.starts_with("<?") {
+            position = skip_past(text, position, "?>")?;
+        } else if rest.starts_with("<!--") {
+            position = skip_past(text, position + 4, "-->")?;
+        } else if rest.starts_with("<![CDATA[") {
+            let end = skip_past(text, position, "]]>")?;
+            match stack.last_mut() {
+                Some(parent) => parent
+                    .children
+                    .push(Node::Text(text[position + 9..end - 3].to_string())),
+                None => {
+                    return Err(XmlError::Syntax {
+                        message: "character data outside root element",
+                        offset: position,
+                    })
+                }
+            }
+            position = end;
+        } else if rest.starts_with("<!DOCTYPE") {
+            let end = skip_past(text, position, ">")?;
+            if root.is_some() || !stack.is_empty() || text[position..end].contains('[') {
+                return Err(XmlError::Syntax {
+                    message: "unsupported document type declaration",
+                    offset: position,
+                });
+            }
+            position = end;
+        } else if rest.starts_with("</") {
+            let name_end = get_name_end(text, position + 2)?;
+            let end = skip_whitespace(text, name_end);
+            if !text[end..].starts_with('>') {
+                return Err(XmlError::Syntax {
+                    message: "expected >",
+                    offset: end,
+                });
+            }
+            let element = match stack.pop() {
+                Some(element) if element.name == text[position + 2..name_end] => element,
+                _ => {
+                    return Err(XmlError::Syntax {
+                        message: "mismatched end tag",
+                        offset: position,
+                    })
+                }
+            };
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(Node::Element(element)),
+                None => root = Some(element),
+            }
+            position = end + 1;
+        } else if rest.starts_with('<') {
+            if root.is_some() {
+                return Err(XmlError::Syntax {
+                    message: "content after root element",
+                    offset: position,
+                });
+            }
+            let name_end = get_name_end(text, position + 1)?;
+            let mut element = Element {
+                name: text[position + 1..name_end].to_string(),
+                ..Element::default()
+            };
+            let (end, is_empty) = parse_start_tag(text, name_end, &mut element)?;
+            if is_empty {
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(Node::Element(element)),
+                    None => root = Some(element),
+                }
+            } else {
+                stack.push(element);
+            }
+            position = end;
+        } else {
+            let end = rest
+                .find('<')
+                .map(|index| position + index)
+                .unwrap_or(text.len());
+            let raw_text = &text[position..end];
+            if !raw_text.trim().is_empty() {
+                match stack.last_mut() {
+                    Some(parent) => parent
+                        .children
+                        .push(Node::Text(unescape(raw_text, position)?)),
+                    None => {
+                        return Err(XmlError::Syntax {
+                            message: "character data outside root element",
+                            offset: position,
+                        })
+                    }
+                }
+            }
+            position = end;
+        }
+    }
+    if !stack.is_empty() {
+        return Err(XmlError::Syntax {
+            message: "unclosed element",
+            offset: text.len(),
+        });
+    }
+    root.ok_or(XmlError::Syntax {
+        message: "missing root element",
+        offset: text.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let root = parse(
+            "\u{feff}<!DOCTYPE note>\n<!-- c --><note a=\"&#x41;&#66;\">\n  <to>T&lt;</to>\n  <empty />\n</note>\n<!-- end -->",
+        )
+        .expect("XML");
+        assert_eq!(root.attribute("a"), Some("AB"));
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(
+            root.child("to").map(|to| to.children.clone()),
+            Some(vec![Node::Text("T<".to_string())])
+        );
+        assert_eq!(
+            root.to_string(),
+            "<note a=\"AB\"><to>T&lt;</to><empty/></note>"
+        );
+
+        let error = |text: &str| match parse(text) {
+            Err(XmlError::Syntax { message, .. }) => message,
+            result => panic!("Expected a syntax error, got {:?}", result),
+        };
+        assert_eq!(error(""), "missing root element");
+        assert_eq!(error("<a>"), "unclosed element");
+        assert_eq!(error("<a/><b/>"), "content after root element");
+        assert_eq!(error("text<a/>"), "character data outside root element");
+        assert_eq!(error("<a b='1' b='2'/>"), "duplicate attribute");
+        assert_eq!(error("<a b=1/>"), "expected quoted value");
+        assert_eq!(error("<a>&unknown;</a>"), "unknown reference");
+        assert_eq!(error("<a>&amp</a>"), "unterminated reference");
+        assert_eq!(error("<1/>"), "invalid name");
+        assert_eq!(error("<a></b>"), "mismatched end tag");
+        assert_eq!(
+            error("<!DOCTYPE a [<!ENTITY x \"y\">]><a>&x;</a>"),
+            "unsupported document type declaration"
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode(
+                b"<?xml version='1.0' encoding='ISO-8859-1'?><a>\xe5</a>",
+                None
+            ),
+            Ok("<?xml version='1.0' encoding='ISO-8859-1'?><a>å</a>".to_string())
+        );
+        assert_eq!(decode(b"<a>\xe5</a>", None), Err(XmlError::InvalidEncoding));
+        assert_eq!(
+            decode(b"<a>\xe5</a>", Some("us-ascii")),
+            Err(XmlError::InvalidEncoding)
+        );
+        assert_eq!(
+            decode(b"<a/>", Some("\"UTF-16\"")),
+            Err(XmlError::UnsupportedCharset("utf-16".to_string()))
+        );
+    }
+}